@@ -287,9 +287,26 @@ pub struct SclHeader {
     /* NOTE: only required elements included here. Add optional elements as needed.*/
     /*好像没什么用 反正没见过用过     */
     pub id: String,
+    /*可选属性 用来生成协商时的 modelVersion     */
+    pub version: String,
+    pub revision: String,
     /*固定值 不用管     */
     pub namestructure: u32,
 }
+impl SclHeader {
+    /*关联协商里面的 modelVersion
+    有 version 就用 version.revision  中间要点 不然 "1"+"23" 和 "12"+"3" 一样
+    没有 revision 只用 version  都没有就只能用 id     */
+    pub fn model_version(&self) -> String {
+        if self.version.is_empty() {
+            self.id.clone()
+        } else if self.revision.is_empty() {
+            self.version.clone()
+        } else {
+            format!("{}.{}", self.version, self.revision)
+        }
+    }
+}
 /*1.2.1  scl/communication/subnet
 因为 comunicaiton 最多一个 所以把他提出来了  */
 /* Data from "Subnetwork" element	*/
@@ -846,6 +863,14 @@ pub struct SclServiceWithMax {
     pub enabled: bool,
     pub max: u32,
 }
+/*ConfDataSet DynDataSet  maxAttributes 是一个数据集最多几个成员     */
+#[derive(Default, Clone, Debug)]
+pub struct SclServiceDataset {
+    pub enabled: bool,
+    pub max: u32,
+    pub maxattributes: u32,
+    pub modify: bool, /* ConfDataSet only	*/
+}
 #[derive(Default, Clone, Debug)]
 pub struct SclServices {
     /* Simple entries just map to booleans.	*/
@@ -868,6 +893,8 @@ pub struct SclServices {
     pub smvsc: SclServiceWithMax,
     pub supsubscription: SclServiceWithMax,
     pub confsigref: SclServiceWithMax,
+    pub confdataset: SclServiceDataset,
+    pub dyndataset: SclServiceDataset,
 
     pub reportsettings: SclReportsettings,
 }
@@ -968,6 +995,13 @@ pub fn _header_sfun(sxdecctrl: &mut IcdParseContext2) {
         return; /* At least one required attr not found. Stop now.	*/
     }
 
+    if let Some(version) = sxdecctrl.scl_get_attr_ptr("version", SCL_ATTR_OPTIONAL) {
+        sxdecctrl.scl_dec_ctrl.sclinfo.header.version = version.trim().to_string();
+    }
+    if let Some(revision) = sxdecctrl.scl_get_attr_ptr("revision", SCL_ATTR_OPTIONAL) {
+        sxdecctrl.scl_dec_ctrl.sclinfo.header.revision = revision.trim().to_string();
+    }
+
     let op_namestructure = sxdecctrl.scl_get_attr_ptr("nameStructure", SCL_ATTR_OPTIONAL);
     if let Some(namestructure) = op_namestructure {
         if !check_eq_2bs(namestructure.as_bytes(), "IEDName".as_bytes()) {
@@ -1368,7 +1402,7 @@ fn services_sefun(sxdecctrl: &mut IcdParseContext2) {
         println!("SCL PARSE: Services section found");
         //ServicesElements
         sxdecctrl.sx_push(vec![
-            60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78,
+            60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80,
         ]);
     } else
     /* reason = SX_ELEMENT_END */
@@ -3154,13 +3188,14 @@ fn _trgops_sfun(sxdecctrl: &mut IcdParseContext2) {
     // println!("now tripgs ");
     let required = false;
 
-    sxdecctrl.scl_dec_ctrl.trgops = 0; /* Start with all bits=0	*/
+    /*位要设到同一个数组上 最后再赋回去     */
+    let mut trgops = [0u8; 1]; /* Start with all bits=0	*/
 
     /* start optional attributes */
     if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("dchg", required) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
             utils::bstr_bit_set_on(
-                &mut [sxdecctrl.scl_dec_ctrl.trgops],
+                &mut trgops,
                 TRGOPS_BITNUM_DATA_CHANGE,
             );
         }
@@ -3169,7 +3204,7 @@ fn _trgops_sfun(sxdecctrl: &mut IcdParseContext2) {
     if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("qchg", required) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
             utils::bstr_bit_set_on(
-                &mut [sxdecctrl.scl_dec_ctrl.trgops],
+                &mut trgops,
                 TRGOPS_BITNUM_QUALITY_CHANGE,
             );
         }
@@ -3177,7 +3212,7 @@ fn _trgops_sfun(sxdecctrl: &mut IcdParseContext2) {
     if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("dupd", required) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
             utils::bstr_bit_set_on(
-                &mut [sxdecctrl.scl_dec_ctrl.trgops],
+                &mut trgops,
                 TRGOPS_BITNUM_DATA_UPDATE,
             );
         }
@@ -3186,7 +3221,7 @@ fn _trgops_sfun(sxdecctrl: &mut IcdParseContext2) {
     if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("period", required) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
             utils::bstr_bit_set_on(
-                &mut [sxdecctrl.scl_dec_ctrl.trgops],
+                &mut trgops,
                 TRGOPS_BITNUM_INTEGRITY,
             );
         }
@@ -3194,13 +3229,13 @@ fn _trgops_sfun(sxdecctrl: &mut IcdParseContext2) {
     if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("gi", required) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
             utils::bstr_bit_set_on(
-                &mut [sxdecctrl.scl_dec_ctrl.trgops],
+                &mut trgops,
                 TRGOPS_BITNUM_GENERAL_INTERROGATION,
             );
         }
     } else {
         utils::bstr_bit_set_on(
-            &mut [sxdecctrl.scl_dec_ctrl.trgops],
+            &mut trgops,
             TRGOPS_BITNUM_GENERAL_INTERROGATION,
         );
     }
@@ -3208,6 +3243,7 @@ fn _trgops_sfun(sxdecctrl: &mut IcdParseContext2) {
     /* NOTE: "gi" defaults to "true".	*/
 
     /* end optional attributes */
+    sxdecctrl.scl_dec_ctrl.trgops = trgops[0];
 }
 
 fn _optflds_sfun(sxdecctrl: &mut IcdParseContext2) {
@@ -3323,41 +3359,41 @@ fn _smvopts_sfun(sxdecctrl: &mut IcdParseContext2) {
         return;
     }
 
-    let mut optflds: u8 = 0;
+    let mut optflds = [0u8; 1];
     let mut securitypres = false;
     let mut datarefpres = false;
 
     /* start optional attributes */
     if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("sampleRate", SCL_ATTR_OPTIONAL) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
-            utils::bstr_bit_set_on(&mut [optflds], SVOPT_BITNUM_SMPRATE);
+            utils::bstr_bit_set_on(&mut optflds, SVOPT_BITNUM_SMPRATE);
         }
     }
 
     if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("refreshTime", SCL_ATTR_OPTIONAL) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
-            utils::bstr_bit_set_on(&mut [optflds], SVOPT_BITNUM_REFRTM);
+            utils::bstr_bit_set_on(&mut optflds, SVOPT_BITNUM_REFRTM);
         }
     }
     if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("sampleSynchronized", SCL_ATTR_OPTIONAL) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
-            utils::bstr_bit_set_on(&mut [optflds], SVOPT_BITNUM_SMPSYNCH);
+            utils::bstr_bit_set_on(&mut optflds, SVOPT_BITNUM_SMPSYNCH);
         }
     }
 
     /* "sampleSynchronized" must be "true" for Edition 2.	*/
     if sxdecctrl.scl_dec_ctrl.sclinfo.edition == 2
-        && !utils::bstr_bit_get(&mut [optflds], SVOPT_BITNUM_SMPSYNCH)
+        && !utils::bstr_bit_get(&mut optflds, SVOPT_BITNUM_SMPSYNCH)
     {
         println! ("sampleSynchronized='false' not allowed for Edition 2. Automatically setting it to 'true'.");
-        utils::bstr_bit_set_on(&mut [optflds], SVOPT_BITNUM_SMPSYNCH);
+        utils::bstr_bit_set_on(&mut optflds, SVOPT_BITNUM_SMPSYNCH);
     }
 
     /* "dataSet" is for Edition 2 only.	*/
     if sxdecctrl.scl_dec_ctrl.sclinfo.edition == 2 {
         if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("dataSet", SCL_ATTR_OPTIONAL) {
             if check_eq_2str_incaseinse(txt.trim(), "true") {
-                utils::bstr_bit_set_on(&mut [optflds], SVOPT_BITNUM_DATSET);
+                utils::bstr_bit_set_on(&mut optflds, SVOPT_BITNUM_DATSET);
             }
         }
     }
@@ -3366,7 +3402,7 @@ fn _smvopts_sfun(sxdecctrl: &mut IcdParseContext2) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
             securitypres = true;
             if sxdecctrl.scl_dec_ctrl.sclinfo.edition == 2 {
-                utils::bstr_bit_set_on(&mut [optflds], SVOPT_BITNUM_SECURITY);
+                utils::bstr_bit_set_on(&mut optflds, SVOPT_BITNUM_SECURITY);
             }
         }
     }
@@ -3379,7 +3415,7 @@ fn _smvopts_sfun(sxdecctrl: &mut IcdParseContext2) {
     sxdecctrl.scl_dec_ctrl.sclinfo.server_vec[servr_len - 1].ld_vec[ld_len - 1].ln_vec
         [ln_len - 1]
         .svcb_vec[smv_len - 1]
-        .optflds = optflds;
+        .optflds = optflds[0];
 
     sxdecctrl.scl_dec_ctrl.sclinfo.server_vec[servr_len - 1].ld_vec[ld_len - 1].ln_vec
         [ln_len - 1]
@@ -4372,6 +4408,50 @@ fn _confsigref_sfun(sxdecctrl: &mut IcdParseContext2) {
     }
 }
 /************************************************************************/
+/*			_ConfDataSet_SFun _DynDataSet_SFun		*/
+/* Fill in structure.							*/
+/************************************************************************/
+fn _dataset_service_parse(sxdecctrl: &mut IcdParseContext2, tag: &str) -> Option<SclServiceDataset> {
+    let mut service = SclServiceDataset {
+        enabled: true,
+        ..Default::default()
+    };
+    if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("max", SCL_ATTR_REQUIRED) {
+        if let Ok(max) = txt.trim().parse::<u32>() {
+            service.max = max;
+        } else {
+            println!("{} max pare err", tag);
+            sxdecctrl.errcode = SX_USER_ERROR;
+            return None;
+        }
+    } else {
+        return None;
+    }
+    if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("maxAttributes", SCL_ATTR_OPTIONAL) {
+        if let Ok(maxattributes) = txt.trim().parse::<u32>() {
+            service.maxattributes = maxattributes;
+        } else {
+            println!("{} maxAttributes pare err", tag);
+            sxdecctrl.errcode = SX_USER_ERROR;
+            return None;
+        }
+    }
+    if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("modify", SCL_ATTR_OPTIONAL) {
+        service.modify = check_eq_2str_incaseinse(txt.trim(), "true");
+    }
+    Some(service)
+}
+fn _confdataset_sfun(sxdecctrl: &mut IcdParseContext2) {
+    if let Some(service) = _dataset_service_parse(sxdecctrl, "_ConfDataSet_SFun") {
+        sxdecctrl.scl_dec_ctrl.scl_services.confdataset = service;
+    }
+}
+fn _dyndataset_sfun(sxdecctrl: &mut IcdParseContext2) {
+    if let Some(service) = _dataset_service_parse(sxdecctrl, "_DynDataSet_SFun") {
+        sxdecctrl.scl_dec_ctrl.scl_services.dyndataset = service;
+    }
+}
+/************************************************************************/
 /*			_ReportSettings_SFun				*/
 /* CRITICAL: default values set earlier by scl_services_init.		*/
 /************************************************************************/
@@ -4408,7 +4488,7 @@ fn _reportsettings_sfun(sxdecctrl: &mut IcdParseContext2) {
     }
     if let Some(ref txt) = sxdecctrl.scl_get_attr_ptr("owner", SCL_ATTR_OPTIONAL) {
         if check_eq_2str_incaseinse(txt.trim(), "true") {
-            sxdecctrl.scl_dec_ctrl.scl_services.reportsettings.resvtms = true;
+            sxdecctrl.scl_dec_ctrl.scl_services.reportsettings.owner = true;
         }
     }
     //println!("get directly serei true  19 {:?}", sxdecctrl.scl_dec_ctrl.scl_services.ReportSettings);
@@ -5495,7 +5575,7 @@ fn gen_enumtypeelements_tb() -> Vec<SxElement> {
 //   {"ReportSettings",		SX_ELF_CSTART|SX_ELF_OPT,	_ReportSettings_SFun, NULL, 0}
 // };
 pub fn gen_serviceselements_tb() -> Vec<SxElement> {
-    let mut tb = Vec::with_capacity(19);
+    let mut tb = Vec::with_capacity(21);
 
     tb.push(SxElement {
         tag: String::from("GetDirectory"), /*INDEX 60*/
@@ -5592,10 +5672,20 @@ pub fn gen_serviceselements_tb() -> Vec<SxElement> {
     });
 
     //   /* More complex entries.	*/
-    //   /* DEBUG: TO DO: Add entries for DynAssociation, SettingGroups, ConfDataSet,*/
-    //   /*        DynDataSet, LogSettings, GSESettings, SMVSettings, ConfLNs.*/
+    //   /* DEBUG: TO DO: Add entries for DynAssociation, SettingGroups,*/
+    //   /*        LogSettings, GSESettings, SMVSettings, ConfLNs.*/
+    tb.push(SxElement {
+        tag: String::from("ConfDataSet"), /*INDEX 78*/
+        elementflags: SX_ELF_CSTART | SX_ELF_OPT,
+        funcptr: Box::new(_confdataset_sfun),
+    });
+    tb.push(SxElement {
+        tag: String::from("DynDataSet"), /*INDEX 79*/
+        elementflags: SX_ELF_CSTART | SX_ELF_OPT,
+        funcptr: Box::new(_dyndataset_sfun),
+    });
     tb.push(SxElement {
-        tag: String::from("ReportSettings"), /*INDEX 78*/
+        tag: String::from("ReportSettings"), /*INDEX 80*/
         elementflags: SX_ELF_CSTART | SX_ELF_OPT,
         funcptr: Box::new(_reportsettings_sfun),
     });
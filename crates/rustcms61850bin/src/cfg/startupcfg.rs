@@ -26,7 +26,7 @@ use crate::comdata;
 
 // 用来读取配置的文件相关
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct StartupCfg {
     pub scl_filename: String,
     pub ied_name: String,
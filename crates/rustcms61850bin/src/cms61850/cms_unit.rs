@@ -1,19 +1,593 @@
-use crate::comdata;
+use super::codec::aper;
+use super::control::handler::CtlDone;
+use super::control::ControlEngine;
+use super::goose::publisher::GoosePublisher;
+use super::goose::sink::RawSocket;
+use super::goose::subscriber::{self, GooseSubscriber};
+use super::goose::ETHERTYPE_GOOSE;
+use super::log::LogEngine;
+use super::model::overlay::Overlay;
+use super::model::{DataSet, Model};
+use super::net::cmscon::frame;
+use super::net::shutdown::Shutdown;
+use super::report::ReportEngine;
+use super::service::common::ServiceError;
+use super::service::{control, data, dataset, directory, goose, log, report, sg};
+use super::sg::SgEngine;
+use crate::cfg::scl2::SclInfo;
+use crate::cfg::startupcfg::StartupCfg;
+use crate::comdata::{self, ExData, ToConData};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::time::{self, Duration, Instant};
 /***************************************/
 /*真正的 汇总中心     */
 /***************************************/
 
+/*控制异步完成的通道深度     */
+const CTL_DONE_CHANNEL: usize = 64;
+/*GOOSE 收的线程到这边的通道深度     */
+const GOOSE_FRAME_CHANNEL: usize = 256;
+/*多久打一次控制的诊断  秒     */
+const DIAG_PERIOD_SEC: u64 = 300;
+
+/*一个连接在汇总中心这边的东西  连接断了一起删     */
+pub struct ConCtx {
+    /*回给这个连接的通道     */
+    pub to_con_tx: mpsc::Sender<ToConData>,
+    /*对端地址  报告控制块的 owner 用     */
+    pub peer: Option<IpAddr>,
+    /*最近一次请求带过来的协商 asdu 长度  报告分段用     */
+    pub neg_asdu_len: usize,
+    /*这个关联建的非持久数据集     */
+    pub nps_vec: Vec<DataSet>,
+}
+
+impl ConCtx {
+    /*owner 是对端 ip 的字节     */
+    pub fn owner(&self) -> Vec<u8> {
+        match self.peer {
+            Some(IpAddr::V4(ip)) => ip.octets().to_vec(),
+            Some(IpAddr::V6(ip)) => ip.octets().to_vec(),
+            None => Vec::new(),
+        }
+    }
+}
+
 pub struct CmsUnit {
     //初始配置
     pub cfg_data: comdata::CfgData,
+    /*startupcfg.json  报告扫描周期这些     */
+    pub startup_cfg: StartupCfg,
+    /*运行时模型     */
+    pub model: Model,
+    /*连接号 -> 连接的上下文     */
+    pub con_map: HashMap<u32, ConCtx>,
+    /*报告控制块的占用和触发     */
+    pub report_engine: ReportEngine,
+    /*日志控制块和日志     */
+    pub log_engine: LogEngine,
+    /*控制对象的选中和命令结束     */
+    pub control_engine: ControlEngine,
+    /*定值组的编辑区占用     */
+    pub sg_engine: SgEngine,
+    /*落盘的改动  没配文件是 None     */
+    overlay: Option<Overlay>,
+    /*GOOSE 发布  没开或者网口打不开是 None     */
+    goose_publisher: Option<GoosePublisher>,
+    /*GOOSE 订阅  没开或者没有 ExtRef 是 None     */
+    goose_subscriber: Option<GooseSubscriber>,
+    /*收的线程丢过来的帧  run 的时候拿走     */
+    goose_frame_rx: Option<mpsc::Receiver<Vec<u8>>>,
+    /*控制处理者异步报回来的结果  run 的时候拿走     */
+    ctl_done_rx: Option<mpsc::Receiver<CtlDone>>,
 }
 impl CmsUnit {
-    /*运行函数     */
-    pub async fn run(&mut self) {}
-    /*构造函数     */
-    pub fn new(cfg:&comdata::CfgData) -> CmsUnit {
-        CmsUnit {
-            cfg_data: cfg.clone(),
+    /*运行函数  所有连接的数据服务都在这里排队处理     */
+    pub async fn run(
+        &mut self,
+        mut from_con_rx: mpsc::Receiver<ExData>,
+        mut shutdown: Shutdown,
+        _shutdown_complete: mpsc::Sender<()>,
+    ) {
+        let scan_rate = Duration::from_millis(self.startup_cfg.report_scan_rate.max(1) as u64);
+        let mut report_scan = time::interval(scan_rate);
+        report_scan.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        let log_rate = Duration::from_millis(self.startup_cfg.logscanratems.max(1) as u64);
+        let mut log_scan = time::interval(log_rate);
+        log_scan.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        let diag_period = Duration::from_secs(DIAG_PERIOD_SEC);
+        let mut diag = time::interval_at(Instant::now() + diag_period, diag_period);
+        diag.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        let mut ctl_done_rx = match self.ctl_done_rx.take() {
+            Some(ctl_done_rx) => ctl_done_rx,
+            None => return,
+        };
+        let mut goose_frame_rx = match self.goose_frame_rx.take() {
+            Some(goose_frame_rx) => goose_frame_rx,
+            None => return,
+        };
+        while !shutdown.is_shutdown() {
+            /*最早的时间激活执行  没有就不等     */
+            let next_due = self.control_engine.next_due();
+            let goose_due = self
+                .goose_publisher
+                .as_ref()
+                .and_then(|publisher| publisher.next_due());
+            let goose_tal = self
+                .goose_subscriber
+                .as_ref()
+                .and_then(|subscriber| subscriber.next_due());
+            tokio::select! {
+                info = from_con_rx.recv() => {
+                    match info {
+                        Some(info) => self.handle_info(info),
+                        None => return,
+                    }
+                }
+                _ = report_scan.tick() => {
+                    self.release_closed();
+                    self.control_engine.expire(&mut self.model, Instant::now());
+                    self.sg_engine.expire(&mut self.model, Instant::now());
+                    self.scan_reports();
+                }
+                Some(done) = ctl_done_rx.recv() => {
+                    self.control_engine.complete(&mut self.model, done);
+                    self.send_terminations();
+                    self.scan_reports();
+                }
+                _ = time::sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => {
+                    self.control_engine.run_due(&mut self.model, Instant::now());
+                    self.send_terminations();
+                    self.scan_reports();
+                }
+                _ = time::sleep_until(goose_due.unwrap_or_else(Instant::now)), if goose_due.is_some() => {
+                    if let Some(publisher) = self.goose_publisher.as_mut() {
+                        publisher.run_due(Instant::now());
+                    }
+                }
+                Some(frame) = goose_frame_rx.recv() => {
+                    let written = self.goose_subscriber.as_mut().is_some_and(|subscriber| {
+                        subscriber.handle_frame(&mut self.model, &frame, Instant::now())
+                    });
+                    if written {
+                        self.scan_reports();
+                    }
+                }
+                _ = time::sleep_until(goose_tal.unwrap_or_else(Instant::now)), if goose_tal.is_some() => {
+                    if let Some(subscriber) = self.goose_subscriber.as_mut() {
+                        subscriber.expire(&mut self.model, Instant::now());
+                    }
+                    self.scan_reports();
+                }
+                _ = log_scan.tick() => {
+                    self.log_engine.scan(&self.model, Instant::now());
+                }
+                _ = diag.tick() => {
+                    self.control_engine.log_diag(Instant::now());
+                }
+                _ = shutdown.recv() => {
+                    println!("cms unit get shutting down cmd");
+                    return;
+                }
+            }
+        }
+    }
+
+    fn handle_info(&mut self, info: ExData) {
+        match info {
+            ExData::ConOpen(con_id, peer, to_con_tx) => {
+                self.con_map.insert(
+                    con_id,
+                    ConCtx {
+                        to_con_tx,
+                        peer,
+                        neg_asdu_len: 0,
+                        nps_vec: Vec::new(),
+                    },
+                );
+            }
+            ExData::ConClose(con_id) => self.release_con(con_id),
+            ExData::ServiceReq(req) => {
+                let serv_code = req.apdu.apch.serv_code;
+                let req_id = req.apdu.asdu.req_id;
+                let result = match serv_code {
+                    frame::SELECTWITHVALUE
+                    | frame::CANCEL
+                    | frame::OPERATE
+                    | frame::TIMEACTIVATEDOPERATE => self.handle_control(&req),
+                    _ => self.handle_service(&req).map_err(|err| {
+                        println!(
+                            "con {} serv code {} req id {} failed {:?}",
+                            req.con_id, serv_code, req_id, err
+                        );
+                        aper::aper_to_bytes(&err).unwrap_or_default()
+                    }),
+                };
+                let (is_err, total_pay_load) = match result {
+                    Ok(pay_load) => (false, pay_load),
+                    Err(pay_load) => (true, pay_load),
+                };
+                let builder = frame::APDUBuilder {
+                    is_err,
+                    is_resp: true,
+                    serv_code,
+                    req_id,
+                    total_pay_load,
+                };
+                self.send_to_con(req.con_id, ToConData::ServiceResp(builder));
+                self.send_terminations();
+                /*写值或者使能带出来的报告马上发  不用等下一次扫描     */
+                self.scan_reports();
+                self.save_overlay();
+            }
+            /*重新加载配置  连接 模型 控制的运行时状态都不动  排着的时间激活执行也留着     */
+            ExData::CfgData(cfg_data) => {
+                self.control_engine
+                    .reload(cfg_data.control_cfg.sim_operate_ms);
+                self.cfg_data = cfg_data;
+                println!("cms unit cfg reloaded");
+                self.control_engine.log_diag(Instant::now());
+            }
         }
     }
+
+    /*连接没了  占着的报告控制块 定值组编辑区 控制选中 时间激活执行都放掉
+    非持久数据集跟着上下文一起删  ConClose 和发送失败都走这里     */
+    fn release_con(&mut self, con_id: u32) {
+        self.control_engine.release_con(&mut self.model, con_id);
+        self.sg_engine.release_con(&mut self.model, con_id);
+        if let Some(con) = self.con_map.remove(&con_id) {
+            report::release_con(
+                &mut self.model,
+                &mut self.report_engine,
+                con_id,
+                &con.owner(),
+            );
+        }
+    }
+
+    /*连接的 ConClose 满了会丢  扫的时候看一下对面的接收端没了的也当断开处理     */
+    fn release_closed(&mut self) {
+        let closed_vec: Vec<u32> = self
+            .con_map
+            .iter()
+            .filter(|(_, con)| con.to_con_tx.is_closed())
+            .map(|(con_id, _)| *con_id)
+            .collect();
+        for con_id in closed_vec {
+            println!("con {} gone without close", con_id);
+            self.release_con(con_id);
+        }
+    }
+
+    /*发给单个连接  不等  对面不收满了就把连接放掉
+    发送端跟着上下文删掉  连接那边收到 None 自己断开     */
+    fn send_to_con(&mut self, con_id: u32, data: ToConData) -> bool {
+        let con = match self.con_map.get(&con_id) {
+            Some(con) => con,
+            None => return false,
+        };
+        match con.to_con_tx.try_send(data) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                println!("con {} queue full  closed", con_id);
+                self.release_con(con_id);
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                self.release_con(con_id);
+                false
+            }
+        }
+    }
+
+    /*按服务码分发  响应载荷不能超过 asdu 减去 reqid     */
+    fn handle_service(&mut self, req: &comdata::ServiceReq) -> Result<Bytes, ServiceError> {
+        let pay_load = &req.apdu.asdu.pay_load;
+        let max_len = req.neg_asdu_len.saturating_sub(2);
+        let con = match self.con_map.get_mut(&req.con_id) {
+            Some(con) => con,
+            None => return Err(ServiceError::FailedDueToCommunicationsConstraint),
+        };
+        con.neg_asdu_len = req.neg_asdu_len;
+        let nps_vec = &mut con.nps_vec;
+        match req.apdu.apch.serv_code {
+            frame::GETSERVERDIRECTORY => {
+                directory::get_server_directory(&self.model, pay_load, max_len)
+            }
+            frame::GETLOGICDEVICEDIRECTORY => {
+                directory::get_logical_device_directory(&self.model, pay_load, max_len)
+            }
+            frame::GETLOGICNODEDIRECTORY => {
+                directory::get_logical_node_directory(&self.model, pay_load, max_len)
+            }
+            frame::GETDATAVALUES => data::get_data_values(&self.model, pay_load, max_len),
+            frame::SETDATAVALUES => data::set_data_values(
+                &mut self.model,
+                &mut self.sg_engine,
+                req.con_id,
+                pay_load,
+                max_len,
+            ),
+            frame::GETDATADIRECTORY => data::get_data_directory(&self.model, pay_load, max_len),
+            frame::GETDATADEFINITION => data::get_data_definition(&self.model, pay_load, max_len),
+            frame::GETALLDATAVALUES => data::get_all_data_values(&self.model, pay_load, max_len),
+            frame::GETALLDATADEFINITION => {
+                data::get_all_data_definition(&self.model, pay_load, max_len)
+            }
+            frame::CREATEDATASET => dataset::create_dataset(&mut self.model, nps_vec, pay_load),
+            frame::DELETEDATASET => dataset::delete_dataset(&mut self.model, nps_vec, pay_load),
+            frame::GETDATASETDIRECTORY => {
+                dataset::get_dataset_directory(&self.model, nps_vec, pay_load, max_len)
+            }
+            frame::GETDATASETVALUES => {
+                dataset::get_dataset_values(&self.model, nps_vec, pay_load, max_len)
+            }
+            frame::SETDATASETVALUES => dataset::set_dataset_values(
+                &mut self.model,
+                &mut self.sg_engine,
+                req.con_id,
+                nps_vec,
+                pay_load,
+                max_len,
+            ),
+            frame::SELECT => control::select(
+                &mut self.model,
+                &mut self.control_engine,
+                req.con_id,
+                pay_load,
+                max_len,
+            ),
+            frame::GETALLCBVALUES => data::get_all_cb_values(&self.model, pay_load, max_len),
+            frame::GETBRCBVALUES => report::get_brcb_values(&self.model, pay_load, max_len),
+            frame::SETBRCBVALUES => report::set_brcb_values(
+                &mut self.model,
+                &mut self.report_engine,
+                req.con_id,
+                &con.owner(),
+                pay_load,
+                max_len,
+            ),
+            frame::GETLCBVALUES => log::get_lcb_values(&self.model, pay_load, max_len),
+            frame::SETLCBVALUES => {
+                log::set_lcb_values(&mut self.model, &mut self.log_engine, pay_load, max_len)
+            }
+            frame::QUERYLOGBYTIME => log::query_log_by_time(&self.log_engine, pay_load, max_len),
+            frame::QUERYLOGAFTER => log::query_log_after(&self.log_engine, pay_load, max_len),
+            frame::GETLOGSTATUSVALUES => {
+                log::get_log_status_values(&self.log_engine, pay_load, max_len)
+            }
+            frame::GETURCBVALUES => report::get_urcb_values(&self.model, pay_load, max_len),
+            frame::SETURCBVALUES => report::set_urcb_values(
+                &mut self.model,
+                &mut self.report_engine,
+                req.con_id,
+                &con.owner(),
+                &con.nps_vec,
+                pay_load,
+                max_len,
+            ),
+            frame::GETGOCBVALUES => goose::get_gocb_values(&self.model, pay_load, max_len),
+            frame::SETGOCBVALUES => goose::set_gocb_values(&mut self.model, pay_load, max_len),
+            frame::SELECTACTIVESG => {
+                sg::select_active_sg(&mut self.model, &mut self.sg_engine, pay_load)
+            }
+            frame::SELECTEDITSG => {
+                sg::select_edit_sg(&mut self.model, &mut self.sg_engine, req.con_id, pay_load)
+            }
+            frame::SETEDITSGVALUE => sg::set_edit_sg_value(
+                &mut self.model,
+                &mut self.sg_engine,
+                req.con_id,
+                pay_load,
+                max_len,
+            ),
+            frame::CONFIRMEDITSGVALUES => sg::confirm_edit_sg_values(
+                &mut self.model,
+                &mut self.sg_engine,
+                req.con_id,
+                pay_load,
+            ),
+            frame::GETEDITSGVALUE => sg::get_edit_sg_value(&self.model, pay_load, max_len),
+            frame::GETSGCBVALUES => sg::get_sgcb_values(&self.model, pay_load, max_len),
+            _ => Err(ServiceError::FailedDueToServerConstraint),
+        }
+    }
+
+    /*69 70 71 73  否定响应的载荷是带 addCause 的 Control-ResponsePDU
+    请求解不出来 对象找不到 才是 ServiceError     */
+    fn handle_control(&mut self, req: &comdata::ServiceReq) -> Result<Bytes, Bytes> {
+        let pay_load = &req.apdu.asdu.pay_load;
+        let max_len = req.neg_asdu_len.saturating_sub(2);
+        if let Some(con) = self.con_map.get_mut(&req.con_id) {
+            con.neg_asdu_len = req.neg_asdu_len;
+        }
+        let (model, engine, con_id) = (&mut self.model, &mut self.control_engine, req.con_id);
+        match req.apdu.apch.serv_code {
+            frame::SELECTWITHVALUE => {
+                control::select_with_value(model, engine, con_id, pay_load, max_len)
+            }
+            frame::CANCEL => control::cancel(model, engine, con_id, pay_load, max_len),
+            frame::OPERATE => control::operate(model, engine, con_id, pay_load, max_len),
+            _ => control::time_activated_operate(model, engine, con_id, pay_load, max_len),
+        }
+    }
+
+    /*命令结束  增强安全的是 CommandTermination  时间激活执行的是 TimeActivatedOperateTermination
+    服务端发的请求 req_id 是 0 没有响应     */
+    fn send_terminations(&mut self) {
+        for (con_id, serv_code, pdu) in self.control_engine.take_terminations() {
+            if !self.con_map.contains_key(&con_id) {
+                continue;
+            }
+            let total_pay_load = match aper::aper_to_bytes(&pdu) {
+                Ok(pay_load) => pay_load,
+                Err(err) => {
+                    println!("con {} command termination encode failed {:?}", con_id, err);
+                    continue;
+                }
+            };
+            let builder = frame::APDUBuilder {
+                is_err: pdu.addcause.is_some(),
+                is_resp: false,
+                serv_code,
+                req_id: 0,
+                total_pay_load,
+            };
+            self.send_to_con(con_id, ToConData::Report(builder));
+        }
+    }
+
+    /*写过的 SP CF  确认过的定值  交给后台重写落盘文件  没改过不写  没配文件也要取走     */
+    fn save_overlay(&mut self) {
+        match self.overlay.as_mut() {
+            Some(overlay) => overlay.save(&mut self.model),
+            None => {
+                self.model.take_persists();
+            }
+        }
+    }
+
+    /*扫一遍报告  发给占着控制块的连接
+    报告是请求 req_id 是 0  超过协商长度的分段发
+    模型里面的变化在这里取  日志的先交给日志那边攒着  GOOSE 的马上发     */
+    fn scan_reports(&mut self) {
+        let change_vec = self.model.take_changes();
+        if let Some(publisher) = self.goose_publisher.as_mut() {
+            publisher.scan(&self.model, &change_vec, Instant::now());
+        }
+        self.log_engine.queue_changes(&change_vec);
+        let report_vec = self
+            .report_engine
+            .scan(&mut self.model, &change_vec, Instant::now());
+        for out in report_vec {
+            let con_id = out.con_id;
+            let neg_asdu_len = match self.con_map.get(&con_id) {
+                Some(con) => con.neg_asdu_len,
+                None => continue,
+            };
+            /*缓存的全部分段都交给连接了才算发过  太大发不了的也跳过 不然一直卡在这一条     */
+            let sent = match report::segment_report(out.report, neg_asdu_len.saturating_sub(2)) {
+                Ok(segment_vec) => segment_vec.into_iter().all(|total_pay_load| {
+                    let builder = frame::APDUBuilder {
+                        is_err: false,
+                        is_resp: false,
+                        serv_code: frame::REPORT,
+                        req_id: 0,
+                        total_pay_load,
+                    };
+                    self.send_to_con(con_id, ToConData::Report(builder))
+                }),
+                Err(err) => {
+                    println!("con {} report too large {:?}", con_id, err);
+                    true
+                }
+            };
+            if let (true, Some((key, entryid))) = (sent, out.brcb) {
+                self.report_engine
+                    .confirm_sent(&mut self.model, &key, entryid);
+            }
+        }
+    }
+
+    /*构造函数  模型从 scl 里面建出来
+    pub_model_vec 是 GOOSE 订阅的发布方  没开订阅就是空的     */
+    pub fn new(
+        cfg: &comdata::CfgData,
+        startup_cfg: &StartupCfg,
+        scl_info: &SclInfo,
+        pub_model_vec: &[Model],
+    ) -> crate::Result<CmsUnit> {
+        let mut model = Model::from_scl(scl_info)?;
+        println!(
+            "model {} ld {} ln {}",
+            model.ied_name,
+            model.ld_vec.len(),
+            model.ld_vec.iter().map(|ld| ld.ln_vec.len()).sum::<usize>()
+        );
+        /*落盘的改动先盖上去  定值组启动的时候按恢复的激活组取值     */
+        let overlay_file = &cfg.overlay_cfg.overlay_file;
+        let overlay = (!overlay_file.is_empty()).then(|| {
+            let header = &scl_info.header;
+            Overlay::open(
+                PathBuf::from(overlay_file),
+                &header.id,
+                &header.version,
+                &header.revision,
+            )
+        });
+        if let Some(overlay) = overlay.as_ref() {
+            overlay.apply(&mut model);
+        }
+        let mut report_engine = ReportEngine::new(startup_cfg.brcbbuffersize as usize);
+        let journal_cfg = &cfg.brcb_journal_cfg;
+        if journal_cfg.enable {
+            let dir = Path::new(&journal_cfg.journal_dir).join(&model.ied_name);
+            report_engine.enable_journal(dir, journal_cfg.retention_sec);
+        }
+        report_engine.start_brcbs(&model);
+        let log_dir = &cfg.log_cfg.log_dir;
+        let log_dir = (!log_dir.is_empty()).then(|| Path::new(log_dir).join(&model.ied_name));
+        let mut log_engine = LogEngine::new(startup_cfg.logmaxentries as usize, log_dir);
+        log_engine.start(&model);
+        let (ctl_done_tx, ctl_done_rx) = mpsc::channel(CTL_DONE_CHANNEL);
+        let mut control_engine = ControlEngine::new(ctl_done_tx, cfg.control_cfg.sim_operate_ms);
+        control_engine.start(&model);
+        let mut sg_engine = SgEngine::new(cfg.sg_cfg.edit_resv_sec);
+        sg_engine.start(&mut model);
+        let goose_cfg = &cfg.goose_cfg;
+        /*发布的套接字只发不收  收的是订阅单独开的     */
+        let goose_publisher = if goose_cfg.enable {
+            match RawSocket::open(&goose_cfg.ifname, 0) {
+                Ok(socket) => {
+                    let src = socket.mac;
+                    Some(GoosePublisher::new(Box::new(socket), src))
+                }
+                Err(err) => {
+                    println!("goose publisher disabled {:?}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let (goose_frame_tx, goose_frame_rx) = mpsc::channel(GOOSE_FRAME_CHANNEL);
+        let mut goose_subscriber = None;
+        if goose_cfg.subscribe {
+            let mut subscriber = GooseSubscriber::new(&model, scl_info, pub_model_vec);
+            let socket = if subscriber.is_empty() {
+                Err(anyhow::anyhow!("no goose extref"))
+            } else {
+                RawSocket::open(&goose_cfg.ifname, ETHERTYPE_GOOSE)
+                    .and_then(|socket| socket.join_all_multicast().map(|_| socket))
+            };
+            match socket {
+                Ok(socket) => {
+                    subscriber.start(&mut model);
+                    subscriber::spawn_receiver(socket, goose_frame_tx);
+                    goose_subscriber = Some(subscriber);
+                }
+                Err(err) => println!("goose subscriber disabled {:?}", err),
+            }
+        }
+        Ok(CmsUnit {
+            cfg_data: cfg.clone(),
+            startup_cfg: startup_cfg.clone(),
+            model,
+            con_map: HashMap::new(),
+            report_engine,
+            log_engine,
+            control_engine,
+            sg_engine,
+            overlay,
+            goose_publisher,
+            goose_subscriber,
+            goose_frame_rx: Some(goose_frame_rx),
+            ctl_done_rx: Some(ctl_done_rx),
+        })
+    }
 }
@@ -0,0 +1,827 @@
+use anyhow::bail;
+use bytes::Bytes;
+/***************************************/
+/* ASN.1 APER (aligned PER, X.691) 编解码
+cms.asn1 里面所有的 PDU 都是用这个编码
+这里只放基础的编码规则 ，具体的 PDU 在 service 里面
+通过实现 AperCodec 来组合     */
+/***************************************/
+
+/***************************************/
+/***********  目录       Start ******************/
+/***************************************/
+/*1 尺寸约束 及 编解码接口     */
+/*2 编码器     */
+/*3 解码器     */
+/***************************************/
+/*********** 目录        End ******************/
+/***************************************/
+
+/*超过这个长度 长度域就要分片 16K     */
+const FRAG_UNIT: usize = 16384;
+/*约束里面上界 小于 64K 的时候 长度用受限整数编码     */
+const MAX_CONSTRAINED_LEN: usize = 65536;
+
+/***************************************/
+/***********1 尺寸约束 及 编解码接口          Start ******************/
+/***************************************/
+
+/*SIZE 约束
+OCTET STRING / BIT STRING / VisibleString / SEQUENCE OF 共用     */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    /* SIZE(n)     */
+    Fixed(usize),
+    /* SIZE(lb..ub)     */
+    Range(usize, usize),
+    /*没有约束     */
+    Unconstrained,
+}
+
+impl Size {
+    /*最大比特数 是否超过16 位 超过了就需要字节对齐     */
+    fn need_align(&self, unit_bits: usize) -> bool {
+        match *self {
+            Size::Fixed(n) => n * unit_bits > 16,
+            Size::Range(_, ub) => ub * unit_bits > 16,
+            Size::Unconstrained => true,
+        }
+    }
+    fn check(&self, len: usize) -> crate::Result<()> {
+        match *self {
+            Size::Fixed(n) if len != n => {
+                bail!(format!("aper size error, need {} but get {}", n, len))
+            }
+            Size::Range(lb, ub) if len < lb || len > ub => {
+                bail!(format!(
+                    "aper size error, need {}..{} but get {}",
+                    lb, ub, len
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/*所有的 PDU 都实现这个接口     */
+pub trait AperCodec: Sized {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()>;
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self>;
+}
+
+/*编码成完整的 asdu 载荷     */
+pub fn aper_to_bytes<T: AperCodec>(pdu: &T) -> crate::Result<Bytes> {
+    let mut enc = AperEncoder::new();
+    pdu.aper_encode(&mut enc)?;
+    Ok(enc.into_bytes())
+}
+/*从 asdu 载荷解码 ，剩余的填充位不检查     */
+pub fn aper_from_bytes<T: AperCodec>(data: &[u8]) -> crate::Result<T> {
+    let mut dec = AperDecoder::new(data);
+    T::aper_decode(&mut dec)
+}
+
+/*受限整数 需要多少位     */
+fn bits_for_range(range: u128) -> usize {
+    /* range 是个数  能表示 0..range-1 就够了     */
+    let mut bits = 0;
+    while (1u128 << bits) < range {
+        bits += 1;
+    }
+    bits
+}
+/*无符号数 最少要几个字节 0 也要一个字节     */
+fn octets_for_unsigned(val: u128) -> usize {
+    let mut n = 1;
+    while n < 16 && (val >> (n * 8)) != 0 {
+        n += 1;
+    }
+    n
+}
+/*有符号数 补码最少几个字节     */
+fn octets_for_signed(val: i128) -> usize {
+    let mut n = 1;
+    while n < 16 {
+        let min = -(1i128 << (n * 8 - 1));
+        let max = (1i128 << (n * 8 - 1)) - 1;
+        if val >= min && val <= max {
+            break;
+        }
+        n += 1;
+    }
+    n
+}
+/***************************************/
+/***********1 尺寸约束 及 编解码接口          End ******************/
+/***************************************/
+
+/***************************************/
+/***********2 编码器          Start ******************/
+/***************************************/
+#[derive(Default, Clone, Debug)]
+pub struct AperEncoder {
+    buf: Vec<u8>,
+    /*已经写入的位数     */
+    bit_len: usize,
+}
+
+impl AperEncoder {
+    pub fn new() -> Self {
+        AperEncoder {
+            buf: Vec::with_capacity(256),
+            bit_len: 0,
+        }
+    }
+    pub fn bit_len(&self) -> usize {
+        self.bit_len
+    }
+    /*当前编码后的字节数 (包括未满的最后一个字节)     */
+    pub fn byte_len(&self) -> usize {
+        self.bit_len.div_ceil(8)
+    }
+
+    pub fn put_bit(&mut self, bit: bool) {
+        if self.bit_len.is_multiple_of(8) {
+            self.buf.push(0);
+        }
+        if bit {
+            let last = self.buf.len() - 1;
+            self.buf[last] |= 0x80 >> (self.bit_len % 8);
+        }
+        self.bit_len += 1;
+    }
+    /*高位在前写入 val 的低 nbits 位     */
+    pub fn put_bits(&mut self, val: u128, nbits: usize) {
+        for i in (0..nbits).rev() {
+            self.put_bit((val >> i) & 1 == 1);
+        }
+    }
+    /*对齐到字节边界 补0     */
+    pub fn align(&mut self) {
+        let rem = self.bit_len % 8;
+        if rem != 0 {
+            self.bit_len += 8 - rem;
+        }
+    }
+    /*写入整字节  调用者保证已经对齐     */
+    fn put_raw_octets(&mut self, data: &[u8]) {
+        if self.bit_len.is_multiple_of(8) {
+            self.buf.extend_from_slice(data);
+            self.bit_len += data.len() * 8;
+        } else {
+            for b in data {
+                self.put_bits(*b as u128, 8);
+            }
+        }
+    }
+    pub fn put_bool(&mut self, val: bool) {
+        self.put_bit(val);
+    }
+
+    /*受限整数 lb..=ub     */
+    pub fn put_constrained_int(&mut self, val: i128, lb: i128, ub: i128) -> crate::Result<()> {
+        if val < lb || val > ub {
+            bail!(format!("aper int {} out of range {}..{}", val, lb, ub));
+        }
+        let range = (ub - lb) as u128 + 1;
+        let off = (val - lb) as u128;
+        if range == 1 {
+            return Ok(());
+        }
+        if range <= 255 {
+            self.put_bits(off, bits_for_range(range));
+        } else if range == 256 {
+            self.align();
+            self.put_bits(off, 8);
+        } else if range <= 65536 {
+            self.align();
+            self.put_bits(off, 16);
+        } else {
+            /*长度不定的 先用受限整数写字节数 1..=max  再对齐写内容     */
+            let max_octets = octets_for_unsigned(range - 1);
+            let n = octets_for_unsigned(off);
+            self.put_constrained_int(n as i128, 1, max_octets as i128)?;
+            self.align();
+            self.put_bits(off, n * 8);
+        }
+        Ok(())
+    }
+    /*半受限整数  只有下界     */
+    pub fn put_semi_constrained_int(&mut self, val: i128, lb: i128) -> crate::Result<()> {
+        if val < lb {
+            bail!(format!("aper int {} less than lb {}", val, lb));
+        }
+        let off = (val - lb) as u128;
+        let n = octets_for_unsigned(off);
+        self.put_length(n)?;
+        self.put_bits(off, n * 8);
+        Ok(())
+    }
+    /*不受限整数  补码     */
+    pub fn put_unconstrained_int(&mut self, val: i128) -> crate::Result<()> {
+        let n = octets_for_signed(val);
+        self.put_length(n)?;
+        self.put_bits(val as u128, n * 8);
+        Ok(())
+    }
+
+    /*不受限长度域  大于 16K 的需要调用者自己分片     */
+    pub fn put_length(&mut self, len: usize) -> crate::Result<()> {
+        self.align();
+        if len < 128 {
+            self.put_bits(len as u128, 8);
+        } else if len < FRAG_UNIT {
+            self.put_bits(0x8000 | len as u128, 16);
+        } else {
+            bail!(format!("aper length {} need fragment", len));
+        }
+        Ok(())
+    }
+    /*带约束的长度域  上界小于64K 用受限整数 否则用不受限长度     */
+    fn put_sized_length(&mut self, len: usize, size: Size) -> crate::Result<()> {
+        size.check(len)?;
+        match size {
+            Size::Fixed(n) if n < MAX_CONSTRAINED_LEN => Ok(()),
+            Size::Range(lb, ub) if ub < MAX_CONSTRAINED_LEN => {
+                self.put_constrained_int(len as i128, lb as i128, ub as i128)
+            }
+            _ => self.put_length(len),
+        }
+    }
+
+    /*按单元写入 内容超过16K 的时候分片     */
+    fn put_fragmented(&mut self, data: &[u8]) -> crate::Result<()> {
+        let mut rest = data;
+        loop {
+            if rest.len() < FRAG_UNIT {
+                self.put_length(rest.len())?;
+                self.put_raw_octets(rest);
+                return Ok(());
+            }
+            /*一次最多 4 个 16K     */
+            let m = std::cmp::min(rest.len() / FRAG_UNIT, 4);
+            self.align();
+            self.put_bits(0xC0 | m as u128, 8);
+            let (head, tail) = rest.split_at(m * FRAG_UNIT);
+            self.put_raw_octets(head);
+            rest = tail;
+            /*正好是 16K 整数倍的时候 最后还要补一个 0 长度     */
+        }
+    }
+
+    /*OCTET STRING 及 已知字符宽度的字符串 公用     */
+    pub fn put_octet_string(&mut self, data: &[u8], size: Size) -> crate::Result<()> {
+        size.check(data.len())?;
+        match size {
+            Size::Fixed(n) if n < MAX_CONSTRAINED_LEN => {
+                if size.need_align(8) {
+                    self.align();
+                }
+                self.put_raw_octets(data);
+            }
+            Size::Range(_, ub) if ub < MAX_CONSTRAINED_LEN => {
+                self.put_sized_length(data.len(), size)?;
+                if size.need_align(8) && !data.is_empty() {
+                    self.align();
+                }
+                self.put_raw_octets(data);
+            }
+            _ => self.put_fragmented(data)?,
+        }
+        Ok(())
+    }
+    /*VisibleString  APER 里面每个字符占 8 位     */
+    pub fn put_visible_string(&mut self, val: &str, size: Size) -> crate::Result<()> {
+        if let Some(c) = val.bytes().find(|c| !(0x20..=0x7e).contains(c)) {
+            bail!(format!("illegal char {:#x} in visible string {}", c, val));
+        }
+        self.put_octet_string(val.as_bytes(), size)
+    }
+    /*UTF8String 约束对 PER 不可见  按不受限 OCTET STRING 处理     */
+    pub fn put_utf8_string(&mut self, val: &str) -> crate::Result<()> {
+        self.put_fragmented(val.as_bytes())
+    }
+    /*BIT STRING  bits 高位在前  nbits 表示有效位数     */
+    pub fn put_bit_string(&mut self, bits: &[u8], nbits: usize, size: Size) -> crate::Result<()> {
+        if bits.len() * 8 < nbits {
+            bail!(format!("bit string buf too short for {} bits", nbits));
+        }
+        match size {
+            Size::Fixed(n) if n < MAX_CONSTRAINED_LEN => {
+                size.check(nbits)?;
+                if size.need_align(1) {
+                    self.align();
+                }
+            }
+            Size::Range(_, ub) if ub < MAX_CONSTRAINED_LEN => {
+                self.put_sized_length(nbits, size)?;
+                if size.need_align(1) && nbits > 0 {
+                    self.align();
+                }
+            }
+            _ => {
+                self.put_length(nbits)?;
+            }
+        }
+        for i in 0..nbits {
+            self.put_bit(bits[i / 8] & (0x80 >> (i % 8)) != 0);
+        }
+        Ok(())
+    }
+    /*CHOICE 索引  count 为根里面的选项个数     */
+    pub fn put_choice_index(
+        &mut self,
+        index: usize,
+        count: usize,
+        extensible: bool,
+    ) -> crate::Result<()> {
+        if extensible {
+            self.put_bit(false);
+        }
+        self.put_constrained_int(index as i128, 0, count as i128 - 1)
+    }
+    /*ENUMERATED 和 CHOICE 的根部编码一样     */
+    pub fn put_enumerated(&mut self, index: usize, count: usize) -> crate::Result<()> {
+        self.put_constrained_int(index as i128, 0, count as i128 - 1)
+    }
+    /*SEQUENCE 头 ：扩展位 + OPTIONAL/DEFAULT 的存在位图     */
+    pub fn put_sequence_preamble(&mut self, extensible: bool, present: &[bool]) {
+        if extensible {
+            self.put_bit(false);
+        }
+        for p in present {
+            self.put_bit(*p);
+        }
+    }
+    /*SEQUENCE OF 的个数     */
+    pub fn put_sequence_of_len(&mut self, count: usize, size: Size) -> crate::Result<()> {
+        self.put_sized_length(count, size)
+    }
+    /*FLOAT32 ::= OCTET STRING (SIZE(4))  IEEE754 大端     */
+    pub fn put_f32(&mut self, val: f32) -> crate::Result<()> {
+        self.put_octet_string(&val.to_be_bytes(), Size::Fixed(4))
+    }
+    pub fn put_f64(&mut self, val: f64) -> crate::Result<()> {
+        self.put_octet_string(&val.to_be_bytes(), Size::Fixed(8))
+    }
+    /*已经编码好的一段 (比如 open type)  必须对齐     */
+    pub fn put_encoded(&mut self, data: &[u8]) -> crate::Result<()> {
+        self.put_fragmented(data)
+    }
+
+    /*完整编码  空编码按规则输出一个 0 字节     */
+    pub fn into_bytes(mut self) -> Bytes {
+        if self.bit_len == 0 {
+            self.buf.push(0);
+        }
+        Bytes::from(self.buf)
+    }
+}
+/***************************************/
+/***********2 编码器          End ******************/
+/***************************************/
+
+/***************************************/
+/***********3 解码器          Start ******************/
+/***************************************/
+#[derive(Clone, Debug)]
+pub struct AperDecoder<'a> {
+    data: &'a [u8],
+    /*当前读到的位置 (位)     */
+    pos: usize,
+}
+
+impl<'a> AperDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        AperDecoder { data, pos: 0 }
+    }
+    pub fn bit_pos(&self) -> usize {
+        self.pos
+    }
+    /*还剩下多少位没读     */
+    pub fn remaining_bits(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+
+    pub fn get_bit(&mut self) -> crate::Result<bool> {
+        if self.pos >= self.data.len() * 8 {
+            bail!("aper decode out of data");
+        }
+        let bit = self.data[self.pos / 8] & (0x80 >> (self.pos % 8)) != 0;
+        self.pos += 1;
+        Ok(bit)
+    }
+    pub fn get_bits(&mut self, nbits: usize) -> crate::Result<u128> {
+        if nbits > 128 {
+            bail!(format!("aper decode too many bits {}", nbits));
+        }
+        if self.remaining_bits() < nbits {
+            bail!("aper decode out of data");
+        }
+        let mut val: u128 = 0;
+        for _ in 0..nbits {
+            val = (val << 1) | self.get_bit()? as u128;
+        }
+        Ok(val)
+    }
+    pub fn align(&mut self) {
+        let rem = self.pos % 8;
+        if rem != 0 {
+            self.pos += 8 - rem;
+        }
+    }
+    fn get_raw_octets(&mut self, n: usize) -> crate::Result<Vec<u8>> {
+        if self.remaining_bits() < n * 8 {
+            bail!("aper decode out of data");
+        }
+        if self.pos.is_multiple_of(8) {
+            let start = self.pos / 8;
+            self.pos += n * 8;
+            return Ok(self.data[start..start + n].to_vec());
+        }
+        let mut ret = Vec::with_capacity(n);
+        for _ in 0..n {
+            ret.push(self.get_bits(8)? as u8);
+        }
+        Ok(ret)
+    }
+    pub fn get_bool(&mut self) -> crate::Result<bool> {
+        self.get_bit()
+    }
+
+    pub fn get_constrained_int(&mut self, lb: i128, ub: i128) -> crate::Result<i128> {
+        let range = (ub - lb) as u128 + 1;
+        let off = if range == 1 {
+            0
+        } else if range <= 255 {
+            self.get_bits(bits_for_range(range))?
+        } else if range == 256 {
+            self.align();
+            self.get_bits(8)?
+        } else if range <= 65536 {
+            self.align();
+            self.get_bits(16)?
+        } else {
+            let max_octets = octets_for_unsigned(range - 1);
+            let n = self.get_constrained_int(1, max_octets as i128)? as usize;
+            self.align();
+            self.get_bits(n * 8)?
+        };
+        let val = lb + off as i128;
+        if val > ub {
+            bail!(format!("aper int {} out of range {}..{}", val, lb, ub));
+        }
+        Ok(val)
+    }
+    pub fn get_semi_constrained_int(&mut self, lb: i128) -> crate::Result<i128> {
+        let n = self.get_length()?;
+        if n == 0 || n > 16 {
+            bail!(format!("aper semi constrained int len {} error", n));
+        }
+        Ok(lb + self.get_bits(n * 8)? as i128)
+    }
+    pub fn get_unconstrained_int(&mut self) -> crate::Result<i128> {
+        let n = self.get_length()?;
+        if n == 0 || n > 16 {
+            bail!(format!("aper unconstrained int len {} error", n));
+        }
+        let raw = self.get_bits(n * 8)?;
+        /*符号扩展     */
+        let shift = 128 - n * 8;
+        Ok(((raw << shift) as i128) >> shift)
+    }
+
+    pub fn get_length(&mut self) -> crate::Result<usize> {
+        self.align();
+        let first = self.get_bits(8)? as usize;
+        if first & 0x80 == 0 {
+            Ok(first)
+        } else if first & 0xC0 == 0x80 {
+            let second = self.get_bits(8)? as usize;
+            Ok(((first & 0x3F) << 8) | second)
+        } else {
+            bail!("aper fragmented length not expected here");
+        }
+    }
+    fn get_sized_length(&mut self, size: Size) -> crate::Result<usize> {
+        let len = match size {
+            Size::Fixed(n) if n < MAX_CONSTRAINED_LEN => n,
+            Size::Range(lb, ub) if ub < MAX_CONSTRAINED_LEN => {
+                self.get_constrained_int(lb as i128, ub as i128)? as usize
+            }
+            _ => self.get_length()?,
+        };
+        size.check(len)?;
+        Ok(len)
+    }
+    fn get_fragmented(&mut self) -> crate::Result<Vec<u8>> {
+        let mut ret = Vec::new();
+        loop {
+            self.align();
+            let first = self.get_bits(8)? as usize;
+            if first & 0xC0 == 0xC0 {
+                let m = first & 0x3F;
+                if m == 0 || m > 4 {
+                    bail!(format!("aper fragment count {} error", m));
+                }
+                ret.extend(self.get_raw_octets(m * FRAG_UNIT)?);
+                continue;
+            }
+            let len = if first & 0x80 == 0 {
+                first
+            } else {
+                ((first & 0x3F) << 8) | self.get_bits(8)? as usize
+            };
+            ret.extend(self.get_raw_octets(len)?);
+            return Ok(ret);
+        }
+    }
+
+    pub fn get_octet_string(&mut self, size: Size) -> crate::Result<Vec<u8>> {
+        match size {
+            Size::Fixed(n) if n < MAX_CONSTRAINED_LEN => {
+                if size.need_align(8) {
+                    self.align();
+                }
+                self.get_raw_octets(n)
+            }
+            Size::Range(_, ub) if ub < MAX_CONSTRAINED_LEN => {
+                let len = self.get_sized_length(size)?;
+                if size.need_align(8) && len > 0 {
+                    self.align();
+                }
+                self.get_raw_octets(len)
+            }
+            _ => {
+                let data = self.get_fragmented()?;
+                size.check(data.len())?;
+                Ok(data)
+            }
+        }
+    }
+    pub fn get_visible_string(&mut self, size: Size) -> crate::Result<String> {
+        let data = self.get_octet_string(size)?;
+        if let Some(c) = data.iter().find(|c| !(0x20..=0x7e).contains(*c)) {
+            bail!(format!("illegal char {:#x} in visible string", c));
+        }
+        Ok(String::from_utf8(data)?)
+    }
+    pub fn get_utf8_string(&mut self) -> crate::Result<String> {
+        Ok(String::from_utf8(self.get_fragmented()?)?)
+    }
+    /*返回 (内容 ，有效位数)     */
+    pub fn get_bit_string(&mut self, size: Size) -> crate::Result<(Vec<u8>, usize)> {
+        let nbits = match size {
+            Size::Fixed(n) if n < MAX_CONSTRAINED_LEN => {
+                if size.need_align(1) {
+                    self.align();
+                }
+                n
+            }
+            Size::Range(_, ub) if ub < MAX_CONSTRAINED_LEN => {
+                let n = self.get_sized_length(size)?;
+                if size.need_align(1) && n > 0 {
+                    self.align();
+                }
+                n
+            }
+            _ => self.get_length()?,
+        };
+        let mut bits = vec![0u8; nbits.div_ceil(8)];
+        for i in 0..nbits {
+            if self.get_bit()? {
+                bits[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        Ok((bits, nbits))
+    }
+    pub fn get_choice_index(&mut self, count: usize, extensible: bool) -> crate::Result<usize> {
+        if extensible && self.get_bit()? {
+            bail!("aper choice extension not supported");
+        }
+        Ok(self.get_constrained_int(0, count as i128 - 1)? as usize)
+    }
+    pub fn get_enumerated(&mut self, count: usize) -> crate::Result<usize> {
+        Ok(self.get_constrained_int(0, count as i128 - 1)? as usize)
+    }
+    /*返回每个 OPTIONAL 的存在标志     */
+    pub fn get_sequence_preamble(
+        &mut self,
+        extensible: bool,
+        optional_count: usize,
+    ) -> crate::Result<Vec<bool>> {
+        if extensible && self.get_bit()? {
+            bail!("aper sequence extension not supported");
+        }
+        let mut present = Vec::with_capacity(optional_count);
+        for _ in 0..optional_count {
+            present.push(self.get_bit()?);
+        }
+        Ok(present)
+    }
+    pub fn get_sequence_of_len(&mut self, size: Size) -> crate::Result<usize> {
+        self.get_sized_length(size)
+    }
+    pub fn get_f32(&mut self) -> crate::Result<f32> {
+        let data = self.get_octet_string(Size::Fixed(4))?;
+        Ok(f32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+    }
+    pub fn get_f64(&mut self) -> crate::Result<f64> {
+        let data = self.get_octet_string(Size::Fixed(8))?;
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(&data);
+        Ok(f64::from_be_bytes(raw))
+    }
+    pub fn get_encoded(&mut self) -> crate::Result<Vec<u8>> {
+        self.get_fragmented()
+    }
+}
+/***************************************/
+/***********3 解码器          End ******************/
+/***************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enc_with(f: impl FnOnce(&mut AperEncoder) -> crate::Result<()>) -> Vec<u8> {
+        let mut enc = AperEncoder::new();
+        f(&mut enc).unwrap();
+        enc.into_bytes().to_vec()
+    }
+
+    #[test]
+    fn constrained_int_small_range_is_bit_field() {
+        /* 0..7 三位 ，5 -> 101 后面补0     */
+        let out = enc_with(|e| e.put_constrained_int(5, 0, 7));
+        assert_eq!(out, vec![0xA0]);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_constrained_int(0, 7).unwrap(), 5);
+    }
+
+    #[test]
+    fn constrained_int_int8_is_one_aligned_octet() {
+        /* INT8 -128..127  范围 256  ，-1 偏移 127     */
+        let out = enc_with(|e| {
+            e.put_bit(true);
+            e.put_constrained_int(-1, -128, 127)
+        });
+        assert_eq!(out, vec![0x80, 0x7F]);
+        let mut dec = AperDecoder::new(&out);
+        assert!(dec.get_bit().unwrap());
+        assert_eq!(dec.get_constrained_int(-128, 127).unwrap(), -1);
+    }
+
+    #[test]
+    fn constrained_int_int32u_uses_length_prefix() {
+        /* INT32U 0x500000 ：长度 3 (1..4 两位 编 2 = 10) 对齐后 3 字节     */
+        let out = enc_with(|e| e.put_constrained_int(0x50_0000, 0, 0xFFFF_FFFF));
+        assert_eq!(out, vec![0x80, 0x50, 0x00, 0x00]);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_constrained_int(0, 0xFFFF_FFFF).unwrap(), 0x50_0000);
+    }
+
+    #[test]
+    fn int64_extremes_round_trip() {
+        for v in [i64::MIN as i128, -1, 0, i64::MAX as i128] {
+            let out = enc_with(|e| e.put_constrained_int(v, i64::MIN as i128, i64::MAX as i128));
+            let mut dec = AperDecoder::new(&out);
+            assert_eq!(
+                dec.get_constrained_int(i64::MIN as i128, i64::MAX as i128)
+                    .unwrap(),
+                v
+            );
+        }
+        let out = enc_with(|e| e.put_constrained_int(u64::MAX as i128, 0, u64::MAX as i128));
+        assert_eq!(
+            out,
+            vec![0xE0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn unconstrained_and_semi_constrained_int() {
+        let out = enc_with(|e| e.put_unconstrained_int(-129));
+        assert_eq!(out, vec![0x02, 0xFF, 0x7F]);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_unconstrained_int().unwrap(), -129);
+
+        let out = enc_with(|e| e.put_semi_constrained_int(300, 0));
+        assert_eq!(out, vec![0x02, 0x01, 0x2C]);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_semi_constrained_int(0).unwrap(), 300);
+    }
+
+    #[test]
+    fn visible_string_with_range() {
+        /* SIZE(0..129) 长度 130 个值 占 8 位 不对齐 ，前面有一位 bool     */
+        let out = enc_with(|e| {
+            e.put_bool(true);
+            e.put_visible_string("LD0", Size::Range(0, 129))
+        });
+        assert_eq!(out, vec![0x81, 0x80, b'L', b'D', b'0']);
+        let mut dec = AperDecoder::new(&out);
+        assert!(dec.get_bool().unwrap());
+        assert_eq!(dec.get_visible_string(Size::Range(0, 129)).unwrap(), "LD0");
+
+        let out = enc_with(|e| e.put_visible_string("ST", Size::Fixed(2)));
+        assert_eq!(out, vec![b'S', b'T']);
+        assert!(enc_with_err(
+            |e| e.put_visible_string("中", Size::Unconstrained)
+        ));
+    }
+
+    fn enc_with_err(f: impl FnOnce(&mut AperEncoder) -> crate::Result<()>) -> bool {
+        let mut enc = AperEncoder::new();
+        f(&mut enc).is_err()
+    }
+
+    #[test]
+    fn utf8_and_unconstrained_octets() {
+        let out = enc_with(|e| e.put_utf8_string("é"));
+        assert_eq!(out, vec![0x02, 0xC3, 0xA9]);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_utf8_string().unwrap(), "é");
+
+        let data = vec![0x55u8; 200];
+        let out = enc_with(|e| e.put_octet_string(&data, Size::Unconstrained));
+        assert_eq!(&out[..2], &[0x80, 0xC8]);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_octet_string(Size::Unconstrained).unwrap(), data);
+    }
+
+    #[test]
+    fn large_octet_string_is_fragmented() {
+        let data: Vec<u8> = (0..(FRAG_UNIT * 2 + 5)).map(|i| i as u8).collect();
+        let out = enc_with(|e| e.put_octet_string(&data, Size::Unconstrained));
+        assert_eq!(out[0], 0xC2);
+        assert_eq!(out[1 + FRAG_UNIT * 2], 0x05);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_octet_string(Size::Unconstrained).unwrap(), data);
+
+        let exact = vec![1u8; FRAG_UNIT];
+        let out = enc_with(|e| e.put_octet_string(&exact, Size::Unconstrained));
+        assert_eq!(out.len(), FRAG_UNIT + 2);
+        assert_eq!(*out.last().unwrap(), 0x00);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_octet_string(Size::Unconstrained).unwrap(), exact);
+    }
+
+    #[test]
+    fn bit_strings() {
+        /* Quality SIZE(13)  不超过16位 不对齐     */
+        let out = enc_with(|e| {
+            e.put_bit(true);
+            e.put_bit_string(&[0xC0, 0x08], 13, Size::Fixed(13))
+        });
+        assert_eq!(out, vec![0xE0, 0x04]);
+        let mut dec = AperDecoder::new(&out);
+        assert!(dec.get_bit().unwrap());
+        assert_eq!(
+            dec.get_bit_string(Size::Fixed(13)).unwrap(),
+            (vec![0xC0, 0x08], 13)
+        );
+
+        /* 不受限 BIT STRING  长度域 + 内容     */
+        let out = enc_with(|e| e.put_bit_string(&[0xF0], 4, Size::Unconstrained));
+        assert_eq!(out, vec![0x04, 0xF0]);
+    }
+
+    #[test]
+    fn sequence_choice_and_sequence_of() {
+        /* SEQUENCE { a BOOLEAN OPTIONAL, b CHOICE(3 选项) , c SEQUENCE OF INT8U }     */
+        let out = enc_with(|e| {
+            e.put_sequence_preamble(false, &[true]);
+            e.put_bool(false);
+            e.put_choice_index(2, 3, false)?;
+            e.put_sequence_of_len(2, Size::Unconstrained)?;
+            e.put_constrained_int(1, 0, 255)?;
+            e.put_constrained_int(2, 0, 255)
+        });
+        assert_eq!(out, vec![0xA0, 0x02, 0x01, 0x02]);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_sequence_preamble(false, 1).unwrap(), vec![true]);
+        assert!(!dec.get_bool().unwrap());
+        assert_eq!(dec.get_choice_index(3, false).unwrap(), 2);
+        assert_eq!(dec.get_sequence_of_len(Size::Unconstrained).unwrap(), 2);
+        assert_eq!(dec.get_constrained_int(0, 255).unwrap(), 1);
+        assert_eq!(dec.get_constrained_int(0, 255).unwrap(), 2);
+    }
+
+    #[test]
+    fn floats_and_empty_encoding() {
+        let out = enc_with(|e| e.put_f32(1.0));
+        assert_eq!(out, vec![0x3F, 0x80, 0x00, 0x00]);
+        let mut dec = AperDecoder::new(&out);
+        assert_eq!(dec.get_f32().unwrap(), 1.0);
+        assert_eq!(enc_with(|_| Ok(())), vec![0x00]);
+    }
+
+    #[test]
+    fn decode_out_of_data_is_error() {
+        let mut dec = AperDecoder::new(&[0x80]);
+        assert!(dec.get_constrained_int(0, 0xFFFF_FFFF).is_err());
+        let mut dec = AperDecoder::new(&[0x05, b'a']);
+        assert!(dec.get_visible_string(Size::Unconstrained).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+use crate::cms61850::model::Value;
+use anyhow::bail;
+/***************************************/
+/* ASN.1 BER (X.690) 编解码  GOOSE 报文用
+GOOSE 走的还是 61850-8-1  数据是 MMS 的 Data  和 cms 的 APER 没有关系
+这里只放 TLV 和 Data 的编解码  goosePdu 的各个字段在 goose 里面拼     */
+/***************************************/
+
+/*MMS Data 的 CHOICE 标签  都是上下文类  数组 结构是构造的     */
+pub const TAG_ARRAY: u8 = 0xa1;
+pub const TAG_STRUCT: u8 = 0xa2;
+pub const TAG_BOOLEAN: u8 = 0x83;
+pub const TAG_BIT_STRING: u8 = 0x84;
+pub const TAG_INTEGER: u8 = 0x85;
+pub const TAG_UNSIGNED: u8 = 0x86;
+pub const TAG_FLOAT: u8 = 0x87;
+pub const TAG_OCTET_STRING: u8 = 0x89;
+pub const TAG_VISIBLE_STRING: u8 = 0x8a;
+pub const TAG_BINARY_TIME: u8 = 0x8c;
+pub const TAG_MMS_STRING: u8 = 0x90;
+pub const TAG_UTC_TIME: u8 = 0x91;
+
+/*浮点前面的指数位数  单精度 8 双精度 11     */
+const FLOAT32_EXP: u8 = 8;
+const FLOAT64_EXP: u8 = 11;
+/*数组 结构体 嵌套最大层数  防止对端发很深的嵌套     */
+const MAX_DATA_DEPTH: usize = 32;
+
+/*编码器  都是定长  构造类型先编里面再套长度     */
+#[derive(Default, Debug)]
+pub struct BerEncoder {
+    buf: Vec<u8>,
+}
+
+impl BerEncoder {
+    pub fn new() -> BerEncoder {
+        BerEncoder::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /*短格式到 127  再长的 0x81 0x82 ... 后面跟长度的字节数     */
+    fn put_len(&mut self, len: usize) {
+        if len < 0x80 {
+            self.buf.push(len as u8);
+            return;
+        }
+        let bytes = (len as u64).to_be_bytes();
+        let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+        self.buf.push(0x80 | (8 - skip) as u8);
+        self.buf.extend_from_slice(&bytes[skip..]);
+    }
+
+    pub fn put_tlv(&mut self, tag: u8, content: &[u8]) {
+        self.buf.push(tag);
+        self.put_len(content.len());
+        self.buf.extend_from_slice(content);
+    }
+
+    /*构造类型  f 往新的编码器里面编  编完套上标签和长度     */
+    pub fn put_constructed(
+        &mut self,
+        tag: u8,
+        f: impl FnOnce(&mut BerEncoder) -> crate::Result<()>,
+    ) -> crate::Result<()> {
+        let mut inner = BerEncoder::new();
+        f(&mut inner)?;
+        self.put_tlv(tag, &inner.buf);
+        Ok(())
+    }
+
+    pub fn put_bool(&mut self, tag: u8, val: bool) {
+        self.put_tlv(tag, &[if val { 0xff } else { 0x00 }]);
+    }
+
+    /*补码  去掉前面多余的 0x00 0xff  至少一个字节     */
+    pub fn put_int(&mut self, tag: u8, val: i64) {
+        let bytes = val.to_be_bytes();
+        let mut skip = 0;
+        while skip < 7 {
+            let (cur, next) = (bytes[skip], bytes[skip + 1]);
+            if (cur == 0x00 && next & 0x80 == 0) || (cur == 0xff && next & 0x80 != 0) {
+                skip += 1;
+            } else {
+                break;
+            }
+        }
+        self.put_tlv(tag, &bytes[skip..]);
+    }
+
+    /*无符号的也是 INTEGER 的编码  最高位是 1 的前面补 0x00     */
+    pub fn put_uint(&mut self, tag: u8, val: u64) {
+        let mut bytes = vec![0u8];
+        bytes.extend_from_slice(&val.to_be_bytes());
+        let mut skip = 0;
+        while skip < 8 && bytes[skip] == 0 && bytes[skip + 1] & 0x80 == 0 {
+            skip += 1;
+        }
+        self.put_tlv(tag, &bytes[skip..]);
+    }
+
+    /*第一个字节是最后一个字节里面没用的位数  没用的位清 0     */
+    pub fn put_bit_string(&mut self, tag: u8, bits: &[u8], nbits: usize) {
+        let count = nbits.div_ceil(8);
+        let unused = count * 8 - nbits;
+        let mut content = Vec::with_capacity(count + 1);
+        content.push(unused as u8);
+        content.extend((0..count).map(|i| bits.get(i).copied().unwrap_or(0)));
+        if count > 0 {
+            content[count] &= 0xff << unused;
+        }
+        self.put_tlv(tag, &content);
+    }
+
+    /*MMS Data  按值的类型选 CHOICE     */
+    pub fn put_data(&mut self, val: &Value) -> crate::Result<()> {
+        match val {
+            Value::Error(err) => bail!(format!("ber data can not be error {:?}", err)),
+            Value::Array(vals) | Value::Struct(vals) => {
+                let tag = match val {
+                    Value::Array(_) => TAG_ARRAY,
+                    _ => TAG_STRUCT,
+                };
+                self.put_constructed(tag, |enc| {
+                    for val in vals.iter() {
+                        enc.put_data(val)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Value::Boolean(v) => self.put_bool(TAG_BOOLEAN, *v),
+            Value::Int8(v) | Value::Enum(v) => self.put_int(TAG_INTEGER, *v as i64),
+            Value::Int16(v) => self.put_int(TAG_INTEGER, *v as i64),
+            Value::Int32(v) => self.put_int(TAG_INTEGER, *v as i64),
+            Value::Int64(v) => self.put_int(TAG_INTEGER, *v),
+            Value::Int8u(v) => self.put_uint(TAG_UNSIGNED, *v as u64),
+            Value::Int16u(v) => self.put_uint(TAG_UNSIGNED, *v as u64),
+            Value::Int32u(v) => self.put_uint(TAG_UNSIGNED, *v as u64),
+            Value::Int64u(v) => self.put_uint(TAG_UNSIGNED, *v),
+            Value::Float32(v) => {
+                let mut content = vec![FLOAT32_EXP];
+                content.extend_from_slice(&v.to_be_bytes());
+                self.put_tlv(TAG_FLOAT, &content);
+            }
+            Value::Float64(v) => {
+                let mut content = vec![FLOAT64_EXP];
+                content.extend_from_slice(&v.to_be_bytes());
+                self.put_tlv(TAG_FLOAT, &content);
+            }
+            Value::BitString(bits, nbits) => self.put_bit_string(TAG_BIT_STRING, bits, *nbits),
+            Value::Octet(v) => self.put_tlv(TAG_OCTET_STRING, v),
+            Value::VisString(v) | Value::ObjRef(v) => {
+                self.put_tlv(TAG_VISIBLE_STRING, v.as_bytes())
+            }
+            Value::Unicode(v) => self.put_tlv(TAG_MMS_STRING, v.as_bytes()),
+            Value::Timestamp(v) => self.put_tlv(TAG_UTC_TIME, v),
+            Value::EntryTime(v) => self.put_tlv(TAG_BINARY_TIME, v),
+            Value::Quality(v) => self.put_bit_string(TAG_BIT_STRING, &v.to_be_bytes(), 13),
+            Value::Dbpos(v) | Value::Tcmd(v) | Value::Check(v) => {
+                self.put_bit_string(TAG_BIT_STRING, &[*v << 6], 2)
+            }
+        }
+        Ok(())
+    }
+}
+
+/*解码器  只认单字节标签  长度域最多 4 个字节     */
+#[derive(Debug)]
+pub struct BerDecoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BerDecoder<'a> {
+    pub fn new(buf: &'a [u8]) -> BerDecoder<'a> {
+        BerDecoder { buf, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn get_byte(&mut self) -> crate::Result<u8> {
+        match self.buf.get(self.pos) {
+            Some(byte) => {
+                self.pos += 1;
+                Ok(*byte)
+            }
+            None => bail!("ber data too short"),
+        }
+    }
+
+    fn get_len(&mut self) -> crate::Result<usize> {
+        let first = self.get_byte()?;
+        if first < 0x80 {
+            return Ok(first as usize);
+        }
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            bail!(format!("ber length form {:02x} not supported", first));
+        }
+        let mut len = 0usize;
+        for _ in 0..count {
+            len = (len << 8) | self.get_byte()? as usize;
+        }
+        Ok(len)
+    }
+
+    /*一个 TLV  返回标签和内容     */
+    pub fn get_tlv(&mut self) -> crate::Result<(u8, &'a [u8])> {
+        let tag = self.get_byte()?;
+        let len = self.get_len()?;
+        let end = self.pos + len;
+        if end > self.buf.len() {
+            bail!(format!(
+                "ber tag {:02x} length {} more than {}",
+                tag,
+                len,
+                self.buf.len() - self.pos
+            ));
+        }
+        let content = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok((tag, content))
+    }
+
+    /*MMS Data  类型只能按标签定  整数都给 Int64 Int64u  位串给 BitString
+    要什么类型由用的地方按模型再转     */
+    pub fn get_data(&mut self) -> crate::Result<Value> {
+        self.get_data_depth(0)
+    }
+
+    fn get_data_depth(&mut self, depth: usize) -> crate::Result<Value> {
+        if depth > MAX_DATA_DEPTH {
+            bail!(format!("ber data nested more than {}", MAX_DATA_DEPTH));
+        }
+        let (tag, content) = self.get_tlv()?;
+        let val = match tag {
+            TAG_ARRAY | TAG_STRUCT => {
+                let mut inner = BerDecoder::new(content);
+                let mut vals = Vec::new();
+                while !inner.is_empty() {
+                    vals.push(inner.get_data_depth(depth + 1)?);
+                }
+                if tag == TAG_ARRAY {
+                    Value::Array(vals)
+                } else {
+                    Value::Struct(vals)
+                }
+            }
+            TAG_BOOLEAN => Value::Boolean(decode_bool(content)?),
+            TAG_INTEGER => Value::Int64(decode_int(content)?),
+            TAG_UNSIGNED => Value::Int64u(decode_uint(content)?),
+            TAG_FLOAT => match (content.first(), content.len()) {
+                (Some(&FLOAT32_EXP), 5) => Value::Float32(f32::from_be_bytes([
+                    content[1], content[2], content[3], content[4],
+                ])),
+                (Some(&FLOAT64_EXP), 9) => {
+                    let mut bytes = [0u8; 8];
+                    bytes.copy_from_slice(&content[1..]);
+                    Value::Float64(f64::from_be_bytes(bytes))
+                }
+                _ => bail!(format!("ber float length {} not supported", content.len())),
+            },
+            TAG_BIT_STRING => {
+                let (unused, bits) = match content.split_first() {
+                    Some((unused, bits)) if *unused < 8 => (*unused as usize, bits),
+                    _ => bail!("ber bit string bad unused bits"),
+                };
+                if bits.is_empty() && unused != 0 {
+                    bail!("ber bit string bad unused bits");
+                }
+                Value::BitString(bits.to_vec(), bits.len() * 8 - unused)
+            }
+            TAG_OCTET_STRING => Value::Octet(content.to_vec()),
+            TAG_VISIBLE_STRING => Value::VisString(String::from_utf8_lossy(content).to_string()),
+            TAG_MMS_STRING => Value::Unicode(String::from_utf8_lossy(content).to_string()),
+            TAG_UTC_TIME => match content.try_into() {
+                Ok(time) => Value::Timestamp(time),
+                Err(_) => bail!(format!("ber utc time length {}", content.len())),
+            },
+            TAG_BINARY_TIME => match content.try_into() {
+                Ok(time) => Value::EntryTime(time),
+                Err(_) => bail!(format!(
+                    "ber binary time length {} not supported",
+                    content.len()
+                )),
+            },
+            _ => bail!(format!("ber data tag {:02x} not supported", tag)),
+        };
+        Ok(val)
+    }
+}
+
+pub fn decode_bool(content: &[u8]) -> crate::Result<bool> {
+    match content {
+        [byte] => Ok(*byte != 0),
+        _ => bail!(format!("ber boolean length {}", content.len())),
+    }
+}
+
+/*补码  最多 8 个字节     */
+pub fn decode_int(content: &[u8]) -> crate::Result<i64> {
+    if content.is_empty() || content.len() > 8 {
+        bail!(format!("ber integer length {}", content.len()));
+    }
+    let init = if content[0] & 0x80 != 0 { -1i64 } else { 0 };
+    Ok(content
+        .iter()
+        .fold(init, |acc, byte| (acc << 8) | *byte as i64))
+}
+
+/*前面可能多一个 0x00  去掉以后最多 8 个字节  负数不行     */
+pub fn decode_uint(content: &[u8]) -> crate::Result<u64> {
+    let bytes = match content {
+        [0x00, rest @ ..] if !rest.is_empty() => rest,
+        _ => content,
+    };
+    if bytes.is_empty() || bytes.len() > 8 || (bytes.len() == content.len() && bytes[0] & 0x80 != 0)
+    {
+        bail!(format!("ber unsigned bad content {:02x?}", content));
+    }
+    Ok(bytes
+        .iter()
+        .fold(0u64, |acc, byte| (acc << 8) | *byte as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enc_with(f: impl FnOnce(&mut BerEncoder)) -> Vec<u8> {
+        let mut enc = BerEncoder::new();
+        f(&mut enc);
+        enc.into_bytes()
+    }
+
+    #[test]
+    fn integer_is_minimal_twos_complement() {
+        assert_eq!(enc_with(|e| e.put_int(0x85, 0)), vec![0x85, 0x01, 0x00]);
+        assert_eq!(enc_with(|e| e.put_int(0x85, 127)), vec![0x85, 0x01, 0x7f]);
+        assert_eq!(
+            enc_with(|e| e.put_int(0x85, 128)),
+            vec![0x85, 0x02, 0x00, 0x80]
+        );
+        assert_eq!(enc_with(|e| e.put_int(0x85, -1)), vec![0x85, 0x01, 0xff]);
+        assert_eq!(
+            enc_with(|e| e.put_int(0x85, -129)),
+            vec![0x85, 0x02, 0xff, 0x7f]
+        );
+        assert_eq!(
+            enc_with(|e| e.put_uint(0x86, u32::MAX as u64)),
+            vec![0x86, 0x05, 0x00, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn long_length_form() {
+        let out = enc_with(|e| e.put_tlv(0x04, &[0u8; 300]));
+        assert_eq!(&out[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        assert_eq!(out.len(), 304);
+    }
+
+    #[test]
+    fn data_bit_strings_and_float() {
+        let mut enc = BerEncoder::new();
+        enc.put_data(&Value::Struct(vec![
+            Value::Dbpos(2),
+            Value::Quality(0xc000),
+            Value::Float32(1.0),
+        ]))
+        .unwrap();
+        assert_eq!(
+            enc.into_bytes(),
+            vec![
+                0xa2, 0x10, 0x84, 0x02, 0x06, 0x80, 0x84, 0x03, 0x03, 0xc0, 0x00, 0x87, 0x05, 0x08,
+                0x3f, 0x80, 0x00, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let val = Value::Struct(vec![
+            Value::Int32(-129),
+            Value::Int32u(u32::MAX),
+            Value::Float64(-2.5),
+            Value::Boolean(true),
+            Value::VisString("E1Q1".to_string()),
+            Value::Array(vec![Value::Quality(0x4020)]),
+        ]);
+        let mut enc = BerEncoder::new();
+        enc.put_data(&val).unwrap();
+        let buf = enc.into_bytes();
+        let mut dec = BerDecoder::new(&buf);
+        assert_eq!(
+            dec.get_data().unwrap(),
+            Value::Struct(vec![
+                Value::Int64(-129),
+                Value::Int64u(u32::MAX as u64),
+                Value::Float64(-2.5),
+                Value::Boolean(true),
+                Value::VisString("E1Q1".to_string()),
+                Value::Array(vec![Value::BitString(vec![0x40, 0x20], 13)]),
+            ])
+        );
+        assert!(dec.is_empty());
+        assert!(BerDecoder::new(&buf[..buf.len() - 1]).get_data().is_err());
+    }
+}
@@ -0,0 +1,5 @@
+/***************************************/
+/*cms.asn1 对应的编解码     */
+/***************************************/
+pub mod aper;
+pub mod ber;
@@ -0,0 +1,92 @@
+use crate::cms61850::model::{Model, ObjRef, Value};
+use crate::cms61850::service::control::Originator;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::{self, Duration};
+/***************************************/
+/*控制的执行  检查都过了的 Operate 交给 DO 上注册的处理者
+处理者可以马上给结果  也可以先回 Pending  过程回来以后往 done 通道里面报
+增强安全的 CommandTermination 等结果出来才发     */
+/***************************************/
+
+/*检查过的命令  ctlVal 已经按模型修正过类型     */
+#[derive(Clone, Debug)]
+pub struct ControlCmd {
+    /*引擎分的号  异步完成的时候带回来     */
+    pub id: u64,
+    /*DO 的引用  不带 fc     */
+    pub objref: ObjRef,
+    pub ctlval: Value,
+    /*ctlVal 对应的 stVal  DO 没有 stVal 是 None     */
+    pub status: Option<Value>,
+    pub origin: Originator,
+    pub ctlnum: u8,
+    pub test: bool,
+    pub check: u8,
+}
+
+/*处理者马上给出的结果     */
+#[derive(Clone, Debug, PartialEq)]
+pub enum CtlOutcome {
+    /*执行完了  带的是新的 stVal  None 不改     */
+    Done(Option<Value>),
+    /*执行不了  否定响应带这个 AddCause     */
+    Failed(i8),
+    /*过程还没回来  以后往 done 通道报 CtlDone     */
+    Pending,
+}
+
+/*异步完成  成功带新的 stVal  失败带 AddCause     */
+#[derive(Clone, Debug)]
+pub struct CtlDone {
+    pub id: u64,
+    pub result: Result<Option<Value>, i8>,
+}
+
+/*一个 DO 的执行者  接真实过程的实现这个  按 DO 引用注册到引擎     */
+pub trait ControlHandler: Send {
+    fn operate(
+        &mut self,
+        model: &Model,
+        cmd: &ControlCmd,
+        done: &mpsc::Sender<CtlDone>,
+    ) -> CtlOutcome;
+}
+
+/*仿真执行  ctlVal 直接当 stVal
+operate_ms 大于 0 的时候  过这么久才到位  模拟开关分合  和引擎共用 重新加载配置的时候跟着变     */
+pub struct SimHandler {
+    pub operate_ms: Arc<AtomicU32>,
+}
+
+impl ControlHandler for SimHandler {
+    fn operate(
+        &mut self,
+        _model: &Model,
+        cmd: &ControlCmd,
+        done: &mpsc::Sender<CtlDone>,
+    ) -> CtlOutcome {
+        println!(
+            "sim control {} ctlval {:?} orcat {} ctlnum {} test {} check {}",
+            cmd.objref, cmd.ctlval, cmd.origin.orcat, cmd.ctlnum, cmd.test, cmd.check
+        );
+        let operate_ms = self.operate_ms.load(Ordering::Relaxed);
+        if operate_ms == 0 {
+            return CtlOutcome::Done(cmd.status.clone());
+        }
+        let done = done.clone();
+        let (id, status) = (cmd.id, cmd.status.clone());
+        let delay = Duration::from_millis(operate_ms as u64);
+        tokio::spawn(async move {
+            time::sleep(delay).await;
+            let _ = done
+                .send(CtlDone {
+                    id,
+                    result: Ok(status),
+                })
+                .await;
+        });
+        CtlOutcome::Pending
+    }
+}
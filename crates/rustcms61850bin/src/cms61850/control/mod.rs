@@ -0,0 +1,1169 @@
+use crate::cms61850::model::value::{utc_time, utc_time_millis};
+use crate::cms61850::model::{DataNode, Model, ObjRef, Value};
+use crate::cms61850::net::cmscon::frame;
+use crate::cms61850::service::common::{self, ServiceError};
+use crate::cms61850::service::control::{ControlRequestPDU, ControlResponsePDU, Originator};
+use handler::{ControlCmd, ControlHandler, CtlDone, CtlOutcome, SimHandler};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+/***************************************/
+/*控制的运行时  按 DO 下面 CF 的 ctlModel 走
+直控 选控  常规安全 增强安全 四种  status-only 不能控
+选中的时候 stSeld 置位  sboTimeout 到了还没执行就自动取消
+执行交给 DO 上注册的处理者  增强安全等处理者有了结果  给客户端发 CommandTermination
+时间激活执行先排着  到了 operTm 再执行  结果发 TimeActivatedOperateTermination     */
+/***************************************/
+pub mod handler;
+
+/*AddCause  用到的几个     */
+pub const ADDCAUSE_NOT_SUPPORTED: i8 = 1;
+pub const ADDCAUSE_BLOCKED_BY_MODE: i8 = 8;
+pub const ADDCAUSE_BLOCKED_BY_INTERLOCKING: i8 = 10;
+pub const ADDCAUSE_BLOCKED_BY_SYNCHROCHECK: i8 = 11;
+pub const ADDCAUSE_COMMAND_ALREADY_IN_EXECUTION: i8 = 12;
+pub const ADDCAUSE_OBJECT_NOT_SELECTED: i8 = 18;
+pub const ADDCAUSE_OBJECT_ALREADY_SELECTED: i8 = 19;
+pub const ADDCAUSE_INCONSISTENT_PARAMETERS: i8 = 26;
+pub const ADDCAUSE_LOCKED_BY_OTHER_CLIENT: i8 = 27;
+
+/*Check 的两位  和 Value::Check 一样     */
+const CHECK_SYNCHRO: u8 = 0x02;
+const CHECK_INTERLOCK: u8 = 0x01;
+
+/*Beh 的 stVal  1 on 2 blocked 3 test 4 test/blocked 5 off     */
+const BEH_TEST: i32 = 3;
+const BEH_TEST_BLOCKED: i32 = 4;
+const BEH_OFF: i32 = 5;
+
+/*sboTimeout 没配或者是 0 的时候  毫秒     */
+const DEFAULT_SBO_TIMEOUT_MS: u64 = 30000;
+
+/*ctlModelEnum     */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CtlModel {
+    StatusOnly,
+    DirectNormal,
+    SboNormal,
+    DirectEnhanced,
+    SboEnhanced,
+}
+
+impl CtlModel {
+    pub fn from_ord(ord: i8) -> CtlModel {
+        match ord {
+            1 => CtlModel::DirectNormal,
+            2 => CtlModel::SboNormal,
+            3 => CtlModel::DirectEnhanced,
+            4 => CtlModel::SboEnhanced,
+            _ => CtlModel::StatusOnly,
+        }
+    }
+    pub fn is_sbo(self) -> bool {
+        matches!(self, CtlModel::SboNormal | CtlModel::SboEnhanced)
+    }
+    pub fn is_enhanced(self) -> bool {
+        matches!(self, CtlModel::DirectEnhanced | CtlModel::SboEnhanced)
+    }
+}
+
+/*一个可控的 DO  每次请求从模型里面现取  CF 写了马上生效     */
+#[derive(Clone, Debug)]
+pub struct CtlObject {
+    /*DO 的引用  不带 fc     */
+    pub objref: ObjRef,
+    pub ctlmodel: CtlModel,
+    /*毫秒     */
+    pub sbotimeout: u64,
+    /*sboClass 是 operate-many  执行以后还保持选中     */
+    pub operatemany: bool,
+    /*Oper.ctlVal 的模型值  对端发来的值按它修正类型     */
+    pub ctlval: Value,
+}
+
+fn child_value<'a>(node: &'a DataNode, name: &str) -> Option<&'a Value> {
+    node.child(name).map(|child| &child.value)
+}
+
+impl CtlObject {
+    /*reference 是 DO 的引用  下面要有 CO 的 Oper     */
+    pub fn resolve(model: &Model, reference: &str) -> Result<CtlObject, ServiceError> {
+        let objref = common::parse_ref_fc(reference, &None)?;
+        let objref = ObjRef { fc: None, ..objref };
+        if objref.path.is_empty() {
+            return Err(ServiceError::ParameterValueInappropriate);
+        }
+        let node = model
+            .get_node(&objref)
+            .ok_or(ServiceError::InstanceNotAvailable)?;
+        let ctlval = node
+            .child("Oper")
+            .filter(|oper| oper.fc == "CO")
+            .and_then(|oper| child_value(oper, "ctlVal"))
+            .ok_or(ServiceError::ClassNotSupported)?
+            .clone();
+        let ctlmodel = match child_value(node, "ctlModel") {
+            Some(Value::Enum(ord)) => CtlModel::from_ord(*ord),
+            _ => CtlModel::StatusOnly,
+        };
+        let sbotimeout = match child_value(node, "sboTimeout") {
+            Some(Value::Int32u(ms)) if *ms > 0 => *ms as u64,
+            _ => DEFAULT_SBO_TIMEOUT_MS,
+        };
+        Ok(CtlObject {
+            objref,
+            ctlmodel,
+            sbotimeout,
+            operatemany: matches!(child_value(node, "sboClass"), Some(Value::Enum(1))),
+            ctlval,
+        })
+    }
+
+    fn key(&self) -> String {
+        self.objref.to_string()
+    }
+}
+
+/*一个选中的对象     */
+#[derive(Debug)]
+struct Selection {
+    objref: ObjRef,
+    con_id: u32,
+    deadline: Instant,
+    /*带值选择的请求  执行的时候参数要对得上  Select 选的是 None     */
+    req: Option<ControlRequestPDU>,
+}
+
+/*处理者还没给结果的命令     */
+#[derive(Debug)]
+struct Running {
+    key: String,
+    objref: ObjRef,
+    con_id: u32,
+    /*有结果以后发的命令结束的服务码  None 不发     */
+    term_code: Option<u8>,
+    req: ControlRequestPDU,
+}
+
+/*排着的时间激活执行  一个 DO 只能有一个     */
+#[derive(Debug)]
+struct Scheduled {
+    obj: CtlObject,
+    con_id: u32,
+    req: ControlRequestPDU,
+    /*operTm 换成本地的时刻     */
+    due: Instant,
+}
+
+pub struct ControlEngine {
+    /*key 是 DO 的引用     */
+    select_map: HashMap<String, Selection>,
+    /*key 是 DO 的引用  没注册的 DO 不能控     */
+    handler_map: HashMap<String, Box<dyn ControlHandler>>,
+    /*命令号 -> 等结果的命令     */
+    running_map: HashMap<u64, Running>,
+    /*key 是 DO 的引用  等 operTm 的命令     */
+    tao_map: HashMap<String, Scheduled>,
+    next_id: u64,
+    /*异步完成往这里报  汇总中心那边收     */
+    done_tx: mpsc::Sender<CtlDone>,
+    /*仿真执行到位的时间  毫秒  仿真处理者都拿着这一个     */
+    sim_operate_ms: Arc<AtomicU32>,
+    /*等着发的命令结束  (连接号, 服务码, 载荷)  回完响应以后取走     */
+    term_vec: Vec<(u32, u8, ControlResponsePDU)>,
+}
+
+impl ControlEngine {
+    pub fn new(done_tx: mpsc::Sender<CtlDone>, sim_operate_ms: u32) -> ControlEngine {
+        ControlEngine {
+            select_map: HashMap::new(),
+            handler_map: HashMap::new(),
+            running_map: HashMap::new(),
+            tao_map: HashMap::new(),
+            next_id: 1,
+            done_tx,
+            sim_operate_ms: Arc::new(AtomicU32::new(sim_operate_ms)),
+            term_vec: Vec::new(),
+        }
+    }
+
+    /*启动的时候  下面有 CO Oper 的 DO 还没有处理者的  都用仿真执行     */
+    pub fn start(&mut self, model: &Model) {
+        for ld in model.ld_vec.iter() {
+            for ln in ld.ln_vec.iter() {
+                for node in ln.do_vec.iter() {
+                    if node.child("Oper").is_none_or(|oper| oper.fc != "CO") {
+                        continue;
+                    }
+                    let key = format!("{}/{}.{}", ld.name, ln.name, node.name);
+                    if !self.handler_map.contains_key(&key) {
+                        let handler = SimHandler {
+                            operate_ms: self.sim_operate_ms.clone(),
+                        };
+                        self.register(&key, Box::new(handler));
+                    }
+                }
+            }
+        }
+    }
+
+    /*给一个 DO 换处理者  reference 是 DO 的引用     */
+    pub fn register(&mut self, reference: &str, handler: Box<dyn ControlHandler>) {
+        self.handler_map.insert(reference.to_string(), handler);
+    }
+
+    /*还没超时的选中     */
+    fn live_selection(&self, key: &str, now: Instant) -> Option<&Selection> {
+        self.select_map.get(key).filter(|sel| sel.deadline > now)
+    }
+
+    /*选中的人不是自己  或者自己已经选了     */
+    fn check_free(&self, obj: &CtlObject, con_id: u32, now: Instant) -> Result<(), i8> {
+        match self.live_selection(&obj.key(), now) {
+            Some(sel) if sel.con_id != con_id => Err(ADDCAUSE_LOCKED_BY_OTHER_CLIENT),
+            Some(_) => Err(ADDCAUSE_OBJECT_ALREADY_SELECTED),
+            None => Ok(()),
+        }
+    }
+
+    fn start_select(
+        &mut self,
+        model: &mut Model,
+        obj: &CtlObject,
+        con_id: u32,
+        req: Option<ControlRequestPDU>,
+        now: Instant,
+    ) {
+        let sel = Selection {
+            objref: obj.objref.clone(),
+            con_id,
+            deadline: now + Duration::from_millis(obj.sbotimeout),
+            req,
+        };
+        self.select_map.insert(obj.key(), sel);
+        set_selected(model, &obj.objref, true);
+    }
+
+    fn deselect(&mut self, model: &mut Model, key: &str) {
+        if let Some(sel) = self.select_map.remove(key) {
+            set_selected(model, &sel.objref, false);
+        }
+    }
+
+    /*68 sbo-with-normal-security 的选择     */
+    pub fn select(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        obj: &CtlObject,
+        now: Instant,
+    ) -> Result<(), ServiceError> {
+        if obj.ctlmodel != CtlModel::SboNormal {
+            return Err(ServiceError::AccessViolation);
+        }
+        match self.check_free(obj, con_id, now) {
+            Err(ADDCAUSE_LOCKED_BY_OTHER_CLIENT) => {
+                return Err(ServiceError::InstanceLockedByOtherClient)
+            }
+            Err(_) => return Err(ServiceError::InstanceInUse),
+            Ok(()) => {}
+        }
+        self.start_select(model, obj, con_id, None, now);
+        Ok(())
+    }
+
+    /*69 sbo-with-enhanced-security 的选择  命令先检查一遍     */
+    pub fn select_with_value(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        obj: &CtlObject,
+        req: &ControlRequestPDU,
+        now: Instant,
+    ) -> Result<(), i8> {
+        if obj.ctlmodel != CtlModel::SboEnhanced {
+            return Err(ADDCAUSE_NOT_SUPPORTED);
+        }
+        self.check_free(obj, con_id, now)?;
+        check_command(model, obj, req)?;
+        self.start_select(model, obj, con_id, Some(req.clone()), now);
+        Ok(())
+    }
+
+    /*70 取消  选控的取消选中  增强安全的 origin ctlNum 要和选择的一样     */
+    pub fn cancel(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        obj: &CtlObject,
+        req: &ControlRequestPDU,
+        now: Instant,
+    ) -> Result<(), i8> {
+        if obj.ctlmodel == CtlModel::StatusOnly {
+            return Err(ADDCAUSE_NOT_SUPPORTED);
+        }
+        let key = obj.key();
+        /*排着的时间激活执行  只有下命令的连接能取消     */
+        if let Some(tao) = self.tao_map.get(&key) {
+            if tao.con_id != con_id {
+                return Err(ADDCAUSE_LOCKED_BY_OTHER_CLIENT);
+            }
+            if tao.req.origin != req.origin || tao.req.ctlnum != req.ctlnum {
+                return Err(ADDCAUSE_INCONSISTENT_PARAMETERS);
+            }
+            self.tao_map.remove(&key);
+            println!("control {} time activated operate canceled", key);
+            return Ok(());
+        }
+        /*直控的没有选中  没有要取消的     */
+        if !obj.ctlmodel.is_sbo() {
+            return Ok(());
+        }
+        let sel = self
+            .live_selection(&key, now)
+            .ok_or(ADDCAUSE_OBJECT_NOT_SELECTED)?;
+        if sel.con_id != con_id {
+            return Err(ADDCAUSE_LOCKED_BY_OTHER_CLIENT);
+        }
+        if let Some(selreq) = sel.req.as_ref() {
+            if selreq.origin != req.origin || selreq.ctlnum != req.ctlnum {
+                return Err(ADDCAUSE_INCONSISTENT_PARAMETERS);
+            }
+        }
+        self.deselect(model, &key);
+        Ok(())
+    }
+
+    /*同一个 DO 上一个命令还没完  或者排着时间激活执行     */
+    fn check_idle(&self, key: &str) -> Result<(), i8> {
+        if self.tao_map.contains_key(key) || self.running_map.values().any(|run| run.key == key) {
+            return Err(ADDCAUSE_COMMAND_ALREADY_IN_EXECUTION);
+        }
+        Ok(())
+    }
+
+    /*选控的要自己选中  带值选择的参数要一样     */
+    fn check_selected(
+        &self,
+        obj: &CtlObject,
+        con_id: u32,
+        req: &ControlRequestPDU,
+        now: Instant,
+    ) -> Result<(), i8> {
+        if !obj.ctlmodel.is_sbo() {
+            return Ok(());
+        }
+        let sel = self
+            .live_selection(&obj.key(), now)
+            .ok_or(ADDCAUSE_OBJECT_NOT_SELECTED)?;
+        if sel.con_id != con_id {
+            return Err(ADDCAUSE_LOCKED_BY_OTHER_CLIENT);
+        }
+        if let Some(selreq) = sel.req.as_ref() {
+            if selreq.ctlval != req.ctlval
+                || selreq.origin != req.origin
+                || selreq.ctlnum != req.ctlnum
+                || selreq.test != req.test
+            {
+                return Err(ADDCAUSE_INCONSISTENT_PARAMETERS);
+            }
+        }
+        Ok(())
+    }
+
+    /*71 执行  operate-once 的执行以后不再选中     */
+    pub fn operate(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        obj: &CtlObject,
+        req: &ControlRequestPDU,
+        now: Instant,
+    ) -> Result<(), i8> {
+        let checked = check_command(model, obj, req)?;
+        let key = obj.key();
+        self.check_idle(&key)?;
+        self.check_selected(obj, con_id, req, now)?;
+        let term_code = obj
+            .ctlmodel
+            .is_enhanced()
+            .then_some(frame::COMMANDTERMINATION);
+        self.execute(model, con_id, obj, req, checked, term_code)?;
+        if obj.ctlmodel.is_sbo() && !obj.operatemany {
+            self.deselect(model, &key);
+        }
+        Ok(())
+    }
+
+    /*73 时间激活执行  检查过了先排着  operTm 已经过了的马上执行
+    选控的选中在这时候就用掉了     */
+    pub fn time_activated_operate(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        obj: &CtlObject,
+        req: &ControlRequestPDU,
+        now: Instant,
+    ) -> Result<(), i8> {
+        let opertm = req.opertm.ok_or(ADDCAUSE_INCONSISTENT_PARAMETERS)?;
+        check_command(model, obj, req)?;
+        let key = obj.key();
+        self.check_idle(&key)?;
+        self.check_selected(obj, con_id, req, now)?;
+        let delay = (utc_time_millis(&opertm) - chrono::Utc::now().timestamp_millis()).max(0);
+        println!(
+            "control {} time activated operate in {} ms ctlnum {}",
+            key, delay, req.ctlnum
+        );
+        let tao = Scheduled {
+            obj: obj.clone(),
+            con_id,
+            req: req.clone(),
+            due: now + Duration::from_millis(delay as u64),
+        };
+        self.tao_map.insert(key.clone(), tao);
+        if obj.ctlmodel.is_sbo() && !obj.operatemany {
+            self.deselect(model, &key);
+        }
+        Ok(())
+    }
+
+    /*最早要执行的时间激活执行     */
+    pub fn next_due(&self) -> Option<Instant> {
+        self.tao_map.values().map(|tao| tao.due).min()
+    }
+
+    /*operTm 到了的  再检查一遍交给处理者  失败马上排一个否定的终止     */
+    pub fn run_due(&mut self, model: &mut Model, now: Instant) {
+        let mut key_vec: Vec<String> = self
+            .tao_map
+            .iter()
+            .filter(|(_, tao)| tao.due <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        key_vec.sort_by_key(|key| self.tao_map[key].due);
+        for key in key_vec {
+            let tao = match self.tao_map.remove(&key) {
+                Some(tao) => tao,
+                None => continue,
+            };
+            let result = match check_command(model, &tao.obj, &tao.req) {
+                Ok(checked) => self.execute(
+                    model,
+                    tao.con_id,
+                    &tao.obj,
+                    &tao.req,
+                    checked,
+                    Some(frame::TIMEACTIVATEDOPERATETERMINATION),
+                ),
+                Err(addcause) => Err(addcause),
+            };
+            if let Err(addcause) = result {
+                self.note_error(tao.con_id, &tao.req, addcause);
+                let resp = ControlResponsePDU::from_req(&tao.req, Some(addcause));
+                self.term_vec
+                    .push((tao.con_id, frame::TIMEACTIVATEDOPERATETERMINATION, resp));
+            }
+        }
+    }
+
+    /*交给处理者  接了以后更新 origin ctlNum  stVal t 等处理者的结果
+    checked 是 check_command 的结果     */
+    fn execute(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        obj: &CtlObject,
+        req: &ControlRequestPDU,
+        checked: (Value, Option<Value>),
+        term_code: Option<u8>,
+    ) -> Result<(), i8> {
+        let key = obj.key();
+        let handler = self
+            .handler_map
+            .get_mut(&key)
+            .ok_or(ADDCAUSE_NOT_SUPPORTED)?;
+        let (ctlval, status) = checked;
+        let cmd = ControlCmd {
+            id: self.next_id,
+            objref: obj.objref.clone(),
+            ctlval,
+            status,
+            origin: req.origin.clone(),
+            ctlnum: req.ctlnum,
+            test: req.test,
+            check: req.check,
+        };
+        self.next_id += 1;
+        let outcome = handler.operate(model, &cmd, &self.done_tx);
+        if let CtlOutcome::Failed(addcause) = outcome {
+            return Err(addcause);
+        }
+        update_do(model, &obj.objref, |node| {
+            if let Some(origin) = node.child_mut("origin") {
+                if let Some(orcat) = origin.child_mut("orCat") {
+                    orcat.value = Value::Enum(req.origin.orcat as i8);
+                }
+                if let Some(orident) = origin.child_mut("orIdent") {
+                    orident.value = Value::Octet(req.origin.orident.clone());
+                }
+            }
+            if let Some(ctlnum) = node.child_mut("ctlNum") {
+                ctlnum.value = Value::Int8u(req.ctlnum);
+            }
+        });
+        let run = Running {
+            key,
+            objref: obj.objref.clone(),
+            con_id,
+            term_code,
+            req: req.clone(),
+        };
+        match outcome {
+            CtlOutcome::Done(status) => self.finish(model, run, Ok(status)),
+            _ => {
+                println!("control {} command {} pending", req.reference, cmd.id);
+                self.running_map.insert(cmd.id, run);
+            }
+        }
+        Ok(())
+    }
+
+    /*处理者异步报回来的结果     */
+    pub fn complete(&mut self, model: &mut Model, done: CtlDone) {
+        match self.running_map.remove(&done.id) {
+            Some(run) => self.finish(model, run, done.result),
+            None => println!("control command {} done but not running", done.id),
+        }
+    }
+
+    /*命令有了结果  成功写 stVal t  要的话排一个命令结束 失败的带 addCause     */
+    fn finish(&mut self, model: &mut Model, run: Running, result: Result<Option<Value>, i8>) {
+        let addcause = result.as_ref().err().copied();
+        match result {
+            Ok(status) => {
+                update_do(model, &run.objref, |node| {
+                    if let (Some(status), Some(stval)) = (status, node.child_mut("stVal")) {
+                        stval.value = status;
+                    }
+                    if let Some(t) = node.child_mut("t") {
+                        t.value = Value::Timestamp(utc_time(chrono::Utc::now()));
+                    }
+                });
+                println!(
+                    "control {} operated ctlnum {} test {}",
+                    run.req.reference, run.req.ctlnum, run.req.test
+                );
+            }
+            Err(addcause) => self.note_error(run.con_id, &run.req, addcause),
+        }
+        if let Some(term_code) = run.term_code {
+            let resp = ControlResponsePDU::from_req(&run.req, addcause);
+            self.term_vec.push((run.con_id, term_code, resp));
+        }
+    }
+
+    /*控制失败  打一条  addCause 跟着否定响应或者命令结束回给客户端     */
+    pub fn note_error(&self, con_id: u32, req: &ControlRequestPDU, addcause: i8) {
+        println!(
+            "con {} control {} failed orcat {} ctlnum {} addcause {}",
+            con_id, req.reference, req.origin.orcat, req.ctlnum, addcause
+        );
+    }
+
+    /*sboTimeout 到了的取消选中     */
+    pub fn expire(&mut self, model: &mut Model, now: Instant) {
+        let key_vec: Vec<String> = self
+            .select_map
+            .iter()
+            .filter(|(_, sel)| sel.deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in key_vec {
+            println!("control {} select timeout", key);
+            self.deselect(model, &key);
+        }
+    }
+
+    /*连接断了  它选中的 排着的时间激活执行 都取消     */
+    pub fn release_con(&mut self, model: &mut Model, con_id: u32) {
+        let key_vec: Vec<String> = self
+            .select_map
+            .iter()
+            .filter(|(_, sel)| sel.con_id == con_id)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in key_vec {
+            self.deselect(model, &key);
+        }
+        self.tao_map.retain(|key, tao| {
+            if tao.con_id == con_id {
+                println!(
+                    "control {} time activated operate dropped with con {}",
+                    key, con_id
+                );
+            }
+            tao.con_id != con_id
+        });
+        self.term_vec.retain(|(id, _, _)| *id != con_id);
+    }
+
+    /*取走等着发的命令结束     */
+    pub fn take_terminations(&mut self) -> Vec<(u32, u8, ControlResponsePDU)> {
+        std::mem::take(&mut self.term_vec)
+    }
+
+    /*重新加载配置  只换仿真到位时间
+    选中的 在执行的 排着的时间激活执行 都是运行时状态 原样留着     */
+    pub fn reload(&mut self, sim_operate_ms: u32) {
+        self.sim_operate_ms.store(sim_operate_ms, Ordering::Relaxed);
+    }
+
+    /*诊断  选中的 在执行的 排着的时间激活执行  按到期先后排     */
+    pub fn diag(&self, now: Instant) -> ControlDiag {
+        let mut tao_vec: Vec<TaoDiag> = self
+            .tao_map
+            .iter()
+            .map(|(key, tao)| TaoDiag {
+                reference: key.clone(),
+                con_id: tao.con_id,
+                ctlval: tao.req.ctlval.clone(),
+                ctlnum: tao.req.ctlnum,
+                due_ms: tao.due.saturating_duration_since(now).as_millis() as u64,
+            })
+            .collect();
+        tao_vec.sort_by_key(|tao| tao.due_ms);
+        ControlDiag {
+            selected: self.select_map.len(),
+            running: self.running_map.len(),
+            tao_vec,
+        }
+    }
+
+    pub fn log_diag(&self, now: Instant) {
+        let diag = self.diag(now);
+        println!(
+            "control diag selected {} running {} scheduled {}",
+            diag.selected,
+            diag.running,
+            diag.tao_vec.len()
+        );
+        for tao in diag.tao_vec.iter() {
+            println!(
+                "control diag tao {} con {} ctlval {:?} ctlnum {} due in {} ms",
+                tao.reference, tao.con_id, tao.ctlval, tao.ctlnum, tao.due_ms
+            );
+        }
+    }
+}
+
+/*排着的一条时间激活执行     */
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaoDiag {
+    pub reference: String,
+    pub con_id: u32,
+    pub ctlval: Value,
+    pub ctlnum: u8,
+    /*还有多少毫秒到 operTm     */
+    pub due_ms: u64,
+}
+
+/*控制引擎的诊断快照     */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ControlDiag {
+    pub selected: usize,
+    pub running: usize,
+    pub tao_vec: Vec<TaoDiag>,
+}
+
+/*改 DO 下面的几个属性  一起记变化     */
+fn update_do(model: &mut Model, objref: &ObjRef, f: impl FnOnce(&mut DataNode)) {
+    let node = match model.get_node_mut(objref) {
+        Some(node) => node,
+        None => return,
+    };
+    let old = node.clone();
+    f(node);
+    model.note_change(objref, &old);
+}
+
+fn set_selected(model: &mut Model, objref: &ObjRef, selected: bool) {
+    update_do(model, objref, |node| {
+        if let Some(stseld) = node.child_mut("stSeld") {
+            stseld.value = Value::Boolean(selected);
+        }
+    });
+}
+
+/*选择和执行之前都要过的检查  返回修正过类型的 ctlVal 和要写到 stVal 的值
+DO 没有 stVal 的话后面那个是 None     */
+fn check_command(
+    model: &Model,
+    obj: &CtlObject,
+    req: &ControlRequestPDU,
+) -> Result<(Value, Option<Value>), i8> {
+    if obj.ctlmodel == CtlModel::StatusOnly {
+        return Err(ADDCAUSE_NOT_SUPPORTED);
+    }
+    let ctlval = req
+        .ctlval
+        .clone()
+        .conform_to(&obj.ctlval)
+        .ok_or(ADDCAUSE_INCONSISTENT_PARAMETERS)?;
+    let status = match model
+        .get_node(&obj.objref)
+        .and_then(|node| node.child("stVal"))
+    {
+        Some(stval) => {
+            Some(status_value(ctlval.clone(), &stval.value).ok_or(ADDCAUSE_NOT_SUPPORTED)?)
+        }
+        None => None,
+    };
+    check_mode(model, obj, req.test)?;
+    check_interlock(model, obj, &ctlval, req.check)?;
+    Ok((ctlval, status))
+}
+
+/*ctlVal 对应的 stVal  DPC 的 BOOLEAN 对 Dbpos  其他的类型一样     */
+fn status_value(ctlval: Value, stval: &Value) -> Option<Value> {
+    match (ctlval, stval) {
+        (Value::Boolean(on), Value::Dbpos(_)) => Some(Value::Dbpos(if on { 2 } else { 1 })),
+        (ctlval, stval) => ctlval.conform_to(stval),
+    }
+}
+
+/*Beh 是 off 不能控  test 位要和 Beh 是不是测试对上  Mod 自己不受限制
+没有 Beh 或者没有初始值的  当作 on     */
+fn check_mode(model: &Model, obj: &CtlObject, test: bool) -> Result<(), i8> {
+    if obj.objref.path.first().is_some_and(|name| name == "Mod") {
+        return Ok(());
+    }
+    let path = ["Beh".to_string(), "stVal".to_string()];
+    let beh = match model.get_ln(&obj.objref).and_then(|ln| ln.get_path(&path)) {
+        Some(node) => match node.value {
+            Value::Int32(beh) => beh,
+            Value::Enum(beh) => beh as i32,
+            _ => 1,
+        },
+        None => 1,
+    };
+    let in_test = beh == BEH_TEST || beh == BEH_TEST_BLOCKED;
+    if beh == BEH_OFF || in_test != test {
+        return Err(ADDCAUSE_BLOCKED_BY_MODE);
+    }
+    Ok(())
+}
+
+/*CSWI 的 Pos  同一个 LD 里面前缀 实例号一样的 CILO RSYN 有的话才查
+合闸看 EnaCls 和 Rel  分闸看 EnaOpn     */
+fn check_interlock(model: &Model, obj: &CtlObject, ctlval: &Value, check: u8) -> Result<(), i8> {
+    let lnname = &obj.objref.ln;
+    if obj.objref.path != ["Pos"] || !lnname.contains("CSWI") {
+        return Ok(());
+    }
+    let close = matches!(ctlval, Value::Boolean(true));
+    let released = |lnclass: &str, doname: &str| {
+        let reference = format!(
+            "{}/{}.{}.stVal[ST]",
+            obj.objref.ld,
+            lnname.replace("CSWI", lnclass),
+            doname
+        );
+        !matches!(
+            model.find(&reference).map(|node| &node.value),
+            Some(Value::Boolean(false))
+        )
+    };
+    if check & CHECK_INTERLOCK != 0 && !released("CILO", if close { "EnaCls" } else { "EnaOpn" }) {
+        return Err(ADDCAUSE_BLOCKED_BY_INTERLOCKING);
+    }
+    if check & CHECK_SYNCHRO != 0 && close && !released("RSYN", "Rel") {
+        return Err(ADDCAUSE_BLOCKED_BY_SYNCHROCHECK);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSWI1_POS: &str = "E1Q1SB1C1/CSWI1.Pos";
+    const CSWI2_POS: &str = "E1Q1SB1C1/CSWI2.Pos";
+
+    /*样例里面 CSWI1.Pos 是直控增强  CSWI2.Pos 是选控增强 operate-once     */
+    async fn setup(sim_operate_ms: u32) -> (Model, ControlEngine, mpsc::Receiver<CtlDone>) {
+        let info = crate::cfg::scl2::scl_parse("sisco_sample.cid", "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let model = Model::from_scl(&info).unwrap();
+        let (done_tx, done_rx) = mpsc::channel(8);
+        let mut engine = ControlEngine::new(done_tx, sim_operate_ms);
+        engine.start(&model);
+        (model, engine, done_rx)
+    }
+
+    fn set_attr(model: &mut Model, reference: &str, name: &str, val: Value) {
+        let objref = ObjRef::parse(&format!("{}.{}", reference, name)).unwrap();
+        model.get_node_mut(&objref).unwrap().value = val;
+    }
+
+    fn obj(model: &mut Model, reference: &str, ctlmodel: Option<i8>) -> CtlObject {
+        if let Some(ord) = ctlmodel {
+            set_attr(model, reference, "ctlModel", Value::Enum(ord));
+        }
+        CtlObject::resolve(model, reference).unwrap()
+    }
+
+    fn req(reference: &str, close: bool, ctlnum: u8) -> ControlRequestPDU {
+        ControlRequestPDU {
+            reference: reference.to_string(),
+            ctlval: Value::Boolean(close),
+            origin: Originator {
+                orcat: 3,
+                orident: b"client".to_vec(),
+            },
+            ctlnum,
+            ..Default::default()
+        }
+    }
+
+    fn value(model: &Model, reference: &str, name: &str) -> Value {
+        model
+            .find(&format!("{}.{}[ST]", reference, name))
+            .unwrap()
+            .value
+            .clone()
+    }
+
+    fn stseld(model: &Model, reference: &str) -> bool {
+        value(model, reference, "stSeld") == Value::Boolean(true)
+    }
+
+    #[tokio::test]
+    async fn direct_normal() {
+        let (mut model, mut engine, _done_rx) = setup(0).await;
+        let now = Instant::now();
+        let pos = obj(&mut model, CSWI1_POS, Some(1));
+        assert_eq!(pos.ctlmodel, CtlModel::DirectNormal);
+        assert_eq!(
+            engine.select(&mut model, 1, &pos, now),
+            Err(ServiceError::AccessViolation)
+        );
+        engine
+            .operate(&mut model, 1, &pos, &req(CSWI1_POS, true, 1), now)
+            .unwrap();
+        assert_eq!(value(&model, CSWI1_POS, "stVal"), Value::Dbpos(2));
+        assert_eq!(value(&model, CSWI1_POS, "ctlNum"), Value::Int8u(1));
+        /*常规安全没有命令结束  直控取消什么都不做     */
+        assert!(engine.take_terminations().is_empty());
+        assert_eq!(
+            engine.cancel(&mut model, 1, &pos, &req(CSWI1_POS, true, 1), now),
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn sbo_normal() {
+        let (mut model, mut engine, _done_rx) = setup(0).await;
+        let now = Instant::now();
+        let pos = obj(&mut model, CSWI2_POS, Some(2));
+        let close = req(CSWI2_POS, true, 1);
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &close, now),
+            Err(ADDCAUSE_OBJECT_NOT_SELECTED)
+        );
+        engine.select(&mut model, 1, &pos, now).unwrap();
+        assert!(stseld(&model, CSWI2_POS));
+        assert_eq!(
+            engine.select(&mut model, 2, &pos, now),
+            Err(ServiceError::InstanceLockedByOtherClient)
+        );
+        assert_eq!(
+            engine.select(&mut model, 1, &pos, now),
+            Err(ServiceError::InstanceInUse)
+        );
+        assert_eq!(
+            engine.operate(&mut model, 2, &pos, &close, now),
+            Err(ADDCAUSE_LOCKED_BY_OTHER_CLIENT)
+        );
+        engine.operate(&mut model, 1, &pos, &close, now).unwrap();
+        assert_eq!(value(&model, CSWI2_POS, "stVal"), Value::Dbpos(2));
+        /*operate-once 执行完不再选中     */
+        assert!(!stseld(&model, CSWI2_POS));
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &close, now),
+            Err(ADDCAUSE_OBJECT_NOT_SELECTED)
+        );
+
+        engine.select(&mut model, 1, &pos, now).unwrap();
+        assert_eq!(
+            engine.cancel(&mut model, 2, &pos, &close, now),
+            Err(ADDCAUSE_LOCKED_BY_OTHER_CLIENT)
+        );
+        engine.cancel(&mut model, 1, &pos, &close, now).unwrap();
+        assert!(!stseld(&model, CSWI2_POS));
+        assert_eq!(
+            engine.cancel(&mut model, 1, &pos, &close, now),
+            Err(ADDCAUSE_OBJECT_NOT_SELECTED)
+        );
+    }
+
+    #[tokio::test]
+    async fn direct_enhanced_termination() {
+        let (mut model, mut engine, _done_rx) = setup(0).await;
+        let now = Instant::now();
+        let pos = obj(&mut model, CSWI1_POS, None);
+        assert_eq!(pos.ctlmodel, CtlModel::DirectEnhanced);
+        assert_eq!(
+            engine.select_with_value(&mut model, 1, &pos, &req(CSWI1_POS, true, 1), now),
+            Err(ADDCAUSE_NOT_SUPPORTED)
+        );
+        engine
+            .operate(&mut model, 1, &pos, &req(CSWI1_POS, false, 2), now)
+            .unwrap();
+        assert_eq!(value(&model, CSWI1_POS, "stVal"), Value::Dbpos(1));
+        let term_vec = engine.take_terminations();
+        assert_eq!(term_vec.len(), 1);
+        let (con_id, serv_code, resp) = &term_vec[0];
+        assert_eq!((*con_id, *serv_code), (1, frame::COMMANDTERMINATION));
+        assert_eq!((resp.ctlnum, resp.addcause), (2, None));
+    }
+
+    #[tokio::test]
+    async fn pending_operate_blocks_until_done() {
+        let (mut model, mut engine, mut done_rx) = setup(10).await;
+        let now = Instant::now();
+        let pos = obj(&mut model, CSWI1_POS, None);
+        engine
+            .operate(&mut model, 1, &pos, &req(CSWI1_POS, true, 1), now)
+            .unwrap();
+        /*过程还没回来  没有命令结束  再下命令在执行中     */
+        assert!(engine.take_terminations().is_empty());
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &req(CSWI1_POS, true, 2), now),
+            Err(ADDCAUSE_COMMAND_ALREADY_IN_EXECUTION)
+        );
+        let done = done_rx.recv().await.unwrap();
+        engine.complete(&mut model, done);
+        assert_eq!(value(&model, CSWI1_POS, "stVal"), Value::Dbpos(2));
+        let term_vec = engine.take_terminations();
+        assert_eq!(term_vec.len(), 1);
+        assert_eq!(term_vec[0].2.addcause, None);
+    }
+
+    #[tokio::test]
+    async fn sbo_enhanced_parameters() {
+        let (mut model, mut engine, _done_rx) = setup(0).await;
+        let now = Instant::now();
+        let pos = obj(&mut model, CSWI2_POS, None);
+        assert_eq!(pos.ctlmodel, CtlModel::SboEnhanced);
+        let close = req(CSWI2_POS, true, 5);
+        engine
+            .select_with_value(&mut model, 1, &pos, &close, now)
+            .unwrap();
+        assert!(stseld(&model, CSWI2_POS));
+        assert_eq!(
+            engine.select_with_value(&mut model, 2, &pos, &close, now),
+            Err(ADDCAUSE_LOCKED_BY_OTHER_CLIENT)
+        );
+        /*执行和取消的参数要和带值选择的一样     */
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &req(CSWI2_POS, true, 6), now),
+            Err(ADDCAUSE_INCONSISTENT_PARAMETERS)
+        );
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &req(CSWI2_POS, false, 5), now),
+            Err(ADDCAUSE_INCONSISTENT_PARAMETERS)
+        );
+        let mut other_origin = close.clone();
+        other_origin.origin.orcat = 2;
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &other_origin, now),
+            Err(ADDCAUSE_INCONSISTENT_PARAMETERS)
+        );
+        assert_eq!(
+            engine.cancel(&mut model, 1, &pos, &req(CSWI2_POS, true, 6), now),
+            Err(ADDCAUSE_INCONSISTENT_PARAMETERS)
+        );
+        assert!(stseld(&model, CSWI2_POS));
+        engine.operate(&mut model, 1, &pos, &close, now).unwrap();
+        assert!(!stseld(&model, CSWI2_POS));
+        assert_eq!(value(&model, CSWI2_POS, "stVal"), Value::Dbpos(2));
+        let term_vec = engine.take_terminations();
+        assert_eq!(term_vec.len(), 1);
+        assert_eq!(term_vec[0].1, frame::COMMANDTERMINATION);
+    }
+
+    #[tokio::test]
+    async fn sbo_timeout() {
+        let (mut model, mut engine, _done_rx) = setup(0).await;
+        let now = Instant::now();
+        set_attr(&mut model, CSWI2_POS, "sboTimeout", Value::Int32u(100));
+        let pos = obj(&mut model, CSWI2_POS, None);
+        assert_eq!(pos.sbotimeout, 100);
+        let close = req(CSWI2_POS, true, 1);
+        engine
+            .select_with_value(&mut model, 1, &pos, &close, now)
+            .unwrap();
+        engine.expire(&mut model, now + Duration::from_millis(50));
+        assert!(stseld(&model, CSWI2_POS));
+        /*超时以后还没扫到  也当没选中     */
+        let later = now + Duration::from_millis(150);
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &close, later),
+            Err(ADDCAUSE_OBJECT_NOT_SELECTED)
+        );
+        engine.expire(&mut model, later);
+        assert!(!stseld(&model, CSWI2_POS));
+        /*别的连接可以选了     */
+        engine
+            .select_with_value(&mut model, 2, &pos, &close, later)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn operate_many_keeps_selection() {
+        let (mut model, mut engine, _done_rx) = setup(0).await;
+        let now = Instant::now();
+        set_attr(&mut model, CSWI2_POS, "sboClass", Value::Enum(1));
+        let pos = obj(&mut model, CSWI2_POS, None);
+        assert!(pos.operatemany);
+        let close = req(CSWI2_POS, true, 1);
+        engine
+            .select_with_value(&mut model, 1, &pos, &close, now)
+            .unwrap();
+        engine.operate(&mut model, 1, &pos, &close, now).unwrap();
+        assert!(stseld(&model, CSWI2_POS));
+        engine.operate(&mut model, 1, &pos, &close, now).unwrap();
+        assert_eq!(engine.take_terminations().len(), 2);
+        engine.cancel(&mut model, 1, &pos, &close, now).unwrap();
+        assert!(!stseld(&model, CSWI2_POS));
+    }
+
+    #[tokio::test]
+    async fn beh_and_test_bit() {
+        let (mut model, mut engine, _done_rx) = setup(0).await;
+        let now = Instant::now();
+        let pos = obj(&mut model, CSWI1_POS, Some(1));
+        let close = req(CSWI1_POS, true, 1);
+        let mut close_test = close.clone();
+        close_test.test = true;
+        let beh = "E1Q1SB1C1/CSWI1.Beh";
+
+        /*Beh on  测试命令不行     */
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &close_test, now),
+            Err(ADDCAUSE_BLOCKED_BY_MODE)
+        );
+        set_attr(&mut model, beh, "stVal", Value::Int32(BEH_TEST));
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &close, now),
+            Err(ADDCAUSE_BLOCKED_BY_MODE)
+        );
+        engine
+            .operate(&mut model, 1, &pos, &close_test, now)
+            .unwrap();
+        set_attr(&mut model, beh, "stVal", Value::Int32(BEH_TEST_BLOCKED));
+        engine
+            .operate(&mut model, 1, &pos, &close_test, now)
+            .unwrap();
+        set_attr(&mut model, beh, "stVal", Value::Int32(BEH_OFF));
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &close, now),
+            Err(ADDCAUSE_BLOCKED_BY_MODE)
+        );
+        /*Mod 自己不受 Beh 限制  不然关了就开不回来     */
+        let md = obj(&mut model, "E1Q1SB1C1/CSWI1.Mod", None);
+        let mut on = req("E1Q1SB1C1/CSWI1.Mod", true, 2);
+        on.ctlval = Value::Int32(1);
+        engine.operate(&mut model, 1, &md, &on, now).unwrap();
+    }
+
+    #[tokio::test]
+    async fn status_only_rejected() {
+        let (mut model, mut engine, _done_rx) = setup(0).await;
+        let now = Instant::now();
+        let pos = obj(&mut model, CSWI1_POS, Some(0));
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &req(CSWI1_POS, true, 1), now),
+            Err(ADDCAUSE_NOT_SUPPORTED)
+        );
+        assert_eq!(
+            engine.cancel(&mut model, 1, &pos, &req(CSWI1_POS, true, 1), now),
+            Err(ADDCAUSE_NOT_SUPPORTED)
+        );
+    }
+
+    #[tokio::test]
+    async fn time_activated_and_release() {
+        let (mut model, mut engine, _done_rx) = setup(0).await;
+        let now = Instant::now();
+        let pos = obj(&mut model, CSWI1_POS, None);
+        let mut tao = req(CSWI1_POS, true, 3);
+        tao.opertm = Some(utc_time(chrono::Utc::now() + chrono::Duration::seconds(60)));
+        engine
+            .time_activated_operate(&mut model, 1, &pos, &tao, now)
+            .unwrap();
+        assert!(engine.next_due().is_some());
+        assert_eq!(
+            engine.operate(&mut model, 1, &pos, &req(CSWI1_POS, true, 4), now),
+            Err(ADDCAUSE_COMMAND_ALREADY_IN_EXECUTION)
+        );
+        assert_eq!(
+            engine.cancel(&mut model, 2, &pos, &tao, now),
+            Err(ADDCAUSE_LOCKED_BY_OTHER_CLIENT)
+        );
+        let due = engine.next_due().unwrap();
+        engine.run_due(&mut model, due);
+        assert_eq!(value(&model, CSWI1_POS, "stVal"), Value::Dbpos(2));
+        let term_vec = engine.take_terminations();
+        assert_eq!(term_vec.len(), 1);
+        assert_eq!(term_vec[0].1, frame::TIMEACTIVATEDOPERATETERMINATION);
+
+        /*连接断了  选中和排着的都放掉     */
+        let sbo = obj(&mut model, CSWI2_POS, None);
+        engine
+            .select_with_value(&mut model, 1, &sbo, &req(CSWI2_POS, true, 5), now)
+            .unwrap();
+        engine
+            .time_activated_operate(&mut model, 1, &pos, &tao, now)
+            .unwrap();
+        engine.release_con(&mut model, 1);
+        assert!(!stseld(&model, CSWI2_POS));
+        assert!(engine.next_due().is_none());
+    }
+
+    #[tokio::test]
+    async fn time_activated_survives_reload() {
+        let (mut model, mut engine, mut done_rx) = setup(0).await;
+        let now = Instant::now();
+        let pos = obj(&mut model, CSWI1_POS, None);
+        let mut tao = req(CSWI1_POS, true, 3);
+        tao.opertm = Some(utc_time(chrono::Utc::now() + chrono::Duration::seconds(60)));
+        engine
+            .time_activated_operate(&mut model, 1, &pos, &tao, now)
+            .unwrap();
+
+        /*重新加载以后还排着  诊断里面看得到     */
+        engine.reload(50);
+        let diag = engine.diag(now);
+        assert_eq!(diag.tao_vec.len(), 1);
+        assert_eq!(diag.tao_vec[0].reference, CSWI1_POS);
+        assert_eq!(diag.tao_vec[0].con_id, 1);
+        assert_eq!(diag.tao_vec[0].ctlnum, 3);
+        assert!(diag.tao_vec[0].due_ms > 50_000);
+
+        /*到了按新的仿真时间执行  先 Pending 再完成     */
+        let due = engine.next_due().unwrap();
+        engine.run_due(&mut model, due);
+        assert!(engine.take_terminations().is_empty());
+        assert_eq!(engine.diag(now).running, 1);
+        let done = done_rx.recv().await.unwrap();
+        engine.complete(&mut model, done);
+        assert_eq!(value(&model, CSWI1_POS, "stVal"), Value::Dbpos(2));
+        assert_eq!(engine.take_terminations().len(), 1);
+        assert_eq!(engine.diag(now), ControlDiag::default());
+    }
+}
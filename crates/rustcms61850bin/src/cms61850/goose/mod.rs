@@ -0,0 +1,194 @@
+use crate::cms61850::codec::ber::{self, BerDecoder, BerEncoder};
+use crate::cms61850::model::cb::PhyComAddr;
+use crate::cms61850::model::dataset::split_dataset_ref;
+use crate::cms61850::model::Value;
+use anyhow::bail;
+/***************************************/
+/*GOOSE  61850-8-1 的原始以太网报文
+以太网头 [802.1Q] 0x88b8 APPID Length 保留1 保留2 然后是 BER 的 goosePdu
+报文里面的引用是 MMS 的写法  LD/LLN0$GO$GoCBName  LD/LLN0$DataSetName     */
+/***************************************/
+
+pub mod publisher;
+pub mod sink;
+pub mod subscriber;
+
+pub const ETHERTYPE_GOOSE: u16 = 0x88b8;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+/*APPID 开始到 PDU 之前  APPID Length 保留1 保留2     */
+const GOOSE_HEADER_LEN: usize = 8;
+
+// IECGoosePdu ::= [APPLICATION 1] IMPLICIT SEQUENCE {
+//     gocbRef [0] IMPLICIT VISIBLE-STRING,
+//     timeAllowedtoLive [1] IMPLICIT INTEGER,
+//     datSet [2] IMPLICIT VISIBLE-STRING,
+//     goID [3] IMPLICIT VISIBLE-STRING OPTIONAL,
+//     t [4] IMPLICIT UtcTime,
+//     stNum [5] IMPLICIT INTEGER,
+//     sqNum [6] IMPLICIT INTEGER,
+//     simulation [7] IMPLICIT BOOLEAN DEFAULT FALSE,
+//     confRev [8] IMPLICIT INTEGER,
+//     ndsCom [9] IMPLICIT BOOLEAN DEFAULT FALSE,
+//     numDatSetEntries [10] IMPLICIT INTEGER,
+//     allData [11] IMPLICIT SEQUENCE OF Data,
+//     security [12] ANY OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GoosePdu {
+    pub gocbref: String,
+    /*ms     */
+    pub timeallowedtolive: u32,
+    pub datset: String,
+    pub goid: String,
+    pub t: [u8; 8],
+    pub stnum: u32,
+    pub sqnum: u32,
+    pub simulation: bool,
+    pub confrev: u32,
+    pub ndscom: bool,
+    pub alldata: Vec<Value>,
+}
+
+impl GoosePdu {
+    pub fn ber_encode(&self) -> crate::Result<Vec<u8>> {
+        let mut enc = BerEncoder::new();
+        enc.put_constructed(0x61, |enc| {
+            enc.put_tlv(0x80, self.gocbref.as_bytes());
+            enc.put_uint(0x81, self.timeallowedtolive as u64);
+            enc.put_tlv(0x82, self.datset.as_bytes());
+            enc.put_tlv(0x83, self.goid.as_bytes());
+            enc.put_tlv(0x84, &self.t);
+            enc.put_uint(0x85, self.stnum as u64);
+            enc.put_uint(0x86, self.sqnum as u64);
+            enc.put_bool(0x87, self.simulation);
+            enc.put_uint(0x88, self.confrev as u64);
+            enc.put_bool(0x89, self.ndscom);
+            enc.put_uint(0x8a, self.alldata.len() as u64);
+            enc.put_constructed(0xab, |enc| {
+                for val in self.alldata.iter() {
+                    enc.put_data(val)?;
+                }
+                Ok(())
+            })
+        })?;
+        Ok(enc.into_bytes())
+    }
+
+    /*不认识的标签跳过  security 也不管
+    numDatSetEntries 和 allData 的个数要对上     */
+    pub fn ber_decode(buf: &[u8]) -> crate::Result<GoosePdu> {
+        let (tag, content) = BerDecoder::new(buf).get_tlv()?;
+        if tag != 0x61 {
+            bail!(format!("goose pdu tag {:02x}", tag));
+        }
+        let mut pdu = GoosePdu::default();
+        let mut entries = None;
+        let mut seen = 0u16;
+        let mut dec = BerDecoder::new(content);
+        while !dec.is_empty() {
+            let (tag, content) = dec.get_tlv()?;
+            let text = || String::from_utf8_lossy(content).to_string();
+            let num = || -> crate::Result<u32> { Ok(u32::try_from(ber::decode_uint(content)?)?) };
+            match tag {
+                0x80 => pdu.gocbref = text(),
+                0x81 => pdu.timeallowedtolive = num()?,
+                0x82 => pdu.datset = text(),
+                0x83 => pdu.goid = text(),
+                0x84 => match content.try_into() {
+                    Ok(t) => pdu.t = t,
+                    Err(_) => bail!(format!("goose t length {}", content.len())),
+                },
+                0x85 => pdu.stnum = num()?,
+                0x86 => pdu.sqnum = num()?,
+                0x87 => pdu.simulation = ber::decode_bool(content)?,
+                0x88 => pdu.confrev = num()?,
+                0x89 => pdu.ndscom = ber::decode_bool(content)?,
+                0x8a => entries = Some(num()? as usize),
+                0xab => {
+                    let mut dec = BerDecoder::new(content);
+                    while !dec.is_empty() {
+                        pdu.alldata.push(dec.get_data()?);
+                    }
+                }
+                _ => continue,
+            }
+            if tag < 0x90 {
+                seen |= 1 << (tag & 0x0f);
+            }
+        }
+        /*必选的  gocbRef timeAllowedtoLive datSet t stNum sqNum confRev numDatSetEntries     */
+        let required = 0b0101_0111_0111u16;
+        if seen & required != required {
+            bail!(format!(
+                "goose pdu {} missing fields {:04x}",
+                pdu.gocbref,
+                required & !seen
+            ));
+        }
+        if entries != Some(pdu.alldata.len()) {
+            bail!(format!(
+                "goose pdu {} entries {:?} but alldata {}",
+                pdu.gocbref,
+                entries,
+                pdu.alldata.len()
+            ));
+        }
+        Ok(pdu)
+    }
+}
+
+/*整个以太网帧  vid 和优先级都是 0 的话不带 802.1Q 头     */
+pub fn encode_frame(dst: &PhyComAddr, src: &[u8; 6], pdu: &GoosePdu) -> crate::Result<Vec<u8>> {
+    let pdu = pdu.ber_encode()?;
+    let mut frame = Vec::with_capacity(pdu.len() + 26);
+    frame.extend_from_slice(&dst.addr);
+    frame.extend_from_slice(src);
+    if dst.vid != 0 || dst.priority != 0 {
+        let tci = ((dst.priority as u16 & 0x07) << 13) | (dst.vid & 0x0fff);
+        frame.extend_from_slice(&ETHERTYPE_VLAN.to_be_bytes());
+        frame.extend_from_slice(&tci.to_be_bytes());
+    }
+    frame.extend_from_slice(&ETHERTYPE_GOOSE.to_be_bytes());
+    frame.extend_from_slice(&dst.appid.to_be_bytes());
+    frame.extend_from_slice(&((pdu.len() + GOOSE_HEADER_LEN) as u16).to_be_bytes());
+    frame.extend_from_slice(&[0, 0, 0, 0]);
+    frame.extend_from_slice(&pdu);
+    Ok(frame)
+}
+
+/*收到的以太网帧  返回 APPID 和 PDU  不是 GOOSE 的报错
+Length 从 APPID 开始算  比帧短的按 Length 截  后面的是填充     */
+pub fn decode_frame(frame: &[u8]) -> crate::Result<(u16, GoosePdu)> {
+    let mut pos = 12;
+    let read_u16 = |pos: usize| match frame.get(pos..pos + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => bail!(format!("goose frame length {}", frame.len())),
+    };
+    let mut ethertype = read_u16(pos)?;
+    if ethertype == ETHERTYPE_VLAN {
+        pos += 4;
+        ethertype = read_u16(pos)?;
+    }
+    if ethertype != ETHERTYPE_GOOSE {
+        bail!(format!("ethertype {:04x} not goose", ethertype));
+    }
+    pos += 2;
+    let appid = read_u16(pos)?;
+    let len = read_u16(pos + 2)? as usize;
+    if len < GOOSE_HEADER_LEN || pos + len > frame.len() {
+        bail!(format!("goose appid {:04x} bad length {}", appid, len));
+    }
+    let pdu = GoosePdu::ber_decode(&frame[pos + GOOSE_HEADER_LEN..pos + len])?;
+    Ok((appid, pdu))
+}
+
+/*LD/LN.Name 转成 MMS 的写法  fc 放在 LN 和名字中间  数据集没有 fc     */
+pub fn mms_ref(reference: &str, fc: Option<&str>) -> String {
+    match split_dataset_ref(reference) {
+        Some((ln_ref, name)) => match fc {
+            Some(fc) => format!("{}/{}${}${}", ln_ref.ld, ln_ref.ln, fc, name),
+            None => format!("{}/{}${}", ln_ref.ld, ln_ref.ln, name),
+        },
+        None => reference.to_string(),
+    }
+}
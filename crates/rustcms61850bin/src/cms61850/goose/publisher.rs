@@ -0,0 +1,285 @@
+use super::sink::GooseSink;
+use super::{encode_frame, mms_ref, GoosePdu};
+use crate::cms61850::model::cb::GoCb;
+use crate::cms61850::model::change::DataChange;
+use crate::cms61850::model::value::utc_time;
+use crate::cms61850::model::{Model, ObjRef, Value};
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+/***************************************/
+/*GOOSE 发布  GoEna 的控制块才发  数据集成员的值变了 StNum 加一 SqNum 从 0 开始马上发
+之后按 MinTime 发  间隔每次翻倍  到 MaxTime 以后就按 MaxTime 一直发
+TimeAllowedToLive 是到下一帧间隔的两倍  订阅端超过它没收到就认为断了
+控制块的配置变了 (GoID DatSet ConfRev 这些) 从 StNum 1 重新开始     */
+/***************************************/
+
+/*GSE 里面没配重发时间的时候用     */
+const DEFAULT_MINTIME: u32 = 10;
+const DEFAULT_MAXTIME: u32 = 1000;
+
+/*一个在发的控制块     */
+#[derive(Debug)]
+struct GoRun {
+    /*开始发的时候的控制块  和模型里面的不一样了就重新开始     */
+    cb: GoCb,
+    member_vec: Vec<ObjRef>,
+    /*上一帧  重发的时候只改 SqNum 和 TimeAllowedToLive     */
+    pdu: GoosePdu,
+    /*到下一帧的间隔 ms     */
+    interval: u32,
+    mintime: u32,
+    maxtime: u32,
+    next_send: Instant,
+}
+
+/*StNum SqNum 翻转以后从 1 开始     */
+fn next_num(num: u32) -> u32 {
+    if num == u32::MAX {
+        1
+    } else {
+        num + 1
+    }
+}
+
+/*成员的值  有一个读不到就不发     */
+fn read_values(model: &Model, member_vec: &[ObjRef]) -> Option<Vec<Value>> {
+    member_vec
+        .iter()
+        .map(|member| model.get_node(member)?.get_value(member.fc.as_deref()))
+        .collect()
+}
+
+pub struct GoosePublisher {
+    sink: Box<dyn GooseSink>,
+    /*源 MAC     */
+    src: [u8; 6],
+    /*key 是 GoCB 的引用 LD/LN.Name  使能的才有     */
+    run_map: HashMap<String, GoRun>,
+}
+
+impl GoosePublisher {
+    pub fn new(sink: Box<dyn GooseSink>, src: [u8; 6]) -> GoosePublisher {
+        GoosePublisher {
+            sink,
+            src,
+            run_map: HashMap::new(),
+        }
+    }
+
+    /*按模型里面的 GoCB 开始停止  然后看变化有没有落在在发的数据集里面
+    报告扫描的时候一起调     */
+    pub fn scan(&mut self, model: &Model, change_vec: &[DataChange], now: Instant) {
+        for ld in model.ld_vec.iter() {
+            for ln in ld.ln_vec.iter() {
+                for cb in ln.gocb_vec.iter() {
+                    let key = format!("{}/{}.{}", ld.name, ln.name, cb.name);
+                    if !cb.goena || cb.ndscom {
+                        if self.run_map.remove(&key).is_some() {
+                            println!("gocb {} publish stopped", key);
+                        }
+                        continue;
+                    }
+                    if self.run_map.get(&key).is_none_or(|run| run.cb != *cb) {
+                        self.start(model, &key, cb, now);
+                        continue;
+                    }
+                    let run = match self.run_map.get_mut(&key) {
+                        Some(run) => run,
+                        None => continue,
+                    };
+                    let hit = change_vec.iter().any(|change| {
+                        run.member_vec
+                            .iter()
+                            .any(|member| change.covered_by(member))
+                    });
+                    if !hit {
+                        continue;
+                    }
+                    /*只有 dupd 值没变的不算新状态     */
+                    if let Some(alldata) = read_values(model, &run.member_vec)
+                        .filter(|alldata| *alldata != run.pdu.alldata)
+                    {
+                        run.pdu.alldata = alldata;
+                        run.pdu.stnum = next_num(run.pdu.stnum);
+                        run.pdu.sqnum = 0;
+                        run.pdu.t = utc_time(chrono::Utc::now());
+                        run.interval = run.mintime;
+                        send(self.sink.as_mut(), &self.src, run, now);
+                    }
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, model: &Model, key: &str, cb: &GoCb, now: Instant) {
+        self.run_map.remove(key);
+        let member_vec = match model.get_dataset(&cb.datset) {
+            Some(dataset) => dataset.member_vec.clone(),
+            None => {
+                println!("gocb {} dataset {} not found", key, cb.datset);
+                return;
+            }
+        };
+        let alldata = match read_values(model, &member_vec) {
+            Some(alldata) => alldata,
+            None => {
+                println!("gocb {} dataset {} member not readable", key, cb.datset);
+                return;
+            }
+        };
+        let mintime = if cb.mintime == 0 {
+            DEFAULT_MINTIME
+        } else {
+            cb.mintime
+        };
+        let maxtime = if cb.maxtime == 0 {
+            DEFAULT_MAXTIME
+        } else {
+            cb.maxtime
+        };
+        let mut run = GoRun {
+            cb: cb.clone(),
+            member_vec,
+            pdu: GoosePdu {
+                gocbref: mms_ref(key, Some("GO")),
+                datset: mms_ref(&cb.datset, None),
+                goid: cb.goid.clone(),
+                t: utc_time(chrono::Utc::now()),
+                stnum: 1,
+                sqnum: 0,
+                confrev: cb.confrev,
+                ndscom: cb.ndscom,
+                alldata,
+                ..Default::default()
+            },
+            interval: mintime,
+            mintime,
+            maxtime: maxtime.max(mintime),
+            next_send: now,
+        };
+        println!(
+            "gocb {} publish started appid {:04x} mintime {} maxtime {}",
+            key, cb.dstaddress.appid, run.mintime, run.maxtime
+        );
+        send(self.sink.as_mut(), &self.src, &mut run, now);
+        self.run_map.insert(key.to_string(), run);
+    }
+
+    /*最早要重发的时间  没有在发的就不等     */
+    pub fn next_due(&self) -> Option<Instant> {
+        self.run_map.values().map(|run| run.next_send).min()
+    }
+
+    /*到时间的重发  SqNum 加一  间隔翻倍     */
+    pub fn run_due(&mut self, now: Instant) {
+        for run in self.run_map.values_mut() {
+            if run.next_send > now {
+                continue;
+            }
+            run.pdu.sqnum = next_num(run.pdu.sqnum);
+            run.interval = run.interval.saturating_mul(2).min(run.maxtime);
+            send(self.sink.as_mut(), &self.src, run, now);
+        }
+    }
+}
+
+/*按现在的间隔算 TimeAllowedToLive  发出去  排下一帧     */
+fn send(sink: &mut dyn GooseSink, src: &[u8; 6], run: &mut GoRun, now: Instant) {
+    run.pdu.timeallowedtolive = run.interval.saturating_mul(2);
+    run.next_send = now + Duration::from_millis(run.interval as u64);
+    let result =
+        encode_frame(&run.cb.dstaddress, src, &run.pdu).and_then(|frame| sink.send(&frame));
+    if let Err(err) = result {
+        println!("gocb {} send failed {:?}", run.pdu.gocbref, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cms61850::goose::sink::MemSink;
+    use crate::cms61850::goose::ETHERTYPE_GOOSE;
+
+    const GOCB: &str = "E1Q1SB1C1/LLN0.ItlPositions";
+
+    /*BER 长度  返回长度和长度域占的字节数     */
+    fn get_len(buf: &[u8]) -> (usize, usize) {
+        if buf[0] < 0x80 {
+            return (buf[0] as usize, 1);
+        }
+        let count = (buf[0] & 0x7f) as usize;
+        let len = buf[1..=count]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, count + 1)
+    }
+
+    /*帧里面 802.1Q 后面的 ethertype APPID  还有 stNum sqNum 的值     */
+    fn parse(frame: &[u8]) -> (u16, u16, u32, u32) {
+        let ethertype = u16::from_be_bytes([frame[16], frame[17]]);
+        let appid = u16::from_be_bytes([frame[18], frame[19]]);
+        assert_eq!(frame[26], 0x61);
+        let (mut stnum, mut sqnum) = (0, 0);
+        let mut pos = 27 + get_len(&frame[27..]).1;
+        while pos < frame.len() {
+            let tag = frame[pos];
+            let (len, skip) = get_len(&frame[pos + 1..]);
+            let content = &frame[pos + 1 + skip..pos + 1 + skip + len];
+            let val = content.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32);
+            match tag {
+                0x85 => stnum = val,
+                0x86 => sqnum = val,
+                _ => {}
+            }
+            pos += 1 + skip + len;
+        }
+        (ethertype, appid, stnum, sqnum)
+    }
+
+    #[tokio::test]
+    async fn publish_retransmit_and_new_state() {
+        let info = crate::cfg::scl2::scl_parse("sisco_sample.cid", "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let mut model = Model::from_scl(&info).unwrap();
+        let sink = MemSink::new();
+        let mut publisher = GoosePublisher::new(Box::new(sink.clone()), [2, 0, 0, 0, 0, 1]);
+        let now = Instant::now();
+        publisher.scan(&model, &[], now);
+        assert!(sink.take_frames().is_empty());
+
+        model.get_gocb_mut(GOCB).unwrap().goena = true;
+        publisher.scan(&model, &[], now);
+        let frame_vec = sink.take_frames();
+        assert_eq!(frame_vec.len(), 1);
+        assert_eq!(parse(&frame_vec[0]), (ETHERTYPE_GOOSE, 0x3000, 1, 0));
+
+        /*MinTime 没配  默认 10ms 之后重发  然后 20ms     */
+        assert_eq!(publisher.next_due(), Some(now + Duration::from_millis(10)));
+        publisher.run_due(now + Duration::from_millis(10));
+        assert_eq!(publisher.next_due(), Some(now + Duration::from_millis(30)));
+        assert_eq!(parse(&sink.take_frames()[0]).3, 1);
+
+        let objref = ObjRef::parse("E1Q1SB1C1/CSWI1.Pos[ST]").unwrap();
+        let old = model.get_node(&objref).unwrap().clone();
+        let node = model.get_node_mut(&objref).unwrap();
+        let leaf = node
+            .child_vec
+            .iter_mut()
+            .find(|c| c.name == "stVal")
+            .unwrap();
+        leaf.value = Value::Dbpos(2);
+        model.note_change(&objref, &old);
+        let change_vec = model.take_changes();
+        assert!(!change_vec.is_empty());
+        publisher.scan(&model, &change_vec, now);
+        let frame_vec = sink.take_frames();
+        assert_eq!(frame_vec.len(), 1);
+        assert_eq!(parse(&frame_vec[0]).2, 2);
+        assert_eq!(parse(&frame_vec[0]).3, 0);
+
+        model.get_gocb_mut(GOCB).unwrap().goena = false;
+        publisher.scan(&model, &[], now);
+        assert_eq!(publisher.next_due(), None);
+    }
+}
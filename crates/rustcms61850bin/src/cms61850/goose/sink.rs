@@ -0,0 +1,227 @@
+use anyhow::bail;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+/***************************************/
+/*GOOSE 报文发到哪里  正常是原始以太网的网口
+测试的时候可以放内存里面  或者 veth 对的一头  另一头抓包
+订阅也是同样的原始套接字  收的时候阻塞  放在单独的线程里面     */
+/***************************************/
+
+/*整个以太网帧  目的地址开始  不带 FCS     */
+pub trait GooseSink: Send {
+    fn send(&mut self, frame: &[u8]) -> crate::Result<()>;
+}
+
+/*发出去的帧都攒在这里  clone 出来的共用一份     */
+#[derive(Default, Clone, Debug)]
+pub struct MemSink {
+    pub frame_vec: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl MemSink {
+    pub fn new() -> MemSink {
+        MemSink::default()
+    }
+    pub fn take_frames(&self) -> Vec<Vec<u8>> {
+        match self.frame_vec.lock() {
+            Ok(mut frame_vec) => std::mem::take(&mut *frame_vec),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl GooseSink for MemSink {
+    fn send(&mut self, frame: &[u8]) -> crate::Result<()> {
+        match self.frame_vec.lock() {
+            Ok(mut frame_vec) => frame_vec.push(frame.to_vec()),
+            Err(_) => bail!("mem sink poisoned"),
+        }
+        Ok(())
+    }
+}
+
+/*linux/if_packet.h 的 packet_mreq  老的 libc 里面没有     */
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct PacketMreq {
+    mr_ifindex: i32,
+    mr_type: u16,
+    mr_alen: u16,
+    mr_address: [u8; 8],
+}
+#[cfg(target_os = "linux")]
+const PACKET_ADD_MEMBERSHIP: i32 = 1;
+#[cfg(target_os = "linux")]
+const PACKET_MR_ALLMULTI: i32 = 2;
+
+/*AF_PACKET 原始套接字  绑在一个网口上  只收 ethertype 的帧
+ethertype 给 0 的是只发不收  不然没人读的帧会一直堆在套接字里面
+发的时候帧自己带以太网头     */
+#[derive(Debug)]
+pub struct RawSocket {
+    fd: i32,
+    ifindex: i32,
+    pub ifname: String,
+    /*网口自己的 MAC  发的时候做源地址     */
+    pub mac: [u8; 6],
+}
+
+#[cfg(target_os = "linux")]
+impl RawSocket {
+    pub fn open(ifname: &str, ethertype: u16) -> crate::Result<RawSocket> {
+        let mac = match mac_address::mac_address_by_name(ifname) {
+            Ok(Some(mac)) => mac.bytes(),
+            _ => bail!(format!("raw socket {} no mac address", ifname)),
+        };
+        let c_name = std::ffi::CString::new(ifname)?;
+        let ifindex = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+        if ifindex == 0 {
+            bail!(format!("raw socket {} no such interface", ifname));
+        }
+        let protocol = ethertype.to_be();
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol as i32) };
+        if fd < 0 {
+            bail!(format!(
+                "raw socket {} open failed {}",
+                ifname,
+                std::io::Error::last_os_error()
+            ));
+        }
+        let socket = RawSocket {
+            fd,
+            ifindex: ifindex as i32,
+            ifname: ifname.to_string(),
+            mac,
+        };
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = protocol;
+        addr.sll_ifindex = ifindex as i32;
+        let ret = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_ll>() as u32,
+            )
+        };
+        if ret < 0 {
+            bail!(format!(
+                "raw socket {} bind failed {}",
+                ifname,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(socket)
+    }
+
+    /*GOOSE 的目的地址是组播  网口要收所有组播的帧  不然网卡直接丢了     */
+    pub fn join_all_multicast(&self) -> crate::Result<()> {
+        let mreq = PacketMreq {
+            mr_ifindex: self.ifindex,
+            mr_type: PACKET_MR_ALLMULTI as u16,
+            mr_alen: 0,
+            mr_address: [0; 8],
+        };
+        let ret = unsafe {
+            libc::setsockopt(
+                self.fd,
+                libc::SOL_PACKET,
+                PACKET_ADD_MEMBERSHIP,
+                &mreq as *const PacketMreq as *const libc::c_void,
+                std::mem::size_of::<PacketMreq>() as u32,
+            )
+        };
+        if ret < 0 {
+            bail!(format!(
+                "raw socket {} join multicast failed {}",
+                self.ifname,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    /*收一帧  超时没收到返回 None  收的线程靠超时去看要不要退出     */
+    pub fn recv(&self, buf: &mut [u8], timeout: Duration) -> crate::Result<Option<usize>> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as i32) };
+        if ret == 0 {
+            return Ok(None);
+        }
+        let ret = if ret > 0 {
+            unsafe {
+                libc::recv(
+                    self.fd,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    libc::MSG_DONTWAIT,
+                )
+            }
+        } else {
+            ret as isize
+        };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            if matches!(
+                err.kind(),
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock
+            ) {
+                return Ok(None);
+            }
+            bail!(format!("raw socket {} recv failed {}", self.ifname, err));
+        }
+        Ok(Some(ret as usize))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl RawSocket {
+    pub fn open(ifname: &str, _ethertype: u16) -> crate::Result<RawSocket> {
+        bail!(format!("raw socket {} only on linux", ifname))
+    }
+    pub fn join_all_multicast(&self) -> crate::Result<()> {
+        bail!("raw socket only on linux")
+    }
+    pub fn recv(&self, _buf: &mut [u8], _timeout: Duration) -> crate::Result<Option<usize>> {
+        bail!("raw socket only on linux")
+    }
+}
+
+impl GooseSink for RawSocket {
+    #[cfg(target_os = "linux")]
+    fn send(&mut self, frame: &[u8]) -> crate::Result<()> {
+        let ret = unsafe {
+            libc::send(
+                self.fd,
+                frame.as_ptr() as *const libc::c_void,
+                frame.len(),
+                libc::MSG_DONTWAIT,
+            )
+        };
+        if ret < 0 {
+            bail!(format!(
+                "raw socket {} send failed {}",
+                self.ifname,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    fn send(&mut self, _frame: &[u8]) -> crate::Result<()> {
+        bail!("raw socket only on linux")
+    }
+}
+
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
@@ -0,0 +1,740 @@
+use super::sink::RawSocket;
+use super::{decode_frame, mms_ref};
+use crate::cfg::scl2::{self, SclExtref, SclInfo};
+use crate::cms61850::model::{DataNode, Model, ObjRef, Value};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+/***************************************/
+/*GOOSE 订阅  scl 里面 LN 的 Inputs/ExtRef 说了要哪个 IED 的哪个数据
+发布方的 GoCB 和数据集从同一个 scl 文件里面再解析一遍那个 IED 拿
+收到的帧 GoID DatSet ConfRev 都对上才用  值写到 intAddr 指的内部地址
+超过 TimeAllowedToLive 没收到  内部地址的 q 置 invalid 和 oldData  再收到清掉     */
+/***************************************/
+
+/*q 的前两位是 validity  01 是 invalid  oldData 是第 7 位     */
+const Q_VALIDITY_MASK: u16 = 0xc000;
+const Q_INVALID: u16 = 0x4000;
+const Q_OLD_DATA: u16 = 0x0100;
+
+/*一帧最大的长度  带 802.1Q 的以太网帧     */
+const MAX_FRAME_LEN: usize = 1522;
+/*收的线程阻塞多久看一下汇总中心还在不在     */
+const RECV_TIMEOUT_MS: u64 = 500;
+
+/*一个输入  allData 里面按 pick 一级一级往下取  写到 target     */
+#[derive(Debug)]
+struct Input {
+    /*第一个是数据集成员的序号  后面是结构体 数组里面的序号     */
+    pick: Vec<usize>,
+    /*fc 一定有  和数据集成员的一样     */
+    target: ObjRef,
+}
+
+/*订阅的一个发布方控制块     */
+#[derive(Debug)]
+struct Subscription {
+    appid: u16,
+    goid: String,
+    /*MMS 的写法     */
+    datset: String,
+    confrev: u32,
+    entries: usize,
+    input_vec: Vec<Input>,
+    /*输入所在 DO 的 q  断了的时候置位     */
+    quality_vec: Vec<ObjRef>,
+    /*上次用过的 StNum  一样的就是重发  不用再写     */
+    stnum: Option<u32>,
+    /*TimeAllowedToLive 到的时间  断了是 None     */
+    deadline: Option<Instant>,
+    stale: bool,
+    /*最近一次不能用的原因  变了才打印     */
+    fault: Option<String>,
+}
+
+impl Subscription {
+    fn set_fault(&mut self, gocbref: &str, fault: Option<String>) {
+        if self.fault != fault {
+            match fault {
+                Some(ref fault) => println!("goose subscribe {} {}", gocbref, fault),
+                None => println!("goose subscribe {} ok", gocbref),
+            }
+            self.fault = fault;
+        }
+    }
+}
+
+pub struct GooseSubscriber {
+    /*key 是发布方 GoCB 的 MMS 引用  报文里面的 gocbRef     */
+    sub_map: HashMap<String, Subscription>,
+}
+
+/*ExtRef 里面提到的发布方 IED  每个从 scl 文件里面再解析一遍建个模型
+解析不了的打印一下跳过  那些输入就一直是断的     */
+pub async fn load_publishers(scl_filename: &str, info: &SclInfo) -> Vec<Model> {
+    let mut iedname_vec: Vec<&str> = Vec::new();
+    for extref in extrefs(info) {
+        if is_goose(extref) && !iedname_vec.contains(&extref.iedname.as_str()) {
+            iedname_vec.push(&extref.iedname);
+        }
+    }
+    let mut model_vec = Vec::new();
+    for iedname in iedname_vec {
+        /*发布方的访问点  优先有 GSE 的     */
+        let cap_vec: Vec<_> = info
+            .subnet_vec
+            .iter()
+            .flat_map(|subnet| subnet.cap_vec.iter())
+            .filter(|cap| cap.iedname == iedname)
+            .collect();
+        let apname = match cap_vec
+            .iter()
+            .find(|cap| !cap.gse_vec.is_empty())
+            .or(cap_vec.first())
+        {
+            Some(cap) => cap.apname.clone(),
+            None => {
+                println!("goose publisher {} not in communication", iedname);
+                continue;
+            }
+        };
+        let model = match scl2::scl_parse(scl_filename, iedname, &apname).await {
+            Ok(pub_info) => Model::from_scl(&pub_info),
+            Err(err) => Err(err),
+        };
+        match model {
+            Ok(model) => model_vec.push(model),
+            Err(err) => println!("goose publisher {} load failed {:?}", iedname, err),
+        }
+    }
+    model_vec
+}
+
+fn extrefs(info: &SclInfo) -> impl Iterator<Item = &SclExtref> + '_ {
+    extref_owners(info).map(|(_, _, extref)| extref)
+}
+
+/*(LD 名字, LN 名字, ExtRef)  只看第一个 server     */
+fn extref_owners(info: &SclInfo) -> impl Iterator<Item = (&str, &str, &SclExtref)> + '_ {
+    info.server_vec
+        .iter()
+        .take(1)
+        .flat_map(|server| server.ld_vec.iter())
+        .flat_map(|ld| {
+            ld.ln_vec.iter().flat_map(move |ln| {
+                ln.extref_vec
+                    .iter()
+                    .map(move |extref| (ld.domname.as_str(), ln.varname.as_str(), extref))
+            })
+        })
+}
+
+/*Ed1 没有 serviceType  当 GOOSE     */
+fn is_goose(extref: &SclExtref) -> bool {
+    !extref.iedname.is_empty()
+        && !extref.doname.is_empty()
+        && (extref.servicetype.is_empty() || extref.servicetype == "GOOSE")
+}
+
+/*intAddr 找内部的节点  可以是 LD/LN.DO.DA  LD 可以不带 IED 名字
+也可以是 LN.DO.DA 在本 LD 里面  或者 DO.DA 在 ExtRef 所在的 LN 里面     */
+fn resolve_intaddr(model: &Model, ld: &str, ln: &str, intaddr: &str) -> Option<ObjRef> {
+    let mut candidate_vec = Vec::new();
+    if intaddr.contains('/') {
+        candidate_vec.push(intaddr.to_string());
+        candidate_vec.push(format!("{}{}", model.ied_name, intaddr));
+    } else {
+        candidate_vec.push(format!("{}/{}", ld, intaddr));
+        candidate_vec.push(format!("{}/{}.{}", ld, ln, intaddr));
+    }
+    candidate_vec
+        .iter()
+        .filter_map(|reference| ObjRef::parse(reference).ok())
+        .find(|objref| !objref.path.is_empty() && model.get_node(objref).is_some())
+}
+
+/*成员节点下面按名字找序号  只数这个 fc 下面的子节点  和报文里面的顺序一样     */
+fn pick_path(node: &DataNode, sub_path: &[String], fc: &str) -> Option<Vec<usize>> {
+    let mut node = node;
+    let mut pick = Vec::with_capacity(sub_path.len());
+    for name in sub_path.iter() {
+        let (index, child) = node
+            .child_vec
+            .iter()
+            .filter(|child| child.has_fc(fc))
+            .enumerate()
+            .find(|(_, child)| child.name == *name)?;
+        pick.push(index);
+        node = child;
+    }
+    Some(pick)
+}
+
+/*target 所在 DO 的 q  target 自己是 DO 的话就是下面的 q     */
+fn quality_ref(model: &Model, target: &ObjRef) -> Option<ObjRef> {
+    let mut path = target.path.clone();
+    while !path.is_empty() {
+        let objref = ObjRef {
+            path: path.iter().cloned().chain(Some("q".to_string())).collect(),
+            fc: None,
+            ..target.clone()
+        };
+        if let Some(node) = model
+            .get_node(&objref)
+            .filter(|node| matches!(node.value, Value::Quality(_)))
+        {
+            return Some(ObjRef {
+                fc: Some(node.fc.clone()),
+                ..objref
+            });
+        }
+        path.pop();
+    }
+    None
+}
+
+/*报文里面整数只有有符号无符号  位串不知道是什么  按模型里面的类型转     */
+fn fit_value(val: Value, model_val: &Value) -> Option<Value> {
+    let int = match val {
+        Value::Int64(v) => Some(v as i128),
+        Value::Int64u(v) => Some(v as i128),
+        _ => None,
+    };
+    if let Some(int) = int {
+        return match model_val {
+            Value::Int8(_) => i8::try_from(int).ok().map(Value::Int8),
+            Value::Enum(_) => i8::try_from(int).ok().map(Value::Enum),
+            Value::Int16(_) => i16::try_from(int).ok().map(Value::Int16),
+            Value::Int32(_) => i32::try_from(int).ok().map(Value::Int32),
+            Value::Int64(_) => i64::try_from(int).ok().map(Value::Int64),
+            Value::Int8u(_) => u8::try_from(int).ok().map(Value::Int8u),
+            Value::Int16u(_) => u16::try_from(int).ok().map(Value::Int16u),
+            Value::Int32u(_) => u32::try_from(int).ok().map(Value::Int32u),
+            Value::Int64u(_) => u64::try_from(int).ok().map(Value::Int64u),
+            _ => None,
+        };
+    }
+    match (val, model_val) {
+        (Value::BitString(bits, 13), Value::Quality(_)) if bits.len() == 2 => {
+            Some(Value::Quality(u16::from_be_bytes([bits[0], bits[1]])))
+        }
+        (Value::BitString(bits, 2), Value::Dbpos(_)) => Some(Value::Dbpos(bits[0] >> 6)),
+        (Value::BitString(bits, 2), Value::Tcmd(_)) => Some(Value::Tcmd(bits[0] >> 6)),
+        (Value::BitString(bits, 2), Value::Check(_)) => Some(Value::Check(bits[0] >> 6)),
+        (Value::Float64(v), Value::Float32(_)) => Some(Value::Float32(v as f32)),
+        (Value::Float32(v), Value::Float64(_)) => Some(Value::Float64(v as f64)),
+        (val, model_val) => val.conform_to(model_val),
+    }
+}
+
+/*收到的值写到节点里面  结构体 数组和 fc 下面的子节点一个一个对     */
+fn write_node(node: &mut DataNode, val: Value, fc: &str) -> bool {
+    if node.is_leaf() {
+        return match fit_value(val, &node.value) {
+            Some(val) => {
+                node.value = val;
+                true
+            }
+            None => false,
+        };
+    }
+    let vals = match val {
+        Value::Struct(vals) | Value::Array(vals) => vals,
+        _ => return false,
+    };
+    let child_vec: Vec<&mut DataNode> = node
+        .child_vec
+        .iter_mut()
+        .filter(|child| child.has_fc(fc))
+        .collect();
+    child_vec.len() == vals.len()
+        && child_vec
+            .into_iter()
+            .zip(vals)
+            .all(|(child, val)| write_node(child, val, fc))
+}
+
+/*先在副本上改  都改成了才换上去  记变化     */
+fn update_node(model: &mut Model, objref: &ObjRef, f: impl FnOnce(&mut DataNode) -> bool) -> bool {
+    let node = match model.get_node_mut(objref) {
+        Some(node) => node,
+        None => return false,
+    };
+    let old = node.clone();
+    let mut new = old.clone();
+    if !f(&mut new) {
+        return false;
+    }
+    *node = new;
+    model.note_change(objref, &old);
+    true
+}
+
+/*allData 里面按 pick 取     */
+fn pick_value(alldata: &[Value], pick: &[usize]) -> Option<Value> {
+    let (first, rest) = pick.split_first()?;
+    let mut val = alldata.get(*first)?;
+    for index in rest.iter() {
+        val = match val {
+            Value::Struct(vals) | Value::Array(vals) => vals.get(*index)?,
+            _ => return None,
+        };
+    }
+    Some(val.clone())
+}
+
+impl GooseSubscriber {
+    /*按本 IED 的 ExtRef 建订阅  对不上的打印一下跳过     */
+    pub fn new(model: &Model, info: &SclInfo, pub_model_vec: &[Model]) -> GooseSubscriber {
+        let mut subscriber = GooseSubscriber {
+            sub_map: HashMap::new(),
+        };
+        for (ld, ln, extref) in extref_owners(info) {
+            if !is_goose(extref) {
+                continue;
+            }
+            if let Err(err) = subscriber.add_extref(model, ld, ln, extref, pub_model_vec) {
+                println!(
+                    "extref {}/{} {}{}{}.{} skipped {}",
+                    ld, ln, extref.iedname, extref.ldinst, extref.lnclass, extref.doname, err
+                );
+            }
+        }
+        for (gocbref, sub) in subscriber.sub_map.iter() {
+            println!(
+                "goose subscribe {} appid {:04x} inputs {}",
+                gocbref,
+                sub.appid,
+                sub.input_vec.len()
+            );
+        }
+        subscriber
+    }
+
+    fn add_extref(
+        &mut self,
+        model: &Model,
+        ld: &str,
+        ln: &str,
+        extref: &SclExtref,
+        pub_model_vec: &[Model],
+    ) -> Result<(), String> {
+        let target = resolve_intaddr(model, ld, ln, &extref.intaddr)
+            .ok_or_else(|| format!("intaddr {:?} not found", extref.intaddr))?;
+        let pub_model = pub_model_vec
+            .iter()
+            .find(|pub_model| pub_model.ied_name == extref.iedname)
+            .ok_or_else(|| format!("publisher {} not loaded", extref.iedname))?;
+        let src = ObjRef {
+            ld: format!("{}{}", extref.iedname, extref.ldinst),
+            ln: format!("{}{}{}", extref.prefix, extref.lnclass, extref.lninst),
+            path: extref
+                .doname
+                .split('.')
+                .chain(extref.daname.split('.').filter(|name| !name.is_empty()))
+                .map(|name| name.to_string())
+                .collect(),
+            fc: None,
+        };
+        /*有 srcCBName 的只找那一个  Ed1 没有的话找数据集里面有这个数据的     */
+        let cb_ln = if extref.srccbname.is_empty() {
+            None
+        } else {
+            let srcldinst = if extref.srcldinst.is_empty() {
+                &extref.ldinst
+            } else {
+                &extref.srcldinst
+            };
+            let srclnclass = if extref.srclnclass.is_empty() {
+                "LLN0"
+            } else {
+                &extref.srclnclass
+            };
+            Some(format!(
+                "{}{}/{}{}{}",
+                extref.iedname, srcldinst, extref.srcprefix, srclnclass, extref.srclninst
+            ))
+        };
+        for pub_ld in pub_model.ld_vec.iter() {
+            for pub_ln in pub_ld.ln_vec.iter() {
+                let ln_ref = format!("{}/{}", pub_ld.name, pub_ln.name);
+                if cb_ln.as_ref().is_some_and(|cb_ln| *cb_ln != ln_ref) {
+                    continue;
+                }
+                for cb in pub_ln.gocb_vec.iter() {
+                    if !extref.srccbname.is_empty() && cb.name != extref.srccbname {
+                        continue;
+                    }
+                    let dataset = match pub_model.get_dataset(&cb.datset) {
+                        Some(dataset) => dataset,
+                        None => continue,
+                    };
+                    let input_vec = inputs(pub_model, &dataset.member_vec, &src, &target);
+                    if input_vec.is_empty() {
+                        continue;
+                    }
+                    let gocbref = mms_ref(&format!("{}.{}", ln_ref, cb.name), Some("GO"));
+                    let sub = self.sub_map.entry(gocbref).or_insert_with(|| Subscription {
+                        appid: cb.dstaddress.appid,
+                        goid: cb.goid.clone(),
+                        datset: mms_ref(&cb.datset, None),
+                        confrev: cb.confrev,
+                        entries: dataset.member_vec.len(),
+                        input_vec: Vec::new(),
+                        quality_vec: Vec::new(),
+                        stnum: None,
+                        deadline: None,
+                        stale: false,
+                        fault: None,
+                    });
+                    for input in input_vec {
+                        if model.get_node(&input.target).is_none() {
+                            return Err(format!("intaddr {} has no fc", input.target));
+                        }
+                        if let Some(q) = quality_ref(model, &input.target) {
+                            if !sub.quality_vec.contains(&q) {
+                                sub.quality_vec.push(q);
+                            }
+                        }
+                        sub.input_vec.push(input);
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        Err(format!("no gocb dataset has {}", src))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sub_map.is_empty()
+    }
+
+    /*启动的时候还没收到过  都当断了     */
+    pub fn start(&mut self, model: &mut Model) {
+        for sub in self.sub_map.values_mut() {
+            set_stale(model, sub, true);
+        }
+    }
+
+    /*收到一帧  不是订阅的忽略  写了值返回 true     */
+    pub fn handle_frame(&mut self, model: &mut Model, frame: &[u8], now: Instant) -> bool {
+        let (appid, pdu) = match decode_frame(frame) {
+            Ok(decoded) => decoded,
+            Err(_) => return false,
+        };
+        let sub = match self.sub_map.get_mut(&pdu.gocbref) {
+            Some(sub) => sub,
+            None => return false,
+        };
+        let fault = if appid != sub.appid {
+            Some(format!("appid {:04x} expected {:04x}", appid, sub.appid))
+        } else if pdu.goid != sub.goid {
+            Some(format!("goid {} expected {}", pdu.goid, sub.goid))
+        } else if pdu.datset != sub.datset {
+            Some(format!("datset {} expected {}", pdu.datset, sub.datset))
+        } else if pdu.confrev != sub.confrev {
+            Some(format!("confrev {} expected {}", pdu.confrev, sub.confrev))
+        } else if pdu.ndscom {
+            Some("ndscom set".to_string())
+        } else if pdu.simulation {
+            Some("simulation set".to_string())
+        } else if pdu.alldata.len() != sub.entries {
+            Some(format!(
+                "entries {} expected {}",
+                pdu.alldata.len(),
+                sub.entries
+            ))
+        } else {
+            None
+        };
+        let ok = fault.is_none();
+        sub.set_fault(&pdu.gocbref, fault);
+        /*对不上的不刷新 TimeAllowedToLive  到时间就按断了处理     */
+        if !ok {
+            return false;
+        }
+        sub.deadline = Some(now + Duration::from_millis(pdu.timeallowedtolive as u64));
+        let recovered = sub.stale;
+        if recovered {
+            println!("goose subscribe {} recovered", pdu.gocbref);
+            set_stale(model, sub, false);
+        }
+        if !recovered && sub.stnum == Some(pdu.stnum) {
+            return false;
+        }
+        sub.stnum = Some(pdu.stnum);
+        for input in sub.input_vec.iter() {
+            let fc = input.target.fc.clone().unwrap_or_default();
+            let written = pick_value(&pdu.alldata, &input.pick).is_some_and(|val| {
+                update_node(model, &input.target, |node| write_node(node, val, &fc))
+            });
+            if !written {
+                println!(
+                    "goose subscribe {} value for {} not fit",
+                    pdu.gocbref, input.target
+                );
+            }
+        }
+        true
+    }
+
+    /*最早的 TimeAllowedToLive  断了的不等     */
+    pub fn next_due(&self) -> Option<Instant> {
+        self.sub_map.values().filter_map(|sub| sub.deadline).min()
+    }
+
+    /*TimeAllowedToLive 到了还没收到下一帧  q 置位     */
+    pub fn expire(&mut self, model: &mut Model, now: Instant) {
+        for (gocbref, sub) in self.sub_map.iter_mut() {
+            if sub.deadline.is_none_or(|deadline| deadline > now) {
+                continue;
+            }
+            println!("goose subscribe {} time allowed to live expired", gocbref);
+            sub.deadline = None;
+            set_stale(model, sub, true);
+        }
+    }
+}
+
+/*断了 q 置 invalid 和 oldData  恢复了 validity 回到 good 清 oldData
+之后的报文里面带了 q 的会再盖上去     */
+fn set_stale(model: &mut Model, sub: &mut Subscription, stale: bool) {
+    sub.stale = stale;
+    if stale {
+        sub.stnum = None;
+    }
+    for q in sub.quality_vec.iter() {
+        update_node(model, q, |node| match node.value {
+            Value::Quality(ref mut q) => {
+                *q &= !(Q_VALIDITY_MASK | Q_OLD_DATA);
+                if stale {
+                    *q |= Q_INVALID | Q_OLD_DATA;
+                }
+                true
+            }
+            _ => false,
+        });
+    }
+}
+
+/*数据集成员和 ExtRef 的数据对上  成员比 ExtRef 大的取一部分  小的写到 intAddr 下面     */
+fn inputs(pub_model: &Model, member_vec: &[ObjRef], src: &ObjRef, target: &ObjRef) -> Vec<Input> {
+    let mut input_vec = Vec::new();
+    for (index, member) in member_vec.iter().enumerate() {
+        if member.ld != src.ld || member.ln != src.ln {
+            continue;
+        }
+        let fc = member.fc.clone().unwrap_or_default();
+        if src.path.starts_with(&member.path) {
+            let node = match pub_model.get_node(member) {
+                Some(node) => node,
+                None => continue,
+            };
+            if let Some(pick) = pick_path(node, &src.path[member.path.len()..], &fc) {
+                input_vec.push(Input {
+                    pick: Some(index).into_iter().chain(pick).collect(),
+                    target: ObjRef {
+                        fc: Some(fc),
+                        ..target.clone()
+                    },
+                });
+                /*一个 ExtRef 只用第一个对上的成员     */
+                break;
+            }
+        } else if member.path.starts_with(&src.path) {
+            let mut target = target.clone();
+            target
+                .path
+                .extend_from_slice(&member.path[src.path.len()..]);
+            target.fc = Some(fc);
+            input_vec.push(Input {
+                pick: vec![index],
+                target,
+            });
+        }
+    }
+    input_vec
+}
+
+/*收的线程  阻塞收帧  丢给汇总中心  汇总中心没了就退出
+汇总中心忙不过来的时候丢帧  GOOSE 反正会重发     */
+pub fn spawn_receiver(socket: RawSocket, frame_tx: mpsc::Sender<Vec<u8>>) {
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; MAX_FRAME_LEN];
+        let timeout = Duration::from_millis(RECV_TIMEOUT_MS);
+        while !frame_tx.is_closed() {
+            match socket.recv(&mut buf, timeout) {
+                Ok(Some(len)) => {
+                    let _ = frame_tx.try_send(buf[..len].to_vec());
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    println!("goose receiver stopped {:?}", err);
+                    return;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cms61850::goose::publisher::GoosePublisher;
+    use crate::cms61850::goose::sink::MemSink;
+    use crate::cms61850::goose::{encode_frame, GoosePdu};
+
+    const GOCB: &str = "E1Q1SB1C1/LLN0.ItlPositions";
+    const SRC: [u8; 6] = [2, 0, 0, 0, 0, 1];
+
+    fn extref(lninst: &str, daname: &str, srccbname: &str, intaddr: &str) -> SclExtref {
+        SclExtref {
+            intaddr: intaddr.to_string(),
+            iedname: "E1Q1SB1".to_string(),
+            ldinst: "C1".to_string(),
+            lnclass: "CSWI".to_string(),
+            lninst: lninst.to_string(),
+            doname: "Pos".to_string(),
+            daname: daname.to_string(),
+            srccbname: srccbname.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn value(model: &Model, reference: &str) -> Value {
+        model.find(reference).unwrap().value.clone()
+    }
+
+    fn set_value(model: &mut Model, reference: &str, val: Value) {
+        let objref = ObjRef::parse(reference).unwrap();
+        model.get_node_mut(&objref).unwrap().value = val;
+    }
+
+    /*发布方和订阅方是同一个 scl 建的两个模型
+    发布方 CSWI1.Pos 整个 DO 给订阅方 CSWI2.Pos  CSWI2.Pos.stVal 给 CSWI1.Pos.stVal (Ed1 没有 srcCBName)     */
+    async fn setup() -> (Model, GooseSubscriber, Model) {
+        let mut info = crate::cfg::scl2::scl_parse("sisco_sample.cid", "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let pub_model = Model::from_scl(&info).unwrap();
+        let ln_vec = &mut info.server_vec[0].ld_vec[0].ln_vec;
+        let cswi2 = ln_vec.iter_mut().find(|ln| ln.varname == "CSWI2").unwrap();
+        cswi2
+            .extref_vec
+            .push(extref("1", "", "ItlPositions", "Pos"));
+        let cswi1 = ln_vec.iter_mut().find(|ln| ln.varname == "CSWI1").unwrap();
+        cswi1
+            .extref_vec
+            .push(extref("2", "stVal", "", "C1/CSWI1.Pos.stVal"));
+        cswi1
+            .extref_vec
+            .push(extref("2", "stVal", "", "C1/CSWI9.Pos.stVal"));
+        let mut model = Model::from_scl(&info).unwrap();
+        let mut subscriber = GooseSubscriber::new(&model, &info, std::slice::from_ref(&pub_model));
+        subscriber.start(&mut model);
+        model.take_changes();
+        (model, subscriber, pub_model)
+    }
+
+    /*发布方按现在的值发一帧     */
+    fn publish(pub_model: &mut Model) -> Vec<u8> {
+        let sink = MemSink::new();
+        let mut publisher = GoosePublisher::new(Box::new(sink.clone()), SRC);
+        pub_model.get_gocb_mut(GOCB).unwrap().goena = true;
+        publisher.scan(pub_model, &[], Instant::now());
+        sink.take_frames().remove(0)
+    }
+
+    /*改一下 PDU 再编回去     */
+    fn tamper(pub_model: &Model, frame: &[u8], f: impl FnOnce(&mut GoosePdu)) -> Vec<u8> {
+        let (_, mut pdu) = decode_frame(frame).unwrap();
+        f(&mut pdu);
+        let cb = pub_model.get_gocb(GOCB).unwrap();
+        encode_frame(&cb.dstaddress, &SRC, &pdu).unwrap()
+    }
+
+    #[tokio::test]
+    async fn values_written_to_intaddr() {
+        let (mut model, mut subscriber, mut pub_model) = setup().await;
+        assert_eq!(subscriber.sub_map.len(), 1);
+        /*没找到 intAddr 的那个跳过     */
+        assert_eq!(
+            subscriber.sub_map.values().next().unwrap().input_vec.len(),
+            2
+        );
+        set_value(&mut pub_model, "E1Q1SB1C1/CSWI1.Pos.stVal", Value::Dbpos(2));
+        set_value(
+            &mut pub_model,
+            "E1Q1SB1C1/CSWI1.Pos.q",
+            Value::Quality(0x0008),
+        );
+        set_value(&mut pub_model, "E1Q1SB1C1/CSWI2.Pos.stVal", Value::Dbpos(1));
+        let frame = publish(&mut pub_model);
+        let now = Instant::now();
+        assert!(subscriber.handle_frame(&mut model, &frame, now));
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI2.Pos.stVal"), Value::Dbpos(2));
+        assert_eq!(
+            value(&model, "E1Q1SB1C1/CSWI2.Pos.q"),
+            Value::Quality(0x0008)
+        );
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI1.Pos.stVal"), Value::Dbpos(1));
+        assert!(model
+            .take_changes()
+            .iter()
+            .any(|change| change.objref.ln == "CSWI2" && change.objref.path == ["Pos", "stVal"]));
+        /*重发的 StNum 一样  不再写     */
+        assert!(!subscriber.handle_frame(&mut model, &frame, now));
+        assert_eq!(subscriber.next_due(), Some(now + Duration::from_millis(20)));
+    }
+
+    #[tokio::test]
+    async fn mismatched_frames_rejected() {
+        let (mut model, mut subscriber, mut pub_model) = setup().await;
+        set_value(&mut pub_model, "E1Q1SB1C1/CSWI1.Pos.stVal", Value::Dbpos(2));
+        let frame = publish(&mut pub_model);
+        let bad_vec = [
+            tamper(&pub_model, &frame, |pdu| pdu.goid = "Other".to_string()),
+            tamper(&pub_model, &frame, |pdu| {
+                pdu.datset = "E1Q1SB1C1/LLN0$Positions2".to_string()
+            }),
+            tamper(&pub_model, &frame, |pdu| pdu.confrev += 1),
+        ];
+        let now = Instant::now();
+        for bad in bad_vec.iter() {
+            assert!(!subscriber.handle_frame(&mut model, bad, now));
+            assert_eq!(subscriber.next_due(), None);
+            assert_eq!(value(&model, "E1Q1SB1C1/CSWI2.Pos.stVal"), Value::Dbpos(0));
+        }
+        assert!(subscriber.handle_frame(&mut model, &frame, now));
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI2.Pos.stVal"), Value::Dbpos(2));
+    }
+
+    #[tokio::test]
+    async fn expire_flags_and_good_frame_clears() {
+        let (mut model, mut subscriber, mut pub_model) = setup().await;
+        let stale = Value::Quality(Q_INVALID | Q_OLD_DATA);
+        /*启动的时候还没收到过     */
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI1.Pos.q"), stale);
+        let frame = publish(&mut pub_model);
+        let now = Instant::now();
+        assert!(subscriber.handle_frame(&mut model, &frame, now));
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI1.Pos.q"), Value::Quality(0));
+
+        subscriber.expire(&mut model, now + Duration::from_millis(19));
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI1.Pos.q"), Value::Quality(0));
+        subscriber.expire(&mut model, now + Duration::from_millis(20));
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI1.Pos.q"), stale);
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI2.Pos.q"), stale);
+        assert_eq!(subscriber.next_due(), None);
+
+        /*同一个 StNum 断了以后再收到也要写     */
+        let later = now + Duration::from_millis(50);
+        assert!(subscriber.handle_frame(&mut model, &frame, later));
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI1.Pos.q"), Value::Quality(0));
+        assert_eq!(value(&model, "E1Q1SB1C1/CSWI2.Pos.q"), Value::Quality(0));
+        assert_eq!(
+            subscriber.next_due(),
+            Some(later + Duration::from_millis(20))
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use crate::cms61850::model::cb::Lcb;
+use crate::cms61850::model::change::DataChange;
+use crate::cms61850::model::{Model, ObjRef};
+use crate::cms61850::report::journal::{journal_path, Journal};
+use crate::cms61850::report::{make_entry, RcbRun, RptEntry};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use tokio::time::Instant;
+/***************************************/
+/*日志的运行时  日志控制块按触发条件往日志里面写条目
+一个日志可以有好几个控制块往里写  条目个数到了 logmaxentries 挤掉最老的
+变化是报告扫描的时候从模型里面取出来的  先放在这里  按 logscanratems 扫
+日志文件和缓存报告的一样  重启以后恢复     */
+/***************************************/
+
+/*日志里面的条目不跟着控制块的配置走  文件头的指纹固定     */
+const LOG_FINGERPRINT: &str = "log";
+
+/*一个日志  EntryID 只往上加     */
+#[derive(Default, Debug)]
+pub struct LogStore {
+    pub entry_vec: VecDeque<RptEntry>,
+    pub max_entries: usize,
+    pub next_id: u64,
+    /*条目估算大小的和  文件大到它的两倍就重写     */
+    pub size: usize,
+    /*没配目录就是 None     */
+    pub journal: Option<Journal>,
+}
+
+impl LogStore {
+    pub fn new(max_entries: usize) -> LogStore {
+        LogStore {
+            max_entries,
+            next_id: 1,
+            ..Default::default()
+        }
+    }
+    pub fn push(&mut self, mut entry: RptEntry) {
+        entry.entryid = self.next_id;
+        self.next_id += 1;
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(err) = journal.append(&entry) {
+                println!("log journal append failed {:?}", err);
+            }
+        }
+        self.keep(entry);
+        let file_size = self.journal.as_ref().map(|journal| journal.file_size());
+        if file_size.is_some_and(|file_size| file_size > 2 * self.size as u64 + 4096) {
+            if let Some(journal) = self.journal.as_mut() {
+                if let Err(err) = journal.rewrite(self.next_id, self.entry_vec.iter()) {
+                    println!("log journal rewrite failed {:?}", err);
+                }
+            }
+        }
+    }
+    /*重启恢复出来的  EntryID 用原来的     */
+    pub fn restore(&mut self, next_id: u64, entry_vec: Vec<RptEntry>) {
+        for entry in entry_vec {
+            self.keep(entry);
+        }
+        self.next_id = self.next_id.max(next_id);
+    }
+    fn keep(&mut self, entry: RptEntry) {
+        self.size += entry.size;
+        self.entry_vec.push_back(entry);
+        while self.entry_vec.len() > self.max_entries {
+            match self.entry_vec.pop_front() {
+                Some(dropped) => self.size -= dropped.size,
+                None => break,
+            }
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct LogEngine {
+    /*key 是日志的引用 LD/LN.LogName     */
+    pub store_map: HashMap<String, LogStore>,
+    /*key 是日志控制块的引用  使能的才有     */
+    pub run_map: HashMap<String, RcbRun>,
+    /*还没扫的变化  没有使能的控制块就不攒     */
+    change_vec: Vec<DataChange>,
+    /*来自 logmaxentries     */
+    pub max_entries: usize,
+    /*日志文件目录  已经带了 IED 名字  None 只放内存     */
+    pub log_dir: Option<PathBuf>,
+}
+
+impl LogEngine {
+    pub fn new(max_entries: usize, log_dir: Option<PathBuf>) -> LogEngine {
+        LogEngine {
+            max_entries,
+            log_dir,
+            ..Default::default()
+        }
+    }
+
+    /*启动的时候  控制块引用到的日志都建出来 有文件就恢复  LogEna 的开始记     */
+    pub fn start(&mut self, model: &Model) {
+        for ld in model.ld_vec.iter() {
+            for ln in ld.ln_vec.iter() {
+                for cb in ln.lcb_vec.iter() {
+                    if !cb.logref.is_empty() && !self.store_map.contains_key(&cb.logref) {
+                        self.open_log(&cb.logref);
+                    }
+                    if !cb.logena {
+                        continue;
+                    }
+                    let dataset = match model.get_dataset(&cb.datset) {
+                        Some(dataset) => dataset,
+                        None => continue,
+                    };
+                    let key = format!("{}/{}.{}", ld.name, ln.name, cb.name);
+                    self.start_lcb(&key, dataset.member_vec.clone(), cb);
+                }
+            }
+        }
+    }
+
+    fn open_log(&mut self, logref: &str) {
+        let mut store = LogStore::new(self.max_entries);
+        if let Some(dir) = self.log_dir.as_ref() {
+            match Journal::open(journal_path(dir, logref), LOG_FINGERPRINT, 0) {
+                Ok((journal, recovered)) => {
+                    store.restore(recovered.next_id, recovered.entry_vec);
+                    store.journal = Some(journal);
+                    println!(
+                        "log {} recovered {} entries next id {}",
+                        logref,
+                        store.entry_vec.len(),
+                        store.next_id
+                    );
+                }
+                Err(err) => println!("log {} journal open failed {:?}", logref, err),
+            }
+        }
+        self.store_map.insert(logref.to_string(), store);
+    }
+
+    pub fn log(&self, logref: &str) -> Option<&LogStore> {
+        self.store_map.get(logref)
+    }
+
+    /*使能  触发条件 完整性周期这些从这时候的控制块取     */
+    pub fn start_lcb(&mut self, key: &str, member_vec: Vec<ObjRef>, cb: &Lcb) {
+        let mut run = RcbRun::new(member_vec, cb.trgops, cb.buftm, cb.intgpd);
+        run.set_enabled(true);
+        self.run_map.insert(key.to_string(), run);
+    }
+    pub fn stop_lcb(&mut self, key: &str) {
+        self.run_map.remove(key);
+    }
+
+    pub fn queue_changes(&mut self, change_vec: &[DataChange]) {
+        if !self.run_map.is_empty() {
+            self.change_vec.extend_from_slice(change_vec);
+        }
+    }
+
+    /*攒着的变化按各个控制块的触发条件出条目  写到控制块的 LogRef 指的日志     */
+    pub fn scan(&mut self, model: &Model, now: Instant) {
+        let change_vec = std::mem::take(&mut self.change_vec);
+        let mut key_vec: Vec<String> = self.run_map.keys().cloned().collect();
+        key_vec.sort();
+        for key in key_vec {
+            let logref = match model.get_lcb(&key) {
+                Some(cb) => cb.logref.as_str(),
+                None => continue,
+            };
+            let (run, store) = match (self.run_map.get_mut(&key), self.store_map.get_mut(logref)) {
+                (Some(run), Some(store)) => (run, store),
+                _ => continue,
+            };
+            for trigger in run.collect(&change_vec, now) {
+                if let Some(entry) = make_entry(model, &run.member_vec, &trigger) {
+                    store.push(entry);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cms61850::model::value::entry_time;
+    use crate::cms61850::model::Value;
+    use crate::cms61850::service::report::EntryData;
+    use std::path::Path;
+
+    const LOGREF: &str = "E1Q1SB1C1/LLN0.C1";
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("cms61850_log_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /*EntryID 由日志分配  这里给 0     */
+    fn entry(ago_sec: i64) -> RptEntry {
+        let time = chrono::Utc::now() - chrono::Duration::seconds(ago_sec);
+        RptEntry {
+            entryid: 0,
+            timeofentry: entry_time(time),
+            member_count: 1,
+            entrydata: vec![EntryData {
+                reference: Some("E1Q1SB1C1/CSWI1.Pos.stVal".to_string()),
+                fc: Some("ST".to_string()),
+                id: 0,
+                value: Value::Dbpos(2),
+                reason: Some(0x40),
+            }],
+            size: 16,
+        }
+    }
+
+    fn reopen(dir: &Path, max_entries: usize) -> LogEngine {
+        let mut engine = LogEngine::new(max_entries, Some(dir.to_path_buf()));
+        engine.open_log(LOGREF);
+        engine
+    }
+
+    fn ids(engine: &LogEngine) -> Vec<u64> {
+        engine
+            .log(LOGREF)
+            .unwrap()
+            .entry_vec
+            .iter()
+            .map(|entry| entry.entryid)
+            .collect()
+    }
+
+    #[test]
+    fn entries_recovered_with_ids() {
+        let dir = test_dir("recover");
+        let mut engine = reopen(&dir, 10);
+        let store = engine.store_map.get_mut(LOGREF).unwrap();
+        for _ in 0..3 {
+            store.push(entry(0));
+        }
+        assert_eq!(ids(&engine), vec![1, 2, 3]);
+        drop(engine);
+
+        let mut engine = reopen(&dir, 10);
+        assert_eq!(ids(&engine), vec![1, 2, 3]);
+        assert_eq!(engine.log(LOGREF).unwrap().next_id, 4);
+        engine.store_map.get_mut(LOGREF).unwrap().push(entry(0));
+        assert_eq!(ids(&engine), vec![1, 2, 3, 4]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn max_entries_and_rewrite_keep_ids() {
+        let dir = test_dir("max");
+        let mut engine = reopen(&dir, 2);
+        let store = engine.store_map.get_mut(LOGREF).unwrap();
+        /*条目多了文件会整理  挤掉的不再恢复     */
+        for _ in 0..400 {
+            store.push(entry(0));
+        }
+        assert_eq!(ids(&engine), vec![399, 400]);
+        drop(engine);
+
+        let engine = reopen(&dir, 2);
+        assert_eq!(ids(&engine), vec![399, 400]);
+        assert_eq!(engine.log(LOGREF).unwrap().next_id, 401);
+        /*上限调大了  文件里面有的都回来  EntryID 不变     */
+        let mut engine = reopen(&dir, 10);
+        assert_eq!(engine.log(LOGREF).unwrap().next_id, 401);
+        engine.store_map.get_mut(LOGREF).unwrap().push(entry(0));
+        assert_eq!(ids(&engine).last(), Some(&401));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn old_entries_not_expired() {
+        let dir = test_dir("old");
+        let mut engine = reopen(&dir, 10);
+        let store = engine.store_map.get_mut(LOGREF).unwrap();
+        store.push(entry(10 * 86400));
+        store.push(entry(0));
+        drop(engine);
+
+        /*日志不按缓存报告的保留时间丢     */
+        let engine = reopen(&dir, 10);
+        assert_eq!(ids(&engine), vec![1, 2]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn memory_only_without_dir() {
+        let mut engine = LogEngine::new(10, None);
+        engine.open_log(LOGREF);
+        let store = engine.store_map.get_mut(LOGREF).unwrap();
+        store.push(entry(0));
+        assert!(store.journal.is_none());
+        assert_eq!(ids(&engine), vec![1]);
+    }
+
+    /*改一个叶子  和服务里面写值一样记变化     */
+    fn set_leaf(model: &mut Model, reference: &str, val: Value) {
+        let objref = ObjRef::parse(reference).unwrap();
+        let node = model.get_node_mut(&objref).unwrap();
+        let old = node.clone();
+        node.value = val;
+        model.note_change(&objref, &old);
+    }
+
+    /*成员是 Pos[ST]  从条目里面取 stVal     */
+    fn stval_of(model: &Model, member: &ObjRef, entry: &RptEntry) -> Value {
+        let index = model
+            .get_node(member)
+            .unwrap()
+            .child_vec
+            .iter()
+            .filter(|child| child.has_fc("ST"))
+            .position(|child| child.name == "stVal")
+            .unwrap();
+        match &entry.entrydata[0].value {
+            Value::Struct(vals) => vals[index].clone(),
+            other => panic!("member value {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn changes_in_one_scan_keep_their_values() {
+        let info = crate::cfg::scl2::scl_parse("sisco_sample.cid", "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let mut model = Model::from_scl(&info).unwrap();
+        let key = "E1Q1SB1C1/LLN0.Log";
+        let cb = model.get_lcb(key).unwrap().clone();
+        let member_vec = model.get_dataset(&cb.datset).unwrap().member_vec.clone();
+        let mut engine = LogEngine::new(10, None);
+        engine.open_log(&cb.logref);
+        engine.start_lcb(key, member_vec.clone(), &cb);
+
+        /*一个扫描周期里面 A -> B -> A  两条都要记  值是各自变的时候的     */
+        let stval = "E1Q1SB1C1/CSWI1.Pos.stVal[ST]";
+        let a = model.find(stval).unwrap().value.clone();
+        let b = if a == Value::Dbpos(2) {
+            Value::Dbpos(1)
+        } else {
+            Value::Dbpos(2)
+        };
+        set_leaf(&mut model, stval, b.clone());
+        set_leaf(&mut model, stval, a.clone());
+        engine.queue_changes(&model.take_changes());
+        engine.scan(&model, Instant::now());
+
+        let store = engine.log(&cb.logref).unwrap();
+        assert_eq!(store.entry_vec.len(), 2);
+        assert_eq!(stval_of(&model, &member_vec[0], &store.entry_vec[0]), b);
+        assert_eq!(stval_of(&model, &member_vec[0], &store.entry_vec[1]), a);
+    }
+}
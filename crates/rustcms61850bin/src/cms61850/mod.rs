@@ -3,4 +3,11 @@ pub mod service;
 
 
 pub mod cms_unit;
-pub mod net;
\ No newline at end of file
+pub mod codec;
+pub mod control;
+pub mod goose;
+pub mod log;
+pub mod model;
+pub mod net;
+pub mod report;
+pub mod sg;
\ No newline at end of file
@@ -0,0 +1,598 @@
+use super::cb::{Brcb, GoCb, Lcb, MsvCb, PhyComAddr, Sgcb, Urcb};
+use super::node::{DataNode, LogicalDevice, LogicalNode, Model, NodeKind};
+use super::objref::split_index;
+use super::value::{ConvertErr, Value};
+use crate::cfg::scl2::{
+    SclBda, SclCap, SclDa, SclDai, SclDatype, SclDotype, SclGse, SclInfo, SclLd, SclLn, SclLntype,
+    SclRcb, SclServiceDataset, SclSgVal, SclSmv, SCL_OBJTYPE_SDO, SX_ERR_CONVERT,
+};
+use anyhow::bail;
+use mac_address::MacAddress;
+/***************************************/
+/*从 SclInfo 构造运行时模型
+模板里面 lntype -> dotype -> datype 逐级展开
+再用 DAI 的值覆盖模板里面的初始值     */
+/***************************************/
+
+/*类型嵌套最大层数  防止模板里面类型互相引用死循环     */
+const MAX_TYPE_DEPTH: usize = 16;
+
+/*Services 里面没写 DynDataSet 的时候  在线建数据集按这个限制
+8 个数据集  每个最多 50 个成员     */
+const DEFAULT_DYN_DATASET: SclServiceDataset = SclServiceDataset {
+    enabled: true,
+    max: 8,
+    maxattributes: 50,
+    modify: false,
+};
+
+/*DA 和 BDA 展开时共用的参数     */
+struct AttrDef<'a> {
+    name: &'a str,
+    kind: NodeKind,
+    fc: &'a str,
+    btype: &'a str,
+    rtype: &'a str,
+    count: u32,
+    valkind: &'a str,
+    saddr: &'a str,
+    dchg: bool,
+    qchg: bool,
+    dupd: bool,
+    val: &'a str,
+    sgval_vec: &'a [SclSgVal],
+}
+
+impl<'a> AttrDef<'a> {
+    fn from_da(da: &'a SclDa) -> Self {
+        AttrDef {
+            name: &da.name,
+            kind: NodeKind::Da,
+            fc: &da.fc,
+            btype: &da.btype,
+            rtype: &da.rtype,
+            count: da.count,
+            valkind: &da.valkind,
+            saddr: &da.saddr,
+            dchg: da.dchg,
+            qchg: da.qchg,
+            dupd: da.dupd,
+            val: &da.val,
+            sgval_vec: &da.sgval_vec,
+        }
+    }
+    /*BDA 继承上级 DA 的 fc 和触发条件     */
+    fn from_bda(bda: &'a SclBda, parent: &AttrDef<'a>) -> Self {
+        AttrDef {
+            name: &bda.name,
+            kind: NodeKind::Bda,
+            fc: parent.fc,
+            btype: &bda.btype,
+            rtype: &bda.rtype,
+            count: bda.count,
+            valkind: &bda.valkind,
+            saddr: &bda.saddr,
+            dchg: parent.dchg,
+            qchg: parent.qchg,
+            dupd: parent.dupd,
+            val: &bda.val,
+            sgval_vec: &bda.sgval_vec,
+        }
+    }
+}
+
+struct ModelBuilder<'a> {
+    info: &'a SclInfo,
+    /*值转换失败的  不影响建模 最后一起报     */
+    convert_err_vec: Vec<ConvertErr>,
+}
+
+impl<'a> ModelBuilder<'a> {
+    fn lntype(&self, id: &str) -> crate::Result<&'a SclLntype> {
+        match self.info.lntype_vec.iter().find(|t| t.id == id) {
+            Some(t) => Ok(t),
+            None => bail!(format!("LNodeType {} not found", id)),
+        }
+    }
+    fn dotype(&self, id: &str) -> crate::Result<&'a SclDotype> {
+        match self.info.dotype_vec.iter().find(|t| t.id == id) {
+            Some(t) => Ok(t),
+            None => bail!(format!("DOType {} not found", id)),
+        }
+    }
+    fn datype(&self, id: &str) -> crate::Result<&'a SclDatype> {
+        match self.info.datype_vec.iter().find(|t| t.id == id) {
+            Some(t) => Ok(t),
+            None => bail!(format!("DAType {} not found", id)),
+        }
+    }
+
+    /*文本转成值  转不了的记下来 用类型的默认值     */
+    fn convert(&mut self, path: &str, btype: &str, rtype: &str, text: &str) -> Value {
+        let enumtype = if btype == "Enum" {
+            self.info.enumtype_vec.iter().find(|t| t.id == rtype)
+        } else {
+            None
+        };
+        match Value::from_scl_text(btype, text, enumtype) {
+            Ok(val) => val,
+            Err(e) => {
+                let err = ConvertErr {
+                    code: SX_ERR_CONVERT,
+                    path: path.to_string(),
+                    btype: btype.to_string(),
+                    text: text.to_string(),
+                    reason: e.to_string(),
+                };
+                println!("{}", err);
+                self.convert_err_vec.push(err);
+                Value::default_for(btype).unwrap_or_default()
+            }
+        }
+    }
+
+    fn convert_sg(
+        &mut self,
+        path: &str,
+        btype: &str,
+        rtype: &str,
+        sgval_vec: &[SclSgVal],
+    ) -> Vec<(u32, Value)> {
+        sgval_vec
+            .iter()
+            .map(|sgval| {
+                let sg_path = format!("{} sGroup {}", path, sgval.sgroup);
+                (
+                    sgval.sgroup,
+                    self.convert(&sg_path, btype, rtype, &sgval.val),
+                )
+            })
+            .collect()
+    }
+
+    fn build_ln(&mut self, scl_ld: &SclLd, scl_ln: &SclLn) -> crate::Result<LogicalNode> {
+        let ld_name = scl_ld.domname.as_str();
+        let lntype = self.lntype(&scl_ln.lntype)?;
+        let mut ln = LogicalNode {
+            name: scl_ln.varname.clone(),
+            desc: scl_ln.desc.clone(),
+            prefix: scl_ln.prefix.clone(),
+            lnclass: scl_ln.lnclass.clone(),
+            inst: scl_ln.inst.clone(),
+            lntype: scl_ln.lntype.clone(),
+            do_vec: Vec::with_capacity(lntype.do_vec.len()),
+            ..Default::default()
+        };
+        let ln_path = format!("{}/{}", ld_name, ln.name);
+        for scl_do in lntype.do_vec.iter() {
+            let node = self
+                .build_do(&ln_path, &scl_do.name, NodeKind::Do, &scl_do.rtype, 0)
+                .map_err(|e| anyhow::anyhow!("{} {}: {}", ln.name, scl_do.name, e))?;
+            ln.do_vec.push(node);
+        }
+        for dai in scl_ln.dai_vec.iter() {
+            self.apply_dai(&ln_path, &mut ln, dai)?;
+        }
+        self.build_cb(scl_ld, scl_ln, &mut ln);
+        Ok(ln)
+    }
+
+    /*控制块的初始状态  数据集 日志都转成对象引用     */
+    fn build_cb(&self, scl_ld: &SclLd, scl_ln: &SclLn, ln: &mut LogicalNode) {
+        let ln_ref = format!("{}/{}", scl_ld.domname, ln.name);
+        let datset_ref = |datset: &str| {
+            if datset.is_empty() {
+                String::new()
+            } else {
+                format!("{}.{}", ln_ref, datset)
+            }
+        };
+        let settings = self
+            .info
+            .server_vec
+            .first()
+            .map(|server| server.scl_services.reportsettings.clone())
+            .unwrap_or_default();
+        for rcb in expand_rcb(&scl_ln.rcb_vec) {
+            let owner = if settings.owner {
+                Some(Vec::new())
+            } else {
+                None
+            };
+            if rcb.buffered {
+                ln.brcb_vec.push(Brcb {
+                    name: rcb.name.clone(),
+                    rptid: rcb.rptid.clone(),
+                    datset: datset_ref(&rcb.datset),
+                    confrev: rcb.confrev,
+                    optflds: rcb.optflds,
+                    buftm: rcb.buftime,
+                    trgops: rcb.trgops,
+                    intgpd: rcb.intgpd,
+                    resvtms: if settings.resvtms { Some(0) } else { None },
+                    owner,
+                    ..Default::default()
+                });
+            } else {
+                ln.urcb_vec.push(Urcb {
+                    name: rcb.name.clone(),
+                    rptid: rcb.rptid.clone(),
+                    datset: datset_ref(&rcb.datset),
+                    confrev: rcb.confrev,
+                    optflds: rcb.optflds,
+                    buftm: rcb.buftime,
+                    trgops: rcb.trgops,
+                    intgpd: rcb.intgpd,
+                    owner,
+                    ..Default::default()
+                });
+            }
+        }
+        for lcb in scl_ln.lcb_vec.iter() {
+            ln.lcb_vec.push(Lcb {
+                name: lcb.name.clone(),
+                logena: lcb.logena,
+                datset: datset_ref(&lcb.datset),
+                trgops: lcb.trgops,
+                intgpd: lcb.intgpd,
+                logref: datset_ref(&lcb.logname),
+                optflds: if lcb.reasoncode { 0x80 } else { 0 },
+                buftm: 0,
+            });
+        }
+        for gcb in scl_ln.gcb_vec.iter() {
+            if !gcb.isgoose {
+                ln.gscb_vec.push(gcb.clone());
+                continue;
+            }
+            let gse = self.find_gse(&scl_ld.inst, &gcb.name);
+            ln.gocb_vec.push(GoCb {
+                name: gcb.name.clone(),
+                goena: false,
+                goid: gcb.appid.clone(),
+                datset: datset_ref(&gcb.datset),
+                confrev: gcb.confrev,
+                ndscom: gcb.datset.is_empty(),
+                dstaddress: gse
+                    .map(|gse| phy_com_addr(gse.mac, gse.vlanpri, gse.vlanid, gse.appid))
+                    .unwrap_or_default(),
+                mintime: gse.map(|gse| gse.mintime).unwrap_or_default(),
+                maxtime: gse.map(|gse| gse.maxtime).unwrap_or_default(),
+            });
+        }
+        for svcb in scl_ln.svcb_vec.iter() {
+            if !svcb.multicast {
+                ln.usvcb_vec.push(svcb.clone());
+                continue;
+            }
+            ln.msvcb_vec.push(MsvCb {
+                name: svcb.name.clone(),
+                svena: false,
+                msvid: svcb.smvid.clone(),
+                datset: datset_ref(&svcb.datset),
+                confrev: svcb.confrev,
+                smpmod: svcb.smpmod,
+                smprate: svcb.smprate.min(u16::MAX as u32) as u16,
+                optflds: svcb.optflds,
+                dstaddress: self
+                    .find_smv(&scl_ld.inst, &svcb.name)
+                    .map(|smv| phy_com_addr(smv.mac, smv.vlanpri, smv.vlanid, smv.appid))
+                    .unwrap_or_default(),
+            });
+        }
+        if scl_ln.sgcb.numofsgs > 0 {
+            ln.sgcb = Some(Sgcb {
+                numofsg: scl_ln.sgcb.numofsgs.min(u8::MAX as u32) as u8,
+                actsg: scl_ln
+                    .sgcb
+                    .actsg
+                    .clamp(1, scl_ln.sgcb.numofsgs.min(u8::MAX as u32))
+                    as u8,
+                ..Default::default()
+            });
+        }
+    }
+
+    /*本 ied 本访问点下面的 ConnectedAP  apname 为空的适配所有     */
+    fn caps(&self) -> impl Iterator<Item = &'a SclCap> + '_ {
+        let (iedname, apname) = match self.info.server_vec.first() {
+            Some(server) => (server.iedname.as_str(), server.apname.as_str()),
+            None => ("", ""),
+        };
+        self.info
+            .subnet_vec
+            .iter()
+            .flat_map(|subnet| subnet.cap_vec.iter())
+            .filter(move |cap| {
+                cap.iedname == iedname && (cap.apname.is_empty() || cap.apname == apname)
+            })
+    }
+
+    fn find_gse(&self, ldinst: &str, cbname: &str) -> Option<&'a SclGse> {
+        self.caps()
+            .flat_map(|cap| cap.gse_vec.iter())
+            .find(|gse| gse.ldinst == ldinst && gse.cbname == cbname)
+    }
+
+    fn find_smv(&self, ldinst: &str, cbname: &str) -> Option<&'a SclSmv> {
+        self.caps()
+            .flat_map(|cap| cap.smv_vec.iter())
+            .find(|smv| smv.ldinst == ldinst && smv.cbname == cbname)
+    }
+
+    fn build_do(
+        &mut self,
+        parent_path: &str,
+        name: &str,
+        kind: NodeKind,
+        rtype: &str,
+        depth: usize,
+    ) -> crate::Result<DataNode> {
+        if depth > MAX_TYPE_DEPTH {
+            bail!(format!("type {} nested too deep", rtype));
+        }
+        let dotype = self.dotype(rtype)?;
+        let path = format!("{}.{}", parent_path, name);
+        let mut node = DataNode {
+            name: name.to_string(),
+            kind,
+            cdc: dotype.cdc.clone(),
+            rtype: rtype.to_string(),
+            ..Default::default()
+        };
+        for da in dotype.da_vec.iter() {
+            let child = if da.objtype == SCL_OBJTYPE_SDO {
+                self.build_do(&path, &da.name, NodeKind::Sdo, &da.rtype, depth + 1)?
+            } else {
+                self.build_attr(&path, &AttrDef::from_da(da), depth + 1)?
+            };
+            node.child_vec.push(child);
+        }
+        Ok(node)
+    }
+
+    /*数组先展开成下标  每个元素再按类型展开     */
+    fn build_attr(
+        &mut self,
+        parent_path: &str,
+        def: &AttrDef,
+        depth: usize,
+    ) -> crate::Result<DataNode> {
+        if depth > MAX_TYPE_DEPTH {
+            bail!(format!("type {} nested too deep", def.rtype));
+        }
+        let mut node = DataNode {
+            name: def.name.to_string(),
+            kind: def.kind,
+            fc: def.fc.to_string(),
+            btype: def.btype.to_string(),
+            rtype: def.rtype.to_string(),
+            count: def.count,
+            valkind: def.valkind.to_string(),
+            saddr: def.saddr.to_string(),
+            dchg: def.dchg,
+            qchg: def.qchg,
+            dupd: def.dupd,
+            ..Default::default()
+        };
+        let path = format!("{}.{}", parent_path, def.name);
+        if def.count > 0 {
+            for ix in 0..def.count {
+                let mut elem = self.build_elem(&format!("{}({})", path, ix), def, depth)?;
+                elem.name = ix.to_string();
+                node.child_vec.push(elem);
+            }
+        } else {
+            let elem = self.build_elem(&path, def, depth)?;
+            node.value = elem.value;
+            node.sg_value_vec = elem.sg_value_vec;
+            node.child_vec = elem.child_vec;
+        }
+        Ok(node)
+    }
+
+    /*单个元素  Struct 展开 BDA  其他的就是叶子     */
+    fn build_elem(&mut self, path: &str, def: &AttrDef, depth: usize) -> crate::Result<DataNode> {
+        let mut elem = DataNode {
+            name: def.name.to_string(),
+            kind: def.kind,
+            fc: def.fc.to_string(),
+            btype: def.btype.to_string(),
+            rtype: def.rtype.to_string(),
+            valkind: def.valkind.to_string(),
+            saddr: def.saddr.to_string(),
+            dchg: def.dchg,
+            qchg: def.qchg,
+            dupd: def.dupd,
+            ..Default::default()
+        };
+        if def.btype == "Struct" {
+            let datype = self.datype(def.rtype)?;
+            for bda in datype.bda_vec.iter() {
+                let child = self.build_attr(path, &AttrDef::from_bda(bda, def), depth + 1)?;
+                elem.child_vec.push(child);
+            }
+        } else {
+            elem.value = self.convert(path, def.btype, def.rtype, def.val);
+            elem.sg_value_vec = self.convert_sg(path, def.btype, def.rtype, def.sgval_vec);
+        }
+        Ok(elem)
+    }
+
+    /*实例化的值 覆盖模板里面的值
+    valkind 解析时默认填的 Set  只有明确写了别的才覆盖     */
+    fn apply_dai(
+        &mut self,
+        ln_path: &str,
+        ln: &mut LogicalNode,
+        dai: &SclDai,
+    ) -> crate::Result<()> {
+        let path = match flattened_to_path(&dai.flattened) {
+            Some(path) => path,
+            None => bail!(format!("{} bad DAI name {}", ln.name, dai.flattened)),
+        };
+        let ln_name = ln.name.clone();
+        let node = match ln.get_path_mut(&path) {
+            Some(node) => node,
+            None => bail!(format!(
+                "{} DAI {} not found in type {}",
+                ln_name, dai.flattened, ln.lntype
+            )),
+        };
+        if !node.is_leaf() {
+            if dai.val.is_empty() && dai.sgval_vec.is_empty() {
+                return Ok(());
+            }
+            bail!(format!("{} DAI {} is not a leaf", ln_name, dai.flattened));
+        }
+        let path = format!("{}.{}", ln_path, dai.flattened.replace('$', "."));
+        if !dai.val.is_empty() {
+            node.value = self.convert(&path, &node.btype, &node.rtype, &dai.val);
+        }
+        if !dai.sgval_vec.is_empty() {
+            node.sg_value_vec = self.convert_sg(&path, &node.btype, &node.rtype, &dai.sgval_vec);
+        }
+        if !dai.saddr.is_empty() {
+            node.saddr = dai.saddr.clone();
+        }
+        if !dai.valkind.is_empty() && dai.valkind != "Set" {
+            node.valkind = dai.valkind.clone();
+        }
+        Ok(())
+    }
+}
+
+/*一个 ReportControl 可以给多个客户端用  max 大于 1 的时候展开成多个实例     */
+fn expand_rcb(rcb_vec: &[SclRcb]) -> Vec<SclRcb> {
+    let mut ret = Vec::new();
+    for rcb in rcb_vec.iter() {
+        if rcb.maxclient <= 1 {
+            ret.push(rcb.clone());
+            continue;
+        }
+        for ix in 1..=rcb.maxclient {
+            let mut inst = rcb.clone();
+            inst.name = format!("{}{:02}", rcb.name, ix);
+            ret.push(inst);
+        }
+    }
+    ret
+}
+
+fn phy_com_addr(mac: MacAddress, vlanpri: u32, vlanid: u32, appid: u32) -> PhyComAddr {
+    PhyComAddr {
+        addr: mac.bytes(),
+        priority: vlanpri.min(u8::MAX as u32) as u8,
+        vid: vlanid.min(u16::MAX as u32) as u16,
+        appid: appid.min(u16::MAX as u32) as u16,
+    }
+}
+
+/*DAI 的名字是 Mod$stVal  或者 sdi[1]$x 这种
+转成模型里面的路径  下标单独一级     */
+fn flattened_to_path(flattened: &str) -> Option<Vec<String>> {
+    let mut path = Vec::new();
+    for part in flattened.split('$') {
+        let (name, ix) = split_index(part, '[', ']')?;
+        path.push(name.to_string());
+        if let Some(ix) = ix {
+            path.push(ix.to_string());
+        }
+    }
+    Some(path)
+}
+
+/*配置的数据集超过 ConfDataSet 的限制  只提示  不影响启动     */
+fn check_conf_dataset(model: &Model, confdataset: &SclServiceDataset) {
+    if !confdataset.enabled {
+        return;
+    }
+    let mut count = 0;
+    for ld in model.ld_vec.iter() {
+        for ln in ld.ln_vec.iter() {
+            for dataset in ln.dataset_vec.iter() {
+                count += 1;
+                if confdataset.maxattributes > 0
+                    && dataset.member_vec.len() > confdataset.maxattributes as usize
+                {
+                    println!(
+                        "dataset {}/{}.{} has {} members over ConfDataSet maxAttributes {}",
+                        ld.name,
+                        ln.name,
+                        dataset.name,
+                        dataset.member_vec.len(),
+                        confdataset.maxattributes
+                    );
+                }
+            }
+        }
+    }
+    if count > confdataset.max {
+        println!(
+            "{} datasets configured over ConfDataSet max {}",
+            count, confdataset.max
+        );
+    }
+}
+
+impl Model {
+    /*只用第一个 server  ied 和 ap 解析的时候已经过滤过了     */
+    pub fn from_scl(info: &SclInfo) -> crate::Result<Model> {
+        let server = match info.server_vec.first() {
+            Some(server) => server,
+            None => bail!("no server in scl"),
+        };
+        let mut builder = ModelBuilder {
+            info,
+            convert_err_vec: Vec::new(),
+        };
+        let mut model = Model {
+            ied_name: server.iedname.clone(),
+            ap_name: server.apname.clone(),
+            ld_vec: Vec::with_capacity(server.ld_vec.len()),
+            dyndataset: if server.scl_services.dyndataset.enabled {
+                server.scl_services.dyndataset.clone()
+            } else {
+                DEFAULT_DYN_DATASET
+            },
+            ..Default::default()
+        };
+        for scl_ld in server.ld_vec.iter() {
+            let mut ld = LogicalDevice {
+                name: scl_ld.domname.clone(),
+                inst: scl_ld.inst.clone(),
+                desc: scl_ld.desc.clone(),
+                ln_vec: Vec::with_capacity(scl_ld.ln_vec.len()),
+            };
+            for scl_ln in scl_ld.ln_vec.iter() {
+                let ln = builder
+                    .build_ln(scl_ld, scl_ln)
+                    .map_err(|e| anyhow::anyhow!("ld {}: {}", ld.name, e))?;
+                ld.ln_vec.push(ln);
+            }
+            model.ld_vec.push(ld);
+        }
+        model.convert_err_vec = builder.convert_err_vec;
+        /*FCDA 可以引用别的 LD  所以所有 LD 建完以后再解析数据集     */
+        for (ld_index, scl_ld) in server.ld_vec.iter().enumerate() {
+            for (ln_index, scl_ln) in scl_ld.ln_vec.iter().enumerate() {
+                for scl_dataset in scl_ln.dataset_vec.iter() {
+                    let dataset = model.resolve_dataset(scl_dataset).map_err(|e| {
+                        anyhow::anyhow!(
+                            "dataset {}/{}.{}: {}",
+                            scl_ld.domname,
+                            scl_ln.varname,
+                            scl_dataset.name,
+                            e
+                        )
+                    })?;
+                    model.ld_vec[ld_index].ln_vec[ln_index]
+                        .dataset_vec
+                        .push(dataset);
+                }
+            }
+        }
+        check_conf_dataset(&model, &server.scl_services.confdataset);
+        Ok(model)
+    }
+}
@@ -0,0 +1,522 @@
+use super::dataset::split_dataset_ref;
+use super::node::Model;
+use crate::cms61850::codec::aper::{AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::service::common;
+/***************************************/
+/*控制块的运行时状态  对应 cms.asn1 里面的 BRCB URCB LCB SGCB GoCB MSVCB
+初始值来自 scl  后面服务读写的都是这里     */
+/***************************************/
+
+/*位串都是高位在前  第 n 位是 0x80 >> (n % 8)     */
+pub const TRGOPS_BITS: usize = 6;
+pub const RCB_OPTFLDS_BITS: usize = 10;
+pub const LCB_OPTFLDS_BITS: usize = 1;
+pub const MSVCB_OPTFLDS_BITS: usize = 5;
+
+/*定值组控制块只有一个  名字固定     */
+pub const SGCB_NAME: &str = "SGCB";
+
+// PHYCOMADDR ::= SEQUENCE {
+//     addr OCTET STRING (SIZE(6)),
+//     priority INT8U,
+//     vid INT16U,
+//     appid INT16U
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct PhyComAddr {
+    pub addr: [u8; 6],
+    pub priority: u8,
+    pub vid: u16,
+    pub appid: u16,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Brcb {
+    pub name: String,
+    pub rptid: String,
+    pub rptena: bool,
+    /*数据集的对象引用  LD/LN.DataSet     */
+    pub datset: String,
+    pub confrev: u32,
+    pub optflds: [u8; 2],
+    pub buftm: u32,
+    pub sqnum: u16,
+    pub trgops: u8,
+    pub intgpd: u32,
+    pub gi: bool,
+    pub purgebuf: bool,
+    pub entryid: [u8; 8],
+    pub timeofentry: [u8; 6],
+    pub resvtms: Option<i16>,
+    pub owner: Option<Vec<u8>>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Urcb {
+    pub name: String,
+    pub rptid: String,
+    pub rptena: bool,
+    pub resv: bool,
+    pub datset: String,
+    pub confrev: u32,
+    pub optflds: [u8; 2],
+    pub buftm: u32,
+    pub sqnum: u8,
+    pub trgops: u8,
+    pub intgpd: u32,
+    pub gi: bool,
+    pub owner: Option<Vec<u8>>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Lcb {
+    pub name: String,
+    pub logena: bool,
+    pub datset: String,
+    pub trgops: u8,
+    pub intgpd: u32,
+    /*日志的对象引用  LD/LN.LogName     */
+    pub logref: String,
+    /*只有一位 reason-for-inclusion     */
+    pub optflds: u8,
+    pub buftm: u32,
+}
+
+impl Lcb {
+    /*日志名  logref 最后一段     */
+    pub fn log_name(&self) -> &str {
+        match self.logref.rfind('.') {
+            Some(pos) => &self.logref[pos + 1..],
+            None => &self.logref,
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Sgcb {
+    pub numofsg: u8,
+    pub actsg: u8,
+    /*0 表示没有在编辑     */
+    pub editsg: u8,
+    pub cnfedit: bool,
+    pub lacttm: [u8; 8],
+    pub resvtms: Option<u16>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GoCb {
+    pub name: String,
+    pub goena: bool,
+    pub goid: String,
+    pub datset: String,
+    pub confrev: u32,
+    /*需要调试  数据集为空的时候置位     */
+    pub ndscom: bool,
+    pub dstaddress: PhyComAddr,
+    /*GSE 里面的重发时间 ms  不在 GoCB 的服务里面  发布用     */
+    pub mintime: u32,
+    pub maxtime: u32,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct MsvCb {
+    pub name: String,
+    pub svena: bool,
+    pub msvid: String,
+    pub datset: String,
+    pub confrev: u32,
+    pub smpmod: i8,
+    pub smprate: u16,
+    pub optflds: u8,
+    pub dstaddress: PhyComAddr,
+}
+
+/*GetAllCBValues 里面的 value CHOICE     */
+#[derive(Clone, Debug, PartialEq)]
+pub enum CbValue {
+    Brcb(Brcb),
+    Urcb(Urcb),
+    Lcb(Lcb),
+    Sgcb(Sgcb),
+    GoCb(GoCb),
+    MsvCb(MsvCb),
+}
+const CB_VALUE_CHOICE_COUNT: usize = 6;
+
+fn put_u(enc: &mut AperEncoder, val: u64, max: u64) -> crate::Result<()> {
+    enc.put_constrained_int(val as i128, 0, max as i128)
+}
+
+fn get_u(dec: &mut AperDecoder, max: u64) -> crate::Result<u64> {
+    Ok(dec.get_constrained_int(0, max as i128)? as u64)
+}
+
+fn put_bits(enc: &mut AperEncoder, bits: &[u8], nbits: usize) -> crate::Result<()> {
+    enc.put_bit_string(bits, nbits, Size::Fixed(nbits))
+}
+
+fn get_bits<const N: usize>(dec: &mut AperDecoder, nbits: usize) -> crate::Result<[u8; N]> {
+    let (bits, _) = dec.get_bit_string(Size::Fixed(nbits))?;
+    let mut out = [0u8; N];
+    for (dst, src) in out.iter_mut().zip(bits.iter()) {
+        *dst = *src;
+    }
+    Ok(out)
+}
+
+fn get_octets<const N: usize>(dec: &mut AperDecoder) -> crate::Result<[u8; N]> {
+    let mut out = [0u8; N];
+    out.copy_from_slice(&dec.get_octet_string(Size::Fixed(N))?);
+    Ok(out)
+}
+
+fn put_vis129(enc: &mut AperEncoder, val: &str) -> crate::Result<()> {
+    enc.put_visible_string(val, Size::Range(0, 129))
+}
+
+fn get_vis129(dec: &mut AperDecoder) -> crate::Result<String> {
+    dec.get_visible_string(Size::Range(0, 129))
+}
+
+impl AperCodec for PhyComAddr {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_octet_string(&self.addr, Size::Fixed(6))?;
+        put_u(enc, self.priority as u64, u8::MAX as u64)?;
+        put_u(enc, self.vid as u64, u16::MAX as u64)?;
+        put_u(enc, self.appid as u64, u16::MAX as u64)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(PhyComAddr {
+            addr: get_octets(dec)?,
+            priority: get_u(dec, u8::MAX as u64)? as u8,
+            vid: get_u(dec, u16::MAX as u64)? as u16,
+            appid: get_u(dec, u16::MAX as u64)? as u16,
+        })
+    }
+}
+
+impl AperCodec for Brcb {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.resvtms.is_some(), self.owner.is_some()]);
+        common::put_obj_name(enc, &self.name)?;
+        put_vis129(enc, &self.rptid)?;
+        enc.put_bool(self.rptena);
+        common::put_obj_ref(enc, &self.datset)?;
+        put_u(enc, self.confrev as u64, u32::MAX as u64)?;
+        put_bits(enc, &self.optflds, RCB_OPTFLDS_BITS)?;
+        put_u(enc, self.buftm as u64, u32::MAX as u64)?;
+        put_u(enc, self.sqnum as u64, u16::MAX as u64)?;
+        put_bits(enc, &[self.trgops], TRGOPS_BITS)?;
+        put_u(enc, self.intgpd as u64, u32::MAX as u64)?;
+        enc.put_bool(self.gi);
+        enc.put_bool(self.purgebuf);
+        enc.put_octet_string(&self.entryid, Size::Fixed(8))?;
+        enc.put_octet_string(&self.timeofentry, Size::Fixed(6))?;
+        if let Some(resvtms) = self.resvtms {
+            enc.put_constrained_int(resvtms as i128, i16::MIN as i128, i16::MAX as i128)?;
+        }
+        if let Some(ref owner) = self.owner {
+            enc.put_octet_string(owner, Size::Range(0, 64))?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 2)?;
+        let mut cb = Brcb {
+            name: common::get_obj_name(dec)?,
+            rptid: get_vis129(dec)?,
+            rptena: dec.get_bool()?,
+            datset: common::get_obj_ref(dec)?,
+            confrev: get_u(dec, u32::MAX as u64)? as u32,
+            optflds: get_bits(dec, RCB_OPTFLDS_BITS)?,
+            buftm: get_u(dec, u32::MAX as u64)? as u32,
+            sqnum: get_u(dec, u16::MAX as u64)? as u16,
+            trgops: get_bits::<1>(dec, TRGOPS_BITS)?[0],
+            intgpd: get_u(dec, u32::MAX as u64)? as u32,
+            gi: dec.get_bool()?,
+            purgebuf: dec.get_bool()?,
+            entryid: get_octets(dec)?,
+            timeofentry: get_octets(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            cb.resvtms = Some(dec.get_constrained_int(i16::MIN as i128, i16::MAX as i128)? as i16);
+        }
+        if present[1] {
+            cb.owner = Some(dec.get_octet_string(Size::Range(0, 64))?);
+        }
+        Ok(cb)
+    }
+}
+
+impl AperCodec for Urcb {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.owner.is_some()]);
+        common::put_obj_name(enc, &self.name)?;
+        put_vis129(enc, &self.rptid)?;
+        enc.put_bool(self.rptena);
+        enc.put_bool(self.resv);
+        common::put_obj_ref(enc, &self.datset)?;
+        put_u(enc, self.confrev as u64, u32::MAX as u64)?;
+        put_bits(enc, &self.optflds, RCB_OPTFLDS_BITS)?;
+        put_u(enc, self.buftm as u64, u32::MAX as u64)?;
+        put_u(enc, self.sqnum as u64, u8::MAX as u64)?;
+        put_bits(enc, &[self.trgops], TRGOPS_BITS)?;
+        put_u(enc, self.intgpd as u64, u32::MAX as u64)?;
+        enc.put_bool(self.gi);
+        if let Some(ref owner) = self.owner {
+            enc.put_octet_string(owner, Size::Range(0, 64))?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut cb = Urcb {
+            name: common::get_obj_name(dec)?,
+            rptid: get_vis129(dec)?,
+            rptena: dec.get_bool()?,
+            resv: dec.get_bool()?,
+            datset: common::get_obj_ref(dec)?,
+            confrev: get_u(dec, u32::MAX as u64)? as u32,
+            optflds: get_bits(dec, RCB_OPTFLDS_BITS)?,
+            buftm: get_u(dec, u32::MAX as u64)? as u32,
+            sqnum: get_u(dec, u8::MAX as u64)? as u8,
+            trgops: get_bits::<1>(dec, TRGOPS_BITS)?[0],
+            intgpd: get_u(dec, u32::MAX as u64)? as u32,
+            gi: dec.get_bool()?,
+            ..Default::default()
+        };
+        if present[0] {
+            cb.owner = Some(dec.get_octet_string(Size::Range(0, 64))?);
+        }
+        Ok(cb)
+    }
+}
+
+impl AperCodec for Lcb {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_name(enc, &self.name)?;
+        enc.put_bool(self.logena);
+        common::put_obj_ref(enc, &self.datset)?;
+        put_bits(enc, &[self.trgops], TRGOPS_BITS)?;
+        put_u(enc, self.intgpd as u64, u32::MAX as u64)?;
+        common::put_obj_ref(enc, &self.logref)?;
+        put_bits(enc, &[self.optflds], LCB_OPTFLDS_BITS)?;
+        put_u(enc, self.buftm as u64, u32::MAX as u64)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(Lcb {
+            name: common::get_obj_name(dec)?,
+            logena: dec.get_bool()?,
+            datset: common::get_obj_ref(dec)?,
+            trgops: get_bits::<1>(dec, TRGOPS_BITS)?[0],
+            intgpd: get_u(dec, u32::MAX as u64)? as u32,
+            logref: common::get_obj_ref(dec)?,
+            optflds: get_bits::<1>(dec, LCB_OPTFLDS_BITS)?[0],
+            buftm: get_u(dec, u32::MAX as u64)? as u32,
+        })
+    }
+}
+
+impl AperCodec for Sgcb {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.resvtms.is_some()]);
+        common::put_obj_name(enc, SGCB_NAME)?;
+        put_u(enc, self.numofsg as u64, u8::MAX as u64)?;
+        put_u(enc, self.actsg as u64, u8::MAX as u64)?;
+        put_u(enc, self.editsg as u64, u8::MAX as u64)?;
+        enc.put_bool(self.cnfedit);
+        enc.put_octet_string(&self.lacttm, Size::Fixed(8))?;
+        if let Some(resvtms) = self.resvtms {
+            put_u(enc, resvtms as u64, u16::MAX as u64)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        common::get_obj_name(dec)?;
+        let mut cb = Sgcb {
+            numofsg: get_u(dec, u8::MAX as u64)? as u8,
+            actsg: get_u(dec, u8::MAX as u64)? as u8,
+            editsg: get_u(dec, u8::MAX as u64)? as u8,
+            cnfedit: dec.get_bool()?,
+            lacttm: get_octets(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            cb.resvtms = Some(get_u(dec, u16::MAX as u64)? as u16);
+        }
+        Ok(cb)
+    }
+}
+
+impl AperCodec for GoCb {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_name(enc, &self.name)?;
+        enc.put_bool(self.goena);
+        put_vis129(enc, &self.goid)?;
+        common::put_obj_ref(enc, &self.datset)?;
+        put_u(enc, self.confrev as u64, u32::MAX as u64)?;
+        enc.put_bool(self.ndscom);
+        self.dstaddress.aper_encode(enc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(GoCb {
+            name: common::get_obj_name(dec)?,
+            goena: dec.get_bool()?,
+            goid: get_vis129(dec)?,
+            datset: common::get_obj_ref(dec)?,
+            confrev: get_u(dec, u32::MAX as u64)? as u32,
+            ndscom: dec.get_bool()?,
+            dstaddress: PhyComAddr::aper_decode(dec)?,
+            ..Default::default()
+        })
+    }
+}
+
+impl AperCodec for MsvCb {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_name(enc, &self.name)?;
+        enc.put_bool(self.svena);
+        put_vis129(enc, &self.msvid)?;
+        common::put_obj_ref(enc, &self.datset)?;
+        put_u(enc, self.confrev as u64, u32::MAX as u64)?;
+        enc.put_constrained_int(self.smpmod as i128, i8::MIN as i128, i8::MAX as i128)?;
+        put_u(enc, self.smprate as u64, u16::MAX as u64)?;
+        put_bits(enc, &[self.optflds], MSVCB_OPTFLDS_BITS)?;
+        self.dstaddress.aper_encode(enc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(MsvCb {
+            name: common::get_obj_name(dec)?,
+            svena: dec.get_bool()?,
+            msvid: get_vis129(dec)?,
+            datset: common::get_obj_ref(dec)?,
+            confrev: get_u(dec, u32::MAX as u64)? as u32,
+            smpmod: dec.get_constrained_int(i8::MIN as i128, i8::MAX as i128)? as i8,
+            smprate: get_u(dec, u16::MAX as u64)? as u16,
+            optflds: get_bits::<1>(dec, MSVCB_OPTFLDS_BITS)?[0],
+            dstaddress: PhyComAddr::aper_decode(dec)?,
+        })
+    }
+}
+
+impl AperCodec for CbValue {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        match self {
+            CbValue::Brcb(cb) => {
+                enc.put_choice_index(0, CB_VALUE_CHOICE_COUNT, false)?;
+                cb.aper_encode(enc)
+            }
+            CbValue::Urcb(cb) => {
+                enc.put_choice_index(1, CB_VALUE_CHOICE_COUNT, false)?;
+                cb.aper_encode(enc)
+            }
+            CbValue::Lcb(cb) => {
+                enc.put_choice_index(2, CB_VALUE_CHOICE_COUNT, false)?;
+                cb.aper_encode(enc)
+            }
+            CbValue::Sgcb(cb) => {
+                enc.put_choice_index(3, CB_VALUE_CHOICE_COUNT, false)?;
+                cb.aper_encode(enc)
+            }
+            CbValue::GoCb(cb) => {
+                enc.put_choice_index(4, CB_VALUE_CHOICE_COUNT, false)?;
+                cb.aper_encode(enc)
+            }
+            CbValue::MsvCb(cb) => {
+                enc.put_choice_index(5, CB_VALUE_CHOICE_COUNT, false)?;
+                cb.aper_encode(enc)
+            }
+        }
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let cb = match dec.get_choice_index(CB_VALUE_CHOICE_COUNT, false)? {
+            0 => CbValue::Brcb(Brcb::aper_decode(dec)?),
+            1 => CbValue::Urcb(Urcb::aper_decode(dec)?),
+            2 => CbValue::Lcb(Lcb::aper_decode(dec)?),
+            3 => CbValue::Sgcb(Sgcb::aper_decode(dec)?),
+            4 => CbValue::GoCb(GoCb::aper_decode(dec)?),
+            _ => CbValue::MsvCb(MsvCb::aper_decode(dec)?),
+        };
+        Ok(cb)
+    }
+}
+
+impl Model {
+    /*LD/LN.RcbName  按引用找缓存报告控制块     */
+    pub fn get_brcb(&self, reference: &str) -> Option<&Brcb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        self.get_ln(&ln_ref)?
+            .brcb_vec
+            .iter()
+            .find(|cb| cb.name == name)
+    }
+    pub fn get_brcb_mut(&mut self, reference: &str) -> Option<&mut Brcb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        self.get_ln_mut(&ln_ref)?
+            .brcb_vec
+            .iter_mut()
+            .find(|cb| cb.name == name)
+    }
+    /*非缓存的     */
+    pub fn get_urcb(&self, reference: &str) -> Option<&Urcb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        self.get_ln(&ln_ref)?
+            .urcb_vec
+            .iter()
+            .find(|cb| cb.name == name)
+    }
+    pub fn get_urcb_mut(&mut self, reference: &str) -> Option<&mut Urcb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        self.get_ln_mut(&ln_ref)?
+            .urcb_vec
+            .iter_mut()
+            .find(|cb| cb.name == name)
+    }
+    /*日志控制块     */
+    pub fn get_lcb(&self, reference: &str) -> Option<&Lcb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        self.get_ln(&ln_ref)?
+            .lcb_vec
+            .iter()
+            .find(|cb| cb.name == name)
+    }
+    pub fn get_lcb_mut(&mut self, reference: &str) -> Option<&mut Lcb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        self.get_ln_mut(&ln_ref)?
+            .lcb_vec
+            .iter_mut()
+            .find(|cb| cb.name == name)
+    }
+    /*GOOSE 控制块     */
+    pub fn get_gocb(&self, reference: &str) -> Option<&GoCb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        self.get_ln(&ln_ref)?
+            .gocb_vec
+            .iter()
+            .find(|cb| cb.name == name)
+    }
+    pub fn get_gocb_mut(&mut self, reference: &str) -> Option<&mut GoCb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        self.get_ln_mut(&ln_ref)?
+            .gocb_vec
+            .iter_mut()
+            .find(|cb| cb.name == name)
+    }
+    /*定值组控制块  名字固定是 SGCB     */
+    pub fn get_sgcb(&self, reference: &str) -> Option<&Sgcb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        if name != SGCB_NAME {
+            return None;
+        }
+        self.get_ln(&ln_ref)?.sgcb.as_ref()
+    }
+    pub fn get_sgcb_mut(&mut self, reference: &str) -> Option<&mut Sgcb> {
+        let (ln_ref, name) = split_dataset_ref(reference)?;
+        if name != SGCB_NAME {
+            return None;
+        }
+        self.get_ln_mut(&ln_ref)?.sgcb.as_mut()
+    }
+}
@@ -0,0 +1,110 @@
+use super::node::{DataNode, Model};
+use super::objref::ObjRef;
+use super::value::{entry_time, Value};
+/***************************************/
+/*数据变化记录  写值的时候按叶子上的 dchg qchg dupd 记下来
+报告 日志 这些按触发条件自己去取     */
+/***************************************/
+
+/*ReasonCode 位串 高位在前  前 6 位和 TriggerConditions 一样     */
+pub const REASON_DCHG: u8 = 0x40;
+pub const REASON_QCHG: u8 = 0x20;
+pub const REASON_DUPD: u8 = 0x10;
+pub const REASON_INTEGRITY: u8 = 0x08;
+pub const REASON_GI: u8 = 0x04;
+pub const REASON_APP: u8 = 0x02;
+pub const REASON_BITS: usize = 7;
+
+/*一个叶子的变化  objref 是叶子的完整路径  fc 一定有
+value time 是变的时候的  报告 日志攒到扫描才出条目  不能到时候再去模型里面读     */
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataChange {
+    pub objref: ObjRef,
+    pub reason: u8,
+    pub value: Value,
+    pub time: [u8; 6],
+}
+
+impl DataChange {
+    /*这个变化在不在 member 下面  member 是数据集成员 fc 要一样     */
+    pub fn covered_by(&self, member: &ObjRef) -> bool {
+        self.objref.ld == member.ld
+            && self.objref.ln == member.ln
+            && self.objref.fc == member.fc
+            && self.objref.path.starts_with(&member.path)
+    }
+}
+
+/*新旧两棵树一起往下走  叶子的值变了或者有 dupd 就记一条     */
+fn diff_node(
+    old: &DataNode,
+    new: &DataNode,
+    fc: Option<&str>,
+    time: [u8; 6],
+    objref: &mut ObjRef,
+    change_vec: &mut Vec<DataChange>,
+) {
+    if new.is_leaf() {
+        if fc.is_some_and(|fc| new.fc != fc) {
+            return;
+        }
+        let changed = old.value != new.value;
+        let mut reason = 0;
+        if changed && new.dchg {
+            reason |= REASON_DCHG;
+        }
+        if changed && new.qchg {
+            reason |= REASON_QCHG;
+        }
+        if new.dupd {
+            reason |= REASON_DUPD;
+        }
+        if reason != 0 {
+            change_vec.push(DataChange {
+                objref: ObjRef {
+                    fc: Some(new.fc.clone()),
+                    ..objref.clone()
+                },
+                reason,
+                value: new.value.clone(),
+                time,
+            });
+        }
+        return;
+    }
+    for (old, new) in old.child_vec.iter().zip(new.child_vec.iter()) {
+        objref.path.push(new.name.clone());
+        diff_node(old, new, fc, time, objref, change_vec);
+        objref.path.pop();
+    }
+}
+
+impl Model {
+    /*objref 指向的节点刚被写过  old 是写之前的副本     */
+    pub fn note_change(&mut self, objref: &ObjRef, old: &DataNode) {
+        let new = match self.get_node(objref) {
+            Some(new) => new,
+            None => return,
+        };
+        let mut change_vec = Vec::new();
+        let mut path = ObjRef {
+            fc: None,
+            ..objref.clone()
+        };
+        let time = entry_time(chrono::Utc::now());
+        diff_node(
+            old,
+            new,
+            objref.fc.as_deref(),
+            time,
+            &mut path,
+            &mut change_vec,
+        );
+        self.change_vec.append(&mut change_vec);
+    }
+
+    /*取走到目前为止的变化     */
+    pub fn take_changes(&mut self) -> Vec<DataChange> {
+        std::mem::take(&mut self.change_vec)
+    }
+}
@@ -0,0 +1,145 @@
+use super::node::{DataNode, LogicalNode, Model};
+use super::objref::ObjRef;
+use crate::cfg::scl2::{SclDataset, SclFcda};
+use anyhow::bail;
+/***************************************/
+/*数据集  FCDA 在建模的时候就解析成模型里面的路径
+报告 日志 GOOSE 都是按这里的成员顺序取值     */
+/***************************************/
+
+#[derive(Default, Clone, Debug)]
+pub struct DataSet {
+    pub name: String,
+    pub desc: String,
+    /*成员  fc 一定有     */
+    pub member_vec: Vec<ObjRef>,
+    /*在线建的才能删  scl 里面配的不能删     */
+    pub deletable: bool,
+}
+
+impl DataSet {
+    /*成员的引用  不带 fc  目录服务和 referenceAfter 用这个     */
+    pub fn member_refs(&self) -> Vec<String> {
+        self.member_vec.iter().map(|m| m.data_ref()).collect()
+    }
+}
+
+impl LogicalNode {
+    pub fn get_dataset(&self, name: &str) -> Option<&DataSet> {
+        self.dataset_vec.iter().find(|ds| ds.name == name)
+    }
+}
+
+/*LD/LN.DSName 拆成 LN 的引用和数据集名字     */
+pub fn split_dataset_ref(reference: &str) -> Option<(ObjRef, &str)> {
+    let (ln_ref, name) = reference.rsplit_once('.')?;
+    let (ld, ln) = ln_ref.split_once('/')?;
+    if ld.is_empty() || ln.is_empty() || name.is_empty() || ln.contains('.') {
+        return None;
+    }
+    let objref = ObjRef {
+        ld: ld.to_string(),
+        ln: ln.to_string(),
+        ..Default::default()
+    };
+    Some((objref, name))
+}
+
+impl Model {
+    /*LD/LN.DSName     */
+    pub fn get_dataset(&self, reference: &str) -> Option<&DataSet> {
+        let (objref, name) = split_dataset_ref(reference)?;
+        self.get_ln(&objref)?.get_dataset(name)
+    }
+
+    /*FCDA 转成模型里面的路径
+    daName 为空就是整个 DO  ix 是数组下标  加在路径上第一个数组的后面     */
+    pub fn resolve_fcda(&self, fcda: &SclFcda) -> crate::Result<ObjRef> {
+        if fcda.doname.is_empty() || fcda.fc.len() != 2 {
+            bail!(format!("bad fcda doName {} fc {}", fcda.doname, fcda.fc));
+        }
+        let mut objref = ObjRef {
+            ld: fcda.domname.clone(),
+            ln: format!("{}{}{}", fcda.prefix, fcda.lnclass, fcda.lninst),
+            path: fcda
+                .doname
+                .split('.')
+                .chain(fcda.daname.split('.').filter(|name| !name.is_empty()))
+                .map(|name| name.to_string())
+                .collect(),
+            fc: Some(fcda.fc.clone()),
+        };
+        let ln = match self.get_ln(&objref) {
+            Some(ln) => ln,
+            None => bail!(format!("fcda ln {} not found", objref.ln_ref())),
+        };
+        if !fcda.ix.is_empty() {
+            if !fcda.ix.bytes().all(|b| b.is_ascii_digit()) {
+                bail!(format!("fcda {} bad ix {}", objref, fcda.ix));
+            }
+            let position = array_position(ln, &objref.path);
+            match position {
+                Some(position) => objref.path.insert(position + 1, fcda.ix.clone()),
+                None => bail!(format!("fcda {} has ix {} but no array", objref, fcda.ix)),
+            }
+        }
+        if self.get_node(&objref).is_none() {
+            bail!(format!("fcda {} not found", objref));
+        }
+        Ok(objref)
+    }
+
+    /*scl 里面的数据集  所有成员都要能找到     */
+    pub fn resolve_dataset(&self, scl_dataset: &SclDataset) -> crate::Result<DataSet> {
+        let mut member_vec = Vec::with_capacity(scl_dataset.fcda_vec.len());
+        for fcda in scl_dataset.fcda_vec.iter() {
+            member_vec.push(self.resolve_fcda(fcda)?);
+        }
+        Ok(DataSet {
+            name: scl_dataset.name.clone(),
+            desc: scl_dataset.desc.clone(),
+            member_vec,
+            deletable: false,
+        })
+    }
+
+    /*有没有控制块用着这个数据集     */
+    pub fn dataset_in_use(&self, reference: &str) -> bool {
+        self.ld_vec
+            .iter()
+            .flat_map(|ld| ld.ln_vec.iter())
+            .any(|ln| {
+                ln.brcb_vec.iter().any(|cb| cb.datset == reference)
+                    || ln.urcb_vec.iter().any(|cb| cb.datset == reference)
+                    || ln.lcb_vec.iter().any(|cb| cb.datset == reference)
+                    || ln.gocb_vec.iter().any(|cb| cb.datset == reference)
+                    || ln.msvcb_vec.iter().any(|cb| cb.datset == reference)
+            })
+    }
+
+    /*在线建的持久数据集个数  非持久的在各自的关联里面     */
+    pub fn dyn_dataset_count(&self) -> usize {
+        self.ld_vec
+            .iter()
+            .flat_map(|ld| ld.ln_vec.iter())
+            .flat_map(|ln| ln.dataset_vec.iter())
+            .filter(|ds| ds.deletable)
+            .count()
+    }
+}
+
+/*路径上第一个是数组的节点的位置     */
+fn array_position(ln: &LogicalNode, path: &[String]) -> Option<usize> {
+    let mut node: Option<&DataNode> = None;
+    for (position, name) in path.iter().enumerate() {
+        let next = match node {
+            Some(node) => node.child(name)?,
+            None => ln.get_do(name)?,
+        };
+        if next.is_array() {
+            return Some(position);
+        }
+        node = Some(next);
+    }
+    None
+}
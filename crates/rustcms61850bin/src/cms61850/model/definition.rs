@@ -0,0 +1,249 @@
+use super::node::{DataNode, NodeKind};
+use super::value::{btype_max_len, Value};
+use crate::cms61850::codec::aper::{AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::service::common::{self, ServiceError};
+use anyhow::bail;
+/***************************************/
+/*类型定义  对应 cms.asn1 里面的 DataDefinition
+从模型节点生成  模型本身就是按 DOType DAType 展开的     */
+/***************************************/
+
+/*DataDefinition CHOICE 一共 25 个分支     */
+const DEFINITION_CHOICE_COUNT: usize = 25;
+/*和 Data 一样  防止对端发很深的嵌套     */
+const MAX_DEFINITION_DEPTH: usize = 32;
+
+/*结构体的一个成员  DO 下面的 DA 带 fc  BDA SDO 不带     */
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructItem {
+    pub name: String,
+    pub fc: Option<String>,
+    pub definition: DataDefinition,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DataDefinition {
+    Error(ServiceError),
+    Structure(Vec<StructItem>),
+    /*元素个数 元素类型     */
+    Array(u32, Box<DataDefinition>),
+    Boolean,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Int8u,
+    Int16u,
+    Int32u,
+    Int64u,
+    Float32,
+    Float64,
+    /*字符串这几个带的是最大长度  位串是位数     */
+    BitString(i32),
+    Octet(i32),
+    VisString(i32),
+    Unicode(i32),
+    UtcTime,
+    BinaryTime,
+    Quality,
+    Dbpos,
+    Tcmd,
+    Check,
+    /*枚举带的是 enumtype 的 id     */
+    Enumerated(String),
+}
+
+impl DataDefinition {
+    /*叶子按 bType 来  和 Value::default_for 对得上     */
+    fn from_leaf(node: &DataNode) -> DataDefinition {
+        let max_len = |default: usize| btype_max_len(&node.btype).unwrap_or(default) as i32;
+        match Value::default_for(&node.btype) {
+            Some(Value::Boolean(_)) => DataDefinition::Boolean,
+            Some(Value::Int8(_)) => DataDefinition::Int8,
+            Some(Value::Int16(_)) => DataDefinition::Int16,
+            Some(Value::Int32(_)) => DataDefinition::Int32,
+            Some(Value::Int64(_)) => DataDefinition::Int64,
+            Some(Value::Int8u(_)) => DataDefinition::Int8u,
+            Some(Value::Int16u(_)) => DataDefinition::Int16u,
+            Some(Value::Int32u(_)) => DataDefinition::Int32u,
+            Some(Value::Int64u(_)) => DataDefinition::Int64u,
+            Some(Value::Float32(_)) => DataDefinition::Float32,
+            Some(Value::Float64(_)) => DataDefinition::Float64,
+            Some(Value::BitString(_, nbits)) => DataDefinition::BitString(nbits as i32),
+            Some(Value::Octet(v)) => DataDefinition::Octet(max_len(v.len())),
+            Some(Value::VisString(_)) | Some(Value::ObjRef(_)) => {
+                DataDefinition::VisString(max_len(0))
+            }
+            Some(Value::Unicode(_)) => DataDefinition::Unicode(max_len(0)),
+            Some(Value::Timestamp(_)) => DataDefinition::UtcTime,
+            Some(Value::EntryTime(_)) => DataDefinition::BinaryTime,
+            Some(Value::Quality(_)) => DataDefinition::Quality,
+            Some(Value::Dbpos(_)) => DataDefinition::Dbpos,
+            Some(Value::Tcmd(_)) => DataDefinition::Tcmd,
+            Some(Value::Check(_)) => DataDefinition::Check,
+            Some(Value::Enum(_)) => DataDefinition::Enumerated(node.rtype.clone()),
+            _ => DataDefinition::Structure(Vec::new()),
+        }
+    }
+
+    /*节点的定义  带了 fc 只要这个 fc 下面的  和 DataNode::get_value 的形状一样     */
+    pub fn from_node(node: &DataNode, fc: Option<&str>) -> Option<DataDefinition> {
+        if let Some(fc) = fc {
+            if !node.has_fc(fc) {
+                return None;
+            }
+        }
+        if node.is_leaf() {
+            return Some(DataDefinition::from_leaf(node));
+        }
+        if node.is_array() {
+            let element = node
+                .child_vec
+                .first()
+                .and_then(|child| DataDefinition::from_node(child, fc))
+                .unwrap_or(DataDefinition::Error(ServiceError::InstanceNotAvailable));
+            return Some(DataDefinition::Array(node.count, Box::new(element)));
+        }
+        let item_vec = node
+            .child_vec
+            .iter()
+            .filter_map(|child| {
+                let definition = DataDefinition::from_node(child, fc)?;
+                Some(StructItem {
+                    name: child.name.clone(),
+                    fc: match child.kind {
+                        NodeKind::Da => Some(child.fc.clone()),
+                        _ => None,
+                    },
+                    definition,
+                })
+            })
+            .collect();
+        Some(DataDefinition::Structure(item_vec))
+    }
+
+    fn choice_index(&self) -> usize {
+        match self {
+            DataDefinition::Error(_) => 0,
+            DataDefinition::Structure(_) => 1,
+            DataDefinition::Array(_, _) => 2,
+            DataDefinition::Boolean => 3,
+            DataDefinition::Int8 => 4,
+            DataDefinition::Int16 => 5,
+            DataDefinition::Int32 => 6,
+            DataDefinition::Int64 => 7,
+            DataDefinition::Int8u => 8,
+            DataDefinition::Int16u => 9,
+            DataDefinition::Int32u => 10,
+            DataDefinition::Int64u => 11,
+            DataDefinition::Float32 => 12,
+            DataDefinition::Float64 => 13,
+            DataDefinition::BitString(_) => 14,
+            DataDefinition::Octet(_) => 15,
+            DataDefinition::VisString(_) => 16,
+            DataDefinition::Unicode(_) => 17,
+            DataDefinition::UtcTime => 18,
+            DataDefinition::BinaryTime => 19,
+            DataDefinition::Quality => 20,
+            DataDefinition::Dbpos => 21,
+            DataDefinition::Tcmd => 22,
+            DataDefinition::Check => 23,
+            DataDefinition::Enumerated(_) => 24,
+        }
+    }
+
+    fn decode_depth(dec: &mut AperDecoder, depth: usize) -> crate::Result<DataDefinition> {
+        if depth > MAX_DEFINITION_DEPTH {
+            bail!("data definition nested too deep");
+        }
+        let get_i32 = |dec: &mut AperDecoder| -> crate::Result<i32> {
+            Ok(dec.get_constrained_int(i32::MIN as i128, i32::MAX as i128)? as i32)
+        };
+        let definition = match dec.get_choice_index(DEFINITION_CHOICE_COUNT, false)? {
+            0 => DataDefinition::Error(ServiceError::aper_decode(dec)?),
+            1 => {
+                let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+                let mut item_vec = Vec::new();
+                for _ in 0..count {
+                    let present = dec.get_sequence_preamble(false, 1)?;
+                    let name = common::get_obj_name(dec)?;
+                    let fc = if present[0] {
+                        Some(common::get_fc(dec)?)
+                    } else {
+                        None
+                    };
+                    item_vec.push(StructItem {
+                        name,
+                        fc,
+                        definition: DataDefinition::decode_depth(dec, depth + 1)?,
+                    });
+                }
+                DataDefinition::Structure(item_vec)
+            }
+            2 => {
+                let count = dec.get_constrained_int(0, u32::MAX as i128)? as u32;
+                let element = DataDefinition::decode_depth(dec, depth + 1)?;
+                DataDefinition::Array(count, Box::new(element))
+            }
+            3 => DataDefinition::Boolean,
+            4 => DataDefinition::Int8,
+            5 => DataDefinition::Int16,
+            6 => DataDefinition::Int32,
+            7 => DataDefinition::Int64,
+            8 => DataDefinition::Int8u,
+            9 => DataDefinition::Int16u,
+            10 => DataDefinition::Int32u,
+            11 => DataDefinition::Int64u,
+            12 => DataDefinition::Float32,
+            13 => DataDefinition::Float64,
+            14 => DataDefinition::BitString(get_i32(dec)?),
+            15 => DataDefinition::Octet(get_i32(dec)?),
+            16 => DataDefinition::VisString(get_i32(dec)?),
+            17 => DataDefinition::Unicode(get_i32(dec)?),
+            18 => DataDefinition::UtcTime,
+            19 => DataDefinition::BinaryTime,
+            20 => DataDefinition::Quality,
+            21 => DataDefinition::Dbpos,
+            22 => DataDefinition::Tcmd,
+            23 => DataDefinition::Check,
+            _ => DataDefinition::Enumerated(common::get_obj_name(dec)?),
+        };
+        Ok(definition)
+    }
+}
+
+impl AperCodec for DataDefinition {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_choice_index(self.choice_index(), DEFINITION_CHOICE_COUNT, false)?;
+        match self {
+            DataDefinition::Error(err) => err.aper_encode(enc),
+            DataDefinition::Structure(item_vec) => {
+                enc.put_sequence_of_len(item_vec.len(), Size::Unconstrained)?;
+                for item in item_vec.iter() {
+                    enc.put_sequence_preamble(false, &[item.fc.is_some()]);
+                    common::put_obj_name(enc, &item.name)?;
+                    if let Some(ref fc) = item.fc {
+                        common::put_fc(enc, fc)?;
+                    }
+                    item.definition.aper_encode(enc)?;
+                }
+                Ok(())
+            }
+            DataDefinition::Array(count, element) => {
+                enc.put_constrained_int(*count as i128, 0, u32::MAX as i128)?;
+                element.aper_encode(enc)
+            }
+            DataDefinition::BitString(len)
+            | DataDefinition::Octet(len)
+            | DataDefinition::VisString(len)
+            | DataDefinition::Unicode(len) => {
+                enc.put_constrained_int(*len as i128, i32::MIN as i128, i32::MAX as i128)
+            }
+            DataDefinition::Enumerated(name) => common::put_obj_name(enc, name),
+            _ => Ok(()),
+        }
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        DataDefinition::decode_depth(dec, 0)
+    }
+}
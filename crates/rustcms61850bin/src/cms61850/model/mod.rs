@@ -0,0 +1,20 @@
+/***************************************/
+/*运行时的 61850 模型
+从 SclInfo 构造  LD -> LN -> DO/SDO -> DA/BDA
+服务都是对着这个模型来读写     */
+/***************************************/
+pub mod build;
+pub mod cb;
+pub mod change;
+pub mod dataset;
+pub mod definition;
+pub mod node;
+pub mod objref;
+pub mod overlay;
+pub mod value;
+
+pub use dataset::DataSet;
+pub use definition::DataDefinition;
+pub use node::{DataNode, LogicalDevice, LogicalNode, Model, NodeKind};
+pub use objref::ObjRef;
+pub use value::Value;
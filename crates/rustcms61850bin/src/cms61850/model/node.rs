@@ -0,0 +1,241 @@
+use super::cb::{Brcb, GoCb, Lcb, MsvCb, Sgcb, Urcb};
+use super::change::DataChange;
+use super::dataset::DataSet;
+use super::objref::ObjRef;
+use super::value::{ConvertErr, Value};
+use crate::cfg::scl2::{SclGcb, SclServiceDataset, SclSvcb};
+/***************************************/
+/*模型节点     */
+/***************************************/
+
+/*节点种类  数组元素和它的数组是同一种     */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NodeKind {
+    #[default]
+    Do,
+    Sdo,
+    Da,
+    Bda,
+}
+
+/*DO SDO DA BDA 都用这一个结构
+DO/SDO 没有 fc  DA 的 fc 来自 SclDa  BDA 继承上面 DA 的 fc
+数组 count > 0  下面的子节点名字就是下标 "0" "1" ...     */
+#[derive(Default, Clone, Debug)]
+pub struct DataNode {
+    pub name: String,
+    pub kind: NodeKind,
+    /*功能约束  ST MX CO ...     */
+    pub fc: String,
+    /*DO/SDO 的公共数据类     */
+    pub cdc: String,
+    /*基本类型 BOOLEAN INT32 Struct Enum ...     */
+    pub btype: String,
+    /*Struct 对应 datype  Enum 对应 enumtype  SDO 对应 dotype     */
+    pub rtype: String,
+    /*数组个数  0 表示不是数组     */
+    pub count: u32,
+    /*Spec Conf RO Set     */
+    pub valkind: String,
+    pub saddr: String,
+    /*触发条件     */
+    pub dchg: bool,
+    pub qchg: bool,
+    pub dupd: bool,
+    /*叶子的值  初始值来自 scl 里面的 Val     */
+    pub value: Value,
+    /*定值组的值  (定值组号, 值)  初始值来自 scl  确认编辑以后改这里     */
+    pub sg_value_vec: Vec<(u32, Value)>,
+    /*SG 叶子的编辑区  用 SE 访问  没在编辑是 None     */
+    pub edit_value: Option<Value>,
+    pub child_vec: Vec<DataNode>,
+}
+
+impl DataNode {
+    /*叶子 就是真正放值的地方     */
+    pub fn is_leaf(&self) -> bool {
+        self.child_vec.is_empty() && matches!(self.kind, NodeKind::Da | NodeKind::Bda)
+    }
+    pub fn is_array(&self) -> bool {
+        self.count > 0
+    }
+    pub fn child(&self, name: &str) -> Option<&DataNode> {
+        self.child_vec.iter().find(|node| node.name == name)
+    }
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut DataNode> {
+        self.child_vec.iter_mut().find(|node| node.name == name)
+    }
+    /*自己或者下面有这个 fc  SE 就是 SG 叶子的编辑区     */
+    pub fn has_fc(&self, fc: &str) -> bool {
+        match self.kind {
+            NodeKind::Do | NodeKind::Sdo => self.child_vec.iter().any(|node| node.has_fc(fc)),
+            NodeKind::Da | NodeKind::Bda => self.fc == fc || (fc == "SE" && self.fc == "SG"),
+        }
+    }
+    /*定值组 sg 的值  scl 里面没给这一组的  用当前值     */
+    pub fn sg_value(&self, sg: u32) -> Value {
+        self.sg_value_vec
+            .iter()
+            .find(|(group, _)| *group == sg)
+            .map(|(_, val)| val.clone())
+            .unwrap_or_else(|| self.value.clone())
+    }
+    pub fn set_sg_value(&mut self, sg: u32, val: Value) {
+        match self.sg_value_vec.iter_mut().find(|(group, _)| *group == sg) {
+            Some((_, old)) => *old = val,
+            None => self.sg_value_vec.push((sg, val)),
+        }
+    }
+    /*叶子直接返回  数组 结构体 DO 从子节点拼
+    带了 fc 的话 只要这个 fc 下面的  一个都没有返回 None
+    SE 读的是 SG 叶子的编辑区     */
+    pub fn get_value(&self, fc: Option<&str>) -> Option<Value> {
+        if self.is_leaf() {
+            return match fc {
+                Some("SE") if self.fc == "SG" => self.edit_value.clone(),
+                Some(fc) if self.fc != fc => None,
+                _ => Some(self.value.clone()),
+            };
+        }
+        let vals: Vec<Value> = self
+            .child_vec
+            .iter()
+            .filter_map(|node| node.get_value(fc))
+            .collect();
+        if vals.is_empty() && fc.is_some() {
+            return None;
+        }
+        if self.is_array() {
+            Some(Value::Array(vals))
+        } else {
+            Some(Value::Struct(vals))
+        }
+    }
+    pub fn get_path(&self, path: &[String]) -> Option<&DataNode> {
+        let mut node = self;
+        for name in path.iter() {
+            node = node.child(name)?;
+        }
+        Some(node)
+    }
+    pub fn get_path_mut(&mut self, path: &[String]) -> Option<&mut DataNode> {
+        let mut node = self;
+        for name in path.iter() {
+            node = node.child_mut(name)?;
+        }
+        Some(node)
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct LogicalNode {
+    /*prefix + lnclass + inst     */
+    pub name: String,
+    pub desc: String,
+    pub prefix: String,
+    pub lnclass: String,
+    pub inst: String,
+    /*对应模板里面的 lntype id     */
+    pub lntype: String,
+    pub do_vec: Vec<DataNode>,
+    /*数据集  成员已经解析成模型里面的路径     */
+    pub dataset_vec: Vec<DataSet>,
+    /*控制块的运行时状态
+    rcb 按 RptEnabled max 展开成实例  名字后面加 01 02 ...     */
+    pub brcb_vec: Vec<Brcb>,
+    pub urcb_vec: Vec<Urcb>,
+    pub lcb_vec: Vec<Lcb>,
+    pub gocb_vec: Vec<GoCb>,
+    pub msvcb_vec: Vec<MsvCb>,
+    /*GSSE 和单播采样值 没有对应的服务  只留配置     */
+    pub gscb_vec: Vec<SclGcb>,
+    pub usvcb_vec: Vec<SclSvcb>,
+    /*只有 LLN0 可能有     */
+    pub sgcb: Option<Sgcb>,
+}
+
+impl LogicalNode {
+    pub fn get_do(&self, name: &str) -> Option<&DataNode> {
+        self.do_vec.iter().find(|node| node.name == name)
+    }
+    pub fn get_do_mut(&mut self, name: &str) -> Option<&mut DataNode> {
+        self.do_vec.iter_mut().find(|node| node.name == name)
+    }
+    /*path 第一级是 DO     */
+    pub fn get_path(&self, path: &[String]) -> Option<&DataNode> {
+        let (first, rest) = path.split_first()?;
+        self.get_do(first)?.get_path(rest)
+    }
+    pub fn get_path_mut(&mut self, path: &[String]) -> Option<&mut DataNode> {
+        let (first, rest) = path.split_first()?;
+        self.get_do_mut(first)?.get_path_mut(rest)
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct LogicalDevice {
+    /*iedname + inst  对象引用里面用的就是这个     */
+    pub name: String,
+    pub inst: String,
+    pub desc: String,
+    pub ln_vec: Vec<LogicalNode>,
+}
+
+impl LogicalDevice {
+    pub fn get_ln(&self, name: &str) -> Option<&LogicalNode> {
+        self.ln_vec.iter().find(|ln| ln.name == name)
+    }
+    pub fn get_ln_mut(&mut self, name: &str) -> Option<&mut LogicalNode> {
+        self.ln_vec.iter_mut().find(|ln| ln.name == name)
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct Model {
+    pub ied_name: String,
+    pub ap_name: String,
+    pub ld_vec: Vec<LogicalDevice>,
+    /*建模的时候 值转换失败的诊断     */
+    pub convert_err_vec: Vec<ConvertErr>,
+    /*在线建数据集的限制  来自 Services 的 DynDataSet  没写用默认的     */
+    pub dyndataset: SclServiceDataset,
+    /*写值带出来的变化  报告那边取走     */
+    pub change_vec: Vec<DataChange>,
+    /*要落盘的改动  写过的 SP CF  确认过的定值组  落盘那边取走     */
+    pub persist_vec: Vec<ObjRef>,
+}
+
+impl Model {
+    pub fn get_ld(&self, name: &str) -> Option<&LogicalDevice> {
+        self.ld_vec.iter().find(|ld| ld.name == name)
+    }
+    pub fn get_ld_mut(&mut self, name: &str) -> Option<&mut LogicalDevice> {
+        self.ld_vec.iter_mut().find(|ld| ld.name == name)
+    }
+    pub fn get_ln(&self, objref: &ObjRef) -> Option<&LogicalNode> {
+        self.get_ld(&objref.ld)?.get_ln(&objref.ln)
+    }
+    pub fn get_ln_mut(&mut self, objref: &ObjRef) -> Option<&mut LogicalNode> {
+        self.get_ld_mut(&objref.ld)?.get_ln_mut(&objref.ln)
+    }
+    /*按对象引用找节点  带了 fc 的话 节点下面必须有这个 fc     */
+    pub fn get_node(&self, objref: &ObjRef) -> Option<&DataNode> {
+        let node = self.get_ln(objref)?.get_path(&objref.path)?;
+        match objref.fc {
+            Some(ref fc) if !node.has_fc(fc) => None,
+            _ => Some(node),
+        }
+    }
+    pub fn get_node_mut(&mut self, objref: &ObjRef) -> Option<&mut DataNode> {
+        let node = self.get_ln_mut(objref)?.get_path_mut(&objref.path)?;
+        match objref.fc {
+            Some(ref fc) if !node.has_fc(fc) => None,
+            _ => Some(node),
+        }
+    }
+    /*直接用字符串找  LD/LN.DO.DA[FC]     */
+    pub fn find(&self, reference: &str) -> Option<&DataNode> {
+        let objref = ObjRef::parse(reference).ok()?;
+        self.get_node(&objref)
+    }
+}
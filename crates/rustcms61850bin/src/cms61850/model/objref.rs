@@ -0,0 +1,117 @@
+use anyhow::bail;
+use std::fmt;
+/***************************************/
+/*对象引用  LD/LN.DO.DA[FC]
+数组元素用 name(ix) 表示  拆开以后 ix 单独算一级     */
+/***************************************/
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct ObjRef {
+    /*逻辑设备名  就是 iedname + ldinst     */
+    pub ld: String,
+    /*逻辑节点名  prefix + lnclass + inst   只有 LD 的时候为空     */
+    pub ln: String,
+    /*LN 下面的路径  DO SDO DA BDA 数组下标     */
+    pub path: Vec<String>,
+    /*功能约束  可选     */
+    pub fc: Option<String>,
+}
+
+impl ObjRef {
+    pub fn parse(reference: &str) -> crate::Result<ObjRef> {
+        let mut body = reference.trim();
+        let mut fc = None;
+        if body.ends_with(']') {
+            match body.rfind('[') {
+                Some(index) => {
+                    let fc_str = &body[index + 1..body.len() - 1];
+                    if fc_str.len() != 2 {
+                        bail!(format!("bad fc in reference {}", reference));
+                    }
+                    fc = Some(fc_str.to_string());
+                    body = &body[..index];
+                }
+                None => bail!(format!("bad reference {}", reference)),
+            }
+        }
+        let (ld, rest) = match body.split_once('/') {
+            Some((ld, rest)) => (ld, Some(rest)),
+            None => (body, None),
+        };
+        if ld.is_empty() {
+            bail!(format!("no ld in reference {}", reference));
+        }
+        let mut objref = ObjRef {
+            ld: ld.to_string(),
+            fc,
+            ..Default::default()
+        };
+        let rest = match rest {
+            Some(rest) => rest,
+            None => return Ok(objref),
+        };
+        let mut parts = rest.split('.');
+        objref.ln = parts.next().unwrap_or_default().to_string();
+        if objref.ln.is_empty() {
+            bail!(format!("no ln in reference {}", reference));
+        }
+        for part in parts {
+            let (name, ix) = split_index(part, '(', ')')
+                .ok_or_else(|| anyhow::anyhow!("bad array index in reference {}", reference))?;
+            if name.is_empty() {
+                bail!(format!("empty name in reference {}", reference));
+            }
+            objref.path.push(name.to_string());
+            if let Some(ix) = ix {
+                objref.path.push(ix.to_string());
+            }
+        }
+        Ok(objref)
+    }
+
+    /*LN 的引用 LD/LN     */
+    pub fn ln_ref(&self) -> String {
+        format!("{}/{}", self.ld, self.ln)
+    }
+
+    /*不带 fc 的引用     */
+    pub fn data_ref(&self) -> String {
+        ObjRef {
+            fc: None,
+            ..self.clone()
+        }
+        .to_string()
+    }
+}
+
+/*name(ix) 或者 name[ix]  拆成 name 和 ix  ix 必须是数字     */
+pub fn split_index(part: &str, open: char, close: char) -> Option<(&str, Option<&str>)> {
+    if !part.ends_with(close) {
+        return Some((part, None));
+    }
+    let index = part.find(open)?;
+    let ix = &part[index + 1..part.len() - 1];
+    if ix.is_empty() || !ix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((&part[..index], Some(ix)))
+}
+
+impl fmt::Display for ObjRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ld)?;
+        if !self.ln.is_empty() {
+            write!(f, "/{}", self.ln)?;
+        }
+        for name in self.path.iter() {
+            if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) {
+                write!(f, "({})", name)?;
+            } else {
+                write!(f, ".{}", name)?;
+            }
+        }
+        if let Some(ref fc) = self.fc {
+            write!(f, "[{}]", fc)?;
+        }
+        Ok(())
+    }
+}
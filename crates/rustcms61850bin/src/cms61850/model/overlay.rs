@@ -0,0 +1,397 @@
+use super::node::{DataNode, Model};
+use super::objref::ObjRef;
+use super::value::Value;
+use crate::cms61850::codec::aper;
+use crate::cms61850::sg::FC_SG;
+use crate::utils;
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::sync::watch;
+/***************************************/
+/*落盘的改动  重启以后盖在 scl 的初始值上面
+SetDataValues 写过的 SP CF  确认过的定值组  激活组
+文件是 json  带 scl 头的 id 和 version  scl 换了整个不要
+值存 aper 编码以后的 hex  读回来按模型里面的类型对一下
+写临时文件再改名  写一半掉电还是原来的
+文件在后台任务里面写  不挡汇总中心  写得慢的时候中间的版本跳过 只写最新的     */
+/***************************************/
+
+/*写了要落盘的功能约束  SG 的按组另外存     */
+pub const PERSIST_FC: [&str; 2] = ["SP", "CF"];
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct OverlayData {
+    pub scl_id: String,
+    /*scl 头的 version 和 revision  分开存  拼起来 "1"+"23" 和 "12"+"3" 分不出来     */
+    pub scl_version: String,
+    #[serde(default)]
+    pub scl_revision: String,
+    /*叶子的引用 LD/LN.DO.DA[FC] -> 值     */
+    #[serde(default)]
+    pub value_map: BTreeMap<String, String>,
+    /*SG 叶子的引用 -> 组号 -> 值     */
+    #[serde(default)]
+    pub sg_map: BTreeMap<String, BTreeMap<u32, String>>,
+    /*SGCB 的引用 -> 激活组     */
+    #[serde(default)]
+    pub actsg_map: BTreeMap<String, u8>,
+}
+
+#[derive(Debug)]
+pub struct Overlay {
+    path: PathBuf,
+    data: OverlayData,
+    /*第一次保存的时候起后台写文件的任务     */
+    write_tx: Option<watch::Sender<String>>,
+}
+
+impl Overlay {
+    /*读文件  没有 坏了 或者 scl 换了  都从空的开始     */
+    pub fn open(path: PathBuf, scl_id: &str, scl_version: &str, scl_revision: &str) -> Overlay {
+        let empty = OverlayData {
+            scl_id: scl_id.to_string(),
+            scl_version: scl_version.to_string(),
+            scl_revision: scl_revision.to_string(),
+            ..Default::default()
+        };
+        let data = match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<OverlayData>(&content) {
+                Ok(data)
+                    if data.scl_id == scl_id
+                        && data.scl_version == scl_version
+                        && data.scl_revision == scl_revision =>
+                {
+                    data
+                }
+                Ok(data) => {
+                    println!(
+                        "overlay {} scl {} {}/{} changed to {} {}/{} discarded",
+                        path.display(),
+                        data.scl_id,
+                        data.scl_version,
+                        data.scl_revision,
+                        scl_id,
+                        scl_version,
+                        scl_revision
+                    );
+                    empty
+                }
+                Err(err) => {
+                    println!("overlay {} parse failed {}", path.display(), err);
+                    empty
+                }
+            },
+            Err(_) => empty,
+        };
+        Overlay {
+            path,
+            data,
+            write_tx: None,
+        }
+    }
+
+    /*盖到刚建好的模型上  要在定值组启动之前  对不上的跳过     */
+    pub fn apply(&self, model: &mut Model) {
+        let mut count = 0;
+        for (reference, hex) in self.data.value_map.iter() {
+            match apply_one(model, reference, hex, |leaf, val| leaf.value = val) {
+                Ok(()) => count += 1,
+                Err(err) => println!("overlay {} skipped {}", reference, err),
+            }
+        }
+        for (reference, sg_map) in self.data.sg_map.iter() {
+            for (sg, hex) in sg_map.iter() {
+                match apply_one(model, reference, hex, |leaf, val| {
+                    leaf.set_sg_value(*sg, val)
+                }) {
+                    Ok(()) => count += 1,
+                    Err(err) => println!("overlay {} sg {} skipped {}", reference, sg, err),
+                }
+            }
+        }
+        for (reference, actsg) in self.data.actsg_map.iter() {
+            match model.get_sgcb_mut(reference) {
+                Some(cb) if (1..=cb.numofsg).contains(actsg) => cb.actsg = *actsg,
+                _ => println!("overlay {} actsg {} skipped", reference, actsg),
+            }
+        }
+        println!("overlay {} applied {} values", self.path.display(), count);
+    }
+
+    /*模型里面记下的落盘改动  有的话整个文件重写  没有 note_persist 过就不写     */
+    pub fn save(&mut self, model: &mut Model) {
+        let persist_vec = model.take_persists();
+        if persist_vec.is_empty() {
+            return;
+        }
+        for objref in persist_vec.iter() {
+            if let Some(node) = model.get_node(objref) {
+                let mut leaf_ref = ObjRef {
+                    fc: None,
+                    ..objref.clone()
+                };
+                collect(node, objref.fc.as_deref(), &mut leaf_ref, &mut self.data);
+            }
+        }
+        self.data.actsg_map = model
+            .ld_vec
+            .iter()
+            .flat_map(|ld| {
+                ld.ln_vec.iter().filter_map(|ln| {
+                    let cb = ln.sgcb.as_ref()?;
+                    Some((format!("{}/{}.SGCB", ld.name, ln.name), cb.actsg))
+                })
+            })
+            .collect();
+        let content = match serde_json::to_string_pretty(&self.data) {
+            Ok(content) => content,
+            Err(err) => {
+                println!("overlay {} encode failed {}", self.path.display(), err);
+                return;
+            }
+        };
+        let path = &self.path;
+        self.write_tx
+            .get_or_insert_with(|| {
+                let (tx, rx) = watch::channel(String::new());
+                tokio::spawn(write_task(path.clone(), rx));
+                tx
+            })
+            .send_replace(content);
+    }
+}
+
+/*后台写文件  每次取最新的内容     */
+async fn write_task(path: PathBuf, mut rx: watch::Receiver<String>) {
+    while rx.changed().await.is_ok() {
+        let content = rx.borrow_and_update().clone();
+        if let Err(err) = write_file(&path, content).await {
+            println!("overlay {} write failed {:?}", path.display(), err);
+        }
+    }
+}
+
+async fn write_file(path: &Path, content: String) -> crate::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, content).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+impl Model {
+    /*objref 下面要落盘的值刚改过  落盘那边取走重写文件     */
+    pub fn note_persist(&mut self, objref: &ObjRef) {
+        self.persist_vec.push(objref.clone());
+    }
+    pub fn take_persists(&mut self) -> Vec<ObjRef> {
+        std::mem::take(&mut self.persist_vec)
+    }
+}
+
+fn encode(val: &Value) -> Option<String> {
+    aper::aper_to_bytes(val)
+        .ok()
+        .map(|data| utils::bytes_to_hex(&data))
+}
+
+/*叶子按功能约束放到对应的表里  objref 一路跟着往下走     */
+fn collect(node: &DataNode, fc: Option<&str>, objref: &mut ObjRef, data: &mut OverlayData) {
+    if node.is_leaf() {
+        if fc.is_some_and(|fc| !node.has_fc(fc)) {
+            return;
+        }
+        let reference = ObjRef {
+            fc: Some(node.fc.clone()),
+            ..objref.clone()
+        }
+        .to_string();
+        if PERSIST_FC.contains(&node.fc.as_str()) {
+            if let Some(hex) = encode(&node.value) {
+                data.value_map.insert(reference, hex);
+            }
+        } else if node.fc == FC_SG {
+            let sg_map = data.sg_map.entry(reference).or_default();
+            for (sg, val) in node.sg_value_vec.iter() {
+                if let Some(hex) = encode(val) {
+                    sg_map.insert(*sg, hex);
+                }
+            }
+        }
+        return;
+    }
+    for child in node.child_vec.iter() {
+        objref.path.push(child.name.clone());
+        collect(child, fc, objref, data);
+        objref.path.pop();
+    }
+}
+
+/*一个叶子  找到 解码 类型对上了才交给 f     */
+fn apply_one(
+    model: &mut Model,
+    reference: &str,
+    hex: &str,
+    f: impl FnOnce(&mut DataNode, Value),
+) -> crate::Result<()> {
+    let objref = ObjRef::parse(reference)?;
+    let node = match model.get_node_mut(&objref) {
+        Some(node) if node.is_leaf() => node,
+        _ => bail!(format!("{} is not a leaf in model", reference)),
+    };
+    let val: Value = aper::aper_from_bytes(&utils::hex_to_bytes(hex)?)?;
+    match val.conform_to(&node.value) {
+        Some(val) => f(node, val),
+        None => bail!(format!("{} type changed", reference)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*样例里面没有 SG  按模板里面的注释把 ING 的 setVal 改成 SG 拷一份     */
+    async fn sg_model(name: &str) -> Model {
+        let scl = std::fs::read_to_string("sisco_sample.cid")
+            .unwrap()
+            .replace("fc=\"SP\"\tbType=\"INT32\"", "fc=\"SG\"\tbType=\"INT32\"");
+        let path = std::env::temp_dir().join(format!(
+            "cms61850_overlay_{}_{}.cid",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, scl).unwrap();
+        let info = crate::cfg::scl2::scl_parse(path.to_str().unwrap(), "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let _ = fs::remove_file(&path);
+        Model::from_scl(&info).unwrap()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "cms61850_overlay_{}_{}.json",
+            name,
+            std::process::id()
+        ))
+    }
+
+    /*第一个这个功能约束的 INT32 或者 BOOLEAN 叶子     */
+    fn find_leaf(model: &Model, fc: &str) -> ObjRef {
+        fn walk(node: &DataNode, fc: &str, objref: &mut ObjRef) -> bool {
+            if node.is_leaf() {
+                return node.fc == fc && matches!(node.value, Value::Int32(_) | Value::Boolean(_));
+            }
+            for child in node.child_vec.iter() {
+                objref.path.push(child.name.clone());
+                if walk(child, fc, objref) {
+                    return true;
+                }
+                objref.path.pop();
+            }
+            false
+        }
+        for ld in model.ld_vec.iter() {
+            for ln in ld.ln_vec.iter() {
+                for node in ln.do_vec.iter() {
+                    let mut objref = ObjRef {
+                        ld: ld.name.clone(),
+                        ln: ln.name.clone(),
+                        path: vec![node.name.clone()],
+                        fc: None,
+                    };
+                    if walk(node, fc, &mut objref) {
+                        objref.fc = Some(fc.to_string());
+                        return objref;
+                    }
+                }
+            }
+        }
+        panic!("no {} leaf in sample", fc);
+    }
+
+    fn other(val: &Value) -> Value {
+        match val {
+            Value::Int32(v) => Value::Int32(v.wrapping_add(7)),
+            Value::Boolean(v) => Value::Boolean(!v),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    /*后台写完 文件改名出来了才算     */
+    async fn wait_written(path: &Path) {
+        for _ in 0..200 {
+            if path.exists() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("{} not written", path.display());
+    }
+
+    #[tokio::test]
+    async fn save_load_round_trip() {
+        let path = temp_path("round_trip");
+        let _ = fs::remove_file(&path);
+        let mut model = sg_model("round_trip").await;
+        let sp = find_leaf(&model, "SP");
+        let cf = find_leaf(&model, "CF");
+        let sg = find_leaf(&model, FC_SG);
+
+        let mut expect_vec = Vec::new();
+        for objref in [&sp, &cf] {
+            let node = model.get_node_mut(objref).unwrap();
+            node.value = other(&node.value);
+            expect_vec.push((objref.clone(), node.value.clone()));
+            model.note_persist(objref);
+        }
+        let node = model.get_node_mut(&sg).unwrap();
+        let sg1 = other(&node.value);
+        let sg2 = other(&sg1);
+        node.set_sg_value(1, sg1.clone());
+        node.set_sg_value(2, sg2.clone());
+        model.note_persist(&sg);
+
+        let mut overlay = Overlay::open(path.clone(), "id", "1", "2");
+        overlay.save(&mut model);
+        wait_written(&path).await;
+
+        let mut model = sg_model("round_trip").await;
+        Overlay::open(path.clone(), "id", "1", "2").apply(&mut model);
+        for (objref, val) in expect_vec.iter() {
+            assert_eq!(&model.get_node(objref).unwrap().value, val);
+        }
+        let node = model.get_node(&sg).unwrap();
+        assert_eq!(node.sg_value(1), sg1);
+        assert_eq!(node.sg_value(2), sg2);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn header_mismatch_discards() {
+        let path = temp_path("mismatch");
+        let _ = fs::remove_file(&path);
+        let mut model = sg_model("mismatch").await;
+        let cf = find_leaf(&model, "CF");
+        let old = model.get_node(&cf).unwrap().value.clone();
+        model.get_node_mut(&cf).unwrap().value = other(&old);
+        model.note_persist(&cf);
+        let mut overlay = Overlay::open(path.clone(), "id", "1", "2");
+        overlay.save(&mut model);
+        wait_written(&path).await;
+
+        /*id version revision 哪个变了都不要     */
+        for (id, version, revision) in [("id2", "1", "2"), ("id", "12", ""), ("id", "1", "3")] {
+            let mut model = sg_model("mismatch").await;
+            Overlay::open(path.clone(), id, version, revision).apply(&mut model);
+            assert_eq!(model.get_node(&cf).unwrap().value, old);
+        }
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,450 @@
+use crate::cfg::scl2::SclEnumtype;
+use crate::cms61850::codec::aper::{AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::service::common::ServiceError;
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use std::fmt;
+/***************************************/
+/*值  对应 cms.asn1 里面的 Data
+模型里面叶子的值都用这个  结构体和数组是从子节点拼出来的     */
+/***************************************/
+
+/*Data CHOICE 一共 24 个分支     */
+const DATA_CHOICE_COUNT: usize = 24;
+/*数组 结构体 嵌套最大层数  防止对端发很深的嵌套     */
+const MAX_DATA_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /*只在响应里面用  表示这一项读失败     */
+    Error(ServiceError),
+    Array(Vec<Value>),
+    Struct(Vec<Value>),
+    Boolean(bool),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Int8u(u8),
+    Int16u(u16),
+    Int32u(u32),
+    Int64u(u64),
+    Float32(f32),
+    Float64(f64),
+    /*位串  字节 高位在前  后面是位数     */
+    BitString(Vec<u8>, usize),
+    Octet(Vec<u8>),
+    VisString(String),
+    Unicode(String),
+    /*秒(4) + 秒的小数(3) + 时间品质(1)     */
+    Timestamp([u8; 8]),
+    /*日内毫秒(4) + 1984-01-01 以来的天数(2)     */
+    EntryTime([u8; 6]),
+    /*13 位  第 n 位是 0x8000 >> n     */
+    Quality(u16),
+    /*0 中间态 1 分 2 合 3 坏状态     */
+    Dbpos(u8),
+    /*0 停止 1 降 2 升 3 保留     */
+    Tcmd(u8),
+    /*bit1 同期检查 bit0 联锁检查     */
+    Check(u8),
+    /*枚举 编码的时候按 int8     */
+    Enum(i8),
+    /*对象引用 编码的时候按 visible-string     */
+    ObjRef(String),
+}
+
+/*SX_ERR_CONVERT 这一类的诊断  path 是实例里面的路径     */
+#[derive(Clone, Debug)]
+pub struct ConvertErr {
+    pub code: u32,
+    pub path: String,
+    pub btype: String,
+    pub text: String,
+    pub reason: String,
+}
+
+impl fmt::Display for ConvertErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SX_ERR_CONVERT({}) {} bType {} val '{}': {}",
+            self.code, self.path, self.btype, self.text, self.reason
+        )
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::Struct(Vec::new())
+    }
+}
+
+/*bType 对应的值的种类 和 字符串的最大长度     */
+pub fn btype_max_len(btype: &str) -> Option<usize> {
+    match btype {
+        "VisString32" => Some(32),
+        "VisString64" => Some(64),
+        "VisString65" => Some(65),
+        "VisString129" | "ObjRef" => Some(129),
+        "VisString255" | "Unicode255" => Some(255),
+        "Octet64" => Some(64),
+        "Octet6" => Some(6),
+        "Octet16" => Some(16),
+        "Currency" => Some(3),
+        _ => None,
+    }
+}
+
+/*UtcTime  小数部分 24 位     */
+pub fn utc_time(dt: DateTime<Utc>) -> [u8; 8] {
+    let mut out = [0u8; 8];
+    out[..4].copy_from_slice(&(dt.timestamp() as u32).to_be_bytes());
+    let fraction = ((dt.timestamp_subsec_nanos() as u64) << 24) / 1_000_000_000;
+    out[4..7].copy_from_slice(&(fraction as u32).to_be_bytes()[1..]);
+    out
+}
+
+/*UtcTime 转回 1970 以来的毫秒     */
+pub fn utc_time_millis(time: &[u8; 8]) -> i64 {
+    let secs = u32::from_be_bytes([time[0], time[1], time[2], time[3]]) as i64;
+    let fraction = u32::from_be_bytes([0, time[4], time[5], time[6]]) as i64;
+    secs * 1000 + ((fraction * 1000) >> 24)
+}
+
+/*1970 到 1984 的天数     */
+const DAYS_1970_1984: i64 = 5113;
+
+/*EntryTime  从 1984-01-01 开始     */
+pub fn entry_time(dt: DateTime<Utc>) -> [u8; 6] {
+    let mut out = [0u8; 6];
+    let ms = dt.timestamp_millis();
+    let days = ms.div_euclid(86_400_000) - DAYS_1970_1984;
+    let ms_of_day = ms.rem_euclid(86_400_000);
+    out[..4].copy_from_slice(&(ms_of_day as u32).to_be_bytes());
+    out[4..].copy_from_slice(&(days.max(0) as u16).to_be_bytes());
+    out
+}
+
+/*EntryTime 转回 1970 以来的毫秒     */
+pub fn entry_time_millis(time: &[u8; 6]) -> i64 {
+    let ms_of_day = u32::from_be_bytes([time[0], time[1], time[2], time[3]]) as i64;
+    let days = u16::from_be_bytes([time[4], time[5]]) as i64;
+    (days + DAYS_1970_1984) * 86_400_000 + ms_of_day
+}
+
+impl Value {
+    /*bType 对应的初始值  Struct 不是叶子 没有     */
+    pub fn default_for(btype: &str) -> Option<Value> {
+        let val = match btype {
+            "BOOLEAN" => Value::Boolean(false),
+            "INT8" => Value::Int8(0),
+            "INT16" => Value::Int16(0),
+            "INT24" | "INT32" => Value::Int32(0),
+            "INT64" | "INT128" => Value::Int64(0),
+            "INT8U" => Value::Int8u(0),
+            "INT16U" => Value::Int16u(0),
+            "INT24U" | "INT32U" => Value::Int32u(0),
+            "INT64U" => Value::Int64u(0),
+            "FLOAT32" => Value::Float32(0.0),
+            "FLOAT64" => Value::Float64(0.0),
+            "Enum" => Value::Enum(0),
+            "Dbpos" => Value::Dbpos(0),
+            "Tcmd" => Value::Tcmd(0),
+            "Quality" => Value::Quality(0),
+            "Timestamp" => Value::Timestamp([0; 8]),
+            "EntryTime" => Value::EntryTime([0; 6]),
+            "Check" => Value::Check(0),
+            "VisString32" | "VisString64" | "VisString65" | "VisString129" | "VisString255"
+            | "Currency" => Value::VisString(String::new()),
+            "Unicode255" => Value::Unicode(String::new()),
+            "Octet64" | "Octet6" | "Octet16" | "PhyComAddr" => Value::Octet(Vec::new()),
+            "EntryID" => Value::Octet(vec![0; 8]),
+            "ObjRef" => Value::ObjRef(String::new()),
+            "TrgOps" => Value::BitString(vec![0], 6),
+            "OptFlds" => Value::BitString(vec![0, 0], 10),
+            "SvOptFlds" => Value::BitString(vec![0], 5),
+            "LogOptFlds" => Value::BitString(vec![0], 1),
+            _ => return None,
+        };
+        Some(val)
+    }
+
+    /*scl 里面 Val 的文本转成值  枚举用 enumtype 里面的名字查 ord     */
+    pub fn from_scl_text(
+        btype: &str,
+        text: &str,
+        enumtype: Option<&SclEnumtype>,
+    ) -> crate::Result<Value> {
+        let text = text.trim();
+        let default = match Value::default_for(btype) {
+            Some(val) => val,
+            None => bail!(format!("unsupported bType {}", btype)),
+        };
+        if text.is_empty() {
+            return Ok(default);
+        }
+        let val = match default {
+            Value::Boolean(_) => match text {
+                "true" | "1" => Value::Boolean(true),
+                "false" | "0" => Value::Boolean(false),
+                _ => bail!(format!("bad boolean {}", text)),
+            },
+            Value::Int8(_) => Value::Int8(text.parse()?),
+            Value::Int16(_) => Value::Int16(text.parse()?),
+            Value::Int32(_) => Value::Int32(text.parse()?),
+            Value::Int64(_) => Value::Int64(text.parse()?),
+            Value::Int8u(_) => Value::Int8u(text.parse()?),
+            Value::Int16u(_) => Value::Int16u(text.parse()?),
+            Value::Int32u(_) => Value::Int32u(text.parse()?),
+            Value::Int64u(_) => Value::Int64u(text.parse()?),
+            Value::Float32(_) => Value::Float32(text.parse()?),
+            Value::Float64(_) => Value::Float64(text.parse()?),
+            Value::Enum(_) => {
+                let by_name = enumtype.and_then(|et| {
+                    et.enumval_vec
+                        .iter()
+                        .find(|ev| ev.enumval == text)
+                        .map(|ev| ev.ord)
+                });
+                let ord = match by_name {
+                    Some(ord) => ord,
+                    None => match text.parse::<i32>() {
+                        Ok(ord) => ord,
+                        Err(_) => bail!(format!("enum value {} not in enum type", text)),
+                    },
+                };
+                match i8::try_from(ord) {
+                    Ok(ord) => Value::Enum(ord),
+                    Err(_) => bail!(format!("enum ord {} out of range", ord)),
+                }
+            }
+            Value::Dbpos(_) => Value::Dbpos(match text {
+                "intermediate-state" => 0,
+                "off" => 1,
+                "on" => 2,
+                "bad-state" => 3,
+                _ => parse_2bits(text)?,
+            }),
+            Value::Tcmd(_) => Value::Tcmd(match text {
+                "stop" => 0,
+                "lower" => 1,
+                "higher" => 2,
+                "reserved" => 3,
+                _ => parse_2bits(text)?,
+            }),
+            Value::Check(_) => Value::Check(parse_2bits(text)?),
+            Value::Quality(_) => {
+                let q: u16 = text.parse()?;
+                if q & 0x0007 != 0 {
+                    bail!(format!("quality {} more than 13 bits", text));
+                }
+                Value::Quality(q)
+            }
+            Value::Timestamp(_) => {
+                let dt = DateTime::parse_from_rfc3339(text)?;
+                Value::Timestamp(utc_time(dt.with_timezone(&Utc)))
+            }
+            Value::EntryTime(_) => {
+                let dt = DateTime::parse_from_rfc3339(text)?;
+                Value::EntryTime(entry_time(dt.with_timezone(&Utc)))
+            }
+            Value::VisString(_) | Value::ObjRef(_) => {
+                if !text.bytes().all(|b| (0x20..=0x7e).contains(&b)) {
+                    bail!(format!("not visible string {}", text));
+                }
+                check_max_len(btype, text.len())?;
+                match default {
+                    Value::ObjRef(_) => Value::ObjRef(text.to_string()),
+                    _ => Value::VisString(text.to_string()),
+                }
+            }
+            Value::Unicode(_) => {
+                check_max_len(btype, text.chars().count())?;
+                Value::Unicode(text.to_string())
+            }
+            Value::Octet(_) => {
+                check_max_len(btype, text.len())?;
+                Value::Octet(text.as_bytes().to_vec())
+            }
+            _ => bail!(format!("bType {} can not set from text", btype)),
+        };
+        Ok(val)
+    }
+
+    /*Data 的分支号     */
+    fn choice_index(&self) -> usize {
+        match self {
+            Value::Error(_) => 0,
+            Value::Array(_) => 1,
+            Value::Struct(_) => 2,
+            Value::Boolean(_) => 3,
+            Value::Int8(_) | Value::Enum(_) => 4,
+            Value::Int16(_) => 5,
+            Value::Int32(_) => 6,
+            Value::Int64(_) => 7,
+            Value::Int8u(_) => 8,
+            Value::Int16u(_) => 9,
+            Value::Int32u(_) => 10,
+            Value::Int64u(_) => 11,
+            Value::Float32(_) => 12,
+            Value::Float64(_) => 13,
+            Value::BitString(_, _) => 14,
+            Value::Octet(_) => 15,
+            Value::VisString(_) | Value::ObjRef(_) => 16,
+            Value::Unicode(_) => 17,
+            Value::Timestamp(_) => 18,
+            Value::EntryTime(_) => 19,
+            Value::Quality(_) => 20,
+            Value::Dbpos(_) => 21,
+            Value::Tcmd(_) => 22,
+            Value::Check(_) => 23,
+        }
+    }
+
+    fn decode_depth(dec: &mut AperDecoder, depth: usize) -> crate::Result<Value> {
+        if depth > MAX_DATA_DEPTH {
+            bail!("data nested too deep");
+        }
+        let index = dec.get_choice_index(DATA_CHOICE_COUNT, false)?;
+        let val = match index {
+            0 => Value::Error(ServiceError::aper_decode(dec)?),
+            1 | 2 => {
+                let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+                let mut vals = Vec::new();
+                for _ in 0..count {
+                    vals.push(Value::decode_depth(dec, depth + 1)?);
+                }
+                if index == 1 {
+                    Value::Array(vals)
+                } else {
+                    Value::Struct(vals)
+                }
+            }
+            3 => Value::Boolean(dec.get_bool()?),
+            4 => Value::Int8(dec.get_constrained_int(i8::MIN as i128, i8::MAX as i128)? as i8),
+            5 => Value::Int16(dec.get_constrained_int(i16::MIN as i128, i16::MAX as i128)? as i16),
+            6 => Value::Int32(dec.get_constrained_int(i32::MIN as i128, i32::MAX as i128)? as i32),
+            7 => Value::Int64(dec.get_constrained_int(i64::MIN as i128, i64::MAX as i128)? as i64),
+            8 => Value::Int8u(dec.get_constrained_int(0, u8::MAX as i128)? as u8),
+            9 => Value::Int16u(dec.get_constrained_int(0, u16::MAX as i128)? as u16),
+            10 => Value::Int32u(dec.get_constrained_int(0, u32::MAX as i128)? as u32),
+            11 => Value::Int64u(dec.get_constrained_int(0, u64::MAX as i128)? as u64),
+            12 => Value::Float32(dec.get_f32()?),
+            13 => Value::Float64(dec.get_f64()?),
+            14 => {
+                let (bits, nbits) = dec.get_bit_string(Size::Unconstrained)?;
+                Value::BitString(bits, nbits)
+            }
+            15 => Value::Octet(dec.get_octet_string(Size::Unconstrained)?),
+            16 => Value::VisString(dec.get_visible_string(Size::Unconstrained)?),
+            17 => Value::Unicode(dec.get_utf8_string()?),
+            18 => {
+                let mut out = [0u8; 8];
+                out.copy_from_slice(&dec.get_octet_string(Size::Fixed(8))?);
+                Value::Timestamp(out)
+            }
+            19 => {
+                let mut out = [0u8; 6];
+                out.copy_from_slice(&dec.get_octet_string(Size::Fixed(6))?);
+                Value::EntryTime(out)
+            }
+            20 => {
+                let (bits, _) = dec.get_bit_string(Size::Fixed(13))?;
+                Value::Quality(u16::from_be_bytes([bits[0], bits[1]]))
+            }
+            21 => Value::Dbpos(get_2bits(dec)?),
+            22 => Value::Tcmd(get_2bits(dec)?),
+            _ => Value::Check(get_2bits(dec)?),
+        };
+        Ok(val)
+    }
+
+    /*对端发来的值  按模型里面的类型修正一下
+    int8 可能是枚举  visible-string 可能是对象引用 其他的必须同一个分支     */
+    pub fn conform_to(self, model_val: &Value) -> Option<Value> {
+        match (self, model_val) {
+            (Value::Int8(v), Value::Enum(_)) => Some(Value::Enum(v)),
+            (Value::VisString(v), Value::ObjRef(_)) => Some(Value::ObjRef(v)),
+            (val, model_val) if val.choice_index() == model_val.choice_index() => Some(val),
+            _ => None,
+        }
+    }
+}
+
+fn parse_2bits(text: &str) -> crate::Result<u8> {
+    let val: u8 = text.parse()?;
+    if val > 3 {
+        bail!(format!("{} more than 2 bits", text));
+    }
+    Ok(val)
+}
+
+fn put_2bits(enc: &mut AperEncoder, val: u8) -> crate::Result<()> {
+    enc.put_bit_string(&[val << 6], 2, Size::Fixed(2))
+}
+
+fn get_2bits(dec: &mut AperDecoder) -> crate::Result<u8> {
+    let (bits, _) = dec.get_bit_string(Size::Fixed(2))?;
+    Ok(bits[0] >> 6)
+}
+
+fn check_max_len(btype: &str, len: usize) -> crate::Result<()> {
+    if let Some(max) = btype_max_len(btype) {
+        if len > max {
+            bail!(format!("length {} more than {}", len, max));
+        }
+    }
+    Ok(())
+}
+
+impl AperCodec for Value {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_choice_index(self.choice_index(), DATA_CHOICE_COUNT, false)?;
+        match self {
+            Value::Error(err) => err.aper_encode(enc),
+            Value::Array(vals) | Value::Struct(vals) => {
+                enc.put_sequence_of_len(vals.len(), Size::Unconstrained)?;
+                for val in vals.iter() {
+                    val.aper_encode(enc)?;
+                }
+                Ok(())
+            }
+            Value::Boolean(v) => {
+                enc.put_bool(*v);
+                Ok(())
+            }
+            Value::Int8(v) | Value::Enum(v) => {
+                enc.put_constrained_int(*v as i128, i8::MIN as i128, i8::MAX as i128)
+            }
+            Value::Int16(v) => {
+                enc.put_constrained_int(*v as i128, i16::MIN as i128, i16::MAX as i128)
+            }
+            Value::Int32(v) => {
+                enc.put_constrained_int(*v as i128, i32::MIN as i128, i32::MAX as i128)
+            }
+            Value::Int64(v) => {
+                enc.put_constrained_int(*v as i128, i64::MIN as i128, i64::MAX as i128)
+            }
+            Value::Int8u(v) => enc.put_constrained_int(*v as i128, 0, u8::MAX as i128),
+            Value::Int16u(v) => enc.put_constrained_int(*v as i128, 0, u16::MAX as i128),
+            Value::Int32u(v) => enc.put_constrained_int(*v as i128, 0, u32::MAX as i128),
+            Value::Int64u(v) => enc.put_constrained_int(*v as i128, 0, u64::MAX as i128),
+            Value::Float32(v) => enc.put_f32(*v),
+            Value::Float64(v) => enc.put_f64(*v),
+            Value::BitString(bits, nbits) => enc.put_bit_string(bits, *nbits, Size::Unconstrained),
+            Value::Octet(v) => enc.put_octet_string(v, Size::Unconstrained),
+            Value::VisString(v) | Value::ObjRef(v) => {
+                enc.put_visible_string(v, Size::Unconstrained)
+            }
+            Value::Unicode(v) => enc.put_utf8_string(v),
+            Value::Timestamp(v) => enc.put_octet_string(v, Size::Fixed(8)),
+            Value::EntryTime(v) => enc.put_octet_string(v, Size::Fixed(6)),
+            Value::Quality(v) => enc.put_bit_string(&v.to_be_bytes(), 13, Size::Fixed(13)),
+            Value::Dbpos(v) | Value::Tcmd(v) | Value::Check(v) => put_2bits(enc, *v),
+        }
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Value::decode_depth(dec, 0)
+    }
+}
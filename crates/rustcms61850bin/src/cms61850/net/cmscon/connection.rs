@@ -1,12 +1,22 @@
 use super::super::shutdown::Shutdown;
 use super::frame;
-use crate::comdata::{self, CfgData, ExData};
+use crate::cms61850::codec::aper::{self, AperCodec};
+use crate::cms61850::service::associate::{
+    self, AbortRequestPDU, AssociateRequestPDU, AssociateResponsePDU, ReleaseRequestPDU,
+    ReleaseResponsePDU,
+};
+use crate::cms61850::service::associatenegotiate::{
+    AssociateNegotiateRequestPDU, AssociateNegotiateResponsePDU, CMS_PROTOCOL_VERSION,
+};
+use crate::cms61850::service::common::ServiceError;
+use crate::comdata::{self, CfgData, ExData, ToConData};
 use anyhow::bail;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::sink::SinkExt;
 use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use tokio::{
     io::{self, BufWriter},
     net::{TcpListener, TcpStream},
@@ -36,30 +46,70 @@ fn default_ping_test_interval_sec() -> u32 {
 fn default_close_count_when_no_ping_pong() -> u32 {
     2
 }
+/***************************************/
+/*关联状态机
+Idle -> Negotiated -> Associated -> Releasing -> Closed
+中止 任何状态下都直接到 Closed     */
+/***************************************/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AssocState {
+    /*刚建立 tcp 连接     */
+    #[default]
+    Idle,
+    /*完成关联协商     */
+    Negotiated,
+    /*完成关联 可以访问数据服务     */
+    Associated,
+    /*收到释放请求 等未完成的响应发完     */
+    Releasing,
+    /*已经释放 或者中止     */
+    Closed,
+}
+
+/*连接管理看到的每个连接的信息     */
+#[derive(Clone, Debug)]
+pub struct ConInfo {
+    /*对端地址     */
+    pub peer: String,
+    pub state: AssocState,
+}
+/*连接号 -> 连接信息  连接管理 和 连接 共享     */
+pub type ConStateMap = Arc<Mutex<HashMap<u32, ConInfo>>>;
+
 #[derive(Default, Clone, Debug)]
 pub struct RunningData {
     /*协商过后的apdu 长度 和asdu 的长度
     本地初始配置 只能从本地配置获取     */
     pub neg_apdu_len: u16,
     pub neg_asdu_len: usize,
+    /*关联状态     */
+    pub state: AssocState,
+    /*关联成功后分配的关联号     */
+    pub association_id: Vec<u8>,
+    /*已经收到 还没有回复的请求个数     */
+    pub outstanding: usize,
+    /*释放请求要等 outstanding 为0 才回复     */
+    pub pending_release: Option<frame::APDU>,
+    /*多帧重组     */
+    pub reassembler: frame::APDUReassembler,
 }
 
 impl RunningData {
     fn neg_len(&mut self, coming_apdu_len: u16, coming_asdu_len: usize) {
         //我的只能比你大 你小了 我就迁就你
         if coming_apdu_len < self.neg_apdu_len {
-            self.neg_apdu_len = coming_apdu_len;
             println!(
-                "con neg len origin {} now {}",
+                "con neg apdu len origin {} now {}",
                 self.neg_apdu_len, coming_apdu_len
             );
+            self.neg_apdu_len = coming_apdu_len;
         }
         if coming_asdu_len < self.neg_asdu_len {
-            self.neg_asdu_len = coming_asdu_len;
             println!(
-                "con neg len origin {} now {}",
+                "con neg asdu len origin {} now {}",
                 self.neg_asdu_len, coming_asdu_len
             );
+            self.neg_asdu_len = coming_asdu_len;
         }
     }
     /*只有第一次初始化的时候才需要  后面使用协商过来的数据     */
@@ -227,16 +277,47 @@ impl ClientConnetion {
             // signal.
             tokio::select! {
                 res = self.connection.next() =>{
-                    if res.is_some() {
-                        //todo
-                        //处理过来的信息
-
-
-                    }else{
-                        return Ok(());
+                    match res {
+                        Some(Ok(apdu)) => {
+                            //处理过来的信息
+                            self.handle_frame(&mut run_data, apdu).await?;
+                            self.try_finish_release(&mut run_data).await?;
+                            if run_data.state == AssocState::Closed {
+                                return Ok(());
+                            }
+                        }
+                        Some(Err(e)) => {
+                            bail!(format!("read apdu failed {}", e));
+                        }
+                        None => {
+                            return Ok(());
+                        }
                     }
 
                 }
+                //汇总中心回过来的响应
+                to_con = self.from_unit_rx.recv() =>{
+                    match to_con {
+                        Some(ToConData::ServiceResp(builder)) => {
+                            run_data.outstanding = run_data.outstanding.saturating_sub(1);
+                            self.send_apdus(&run_data, builder).await?;
+                            self.try_finish_release(&mut run_data).await?;
+                            if run_data.state == AssocState::Closed {
+                                return Ok(());
+                            }
+                        }
+                        /*只有关联上了才发报告     */
+                        Some(ToConData::Report(builder)) => {
+                            if run_data.state == AssocState::Associated {
+                                self.send_apdus(&run_data, builder).await?;
+                            }
+                        }
+                        /*汇总中心那边队列满了把这个连接放掉  或者汇总中心退了     */
+                        None => {
+                            bail!("dropped by cms unit");
+                        }
+                    }
+                }
                 //收到主路信息
                 maininfo= self.to_each_handle_rcvch.recv() =>{
                     // use super::cmd;
@@ -278,8 +359,291 @@ impl ClientConnetion {
     }
 }
 
+impl ClientConnetion {
+    /*先重组后续帧  完整了再分发     */
+    async fn handle_frame(
+        &mut self,
+        run_data: &mut RunningData,
+        apdu: frame::APDU,
+    ) -> crate::Result<()> {
+        let max_asdu_len = run_data.neg_asdu_len;
+        for item in run_data.reassembler.push(apdu, max_asdu_len) {
+            match &item {
+                frame::Reassembled::Complete(_) => {}
+                frame::Reassembled::Interleaved(head) => println!(
+                    "con {} frame sequence serv code {} req id {} interleaved",
+                    self.con_id, head.apch.serv_code, head.asdu.req_id
+                ),
+                frame::Reassembled::Oversized(head) => println!(
+                    "con {} frame sequence serv code {} req id {} exceed asdu len {}",
+                    self.con_id, head.apch.serv_code, head.asdu.req_id, max_asdu_len
+                ),
+            }
+            match item {
+                frame::Reassembled::Complete(apdu) => self.handle_apdu(run_data, apdu).await?,
+                /*响应帧出错 error_resp 是 None 不用回     */
+                item => {
+                    if let Some(builder) = item.error_resp() {
+                        self.send_apdus(run_data, builder).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /*按服务码分发     */
+    async fn handle_apdu(
+        &mut self,
+        run_data: &mut RunningData,
+        apdu: frame::APDU,
+    ) -> crate::Result<()> {
+        if apdu.apch.is_resp {
+            /*服务端不应该收到响应 丢掉     */
+            println!("get resp apdu serv code {}", apdu.apch.serv_code);
+            return Ok(());
+        }
+        match apdu.apch.serv_code {
+            frame::ASSOCIATENEGOTIATE => self.associate_negotiate(run_data, apdu).await,
+            frame::ASSOCIATE => self.associate(run_data, apdu).await,
+            frame::RELEASE => self.release(run_data, apdu).await,
+            frame::ABORT => {
+                self.abort(run_data, apdu);
+                Ok(())
+            }
+            _ => {
+                /*没有关联 或者正在释放 都不能访问数据服务     */
+                if run_data.state != AssocState::Associated {
+                    println!(
+                        "con {} serv code {} refused in state {:?}",
+                        self.con_id, apdu.apch.serv_code, run_data.state
+                    );
+                    return self
+                        .send_service_error(
+                            run_data,
+                            &apdu,
+                            ServiceError::AccessNotAllowedInCurrentState,
+                        )
+                        .await;
+                }
+                self.forward_service(run_data, apdu).await
+            }
+        }
+    }
+
+    /*数据服务交给汇总中心  响应从 from_unit_rx 回来     */
+    async fn forward_service(
+        &mut self,
+        run_data: &mut RunningData,
+        apdu: frame::APDU,
+    ) -> crate::Result<()> {
+        let req = comdata::ServiceReq {
+            con_id: self.con_id,
+            apdu: apdu.clone(),
+            neg_asdu_len: run_data.neg_asdu_len,
+        };
+        if self.to_main_info_tx.send(ExData::ServiceReq(req)).await.is_err() {
+            println!("con {} cms unit closed", self.con_id);
+            return self
+                .send_service_error(run_data, &apdu, ServiceError::FailedDueToServerConstraint)
+                .await;
+        }
+        run_data.outstanding += 1;
+        Ok(())
+    }
+
+    /*状态变化 同步给连接管理     */
+    fn set_state(&self, run_data: &mut RunningData, state: AssocState) {
+        println!(
+            "con {} state {:?} -> {:?}",
+            self.con_id, run_data.state, state
+        );
+        run_data.state = state;
+        if let Ok(mut states) = self.con_states.lock() {
+            if let Some(info) = states.get_mut(&self.con_id) {
+                info.state = state;
+            }
+        }
+    }
+
+    /*关联  必须先协商     */
+    async fn associate(&mut self, run_data: &mut RunningData, apdu: frame::APDU) -> crate::Result<()> {
+        if run_data.state != AssocState::Negotiated {
+            return self
+                .send_service_error(run_data, &apdu, ServiceError::AccessNotAllowedInCurrentState)
+                .await;
+        }
+        let req: AssociateRequestPDU = match aper::aper_from_bytes(&apdu.asdu.pay_load) {
+            Ok(req) => req,
+            Err(e) => {
+                println!("decode associate failed {}", e);
+                return self
+                    .send_service_error(run_data, &apdu, ServiceError::ParameterValueInappropriate)
+                    .await;
+            }
+        };
+        if let Some(ref sap) = req.serveraccesspointreference {
+            println!("con {} associate to {}", self.con_id, sap);
+        }
+        run_data.association_id = associate::gen_association_id(self.con_id);
+        let resp = AssociateResponsePDU {
+            associationid: run_data.association_id.clone(),
+            serviceerror: None,
+            authenticationparameter: None,
+        };
+        self.send_resp(run_data, &apdu, false, &resp).await?;
+        self.set_state(run_data, AssocState::Associated);
+        Ok(())
+    }
+
+    /*释放  先进入 Releasing  等未完成的响应都发完了再回复     */
+    async fn release(&mut self, run_data: &mut RunningData, apdu: frame::APDU) -> crate::Result<()> {
+        if run_data.state != AssocState::Associated {
+            return self
+                .send_service_error(run_data, &apdu, ServiceError::AccessNotAllowedInCurrentState)
+                .await;
+        }
+        let req: ReleaseRequestPDU = match aper::aper_from_bytes(&apdu.asdu.pay_load) {
+            Ok(req) => req,
+            Err(e) => {
+                println!("decode release failed {}", e);
+                return self
+                    .send_service_error(run_data, &apdu, ServiceError::ParameterValueInappropriate)
+                    .await;
+            }
+        };
+        /*关联号对不上  和别的错误一样回 ServiceError  关联保持不变     */
+        if req.associationid != run_data.association_id {
+            println!("con {} release with wrong association id", self.con_id);
+            return self
+                .send_service_error(run_data, &apdu, ServiceError::ParameterValueInconsistent)
+                .await;
+        }
+        run_data.pending_release = Some(apdu);
+        self.set_state(run_data, AssocState::Releasing);
+        Ok(())
+    }
+
+    /*没有未完成的响应了 回复释放 然后关闭     */
+    async fn try_finish_release(&mut self, run_data: &mut RunningData) -> crate::Result<()> {
+        if run_data.state != AssocState::Releasing || run_data.outstanding > 0 {
+            return Ok(());
+        }
+        if let Some(apdu) = run_data.pending_release.take() {
+            let resp = ReleaseResponsePDU {
+                associationid: run_data.association_id.clone(),
+                serviceerror: None,
+            };
+            self.send_resp(run_data, &apdu, false, &resp).await?;
+        }
+        self.set_state(run_data, AssocState::Closed);
+        Ok(())
+    }
+
+    /*中止  不回复 直接关掉连接     */
+    fn abort(&mut self, run_data: &mut RunningData, apdu: frame::APDU) {
+        match aper::aper_from_bytes::<AbortRequestPDU>(&apdu.asdu.pay_load) {
+            Ok(req) => println!("con {} abort reason {}", self.con_id, req.reason),
+            Err(e) => println!("con {} abort with bad pdu {}", self.con_id, e),
+        }
+        self.set_state(run_data, AssocState::Closed);
+    }
+
+    /*关联协商  取双方较小的长度 ，协议版本不对直接回否定响应     */
+    async fn associate_negotiate(
+        &mut self,
+        run_data: &mut RunningData,
+        apdu: frame::APDU,
+    ) -> crate::Result<()> {
+        let req: AssociateNegotiateRequestPDU = match aper::aper_from_bytes(&apdu.asdu.pay_load)
+        {
+            Ok(req) => req,
+            Err(e) => {
+                println!("decode associate negotiate failed {}", e);
+                return self
+                    .send_service_error(
+                        run_data,
+                        &apdu,
+                        ServiceError::ParameterValueInappropriate,
+                    )
+                    .await;
+            }
+        };
+        if run_data.state != AssocState::Idle {
+            return self
+                .send_service_error(run_data, &apdu, ServiceError::AccessNotAllowedInCurrentState)
+                .await;
+        }
+        if req.protocolversion != CMS_PROTOCOL_VERSION {
+            println!(
+                "unsupported protocol version {} ,only {}",
+                req.protocolversion, CMS_PROTOCOL_VERSION
+            );
+            return self
+                .send_service_error(run_data, &apdu, ServiceError::ParameterValueInconsistent)
+                .await;
+        }
+        run_data.neg_len(req.apdusize, req.asdusize as usize);
+        let resp = AssociateNegotiateResponsePDU {
+            apdusize: run_data.neg_apdu_len,
+            asdusize: std::cmp::min(run_data.neg_asdu_len, u32::MAX as usize) as u32,
+            protocolversion: CMS_PROTOCOL_VERSION,
+            modelversion: self.cfg.model_version.clone(),
+        };
+        self.send_resp(run_data, &apdu, false, &resp).await?;
+        self.set_state(run_data, AssocState::Negotiated);
+        Ok(())
+    }
+
+    /*否定响应 载荷就是 ServiceError     */
+    async fn send_service_error(
+        &mut self,
+        run_data: &RunningData,
+        req: &frame::APDU,
+        err: ServiceError,
+    ) -> crate::Result<()> {
+        self.send_resp(run_data, req, true, &err).await
+    }
+
+    /*回复请求  和请求同一个服务码和 reqid     */
+    async fn send_resp<T: AperCodec>(
+        &mut self,
+        run_data: &RunningData,
+        req: &frame::APDU,
+        is_err: bool,
+        pdu: &T,
+    ) -> crate::Result<()> {
+        let builder = frame::APDUBuilder {
+            is_err,
+            is_resp: true,
+            serv_code: req.apch.serv_code,
+            req_id: req.asdu.req_id,
+            total_pay_load: aper::aper_to_bytes(pdu)?,
+        };
+        self.send_apdus(run_data, builder).await
+    }
+
+    /*应答 报告 都从这里发  分好的包连着写进去 最后一起 flush     */
+    async fn send_apdus(
+        &mut self,
+        run_data: &RunningData,
+        builder: frame::APDUBuilder,
+    ) -> crate::Result<()> {
+        for apdu in builder.pack(run_data.neg_apdu_len, run_data.neg_asdu_len) {
+            self.connection.feed(apdu).await?;
+        }
+        self.connection.flush().await?;
+        Ok(())
+    }
+}
+
 impl Drop for ClientConnetion {
     fn drop(&mut self) {
+        if let Ok(mut states) = self.con_states.lock() {
+            states.remove(&self.con_id);
+        }
+        /*告诉汇总中心 不要再往这个连接发了  满了丢掉也没事 汇总中心扫的时候看通道关了会清     */
+        let _ = self.to_main_info_tx.try_send(ExData::ConClose(self.con_id));
         self.limit_connections.add_permits(1);
     }
 }
@@ -288,12 +652,18 @@ impl Drop for ClientConnetion {
 /// commands to `db`.
 #[derive(Debug)]
 pub struct ClientConnetion {
+    /*连接号  全局唯一     */
+    pub con_id: u32,
     pub cfg: comdata::CfgData,
     pub to_main_info_tx: mpsc::Sender<ExData>,
+    /*汇总中心回给这个连接的     */
+    pub from_unit_rx: mpsc::Receiver<ToConData>,
     pub connection: Framed<BufWriter<TcpStream>, APDUCodec>,
     pub limit_connections: Arc<Semaphore>,
     pub shutdown: Shutdown,
     /// Not used directly. Instead, when `ClientConnetion` is dropped...?
     pub _shutdown_complete: mpsc::Sender<()>,
     pub to_each_handle_rcvch: broadcast::Receiver<ExData>,
+    /*关联状态 给连接管理看     */
+    pub con_states: ConStateMap,
 }
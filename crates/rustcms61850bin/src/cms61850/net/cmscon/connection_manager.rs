@@ -3,12 +3,14 @@ use super::frame;
 use crate::comdata::{self, ExData};
 use serde::{Deserialize, Serialize};
 
-use super::connection::{APDUCodec, ClientConnetion};
+use super::connection::{APDUCodec, AssocState, ClientConnetion, ConInfo, ConStateMap};
 use anyhow::bail;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::sink::SinkExt;
 use futures::stream::StreamExt;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::{
     io::{self, BufWriter},
     net::{TcpListener, TcpStream},
@@ -84,6 +86,12 @@ fn default_asdu_len() -> usize {
 
 /// Server listener state. Created in the `run` call. It includes a `run` method
 /// which performs the TCP listening and initialization of per-connection state.
+/*连接号  安全和非安全接口共用     */
+static NEXT_CON_ID: AtomicU32 = AtomicU32::new(1);
+/*汇总中心回给单个连接的通道长度  汇总中心不等  满了就断开这个连接
+总召的分段报告一次会来一批  留够     */
+const CON_CHANNEL_LEN: usize = 256;
+
 #[derive(Debug)]
 struct ConManager {
     pub cfg: Box<comdata::CfgData>,
@@ -99,6 +107,8 @@ struct ConManager {
     shutdown_complete_rx: mpsc::Receiver<()>,
     shutdown_complete_tx: mpsc::Sender<()>,
     to_each_handle_pubch_gui: broadcast::Sender<ExData>,
+    /*每个连接的关联状态     */
+    con_states: ConStateMap,
 }
 
 pub async fn run(
@@ -124,6 +134,7 @@ pub async fn run(
         shutdown_complete_tx,
         shutdown_complete_rx,
         to_each_handle_pubch_gui,
+        con_states: Arc::new(Mutex::new(HashMap::new())),
     });
     drop(cfg);
 
@@ -175,14 +186,37 @@ impl ConManager {
             self.limit_connections.acquire().await.unwrap().forget();
 
             let socket = self.accept().await?;
-            println!("get a client ");
+            let con_id = NEXT_CON_ID.fetch_add(1, Ordering::Relaxed);
+            let peer_addr = socket.peer_addr().ok();
+            let peer = peer_addr
+                .map(|addr| addr.to_string())
+                .unwrap_or_default();
+            println!("get a client {} con {}", peer, con_id);
+            /*先把回给连接的通道交给汇总中心 再登记 再开始收请求
+            汇总中心没了就不登记  许可还回去     */
+            let (to_con_tx, from_unit_rx) = mpsc::channel(CON_CHANNEL_LEN);
+            if let Err(err) = self
+                .to_main_info_tx
+                .send(ExData::ConOpen(
+                    con_id,
+                    peer_addr.map(|addr| addr.ip()),
+                    to_con_tx,
+                ))
+                .await
+            {
+                self.limit_connections.add_permits(1);
+                return Err(err.into());
+            }
+            self.register_con(con_id, peer);
 
             // Create the necessary per-connection ClientConnetion state.
             let mut client_connetion = Box::new(ClientConnetion {
+                con_id,
                 cfg: *self.cfg.clone(),
                 // Get a handle to the shared database. Internally, this is an
                 // `Arc`, so a clone only increments the ref count.
                 to_main_info_tx: self.to_main_info_tx.clone(),
+                from_unit_rx,
                 // Initialize the connection state. This allocates read/write
                 // buffers to perform redis protocol frame parsing.
                 // connection: Framed<BufWriter<TcpStream>, APDUCodec>,
@@ -203,6 +237,8 @@ impl ConManager {
                 _shutdown_complete: self.shutdown_complete_tx.clone(),
 
                 to_each_handle_rcvch: self.to_each_handle_pubch_gui.subscribe(),
+
+                con_states: self.con_states.clone(),
             });
             // println!("get a client 002");
 
@@ -217,6 +253,28 @@ impl ConManager {
         }
     }
 
+    /*登记新连接 顺便打印一下各个状态的连接数     */
+    fn register_con(&self, con_id: u32, peer: String) {
+        if let Ok(mut states) = self.con_states.lock() {
+            states.insert(
+                con_id,
+                ConInfo {
+                    peer,
+                    state: AssocState::Idle,
+                },
+            );
+            let associated = states
+                .values()
+                .filter(|info| info.state == AssocState::Associated)
+                .count();
+            println!(
+                "connections {} associated {}",
+                states.len(),
+                associated
+            );
+        }
+    }
+
     async fn accept(&mut self) -> crate::Result<TcpStream> {
         let mut backoff = 1;
 
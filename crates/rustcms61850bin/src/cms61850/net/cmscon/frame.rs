@@ -1,8 +1,9 @@
-use crate::utils;
+use crate::cms61850::codec::aper;
+use crate::cms61850::service::common::ServiceError;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 /*关联服务     */
-const ASSOCIATE: u8 = 1;
+pub const ASSOCIATE: u8 = 1;
 pub const ABORT: u8 = 2;
 pub const RELEASE: u8 = 3;
 /*模型和数据服务     */
@@ -78,6 +79,10 @@ pub const ASSOCIATENEGOTIATE: u8 = 154;
 
 pub struct FrameCfg {}
 
+/*ServiceError::FailedDueToCommunicationsConstraint 的 aper 编码
+应答超长的时候 用这个当否定响应的载荷     */
+const COMMUNICATIONS_CONSTRAINT_ERR: [u8; 1] = [0xb0];
+
 #[derive(Default, Clone, Debug)]
 pub struct APCH {
     /*协议号 只能 是1  表示61850     */
@@ -98,14 +103,15 @@ impl APCH {
         let mut buf = BytesMut::with_capacity(4);
         let mut first_b: u8 = 0;
         first_b |= 0x01;
+        /*和解码一致 bit5 错误 bit6 响应 bit7 后续帧     */
         if self.is_err {
-            utils::bstr_bit_set_on(&mut [first_b], 5);
+            first_b |= 1 << 5;
         }
         if self.is_resp {
-            utils::bstr_bit_set_on(&mut [first_b], 6);
+            first_b |= 1 << 6;
         }
         if self.have_next_frame {
-            utils::bstr_bit_set_on(&mut [first_b], 7);
+            first_b |= 1 << 7;
         }
         buf.put_u8(first_b);
         buf.put_u8(self.serv_code);
@@ -170,7 +176,131 @@ impl APDU {
     }
 }
 
+/***************************************/
+/*多帧重组  同一个服务码 同一个 req_id 的后续帧拼成一个完整的 asdu     */
+/***************************************/
+#[derive(Clone, Debug)]
+pub enum Reassembled {
+    /*完整的一帧 可以交给服务处理     */
+    Complete(APDU),
+    /*上一个序列没收完 就来了别的服务码或 req_id  上一个序列作废     */
+    Interleaved(APDU),
+    /*累计长度超过协商的 asdu 长度  整个序列作废     */
+    Oversized(APDU),
+}
+
+impl Reassembled {
+    /*作废的请求序列要回的否定响应  和请求同一个服务码和 req_id
+    交错的是 ParameterValueInconsistent  超长的是 FailedDueToCommunicationsConstraint
+    完整的 和响应帧出错的 不用回     */
+    pub fn error_resp(&self) -> Option<APDUBuilder> {
+        let (head, err) = match self {
+            Reassembled::Complete(_) => return None,
+            Reassembled::Interleaved(head) => (head, ServiceError::ParameterValueInconsistent),
+            Reassembled::Oversized(head) => {
+                (head, ServiceError::FailedDueToCommunicationsConstraint)
+            }
+        };
+        if head.apch.is_resp {
+            return None;
+        }
+        Some(APDUBuilder {
+            is_err: true,
+            is_resp: true,
+            serv_code: head.apch.serv_code,
+            req_id: head.asdu.req_id,
+            total_pay_load: aper::aper_to_bytes(&err).ok()?,
+        })
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct APDUReassembler {
+    /*正在重组的序列  apdu 里面只放头和 req_id     */
+    head: Option<APDU>,
+    pay_load: BytesMut,
+    /*超长的序列 后续帧都丢掉  直到最后一帧     */
+    discard: Option<(u8, u16)>,
+}
+
+impl APDUReassembler {
+    pub fn new() -> Self {
+        APDUReassembler {
+            ..Default::default()
+        }
+    }
+    /*max_asdu_len 是协商过的 asdu 长度 ，包含 2 个字节的 req_id     */
+    pub fn push(&mut self, apdu: APDU, max_asdu_len: usize) -> Vec<Reassembled> {
+        let mut ret = Vec::new();
+        let key = (apdu.apch.serv_code, apdu.asdu.req_id);
+
+        if let Some(discard) = self.discard {
+            if discard == key {
+                if !apdu.apch.have_next_frame {
+                    self.discard = None;
+                }
+                return ret;
+            }
+            self.discard = None;
+        }
+
+        if let Some(head) = self.head.take() {
+            if (head.apch.serv_code, head.asdu.req_id) != key {
+                self.pay_load.clear();
+                ret.push(Reassembled::Interleaved(head));
+            } else {
+                self.head = Some(head);
+            }
+        }
+
+        if self.pay_load.len() + apdu.asdu.pay_load.len() + 2 > max_asdu_len {
+            let head = match self.head.take() {
+                Some(head) => head,
+                None => Self::head_of(&apdu),
+            };
+            self.pay_load.clear();
+            if apdu.apch.have_next_frame {
+                self.discard = Some(key);
+            }
+            ret.push(Reassembled::Oversized(head));
+            return ret;
+        }
+
+        if apdu.apch.have_next_frame {
+            if self.head.is_none() {
+                self.head = Some(Self::head_of(&apdu));
+            }
+            self.pay_load.extend_from_slice(apdu.asdu.pay_load.as_ref());
+            return ret;
+        }
+
+        match self.head.take() {
+            Some(mut head) => {
+                self.pay_load.extend_from_slice(apdu.asdu.pay_load.as_ref());
+                head.apch.is_err = apdu.apch.is_err;
+                head.apch.have_next_frame = false;
+                head.asdu.pay_load = self.pay_load.split().freeze();
+                ret.push(Reassembled::Complete(head));
+            }
+            None => ret.push(Reassembled::Complete(apdu)),
+        }
+        ret
+    }
+
+    fn head_of(apdu: &APDU) -> APDU {
+        let mut head = APDU {
+            apch: apdu.apch.clone(),
+            asdu: ASDU::default(),
+        };
+        head.asdu.set_req_id(apdu.asdu.req_id);
+        head
+    }
+}
+
 impl APDUBuilder {
+    /*按协商的 apdu 长度分包
+    每一帧 = 4 字节 apch + 2 字节 req_id + 载荷  不能超过 neg_apdu_len
+    整个 asdu (2 字节 req_id + 全部载荷) 不能超过 neg_asdu_len     */
     pub fn pack(self, neg_apdu_len: u16, neg_asdu_len: usize) -> Vec<APDU> {
         let mut ret: Vec<APDU> = Vec::new();
         let mut apch = APCH::default();
@@ -180,77 +310,51 @@ impl APDUBuilder {
             .set_serv_code(self.serv_code);
         let mut asdu = ASDU::default();
         asdu.set_req_id(self.req_id);
-        asdu.pay_load = Bytes::new();
-        let frame_support = if usize::from(neg_apdu_len) < neg_asdu_len {
-            true
-        } else {
-            false
-        };
-        let need_frame = if self.total_pay_load.len() + 6 > neg_apdu_len as usize {
-            true
-        } else {
-            false
-        };
-        if self.total_pay_load.len() + 2 > neg_asdu_len as usize || (need_frame && !frame_support) {
-            let mut apdu = APDU::default();
-            // apdu.apch.set
-            apdu.apch.set_err(true); /*分包不能 长度超长 就发送错误消息吧     */
-            apdu.apch.set_asdu_len(2); /* 2  for  reqid     */
+
+        if self.total_pay_load.len() + 2 > neg_asdu_len {
+            /*超过协商的 asdu 长度  发不了 ，回一个错误     */
+            let mut apdu = APDU { apch, asdu };
+            apdu.apch.set_err(true);
             apdu.apch.set_have_next_frame(false);
-            apdu.asdu = asdu;
+            apdu.asdu.pay_load = Bytes::from_static(&COMMUNICATIONS_CONSTRAINT_ERR);
+            apdu.apch.set_asdu_len(2 + apdu.asdu.pay_load.len() as u16);
             ret.push(apdu);
             return ret;
         }
 
-        /*1、分包发送     */
-        if need_frame {
-            //分包发送
-            let packet_piece_len = neg_apdu_len as usize - 6;
-            let last_piece_len = self.total_pay_load.len() % packet_piece_len;
-            let last_piece_len = if last_piece_len == 0 {
-                packet_piece_len
-            } else {
-                last_piece_len
-            };
-            let packet_count =
-                (self.total_pay_load.len() + packet_piece_len - 1) / packet_piece_len;
-            for i in 0..packet_count {
-                let mut apdu_ele = APDU::default();
-                apdu_ele.apch = apch.clone();
-                if i == packet_count - 1 {
-                    apdu_ele.apch.set_have_next_frame(false);
-                    apdu_ele.apch.set_asdu_len(2 + packet_piece_len as u16);
-                    apdu_ele.asdu.pay_load = self
-                        .total_pay_load
-                        .slice(i * packet_piece_len as usize..(i + 1) * packet_piece_len as usize);
-                } else {
-                    apdu_ele.apch.set_have_next_frame(true);
-                    apdu_ele.apch.set_asdu_len(2 + last_piece_len as u16);
-                    apdu_ele.asdu.pay_load = self
-                        .total_pay_load
-                        .slice(i * packet_piece_len as usize..(i + 1) * packet_piece_len as usize);
-                }
-            }
-            return ret;
-        } else {
-            let mut apdu_ele = APDU::default();
-            apdu_ele.apch = apch.clone();
+        /*每帧最多能放的载荷  至少放一个字节     */
+        let packet_piece_len = (neg_apdu_len as usize).saturating_sub(6).max(1);
+        if self.total_pay_load.len() <= packet_piece_len {
+            let mut apdu_ele = APDU { apch, asdu };
             apdu_ele.apch.set_have_next_frame(false);
             apdu_ele
                 .apch
                 .set_asdu_len(2 + self.total_pay_load.len() as u16);
-            apdu_ele.asdu.pay_load = self.total_pay_load.clone();
+            apdu_ele.asdu.pay_load = self.total_pay_load;
             ret.push(apdu_ele);
             return ret;
         }
-    }
 
-     
-
-      
-     
+        /*分包发送  最后一帧 have_next_frame 为 false     */
+        let total_len = self.total_pay_load.len();
+        let packet_count = total_len.div_ceil(packet_piece_len);
+        for i in 0..packet_count {
+            let start = i * packet_piece_len;
+            let end = total_len.min(start + packet_piece_len);
+            let mut apdu_ele = APDU {
+                apch: apch.clone(),
+                asdu: asdu.clone(),
+            };
+            apdu_ele.apch.set_have_next_frame(i + 1 < packet_count);
+            apdu_ele.apch.set_asdu_len(2 + (end - start) as u16);
+            apdu_ele.asdu.pay_load = self.total_pay_load.slice(start..end);
+            ret.push(apdu_ele);
+        }
+        ret
+    }
 }
 
+#[derive(Default, Clone, Debug)]
 pub struct APDUBuilder {
     /*0 没有错误 ，1 有错误     */
     pub is_err: bool,
@@ -263,3 +367,131 @@ pub struct APDUBuilder {
     /*内容载荷     */
     pub total_pay_load: Bytes,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn builder(pay_load: Vec<u8>) -> APDUBuilder {
+        APDUBuilder {
+            is_err: false,
+            is_resp: true,
+            serv_code: GETDATAVALUES,
+            req_id: 7,
+            total_pay_load: Bytes::from(pay_load),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn pack_then_reassemble(
+            pay_load in proptest::collection::vec(any::<u8>(), 0..4096),
+            neg_apdu_len in 7u16..2048,
+        ) {
+            let apdus = builder(pay_load.clone()).pack(neg_apdu_len, usize::MAX);
+            let mut reassembler = APDUReassembler::new();
+            let mut out = Vec::new();
+            for (i, apdu) in apdus.iter().enumerate() {
+                prop_assert!(apdu.to_bytes().len() <= neg_apdu_len as usize);
+                prop_assert_eq!(apdu.apch.asdu_len as usize, apdu.asdu.pay_load.len() + 2);
+                prop_assert_eq!(apdu.apch.have_next_frame, i + 1 < apdus.len());
+                prop_assert_eq!(apdu.asdu.req_id, 7);
+                out.extend(reassembler.push(apdu.clone(), usize::MAX));
+            }
+            prop_assert_eq!(out.len(), 1);
+            match out.pop() {
+                Some(Reassembled::Complete(apdu)) => {
+                    prop_assert_eq!(apdu.apch.serv_code, GETDATAVALUES);
+                    prop_assert_eq!(apdu.asdu.pay_load.as_ref(), &pay_load[..]);
+                }
+                other => prop_assert!(false, "unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn pack_over_asdu_len() {
+        let apdus = builder(vec![0; 100]).pack(65535, 50);
+        assert_eq!(apdus.len(), 1);
+        assert!(apdus[0].apch.is_err);
+        assert_eq!(apdus[0].asdu.req_id, 7);
+        assert_eq!(apdus[0].asdu.pay_load.as_ref(), &COMMUNICATIONS_CONSTRAINT_ERR);
+    }
+
+    /*请求按 10 字节载荷一帧分包     */
+    fn req_frames(req_id: u16, len: usize) -> Vec<APDU> {
+        APDUBuilder {
+            is_err: false,
+            is_resp: false,
+            serv_code: GETDATAVALUES,
+            req_id,
+            total_pay_load: Bytes::from(vec![0x5a; len]),
+        }
+        .pack(16, usize::MAX)
+    }
+
+    fn check_err_resp(item: &Reassembled, req_id: u16, err: ServiceError) {
+        let resp = item.error_resp().unwrap();
+        assert!(resp.is_err && resp.is_resp);
+        assert_eq!(resp.serv_code, GETDATAVALUES);
+        assert_eq!(resp.req_id, req_id);
+        assert_eq!(resp.total_pay_load, aper::aper_to_bytes(&err).unwrap());
+    }
+
+    #[test]
+    fn reassemble_interleaved() {
+        let mut reassembler = APDUReassembler::new();
+        let first = req_frames(7, 30);
+        assert_eq!(first.len(), 3);
+        assert!(reassembler.push(first[0].clone(), usize::MAX).is_empty());
+
+        /*7 没收完就来了 8  7 作废  8 照常     */
+        let mut out = reassembler.push(req_frames(8, 5).remove(0), usize::MAX);
+        assert_eq!(out.len(), 2);
+        match &out[0] {
+            Reassembled::Interleaved(head) => assert_eq!(head.asdu.req_id, 7),
+            other => panic!("unexpected {:?}", other),
+        }
+        check_err_resp(&out[0], 7, ServiceError::ParameterValueInconsistent);
+        match out.pop() {
+            Some(Reassembled::Complete(apdu)) => {
+                assert_eq!(apdu.asdu.req_id, 8);
+                assert_eq!(apdu.asdu.pay_load.as_ref(), &[0x5a; 5]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reassemble_oversized() {
+        let mut reassembler = APDUReassembler::new();
+        let frames = req_frames(7, 30);
+        assert!(reassembler.push(frames[0].clone(), 20).is_empty());
+
+        /*第二帧累计超过 20  报一次超长  后面的帧丢掉     */
+        let out = reassembler.push(frames[1].clone(), 20);
+        assert_eq!(out.len(), 1);
+        match &out[0] {
+            Reassembled::Oversized(head) => assert_eq!(head.asdu.req_id, 7),
+            other => panic!("unexpected {:?}", other),
+        }
+        check_err_resp(&out[0], 7, ServiceError::FailedDueToCommunicationsConstraint);
+        assert_eq!(
+            out[0].error_resp().unwrap().total_pay_load.as_ref(),
+            &COMMUNICATIONS_CONSTRAINT_ERR
+        );
+        assert!(reassembler.push(frames[2].clone(), 20).is_empty());
+
+        /*丢完了  下一个请求正常     */
+        let out = reassembler.push(req_frames(8, 5).remove(0), 20);
+        assert!(matches!(&out[..], [Reassembled::Complete(apdu)] if apdu.asdu.req_id == 8));
+
+        /*响应帧超长不用回     */
+        let mut resp = req_frames(9, 30).remove(0);
+        resp.apch.is_resp = true;
+        let out = reassembler.push(resp, 10);
+        assert_eq!(out.len(), 1);
+        assert!(out[0].error_resp().is_none());
+    }
+}
@@ -0,0 +1,434 @@
+use super::RptEntry;
+use crate::cms61850::codec::aper;
+use crate::cms61850::model::value::entry_time_millis;
+use crate::cms61850::service::report::EntryData;
+use crate::utils::crc32;
+use anyhow::bail;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+/***************************************/
+/*缓存报告控制块的日志文件  只往后追加  日志服务的日志也存在这里
+一条记录  长度(4) + crc32(4) + 类型(1) + 内容
+第一条是头  带下一个 EntryID 和配置的指纹  后面都是条目
+写到一半掉电  恢复的时候从第一条坏的截掉  配置变了整个不要
+清缓存 换配置 文件太大  都是写临时文件再改名     */
+/***************************************/
+
+const REC_HEAD: u8 = 1;
+const REC_ENTRY: u8 = 2;
+/*长度 + crc     */
+const REC_PREFIX_LEN: usize = 8;
+/*一条记录的上限  长度比这个大就当坏了     */
+const REC_MAX_LEN: usize = 1 << 24;
+
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    file_size: u64,
+    /*数据集成员 触发条件这些  恢复的时候对不上就不要了     */
+    fingerprint: String,
+}
+
+/*恢复出来的东西     */
+#[derive(Default, Debug)]
+pub struct Recovered {
+    pub next_id: u64,
+    pub entry_vec: Vec<RptEntry>,
+}
+
+impl Journal {
+    /*打开日志  读出还能用的条目  然后整理一遍重新写
+    retention_sec 以前的条目不要  0 都要     */
+    pub fn open(
+        path: PathBuf,
+        fingerprint: &str,
+        retention_sec: u32,
+    ) -> crate::Result<(Journal, Recovered)> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut recovered = match fs::read(&path) {
+            Ok(data) => parse(&path, &data, fingerprint),
+            Err(_) => Recovered::default(),
+        };
+        if retention_sec > 0 {
+            let oldest = chrono::Utc::now().timestamp_millis() - retention_sec as i64 * 1000;
+            recovered
+                .entry_vec
+                .retain(|entry| entry_time_millis(&entry.timeofentry) >= oldest);
+        }
+        if let Some(last) = recovered.entry_vec.last() {
+            recovered.next_id = recovered.next_id.max(last.entryid + 1);
+        }
+        recovered.next_id = recovered.next_id.max(1);
+        let data = snapshot(recovered.next_id, fingerprint, recovered.entry_vec.iter())?;
+        let journal = Journal {
+            file: replace_file(&path, &data)?,
+            path,
+            file_size: data.len() as u64,
+            fingerprint: fingerprint.to_string(),
+        };
+        Ok((journal, recovered))
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /*控制块换了配置  下一次整理的时候写新的头     */
+    pub fn set_fingerprint(&mut self, fingerprint: &str) {
+        self.fingerprint = fingerprint.to_string();
+    }
+
+    pub fn append(&mut self, entry: &RptEntry) -> crate::Result<()> {
+        let record = encode_record(REC_ENTRY, &encode_entry(entry)?);
+        self.file.write_all(&record)?;
+        self.file_size += record.len() as u64;
+        Ok(())
+    }
+
+    /*按现在的缓存重写     */
+    pub fn rewrite<'a>(
+        &mut self,
+        next_id: u64,
+        entry_iter: impl Iterator<Item = &'a RptEntry>,
+    ) -> crate::Result<()> {
+        let data = snapshot(next_id, &self.fingerprint, entry_iter)?;
+        self.file = replace_file(&self.path, &data)?;
+        self.file_size = data.len() as u64;
+        Ok(())
+    }
+}
+
+/*头加上所有条目     */
+fn snapshot<'a>(
+    next_id: u64,
+    fingerprint: &str,
+    entry_iter: impl Iterator<Item = &'a RptEntry>,
+) -> crate::Result<Vec<u8>> {
+    let mut data = encode_record(REC_HEAD, &encode_head(next_id, fingerprint));
+    for entry in entry_iter {
+        data.extend_from_slice(&encode_record(REC_ENTRY, &encode_entry(entry)?));
+    }
+    Ok(data)
+}
+
+/*先写临时文件 落盘以后再改名  中途掉电旧文件还在  返回追加打开的新文件     */
+fn replace_file(path: &Path, data: &[u8]) -> crate::Result<File> {
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(data)?;
+        tmp.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(OpenOptions::new().append(true).open(path)?)
+}
+
+/*控制块引用 LD/LN.RCB 对应的文件  dir 已经带了 IED 名字     */
+pub fn journal_path(dir: &Path, key: &str) -> PathBuf {
+    match key.split_once('/') {
+        Some((ld, rcb)) => dir.join(ld).join(format!("{}.jnl", rcb)),
+        None => dir.join(format!("{}.jnl", key)),
+    }
+}
+
+fn encode_record(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut content = Vec::with_capacity(body.len() + 1);
+    content.push(kind);
+    content.extend_from_slice(body);
+    let mut record = Vec::with_capacity(content.len() + REC_PREFIX_LEN);
+    record.extend_from_slice(&(content.len() as u32).to_be_bytes());
+    record.extend_from_slice(&crc32(&content).to_be_bytes());
+    record.extend_from_slice(&content);
+    record
+}
+
+fn encode_head(next_id: u64, fingerprint: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&next_id.to_be_bytes());
+    body.extend_from_slice(fingerprint.as_bytes());
+    body
+}
+
+/*entryid(8) timeofentry(6) 成员个数(2) 估算大小(4) 然后每个 EntryData 长度(2) + aper     */
+fn encode_entry(entry: &RptEntry) -> crate::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&entry.entryid.to_be_bytes());
+    body.extend_from_slice(&entry.timeofentry);
+    body.extend_from_slice(&(entry.member_count as u16).to_be_bytes());
+    body.extend_from_slice(&(entry.size as u32).to_be_bytes());
+    for data in entry.entrydata.iter() {
+        let bytes = aper::aper_to_bytes(data)?;
+        if bytes.len() > u16::MAX as usize {
+            bail!(format!("entry data {} too large", bytes.len()));
+        }
+        body.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+        body.extend_from_slice(&bytes);
+    }
+    Ok(body)
+}
+
+fn decode_entry(body: &[u8]) -> crate::Result<RptEntry> {
+    if body.len() < 20 {
+        bail!(format!("entry record too short {}", body.len()));
+    }
+    let mut entryid = [0u8; 8];
+    entryid.copy_from_slice(&body[..8]);
+    let mut timeofentry = [0u8; 6];
+    timeofentry.copy_from_slice(&body[8..14]);
+    let member_count = u16::from_be_bytes([body[14], body[15]]) as usize;
+    let size = u32::from_be_bytes([body[16], body[17], body[18], body[19]]) as usize;
+    let mut entrydata = Vec::new();
+    let mut rest = &body[20..];
+    while !rest.is_empty() {
+        if rest.len() < 2 {
+            bail!("entry data length cut");
+        }
+        let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        if rest.len() < 2 + len {
+            bail!("entry data cut");
+        }
+        entrydata.push(aper::aper_from_bytes::<EntryData>(&rest[2..2 + len])?);
+        rest = &rest[2 + len..];
+    }
+    Ok(RptEntry {
+        entryid: u64::from_be_bytes(entryid),
+        timeofentry,
+        member_count,
+        entrydata,
+        size,
+    })
+}
+
+/*从头往后读  遇到坏的就停  头的指纹对不上只留下 EntryID     */
+fn parse(path: &Path, data: &[u8], fingerprint: &str) -> Recovered {
+    let mut recovered = Recovered::default();
+    let mut matched = false;
+    let mut offset = 0;
+    while data.len() - offset >= REC_PREFIX_LEN {
+        let len = u32::from_be_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]) as usize;
+        let crc = u32::from_be_bytes([
+            data[offset + 4],
+            data[offset + 5],
+            data[offset + 6],
+            data[offset + 7],
+        ]);
+        let start = offset + REC_PREFIX_LEN;
+        if len == 0 || len > REC_MAX_LEN || data.len() - start < len {
+            break;
+        }
+        let content = &data[start..start + len];
+        if crc32(content) != crc {
+            break;
+        }
+        let body = &content[1..];
+        match content[0] {
+            REC_HEAD if offset == 0 && body.len() >= 8 => {
+                let mut next_id = [0u8; 8];
+                next_id.copy_from_slice(&body[..8]);
+                recovered.next_id = u64::from_be_bytes(next_id);
+                matched = &body[8..] == fingerprint.as_bytes();
+            }
+            REC_ENTRY if offset > 0 => match decode_entry(body) {
+                Ok(entry) => {
+                    if entry.entryid >= recovered.next_id {
+                        recovered.next_id = entry.entryid + 1;
+                    }
+                    recovered.entry_vec.push(entry);
+                }
+                Err(_) => break,
+            },
+            _ => break,
+        }
+        offset = start + len;
+    }
+    if offset < data.len() {
+        println!(
+            "brcb journal {} cut at {} of {}",
+            path.display(),
+            offset,
+            data.len()
+        );
+    }
+    if !matched {
+        if offset > 0 {
+            println!("brcb journal {} config changed", path.display());
+        }
+        recovered.entry_vec.clear();
+    }
+    recovered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cms61850::model::value::entry_time;
+    use crate::cms61850::model::Value;
+
+    /*每个测试一个目录  跑完删掉     */
+    fn test_path(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("cms61850_journal_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("LD0").join("LLN0.brcb01.jnl")
+    }
+
+    fn cleanup(path: &Path) {
+        if let Some(dir) = path.parent().and_then(|dir| dir.parent()) {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    fn entry(entryid: u64, ago_sec: i64) -> RptEntry {
+        let time = chrono::Utc::now() - chrono::Duration::seconds(ago_sec);
+        RptEntry {
+            entryid,
+            timeofentry: entry_time(time),
+            member_count: 2,
+            entrydata: vec![EntryData {
+                reference: Some("E1Q1SB1C1/CSWI1.Pos.stVal".to_string()),
+                fc: Some("ST".to_string()),
+                id: 0,
+                value: Value::Dbpos(entryid as u8 % 4),
+                reason: Some(0x40),
+            }],
+            size: 16,
+        }
+    }
+
+    fn ids(recovered: &Recovered) -> Vec<u64> {
+        recovered
+            .entry_vec
+            .iter()
+            .map(|entry| entry.entryid)
+            .collect()
+    }
+
+    /*写一个新文件  条目按顺序追加     */
+    fn write(path: &Path, fingerprint: &str, entry_vec: &[RptEntry]) {
+        let (mut journal, _) = Journal::open(path.to_path_buf(), fingerprint, 0).unwrap();
+        for entry in entry_vec.iter() {
+            journal.append(entry).unwrap();
+        }
+    }
+
+    /*每条记录的起点     */
+    fn record_offsets(data: &[u8]) -> Vec<usize> {
+        let mut offset_vec = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            offset_vec.push(offset);
+            let len = u32::from_be_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ]) as usize;
+            offset += REC_PREFIX_LEN + len;
+        }
+        offset_vec
+    }
+
+    #[test]
+    fn truncated_tail_dropped() {
+        let path = test_path("tail");
+        write(&path, "fp", &[entry(1, 0), entry(2, 0), entry(3, 0)]);
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 5]).unwrap();
+
+        let (_, recovered) = Journal::open(path.clone(), "fp", 0).unwrap();
+        assert_eq!(ids(&recovered), vec![1, 2]);
+        assert_eq!(recovered.next_id, 3);
+        /*打开的时候整理过  再读一遍是干净的     */
+        let data = fs::read(&path).unwrap();
+        assert_eq!(record_offsets(&data).len(), 3);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn crc_mismatch_stops_parse() {
+        let path = test_path("crc");
+        write(&path, "fp", &[entry(1, 0), entry(2, 0), entry(3, 0)]);
+        let mut data = fs::read(&path).unwrap();
+        let offset_vec = record_offsets(&data);
+        assert_eq!(offset_vec.len(), 4);
+        /*第二个条目的内容改一个字节     */
+        data[offset_vec[2] + REC_PREFIX_LEN + 3] ^= 0xff;
+        fs::write(&path, &data).unwrap();
+
+        let (_, recovered) = Journal::open(path.clone(), "fp", 0).unwrap();
+        assert_eq!(ids(&recovered), vec![1]);
+        assert_eq!(recovered.next_id, 2);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn fingerprint_change_keeps_next_id() {
+        let path = test_path("fingerprint");
+        write(&path, "fp1", &[entry(1, 0), entry(2, 0)]);
+
+        let (_, recovered) = Journal::open(path.clone(), "fp2", 0).unwrap();
+        assert!(recovered.entry_vec.is_empty());
+        assert_eq!(recovered.next_id, 3);
+        /*新的头已经写下去了  再用新指纹打开 EntryID 还接着     */
+        let (_, recovered) = Journal::open(path.clone(), "fp2", 0).unwrap();
+        assert!(recovered.entry_vec.is_empty());
+        assert_eq!(recovered.next_id, 3);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn retention_drops_old_entries() {
+        let path = test_path("retention");
+        write(
+            &path,
+            "fp",
+            &[entry(1, 7200), entry(2, 7200), entry(3, 10), entry(4, 0)],
+        );
+
+        let (_, recovered) = Journal::open(path.clone(), "fp", 3600).unwrap();
+        assert_eq!(ids(&recovered), vec![3, 4]);
+        assert_eq!(recovered.next_id, 5);
+        /*0 是都要     */
+        let path_all = test_path("retention_all");
+        write(&path_all, "fp", &[entry(1, 7200), entry(2, 0)]);
+        let (_, recovered) = Journal::open(path_all.clone(), "fp", 0).unwrap();
+        assert_eq!(ids(&recovered), vec![1, 2]);
+        cleanup(&path);
+        cleanup(&path_all);
+    }
+
+    #[test]
+    fn entry_id_continues_after_open() {
+        let path = test_path("continuity");
+        write(&path, "fp", &[entry(1, 0), entry(2, 0)]);
+
+        let (mut journal, recovered) = Journal::open(path.clone(), "fp", 0).unwrap();
+        assert_eq!(ids(&recovered), vec![1, 2]);
+        assert_eq!(recovered.next_id, 3);
+        journal.append(&entry(recovered.next_id, 0)).unwrap();
+        drop(journal);
+
+        let (mut journal, recovered) = Journal::open(path.clone(), "fp", 0).unwrap();
+        assert_eq!(ids(&recovered), vec![1, 2, 3]);
+        assert_eq!(recovered.next_id, 4);
+        /*清了缓存  条目没了 EntryID 还在头里面     */
+        journal
+            .rewrite(recovered.next_id, std::iter::empty())
+            .unwrap();
+        drop(journal);
+
+        let (_, recovered) = Journal::open(path.clone(), "fp", 0).unwrap();
+        assert!(recovered.entry_vec.is_empty());
+        assert_eq!(recovered.next_id, 4);
+        cleanup(&path);
+    }
+}
@@ -0,0 +1,780 @@
+use crate::cms61850::codec::aper;
+use crate::cms61850::model::change::{DataChange, REASON_GI, REASON_INTEGRITY};
+use crate::cms61850::model::value::entry_time;
+use crate::cms61850::model::{DataNode, Model, ObjRef, Value};
+use crate::cms61850::service::data::read_ref;
+use crate::cms61850::service::report::{
+    opt_bit, set_opt_bit, EntryData, ReportPDU, OPT_BUFOVFL, OPT_CONFREV, OPT_DATAREF, OPT_DATSET,
+    OPT_ENTRYID, OPT_REASON, OPT_SEGMENTATION, OPT_SEQNUM, OPT_TIMESTAMP,
+};
+use journal::{journal_path, Journal};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use tokio::time::{Duration, Instant};
+/***************************************/
+/*报告的运行时  谁占着哪个控制块  使能以后攒着的触发
+汇总中心按 report_scan_rate 扫一遍  要发的报告交给对应的连接
+缓存报告控制块没使能 连接断了 也一直在攒  重新使能以后接着发
+配了日志文件的话 缓存同时写到文件里  重启以后恢复     */
+/***************************************/
+
+pub mod journal;
+
+/*缓存报告控制块一次扫描最多发几条  剩下的下次扫描接着发     */
+const BRCB_SEND_PER_SCAN: usize = 16;
+
+/*一条报告条目  值是产生的时候取的
+reference fc reason 都带上  发的时候按 optFlds 去掉     */
+#[derive(Clone, Debug)]
+pub struct RptEntry {
+    pub entryid: u64,
+    pub timeofentry: [u8; 6],
+    /*数据集成员个数  inclusion 位串的长度     */
+    pub member_count: usize,
+    pub entrydata: Vec<EntryData>,
+    /*编码以后的长度  算缓存大小用     */
+    pub size: usize,
+}
+
+/*缓存报告控制块的缓存  按字节数限制  满了挤掉最老的     */
+#[derive(Default, Debug)]
+pub struct RptBuffer {
+    pub entry_vec: VecDeque<RptEntry>,
+    pub size: usize,
+    pub max_size: usize,
+    /*下一条的 EntryID  只往上加  清缓存也不回头     */
+    pub next_id: u64,
+    /*已经发出去的最后一条  0 表示从头发     */
+    pub sent_id: u64,
+    /*有没发的被挤掉了  下一个报告带 BufOvfl     */
+    pub ovfl: bool,
+    /*没配日志文件就是 None     */
+    pub journal: Option<Journal>,
+}
+
+impl RptBuffer {
+    pub fn new(max_size: usize) -> RptBuffer {
+        RptBuffer {
+            max_size,
+            next_id: 1,
+            ..Default::default()
+        }
+    }
+    pub fn push(&mut self, mut entry: RptEntry) {
+        entry.entryid = self.next_id;
+        self.next_id += 1;
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(err) = journal.append(&entry) {
+                println!("brcb journal append failed {:?}", err);
+            }
+        }
+        self.keep(entry);
+        /*文件里面挤掉的还在  大到缓存的两倍就重写一遍     */
+        let file_size = self.journal.as_ref().map(|journal| journal.file_size());
+        if file_size.is_some_and(|file_size| file_size > 2 * self.max_size as u64 + 4096) {
+            self.rewrite_journal();
+        }
+    }
+    /*重启恢复出来的  EntryID 用原来的     */
+    pub fn restore(&mut self, next_id: u64, entry_vec: Vec<RptEntry>) {
+        for entry in entry_vec {
+            self.keep(entry);
+        }
+        self.next_id = self.next_id.max(next_id);
+        self.ovfl = false;
+    }
+    fn keep(&mut self, entry: RptEntry) {
+        self.size += entry.size;
+        self.entry_vec.push_back(entry);
+        while self.size > self.max_size {
+            let dropped = match self.entry_vec.pop_front() {
+                Some(dropped) => dropped,
+                None => break,
+            };
+            self.size -= dropped.size;
+            if dropped.entryid > self.sent_id {
+                self.ovfl = true;
+            }
+        }
+    }
+    pub fn purge(&mut self) {
+        self.entry_vec.clear();
+        self.size = 0;
+        self.sent_id = 0;
+        self.ovfl = false;
+        self.rewrite_journal();
+    }
+    fn rewrite_journal(&mut self) {
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(err) = journal.rewrite(self.next_id, self.entry_vec.iter()) {
+                println!("brcb journal rewrite failed {:?}", err);
+            }
+        }
+    }
+    pub fn contains(&self, entryid: u64) -> bool {
+        self.entry_vec.iter().any(|entry| entry.entryid == entryid)
+    }
+    /*sent_id 后面还没发的     */
+    pub fn unsent(&self) -> impl Iterator<Item = &RptEntry> {
+        let sent_id = self.sent_id;
+        self.entry_vec
+            .iter()
+            .filter(move |entry| entry.entryid > sent_id)
+    }
+}
+
+/*出一条的依据  各成员的原因  和攒着的变化
+变化里面带着变的时候的值  同一个成员变了两次 两条各是各的值     */
+#[derive(Default, Debug)]
+pub struct Trigger {
+    pub reason_vec: Vec<u8>,
+    pub change_vec: Vec<DataChange>,
+}
+
+/*触发条件 缓存时间 完整性周期 开始运行的时候就定了  使能期间改不了
+非缓存的使能才有  去使能就丢掉  缓存的一直都在  换配置的时候重建     */
+#[derive(Default, Debug)]
+pub struct RcbRun {
+    /*数据集的成员     */
+    pub member_vec: Vec<ObjRef>,
+    /*每个成员攒着的原因  0 表示没有     */
+    pub pending_vec: Vec<u8>,
+    /*攒着的变化  出条目的时候用里面的值     */
+    pub pending_change_vec: Vec<DataChange>,
+    pub trgops: u8,
+    pub buftm: Duration,
+    /*第一个触发进来的时候开始算     */
+    pub buf_deadline: Option<Instant>,
+    /*触发条件里面没有完整性 或者周期是 0 就是 None     */
+    pub intgpd: Option<Duration>,
+    pub intg_deadline: Option<Instant>,
+    /*客户端要了总召  下一次扫描发     */
+    pub gi: bool,
+    pub enabled: bool,
+    /*缓存报告控制块才有     */
+    pub buffer: Option<RptBuffer>,
+}
+
+impl RcbRun {
+    pub fn new(member_vec: Vec<ObjRef>, trgops: u8, buftm: u32, intgpd: u32) -> RcbRun {
+        RcbRun {
+            pending_vec: vec![0; member_vec.len()],
+            member_vec,
+            trgops,
+            buftm: Duration::from_millis(buftm as u64),
+            intgpd: if trgops & REASON_INTEGRITY != 0 && intgpd > 0 {
+                Some(Duration::from_millis(intgpd as u64))
+            } else {
+                None
+            },
+            ..Default::default()
+        }
+    }
+    /*完整性周期从使能开始算     */
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.intg_deadline = match self.intgpd {
+            Some(intgpd) if enabled => Some(Instant::now() + intgpd),
+            _ => None,
+        };
+        if !enabled {
+            self.gi = false;
+        }
+    }
+    /*日志文件里面记的配置  重启以后对不上 以前攒的就不要了     */
+    pub fn fingerprint(&self) -> String {
+        let member_vec: Vec<String> = self.member_vec.iter().map(|m| m.to_string()).collect();
+        format!(
+            "{}|{}|{}|{}",
+            member_vec.join(","),
+            self.trgops,
+            self.buftm.as_millis(),
+            self.intgpd.map(|intgpd| intgpd.as_millis()).unwrap_or(0)
+        )
+    }
+    /*按触发条件把变化攒起来  到了缓存时间 完整性周期 或者要了总召 就出一条     */
+    pub fn collect(&mut self, change_vec: &[DataChange], now: Instant) -> Vec<Trigger> {
+        let mut trigger_vec = Vec::new();
+        for change in change_vec.iter() {
+            let reason = change.reason & self.trgops;
+            if reason == 0 {
+                continue;
+            }
+            for index in 0..self.member_vec.len() {
+                if !change.covered_by(&self.member_vec[index]) {
+                    continue;
+                }
+                /*同一个成员又变了  前面攒着的先出     */
+                if self.pending_vec[index] != 0 {
+                    trigger_vec.push(self.take_pending());
+                }
+                self.pending_vec[index] |= reason;
+                self.pending_change_vec.push(change.clone());
+                if self.buf_deadline.is_none() {
+                    self.buf_deadline = Some(now + self.buftm);
+                }
+            }
+        }
+        if self.has_pending() && self.buf_deadline.is_some_and(|deadline| deadline <= now) {
+            trigger_vec.push(self.take_pending());
+        }
+        if let (Some(intgpd), Some(deadline)) = (self.intgpd, self.intg_deadline) {
+            if self.enabled && deadline <= now {
+                if self.has_pending() {
+                    trigger_vec.push(self.take_pending());
+                }
+                trigger_vec.push(Trigger {
+                    reason_vec: vec![REASON_INTEGRITY; self.member_vec.len()],
+                    change_vec: Vec::new(),
+                });
+                self.intg_deadline = Some(now + intgpd);
+            }
+        }
+        if self.gi {
+            self.gi = false;
+            if self.has_pending() {
+                trigger_vec.push(self.take_pending());
+            }
+            trigger_vec.push(Trigger {
+                reason_vec: vec![REASON_GI; self.member_vec.len()],
+                change_vec: Vec::new(),
+            });
+        }
+        trigger_vec
+    }
+    fn take_pending(&mut self) -> Trigger {
+        self.buf_deadline = None;
+        Trigger {
+            reason_vec: std::mem::replace(&mut self.pending_vec, vec![0; self.member_vec.len()]),
+            change_vec: std::mem::take(&mut self.pending_change_vec),
+        }
+    }
+    fn has_pending(&self) -> bool {
+        self.pending_vec.iter().any(|reason| *reason != 0)
+    }
+}
+
+/*扫描出来要发的报告  缓存的带着控制块和 EntryID  发出去了才 confirm_sent     */
+#[derive(Debug)]
+pub struct RptOut {
+    pub con_id: u32,
+    pub report: ReportPDU,
+    pub brcb: Option<(String, u64)>,
+}
+
+/*key 都是控制块的引用 LD/LN.RcbName     */
+#[derive(Default, Debug)]
+pub struct ReportEngine {
+    /*占着控制块的连接  Resv 使能 ResvTms 都算     */
+    pub owner_map: HashMap<String, u32>,
+    /*连接断了 按 ResvTms 留着的  (对端地址, 到期时间)     */
+    pub hold_map: HashMap<String, (Vec<u8>, Instant)>,
+    pub run_map: HashMap<String, RcbRun>,
+    /*每个缓存报告控制块的缓存字节数  来自 brcbbuffersize     */
+    pub brcb_buffer_size: usize,
+    /*日志文件目录  已经带了 IED 名字  None 不写文件     */
+    pub journal_dir: Option<PathBuf>,
+    pub journal_retention_sec: u32,
+}
+
+impl ReportEngine {
+    pub fn new(brcb_buffer_size: usize) -> ReportEngine {
+        ReportEngine {
+            brcb_buffer_size,
+            ..Default::default()
+        }
+    }
+
+    /*要在 start_brcbs 之前  启动的时候就按文件恢复     */
+    pub fn enable_journal(&mut self, dir: PathBuf, retention_sec: u32) {
+        self.journal_dir = Some(dir);
+        self.journal_retention_sec = retention_sec;
+    }
+
+    pub fn owner(&self, key: &str) -> Option<u32> {
+        self.owner_map.get(key).copied()
+    }
+    pub fn set_owner(&mut self, key: &str, con_id: u32) {
+        self.hold_map.remove(key);
+        self.owner_map.insert(key.to_string(), con_id);
+    }
+    pub fn release(&mut self, key: &str) {
+        self.hold_map.remove(key);
+        self.owner_map.remove(key);
+    }
+    /*连接断了  留给同一个地址 secs 秒     */
+    pub fn hold(&mut self, key: &str, addr: &[u8], secs: u64) {
+        self.owner_map.remove(key);
+        self.hold_map.insert(
+            key.to_string(),
+            (addr.to_vec(), Instant::now() + Duration::from_secs(secs)),
+        );
+    }
+    /*别的连接占着  或者留给了别的地址     */
+    pub fn locked(&self, key: &str, con_id: u32, addr: &[u8]) -> bool {
+        if self.owner(key).is_some_and(|owner| owner != con_id) {
+            return true;
+        }
+        self.hold_map
+            .get(key)
+            .is_some_and(|(hold_addr, _)| hold_addr.as_slice() != addr)
+    }
+    /*这个连接占着的控制块     */
+    pub fn owned_by(&self, con_id: u32) -> Vec<String> {
+        self.owner_map
+            .iter()
+            .filter(|(_, owner)| **owner == con_id)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    pub fn start(&mut self, key: &str, run: RcbRun) {
+        self.run_map.insert(key.to_string(), run);
+    }
+    pub fn stop(&mut self, key: &str) {
+        self.run_map.remove(key);
+    }
+    pub fn run(&self, key: &str) -> Option<&RcbRun> {
+        self.run_map.get(key)
+    }
+    pub fn run_mut(&mut self, key: &str) -> Option<&mut RcbRun> {
+        self.run_map.get_mut(key)
+    }
+    /*缓存报告控制块换了配置  缓存清掉重新攒  EntryID 接着原来的
+    第一次开始的时候 有日志文件就从文件恢复     */
+    pub fn restart_buffered(&mut self, key: &str, mut run: RcbRun) {
+        let mut buffer = RptBuffer::new(self.brcb_buffer_size);
+        let fingerprint = run.fingerprint();
+        let old = self.run_map.get_mut(key).and_then(|run| run.buffer.take());
+        match (old, self.journal_dir.as_ref()) {
+            (Some(mut old), _) => {
+                buffer.next_id = old.next_id;
+                buffer.journal = old.journal.take();
+                if let Some(journal) = buffer.journal.as_mut() {
+                    journal.set_fingerprint(&fingerprint);
+                }
+                buffer.purge();
+            }
+            (None, Some(dir)) => {
+                let path = journal_path(dir, key);
+                match Journal::open(path, &fingerprint, self.journal_retention_sec) {
+                    Ok((journal, recovered)) => {
+                        println!(
+                            "brcb {} recovered {} entries next id {}",
+                            key,
+                            recovered.entry_vec.len(),
+                            recovered.next_id
+                        );
+                        buffer.restore(recovered.next_id, recovered.entry_vec);
+                        buffer.journal = Some(journal);
+                    }
+                    Err(err) => println!("brcb {} journal open failed {:?}", key, err),
+                }
+            }
+            (None, None) => {}
+        }
+        run.buffer = Some(buffer);
+        self.start(key, run);
+    }
+    pub fn request_gi(&mut self, key: &str) {
+        if let Some(run) = self.run_map.get_mut(key) {
+            run.gi = run.enabled;
+        }
+    }
+
+    /*启动的时候  数据集有效的缓存报告控制块都开始攒     */
+    pub fn start_brcbs(&mut self, model: &Model) {
+        for ld in model.ld_vec.iter() {
+            for ln in ld.ln_vec.iter() {
+                for cb in ln.brcb_vec.iter() {
+                    let dataset = match model.get_dataset(&cb.datset) {
+                        Some(dataset) => dataset,
+                        None => continue,
+                    };
+                    let key = format!("{}/{}.{}", ld.name, ln.name, cb.name);
+                    let run =
+                        RcbRun::new(dataset.member_vec.clone(), cb.trgops, cb.buftm, cb.intgpd);
+                    self.restart_buffered(&key, run);
+                }
+            }
+        }
+    }
+
+    /*ResvTms 到了  放掉     */
+    fn expire_holds(&mut self, model: &mut Model, now: Instant) {
+        let expired: Vec<String> = self
+            .hold_map
+            .iter()
+            .filter(|(_, (_, deadline))| *deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.hold_map.remove(&key);
+            if let Some(cb) = model.get_brcb_mut(&key) {
+                cb.resvtms = cb.resvtms.map(|_| 0);
+                if cb.owner.is_some() {
+                    cb.owner = Some(Vec::new());
+                }
+            }
+        }
+    }
+
+    /*change_vec 是模型里面取出来的变化  按各自的触发条件攒起来
+    非缓存的直接出报告  缓存的先进缓存 使能了再按顺序发
+    缓存的这里不算发过  没确认的下次扫描还会再出     */
+    pub fn scan(
+        &mut self,
+        model: &mut Model,
+        change_vec: &[DataChange],
+        now: Instant,
+    ) -> Vec<RptOut> {
+        self.expire_holds(model, now);
+        let mut key_vec: Vec<String> = self.run_map.keys().cloned().collect();
+        key_vec.sort();
+        let mut ret = Vec::new();
+        for key in key_vec {
+            let con_id = self.owner_map.get(&key).copied();
+            let run = match self.run_map.get_mut(&key) {
+                Some(run) => run,
+                None => continue,
+            };
+            for trigger in run.collect(change_vec, now) {
+                let entry = match make_entry(model, &run.member_vec, &trigger) {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                match (run.buffer.as_mut(), con_id) {
+                    (Some(buffer), _) => buffer.push(entry),
+                    (None, Some(con_id)) => {
+                        if let Some(report) = build_report(model, &key, &entry, false) {
+                            ret.push(RptOut {
+                                con_id,
+                                report,
+                                brcb: None,
+                            });
+                        }
+                    }
+                    (None, None) => {}
+                }
+            }
+            let (buffer, con_id) = match (run.buffer.as_mut(), con_id) {
+                (Some(buffer), Some(con_id)) if run.enabled => (buffer, con_id),
+                _ => continue,
+            };
+            let entry_vec: Vec<RptEntry> =
+                buffer.unsent().take(BRCB_SEND_PER_SCAN).cloned().collect();
+            /*BufOvfl 只带在第一条上     */
+            let mut ovfl = buffer.ovfl;
+            for entry in entry_vec {
+                let mut report = match build_report(model, &key, &entry, true) {
+                    Some(report) => report,
+                    None => continue,
+                };
+                if report.bufovfl.is_some() {
+                    report.bufovfl = Some(ovfl);
+                }
+                ovfl = false;
+                ret.push(RptOut {
+                    con_id,
+                    report,
+                    brcb: Some((key.clone(), entry.entryid)),
+                });
+            }
+        }
+        ret
+    }
+
+    /*缓存报告交给连接了  这时候才算发过  控制块的 EntryID TimeOfEntry 跟着走     */
+    pub fn confirm_sent(&mut self, model: &mut Model, key: &str, entryid: u64) {
+        let buffer = match self
+            .run_map
+            .get_mut(key)
+            .and_then(|run| run.buffer.as_mut())
+        {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        buffer.ovfl = false;
+        buffer.sent_id = entryid;
+        let timeofentry = buffer
+            .entry_vec
+            .iter()
+            .find(|entry| entry.entryid == entryid)
+            .map(|entry| entry.timeofentry);
+        if let (Some(cb), Some(timeofentry)) = (model.get_brcb_mut(key), timeofentry) {
+            cb.entryid = entryid.to_be_bytes();
+            cb.timeofentry = timeofentry;
+        }
+    }
+}
+
+/*按原因取值  一个成员都读不到就没有这一条  日志也用这个
+变了的叶子用变化里面带的值  成员下面别的叶子用现在的
+时间是最后一个变化的  总召 完整性没有变化 用现在     */
+pub fn make_entry(model: &Model, member_vec: &[ObjRef], trigger: &Trigger) -> Option<RptEntry> {
+    let mut entrydata = Vec::new();
+    let mut size = 0;
+    for (index, (member, reason)) in member_vec.iter().zip(trigger.reason_vec.iter()).enumerate() {
+        if *reason == 0 {
+            continue;
+        }
+        let (node, mut value) = match (model.get_node(member), read_ref(model, member)) {
+            (Some(node), Ok(value)) => (node, value),
+            _ => continue,
+        };
+        for change in trigger.change_vec.iter().filter(|c| c.covered_by(member)) {
+            let path = &change.objref.path[member.path.len()..];
+            patch_value(node, member.fc.as_deref(), &mut value, path, &change.value);
+        }
+        let data = EntryData {
+            reference: Some(member.data_ref()),
+            fc: member.fc.clone(),
+            id: index as u16,
+            value,
+            reason: Some(*reason),
+        };
+        size += aper::aper_to_bytes(&data).map(|b| b.len()).unwrap_or(0);
+        entrydata.push(data);
+    }
+    if entrydata.is_empty() {
+        return None;
+    }
+    let timeofentry = match trigger.change_vec.last() {
+        Some(change) => change.time,
+        None => entry_time(chrono::Utc::now()),
+    };
+    Some(RptEntry {
+        entryid: 0,
+        timeofentry,
+        member_count: member_vec.len(),
+        entrydata,
+        size: size + 16,
+    })
+}
+
+/*value 是 node 按 fc 取出来的值  path 下面的叶子换成 leaf
+和 DataNode::get_value 一样  带 fc 的时候没有这个 fc 的子节点不占位置     */
+fn patch_value(
+    node: &DataNode,
+    fc: Option<&str>,
+    value: &mut Value,
+    path: &[String],
+    leaf: &Value,
+) {
+    let (name, rest) = match path.split_first() {
+        Some(split) => split,
+        None => {
+            *value = leaf.clone();
+            return;
+        }
+    };
+    let index = node
+        .child_vec
+        .iter()
+        .filter(|child| fc.is_none_or(|fc| child.has_fc(fc)))
+        .position(|child| &child.name == name);
+    let (index, child) = match (index, node.child(name)) {
+        (Some(index), Some(child)) => (index, child),
+        _ => return,
+    };
+    if let Value::Struct(vals) | Value::Array(vals) = value {
+        if let Some(val) = vals.get_mut(index) {
+            patch_value(child, fc, val, rest, leaf);
+        }
+    }
+}
+
+/*报告头用到的控制块属性  缓存和非缓存的都有     */
+struct RcbHead {
+    rptid: String,
+    optflds: [u8; 2],
+    sqnum: u16,
+    datset: String,
+    confrev: u32,
+}
+
+fn rcb_head(model: &Model, key: &str, buffered: bool) -> Option<RcbHead> {
+    if buffered {
+        let cb = model.get_brcb(key)?;
+        Some(RcbHead {
+            rptid: cb.rptid.clone(),
+            optflds: cb.optflds,
+            sqnum: cb.sqnum,
+            datset: cb.datset.clone(),
+            confrev: cb.confrev,
+        })
+    } else {
+        let cb = model.get_urcb(key)?;
+        Some(RcbHead {
+            rptid: cb.rptid.clone(),
+            optflds: cb.optflds,
+            sqnum: cb.sqnum as u16,
+            datset: cb.datset.clone(),
+            confrev: cb.confrev,
+        })
+    }
+}
+
+/*按控制块当前的 optFlds 组报告  发了序号加一
+非缓存的没有 BufOvfl 和 EntryID     */
+fn build_report(
+    model: &mut Model,
+    key: &str,
+    entry: &RptEntry,
+    buffered: bool,
+) -> Option<ReportPDU> {
+    let head = rcb_head(model, key, buffered)?;
+    let mut optflds = head.optflds;
+    if !buffered {
+        set_opt_bit(&mut optflds, OPT_BUFOVFL, false);
+        set_opt_bit(&mut optflds, OPT_ENTRYID, false);
+    }
+    let with_ref = opt_bit(&optflds, OPT_DATAREF);
+    let with_reason = opt_bit(&optflds, OPT_REASON);
+    let mut inclusion = vec![false; entry.member_count];
+    let entrydata = entry
+        .entrydata
+        .iter()
+        .map(|data| {
+            if let Some(on) = inclusion.get_mut(data.id as usize) {
+                *on = true;
+            }
+            EntryData {
+                reference: data.reference.clone().filter(|_| with_ref),
+                fc: data.fc.clone().filter(|_| with_ref),
+                reason: data.reason.filter(|_| with_reason),
+                ..data.clone()
+            }
+        })
+        .collect();
+    let segmented = opt_bit(&optflds, OPT_SEGMENTATION);
+    let report = ReportPDU {
+        rptid: if head.rptid.is_empty() {
+            key.to_string()
+        } else {
+            head.rptid
+        },
+        optflds,
+        sqnum: opt_bit(&optflds, OPT_SEQNUM).then_some(head.sqnum),
+        subsqnum: segmented.then_some(0),
+        moresegmentsfollow: segmented.then_some(false),
+        datset: opt_bit(&optflds, OPT_DATSET).then_some(head.datset),
+        bufovfl: opt_bit(&optflds, OPT_BUFOVFL).then_some(false),
+        confrev: opt_bit(&optflds, OPT_CONFREV).then_some(head.confrev),
+        timeofentry: opt_bit(&optflds, OPT_TIMESTAMP).then_some(entry.timeofentry),
+        entryid: opt_bit(&optflds, OPT_ENTRYID).then_some(entry.entryid.to_be_bytes()),
+        inclusion,
+        entrydata,
+    };
+    if buffered {
+        if let Some(cb) = model.get_brcb_mut(key) {
+            cb.sqnum = cb.sqnum.wrapping_add(1);
+        }
+    } else if let Some(cb) = model.get_urcb_mut(key) {
+        cb.sqnum = cb.sqnum.wrapping_add(1);
+    }
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cms61850::model::change::REASON_DCHG;
+
+    const STVAL: &str = "E1Q1SB1C1/CSWI1.Pos.stVal[ST]";
+
+    fn set_leaf(model: &mut Model, reference: &str, val: Value) {
+        let objref = ObjRef::parse(reference).unwrap();
+        let node = model.get_node_mut(&objref).unwrap();
+        let old = node.clone();
+        node.value = val;
+        model.note_change(&objref, &old);
+    }
+
+    /*成员是 Pos[ST]  从条目里面取 stVal     */
+    fn stval_of(model: &Model, member: &ObjRef, entry: &RptEntry) -> Value {
+        let index = model
+            .get_node(member)
+            .unwrap()
+            .child_vec
+            .iter()
+            .filter(|child| child.has_fc("ST"))
+            .position(|child| child.name == "stVal")
+            .unwrap();
+        match &entry.entrydata[0].value {
+            Value::Struct(vals) => vals[index].clone(),
+            other => panic!("member value {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn member_changed_again_flushes_first_value() {
+        let info = crate::cfg::scl2::scl_parse("sisco_sample.cid", "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let mut model = Model::from_scl(&info).unwrap();
+        let member_vec = vec![ObjRef::parse("E1Q1SB1C1/CSWI1.Pos[ST]").unwrap()];
+        let mut run = RcbRun::new(member_vec.clone(), REASON_DCHG, 1000, 0);
+        run.set_enabled(true);
+        let now = Instant::now();
+
+        let a = model.find(STVAL).unwrap().value.clone();
+        let b = if a == Value::Dbpos(2) {
+            Value::Dbpos(1)
+        } else {
+            Value::Dbpos(2)
+        };
+        set_leaf(&mut model, STVAL, b.clone());
+        set_leaf(&mut model, STVAL, a.clone());
+
+        /*第二次变化把第一次攒着的挤出来  带的是第一次的值     */
+        let trigger_vec = run.collect(&model.take_changes(), now);
+        assert_eq!(trigger_vec.len(), 1);
+        let entry = make_entry(&model, &member_vec, &trigger_vec[0]).unwrap();
+        assert_eq!(entry.entrydata[0].reason, Some(REASON_DCHG));
+        assert_eq!(stval_of(&model, &member_vec[0], &entry), b);
+
+        /*缓存时间到了出第二条     */
+        assert!(run
+            .collect(&[], now + Duration::from_millis(500))
+            .is_empty());
+        let trigger_vec = run.collect(&[], now + Duration::from_millis(1000));
+        assert_eq!(trigger_vec.len(), 1);
+        let entry = make_entry(&model, &member_vec, &trigger_vec[0]).unwrap();
+        assert_eq!(stval_of(&model, &member_vec[0], &entry), a);
+    }
+
+    #[tokio::test]
+    async fn brcb_sent_only_after_confirm() {
+        let info = crate::cfg::scl2::scl_parse("sisco_sample.cid", "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let mut model = Model::from_scl(&info).unwrap();
+        let mut engine = ReportEngine::new(10000);
+        engine.start_brcbs(&model);
+        let key = engine
+            .run_map
+            .keys()
+            .find(|key| key.ends_with("LLN0.PosReport01"))
+            .unwrap()
+            .clone();
+        engine.set_owner(&key, 1);
+        engine.run_mut(&key).unwrap().set_enabled(true);
+        engine.request_gi(&key);
+        let now = Instant::now();
+
+        let out_vec = engine.scan(&mut model, &[], now);
+        assert_eq!(out_vec.len(), 1);
+        let (_, entryid) = out_vec[0].brcb.clone().unwrap();
+
+        /*没确认  下次扫描还是这一条  控制块的 EntryID 也没动     */
+        let out_vec = engine.scan(&mut model, &[], now);
+        assert_eq!(out_vec.len(), 1);
+        assert_eq!(out_vec[0].brcb.as_ref().unwrap().1, entryid);
+        assert_ne!(model.get_brcb(&key).unwrap().entryid, entryid.to_be_bytes());
+
+        engine.confirm_sent(&mut model, &key, entryid);
+        assert_eq!(model.get_brcb(&key).unwrap().entryid, entryid.to_be_bytes());
+        assert!(engine.scan(&mut model, &[], now).is_empty());
+    }
+}
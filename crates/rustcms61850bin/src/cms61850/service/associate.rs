@@ -0,0 +1,212 @@
+use super::common::{self, ServiceError};
+use crate::cms61850::codec::aper::{AperCodec, AperDecoder, AperEncoder, Size};
+/***************************************/
+/*关联 / 释放 / 中止     */
+/***************************************/
+
+/*关联号固定 64 字节     */
+pub const ASSOCIATION_ID_LEN: usize = 64;
+
+// AuthenticationParameter ::= SEQUENCE {
+//     signatureCertificate OCTET STRING,
+//     signedTime UtcTime,
+//     signedValue OCTET STRING
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct AuthenticationParameter {
+    pub signaturecertificate: Vec<u8>,
+    pub signedtime: Vec<u8>,
+    pub signedvalue: Vec<u8>,
+}
+
+// Associate-RequestPDU ::= SEQUENCE {
+//     serverAccessPointReference ObjectReference OPTIONAL,
+//     authenticationParameter AuthenticationParameter OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct AssociateRequestPDU {
+    pub serveraccesspointreference: Option<String>,
+    pub authenticationparameter: Option<AuthenticationParameter>,
+}
+
+// Associate-ResponsePDU ::= SEQUENCE {
+//     associationId OCTET STRING (SIZE(64)),
+//     serviceError ServiceError OPTIONAL,
+//     authenticationParameter AuthenticationParameter OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct AssociateResponsePDU {
+    pub associationid: Vec<u8>,
+    pub serviceerror: Option<ServiceError>,
+    pub authenticationparameter: Option<AuthenticationParameter>,
+}
+
+// Abort-RequestPDU ::= SEQUENCE {
+//     associationId OCTET STRING (SIZE(64)),
+//     reason INTEGER (0..5)
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct AbortRequestPDU {
+    pub associationid: Vec<u8>,
+    pub reason: u8,
+}
+
+// Release-RequestPDU ::= SEQUENCE {
+//     associationId OCTET STRING (SIZE(64))
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ReleaseRequestPDU {
+    pub associationid: Vec<u8>,
+}
+
+// Release-ResponsePDU ::= SEQUENCE {
+//     associationId OCTET STRING (SIZE(64)),
+//     serviceError ServiceError OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ReleaseResponsePDU {
+    pub associationid: Vec<u8>,
+    pub serviceerror: Option<ServiceError>,
+}
+
+/*生成关联号  连接号 + 时间 ，剩下的补0     */
+pub fn gen_association_id(con_id: u32) -> Vec<u8> {
+    let mut id = Vec::with_capacity(ASSOCIATION_ID_LEN);
+    id.extend_from_slice(&con_id.to_be_bytes());
+    let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    id.extend_from_slice(&nanos.to_be_bytes());
+    id.resize(ASSOCIATION_ID_LEN, 0);
+    id
+}
+
+fn put_association_id(enc: &mut AperEncoder, id: &[u8]) -> crate::Result<()> {
+    enc.put_octet_string(id, Size::Fixed(ASSOCIATION_ID_LEN))
+}
+fn get_association_id(dec: &mut AperDecoder) -> crate::Result<Vec<u8>> {
+    dec.get_octet_string(Size::Fixed(ASSOCIATION_ID_LEN))
+}
+
+impl AperCodec for AuthenticationParameter {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_octet_string(&self.signaturecertificate, Size::Unconstrained)?;
+        enc.put_octet_string(&self.signedtime, Size::Fixed(8))?;
+        enc.put_octet_string(&self.signedvalue, Size::Unconstrained)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(AuthenticationParameter {
+            signaturecertificate: dec.get_octet_string(Size::Unconstrained)?,
+            signedtime: dec.get_octet_string(Size::Fixed(8))?,
+            signedvalue: dec.get_octet_string(Size::Unconstrained)?,
+        })
+    }
+}
+
+impl AperCodec for AssociateRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[
+                self.serveraccesspointreference.is_some(),
+                self.authenticationparameter.is_some(),
+            ],
+        );
+        if let Some(ref sap) = self.serveraccesspointreference {
+            common::put_obj_ref(enc, sap)?;
+        }
+        if let Some(ref auth) = self.authenticationparameter {
+            auth.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 2)?;
+        let mut pdu = AssociateRequestPDU::default();
+        if present[0] {
+            pdu.serveraccesspointreference = Some(common::get_obj_ref(dec)?);
+        }
+        if present[1] {
+            pdu.authenticationparameter = Some(AuthenticationParameter::aper_decode(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for AssociateResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[
+                self.serviceerror.is_some(),
+                self.authenticationparameter.is_some(),
+            ],
+        );
+        put_association_id(enc, &self.associationid)?;
+        if let Some(ref err) = self.serviceerror {
+            err.aper_encode(enc)?;
+        }
+        if let Some(ref auth) = self.authenticationparameter {
+            auth.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 2)?;
+        let mut pdu = AssociateResponsePDU {
+            associationid: get_association_id(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.serviceerror = Some(ServiceError::aper_decode(dec)?);
+        }
+        if present[1] {
+            pdu.authenticationparameter = Some(AuthenticationParameter::aper_decode(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for AbortRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        put_association_id(enc, &self.associationid)?;
+        enc.put_constrained_int(self.reason as i128, 0, 5)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(AbortRequestPDU {
+            associationid: get_association_id(dec)?,
+            reason: dec.get_constrained_int(0, 5)? as u8,
+        })
+    }
+}
+
+impl AperCodec for ReleaseRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        put_association_id(enc, &self.associationid)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(ReleaseRequestPDU {
+            associationid: get_association_id(dec)?,
+        })
+    }
+}
+
+impl AperCodec for ReleaseResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.serviceerror.is_some()]);
+        put_association_id(enc, &self.associationid)?;
+        if let Some(ref err) = self.serviceerror {
+            err.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut pdu = ReleaseResponsePDU {
+            associationid: get_association_id(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.serviceerror = Some(ServiceError::aper_decode(dec)?);
+        }
+        Ok(pdu)
+    }
+}
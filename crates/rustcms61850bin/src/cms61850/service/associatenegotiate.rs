@@ -1,3 +1,7 @@
+use crate::cms61850::codec::aper::{AperCodec, AperDecoder, AperEncoder, Size};
+
+/*目前只支持协议版本 1  其他的版本协商时直接拒绝     */
+pub const CMS_PROTOCOL_VERSION: u32 = 1;
 /***************************************/
 /*协商参数大小     */
 /***************************************/
@@ -6,6 +10,7 @@
 //     asduSize       [1] IMPLICIT INT32U,
 //     protocolVersion [2] IMPLICIT INT32U
 // }
+#[derive(Default, Clone, Debug, PartialEq)]
 pub struct AssociateNegotiateRequestPDU {
     pub apdusize: u16,
     pub asdusize: u32,
@@ -18,10 +23,81 @@ pub struct AssociateNegotiateRequestPDU {
 //     protocolVersion [2] IMPLICIT INT32U,
 //     modelVersion   [3] IMPLICIT VisibleString
 // }
-
+#[derive(Default, Clone, Debug, PartialEq)]
 pub struct AssociateNegotiateResponsePDU {
     pub apdusize: u16,
     pub asdusize: u32,
     pub protocolversion: u32,
     pub modelversion: String,
 }
+
+impl AperCodec for AssociateNegotiateRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_constrained_int(self.apdusize as i128, 0, u16::MAX as i128)?;
+        enc.put_constrained_int(self.asdusize as i128, 0, u32::MAX as i128)?;
+        enc.put_constrained_int(self.protocolversion as i128, 0, u32::MAX as i128)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(AssociateNegotiateRequestPDU {
+            apdusize: dec.get_constrained_int(0, u16::MAX as i128)? as u16,
+            asdusize: dec.get_constrained_int(0, u32::MAX as i128)? as u32,
+            protocolversion: dec.get_constrained_int(0, u32::MAX as i128)? as u32,
+        })
+    }
+}
+
+impl AperCodec for AssociateNegotiateResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_constrained_int(self.apdusize as i128, 0, u16::MAX as i128)?;
+        enc.put_constrained_int(self.asdusize as i128, 0, u32::MAX as i128)?;
+        enc.put_constrained_int(self.protocolversion as i128, 0, u32::MAX as i128)?;
+        enc.put_visible_string(&self.modelversion, Size::Unconstrained)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(AssociateNegotiateResponsePDU {
+            apdusize: dec.get_constrained_int(0, u16::MAX as i128)? as u16,
+            asdusize: dec.get_constrained_int(0, u32::MAX as i128)? as u32,
+            protocolversion: dec.get_constrained_int(0, u32::MAX as i128)? as u32,
+            modelversion: dec.get_visible_string(Size::Unconstrained)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cms61850::codec::aper::{aper_from_bytes, aper_to_bytes};
+
+    #[test]
+    fn negotiate_request_bytes() {
+        let req = AssociateNegotiateRequestPDU {
+            apdusize: 65535,
+            asdusize: 5242880,
+            protocolversion: 1,
+        };
+        let out = aper_to_bytes(&req).unwrap();
+        assert_eq!(
+            out.as_ref(),
+            &[0xFF, 0xFF, 0x80, 0x50, 0x00, 0x00, 0x00, 0x01]
+        );
+        let back: AssociateNegotiateRequestPDU = aper_from_bytes(&out).unwrap();
+        assert_eq!(back, req);
+    }
+
+    #[test]
+    fn negotiate_response_bytes() {
+        let resp = AssociateNegotiateResponsePDU {
+            apdusize: 1024,
+            asdusize: 65536,
+            protocolversion: 1,
+            modelversion: "v1".to_string(),
+        };
+        let out = aper_to_bytes(&resp).unwrap();
+        assert_eq!(
+            out.as_ref(),
+            &[0x04, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x01, 0x02, b'v', b'1']
+        );
+        let back: AssociateNegotiateResponsePDU = aper_from_bytes(&out).unwrap();
+        assert_eq!(back, resp);
+    }
+}
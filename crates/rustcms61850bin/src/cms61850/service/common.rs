@@ -0,0 +1,291 @@
+use crate::cms61850::codec::aper::{AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::model::{LogicalNode, Model, ObjRef};
+/***************************************/
+/*各个服务公用的类型     */
+/***************************************/
+
+/*服务错误  所有服务的否定响应 载荷就是这个
+ServiceError ::= ENUMERATED  共 13 个     */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServiceError {
+    NoError = 0,
+    InstanceNotAvailable = 1,
+    InstanceInUse = 2,
+    AccessViolation = 3,
+    AccessNotAllowedInCurrentState = 4,
+    ParameterValueInappropriate = 5,
+    ParameterValueInconsistent = 6,
+    ClassNotSupported = 7,
+    InstanceLockedByOtherClient = 8,
+    ControlMustBeSelected = 9,
+    TypeConflict = 10,
+    FailedDueToCommunicationsConstraint = 11,
+    FailedDueToServerConstraint = 12,
+}
+const SERVICE_ERROR_COUNT: usize = 13;
+
+impl ServiceError {
+    pub fn from_index(index: usize) -> Option<ServiceError> {
+        use ServiceError::*;
+        let all = [
+            NoError,
+            InstanceNotAvailable,
+            InstanceInUse,
+            AccessViolation,
+            AccessNotAllowedInCurrentState,
+            ParameterValueInappropriate,
+            ParameterValueInconsistent,
+            ClassNotSupported,
+            InstanceLockedByOtherClient,
+            ControlMustBeSelected,
+            TypeConflict,
+            FailedDueToCommunicationsConstraint,
+            FailedDueToServerConstraint,
+        ];
+        all.get(index).copied()
+    }
+}
+
+impl AperCodec for ServiceError {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_enumerated(*self as usize, SERVICE_ERROR_COUNT)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let index = dec.get_enumerated(SERVICE_ERROR_COUNT)?;
+        match ServiceError::from_index(index) {
+            Some(err) => Ok(err),
+            None => anyhow::bail!(format!("unknown service error {}", index)),
+        }
+    }
+}
+
+/*响应是 NULL 的服务  编出来没有位  按 APER 补一个 0 字节     */
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct NullPDU;
+
+impl AperCodec for NullPDU {
+    fn aper_encode(&self, _enc: &mut AperEncoder) -> crate::Result<()> {
+        Ok(())
+    }
+    fn aper_decode(_dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(NullPDU)
+    }
+}
+
+/*NULL 响应的载荷     */
+pub fn null_resp() -> bytes::Bytes {
+    crate::cms61850::codec::aper::aper_to_bytes(&NullPDU).unwrap_or_default()
+}
+
+/***************************************/
+/*常用的字符串类型  长度约束见 cms.asn1     */
+/***************************************/
+/* ObjectReference / SubReference ::= VisibleString129     */
+pub fn put_obj_ref(enc: &mut AperEncoder, val: &str) -> crate::Result<()> {
+    enc.put_visible_string(val, Size::Range(0, 129))
+}
+pub fn get_obj_ref(dec: &mut AperDecoder) -> crate::Result<String> {
+    dec.get_visible_string(Size::Range(0, 129))
+}
+/* ObjectName ::= VisibleString64     */
+pub fn put_obj_name(enc: &mut AperEncoder, val: &str) -> crate::Result<()> {
+    enc.put_visible_string(val, Size::Range(0, 64))
+}
+pub fn get_obj_name(dec: &mut AperDecoder) -> crate::Result<String> {
+    dec.get_visible_string(Size::Range(0, 64))
+}
+/* FunctionalConstraint ::= VisibleString (SIZE(2))     */
+pub fn put_fc(enc: &mut AperEncoder, val: &str) -> crate::Result<()> {
+    enc.put_visible_string(val, Size::Fixed(2))
+}
+pub fn get_fc(dec: &mut AperDecoder) -> crate::Result<String> {
+    dec.get_visible_string(Size::Fixed(2))
+}
+
+/* ReferenceChoice ::= CHOICE { ldName ObjectName, lnReference ObjectReference }     */
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReferenceChoice {
+    LdName(String),
+    LnReference(String),
+}
+
+impl Default for ReferenceChoice {
+    fn default() -> Self {
+        ReferenceChoice::LdName(String::new())
+    }
+}
+
+impl AperCodec for ReferenceChoice {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        match self {
+            ReferenceChoice::LdName(name) => {
+                enc.put_choice_index(0, 2, false)?;
+                put_obj_name(enc, name)
+            }
+            ReferenceChoice::LnReference(reference) => {
+                enc.put_choice_index(1, 2, false)?;
+                put_obj_ref(enc, reference)
+            }
+        }
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        match dec.get_choice_index(2, false)? {
+            0 => Ok(ReferenceChoice::LdName(get_obj_name(dec)?)),
+            _ => Ok(ReferenceChoice::LnReference(get_obj_ref(dec)?)),
+        }
+    }
+}
+
+/* moreFollows BOOLEAN DEFAULT TRUE  等于默认值的时候不编码
+序列的前导位里面 这一位是 !more_follows     */
+pub fn put_more_follows(enc: &mut AperEncoder, more_follows: bool) {
+    if !more_follows {
+        enc.put_bool(false);
+    }
+}
+pub fn get_more_follows(dec: &mut AperDecoder, present: bool) -> crate::Result<bool> {
+    if present {
+        dec.get_bool()
+    } else {
+        Ok(true)
+    }
+}
+
+/*referenceAfter  跳过 after 以及它前面的     */
+pub fn skip_after<T>(
+    items: Vec<T>,
+    after: &Option<String>,
+    key: impl Fn(&T) -> &str,
+) -> Result<Vec<T>, ServiceError> {
+    let after = match after {
+        Some(after) => after,
+        None => return Ok(items),
+    };
+    match items.iter().position(|item| key(item) == after.as_str()) {
+        Some(index) => Ok(items.into_iter().skip(index + 1).collect()),
+        None => Err(ServiceError::ParameterValueInconsistent),
+    }
+}
+
+/*响应超过协商的 asdu 长度时 续传
+从 items 里面取尽量多的 放进一个响应  取不完 moreFollows 就是 true
+max_len 是 asdu 去掉 req_id 以后的长度     */
+pub fn encode_fit<T: Clone, P: AperCodec>(
+    items: &[T],
+    max_len: usize,
+    make: impl Fn(Vec<T>, bool) -> P,
+) -> Result<bytes::Bytes, ServiceError> {
+    let encode = |count: usize| {
+        crate::cms61850::codec::aper::aper_to_bytes(&make(
+            items[..count].to_vec(),
+            count < items.len(),
+        ))
+        .map_err(|_| ServiceError::FailedDueToServerConstraint)
+    };
+    let all = encode(items.len())?;
+    if all.len() <= max_len {
+        return Ok(all);
+    }
+    /*二分找能放下的最多个数     */
+    let (mut lo, mut hi) = (0, items.len());
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if encode(mid)?.len() <= max_len {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    if lo == 0 {
+        return Err(ServiceError::FailedDueToCommunicationsConstraint);
+    }
+    encode(lo)
+}
+
+/*请求解码失败 统一回 ParameterValueInappropriate     */
+pub fn decode_req<T: AperCodec>(pay_load: &[u8]) -> Result<T, ServiceError> {
+    crate::cms61850::codec::aper::aper_from_bytes(pay_load).map_err(|e| {
+        println!("decode req failed {}", e);
+        ServiceError::ParameterValueInappropriate
+    })
+}
+
+/* SEQUENCE { reference ObjectReference, fc FunctionalConstraint OPTIONAL }
+读值 读定义 都是这个     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct RefFc {
+    pub reference: String,
+    pub fc: Option<String>,
+}
+
+impl AperCodec for RefFc {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.fc.is_some()]);
+        put_obj_ref(enc, &self.reference)?;
+        if let Some(ref fc) = self.fc {
+            put_fc(enc, fc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut item = RefFc {
+            reference: get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            item.fc = Some(get_fc(dec)?);
+        }
+        Ok(item)
+    }
+}
+
+/*引用和 fc 合成 ObjRef  解析不了就是参数不对     */
+pub fn parse_ref_fc(reference: &str, fc: &Option<String>) -> Result<ObjRef, ServiceError> {
+    let mut objref = ObjRef::parse(reference).map_err(|e| {
+        println!("parse reference {} failed {}", reference, e);
+        ServiceError::ParameterValueInappropriate
+    })?;
+    if fc.is_some() {
+        objref.fc = fc.clone();
+    }
+    Ok(objref)
+}
+
+/*没有续传的响应  超过协商长度只能整个失败     */
+pub fn check_fit(pay_load: bytes::Bytes, max_len: usize) -> Result<bytes::Bytes, ServiceError> {
+    if pay_load.len() > max_len {
+        return Err(ServiceError::FailedDueToCommunicationsConstraint);
+    }
+    Ok(pay_load)
+}
+
+/*ReferenceChoice 对应的逻辑节点  (子引用前缀, LN)
+给的是 LD 的话 是它下面所有的 LN  子引用前面要带 "LN."     */
+pub fn resolve_lns<'a>(
+    model: &'a Model,
+    reference: &ReferenceChoice,
+) -> Result<Vec<(String, &'a LogicalNode)>, ServiceError> {
+    match reference {
+        ReferenceChoice::LdName(ldname) => {
+            let ld = model
+                .get_ld(ldname)
+                .ok_or(ServiceError::InstanceNotAvailable)?;
+            Ok(ld
+                .ln_vec
+                .iter()
+                .map(|ln| (format!("{}.", ln.name), ln))
+                .collect())
+        }
+        ReferenceChoice::LnReference(lnref) => {
+            let (ldname, lnname) = lnref
+                .split_once('/')
+                .ok_or(ServiceError::ParameterValueInappropriate)?;
+            let ln = model
+                .get_ld(ldname)
+                .and_then(|ld| ld.get_ln(lnname))
+                .ok_or(ServiceError::InstanceNotAvailable)?;
+            Ok(vec![(String::new(), ln)])
+        }
+    }
+}
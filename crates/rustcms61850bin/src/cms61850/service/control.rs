@@ -0,0 +1,362 @@
+use super::common::{self, ServiceError};
+use crate::cms61850::codec::aper::{self, AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::control::{ControlEngine, CtlObject};
+use crate::cms61850::model::{Model, Value};
+use bytes::Bytes;
+use tokio::time::Instant;
+/***************************************/
+/*控制服务  68 选择  69 带值选择  70 取消  71 执行  73 时间激活执行
+72 74 是服务端发给客户端的  没有响应
+69 70 71 73 的否定响应不是 ServiceError  是带 addCause 的 Control-ResponsePDU     */
+/***************************************/
+
+// Originator ::= SEQUENCE {
+//     orCat INTEGER (0..8),
+//     orIdent Octet64
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Originator {
+    pub orcat: u8,
+    pub orident: Vec<u8>,
+}
+
+// Select-RequestPDU ::= SEQUENCE {
+//     reference ObjectReference
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SelectRequestPDU {
+    pub reference: String,
+}
+
+// Select-ResponsePDU ::= SEQUENCE {
+//     reference ObjectReference
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SelectResponsePDU {
+    pub reference: String,
+}
+
+// Control-RequestPDU ::= SEQUENCE {
+//     reference ObjectReference,
+//     ctlVal Data,
+//     operTm UtcTime OPTIONAL,
+//     origin Originator,
+//     ctlNum INT8U,
+//     t UtcTime,
+//     test BOOLEAN,
+//     check Check
+// }
+/*69 70 71 73 的请求都是这个     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ControlRequestPDU {
+    pub reference: String,
+    pub ctlval: Value,
+    pub opertm: Option<[u8; 8]>,
+    pub origin: Originator,
+    pub ctlnum: u8,
+    pub t: [u8; 8],
+    pub test: bool,
+    /*bit1 同期检查 bit0 联锁检查  和 Value::Check 一样     */
+    pub check: u8,
+}
+
+// Control-ResponsePDU ::= SEQUENCE {
+//     reference ObjectReference,
+//     ctlVal Data,
+//     operTm UtcTime OPTIONAL,
+//     origin Originator,
+//     ctlNum INT8U,
+//     t UtcTime,
+//     test BOOLEAN,
+//     check Check,
+//     addCause AddCause OPTIONAL
+// }
+/*请求原样带回去  否定响应和失败的命令结束才有 addCause
+CommandTermination TimeActivatedOperateTermination 也是这个     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ControlResponsePDU {
+    pub reference: String,
+    pub ctlval: Value,
+    pub opertm: Option<[u8; 8]>,
+    pub origin: Originator,
+    pub ctlnum: u8,
+    pub t: [u8; 8],
+    pub test: bool,
+    pub check: u8,
+    pub addcause: Option<i8>,
+}
+
+impl ControlResponsePDU {
+    pub fn from_req(req: &ControlRequestPDU, addcause: Option<i8>) -> ControlResponsePDU {
+        ControlResponsePDU {
+            reference: req.reference.clone(),
+            ctlval: req.ctlval.clone(),
+            opertm: req.opertm,
+            origin: req.origin.clone(),
+            ctlnum: req.ctlnum,
+            t: req.t,
+            test: req.test,
+            check: req.check,
+            addcause,
+        }
+    }
+}
+
+fn put_utc_time(enc: &mut AperEncoder, time: &[u8; 8]) -> crate::Result<()> {
+    enc.put_octet_string(time, Size::Fixed(8))
+}
+
+fn get_utc_time(dec: &mut AperDecoder) -> crate::Result<[u8; 8]> {
+    let mut time = [0u8; 8];
+    time.copy_from_slice(&dec.get_octet_string(Size::Fixed(8))?);
+    Ok(time)
+}
+
+fn put_check(enc: &mut AperEncoder, check: u8) -> crate::Result<()> {
+    enc.put_bit_string(&[check << 6], 2, Size::Fixed(2))
+}
+
+fn get_check(dec: &mut AperDecoder) -> crate::Result<u8> {
+    let (bits, _) = dec.get_bit_string(Size::Fixed(2))?;
+    Ok(bits[0] >> 6)
+}
+
+impl AperCodec for Originator {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_constrained_int(self.orcat as i128, 0, 8)?;
+        enc.put_octet_string(&self.orident, Size::Range(0, 64))
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(Originator {
+            orcat: dec.get_constrained_int(0, 8)? as u8,
+            orident: dec.get_octet_string(Size::Range(0, 64))?,
+        })
+    }
+}
+
+impl AperCodec for SelectRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.reference)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(SelectRequestPDU {
+            reference: common::get_obj_ref(dec)?,
+        })
+    }
+}
+
+impl AperCodec for SelectResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.reference)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(SelectResponsePDU {
+            reference: common::get_obj_ref(dec)?,
+        })
+    }
+}
+
+impl AperCodec for ControlRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.opertm.is_some()]);
+        common::put_obj_ref(enc, &self.reference)?;
+        self.ctlval.aper_encode(enc)?;
+        if let Some(ref opertm) = self.opertm {
+            put_utc_time(enc, opertm)?;
+        }
+        self.origin.aper_encode(enc)?;
+        enc.put_constrained_int(self.ctlnum as i128, 0, 255)?;
+        put_utc_time(enc, &self.t)?;
+        enc.put_bool(self.test);
+        put_check(enc, self.check)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let reference = common::get_obj_ref(dec)?;
+        let ctlval = Value::aper_decode(dec)?;
+        let opertm = if present[0] {
+            Some(get_utc_time(dec)?)
+        } else {
+            None
+        };
+        Ok(ControlRequestPDU {
+            reference,
+            ctlval,
+            opertm,
+            origin: Originator::aper_decode(dec)?,
+            ctlnum: dec.get_constrained_int(0, 255)? as u8,
+            t: get_utc_time(dec)?,
+            test: dec.get_bool()?,
+            check: get_check(dec)?,
+        })
+    }
+}
+
+impl AperCodec for ControlResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.opertm.is_some(), self.addcause.is_some()]);
+        common::put_obj_ref(enc, &self.reference)?;
+        self.ctlval.aper_encode(enc)?;
+        if let Some(ref opertm) = self.opertm {
+            put_utc_time(enc, opertm)?;
+        }
+        self.origin.aper_encode(enc)?;
+        enc.put_constrained_int(self.ctlnum as i128, 0, 255)?;
+        put_utc_time(enc, &self.t)?;
+        enc.put_bool(self.test);
+        put_check(enc, self.check)?;
+        if let Some(addcause) = self.addcause {
+            enc.put_constrained_int(addcause as i128, -128, 127)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 2)?;
+        let reference = common::get_obj_ref(dec)?;
+        let ctlval = Value::aper_decode(dec)?;
+        let opertm = if present[0] {
+            Some(get_utc_time(dec)?)
+        } else {
+            None
+        };
+        let mut resp = ControlResponsePDU {
+            reference,
+            ctlval,
+            opertm,
+            origin: Originator::aper_decode(dec)?,
+            ctlnum: dec.get_constrained_int(0, 255)? as u8,
+            t: get_utc_time(dec)?,
+            test: dec.get_bool()?,
+            check: get_check(dec)?,
+            addcause: None,
+        };
+        if present[1] {
+            resp.addcause = Some(dec.get_constrained_int(-128, 127)? as i8);
+        }
+        Ok(resp)
+    }
+}
+
+/*68 选择  只有 sbo-with-normal-security 用这个  失败是 ServiceError     */
+pub fn select(
+    model: &mut Model,
+    engine: &mut ControlEngine,
+    con_id: u32,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: SelectRequestPDU = common::decode_req(pay_load)?;
+    let obj = CtlObject::resolve(model, &req.reference)?;
+    engine.select(model, con_id, &obj, Instant::now())?;
+    let resp = SelectResponsePDU {
+        reference: req.reference,
+    };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*ServiceError 做否定响应的载荷  请求解不出来 对象找不到的时候用     */
+fn service_err(err: ServiceError) -> Bytes {
+    aper::aper_to_bytes(&err).unwrap_or_default()
+}
+
+/*69 70 71 73 公共的部分  解请求 找对象 结果做成响应
+成功原样带回去  失败带上 addCause     */
+fn control(
+    model: &mut Model,
+    engine: &mut ControlEngine,
+    con_id: u32,
+    pay_load: &[u8],
+    max_len: usize,
+    act: impl FnOnce(&mut Model, &mut ControlEngine, &CtlObject, &ControlRequestPDU) -> Result<(), i8>,
+) -> Result<Bytes, Bytes> {
+    let req: ControlRequestPDU = common::decode_req(pay_load).map_err(service_err)?;
+    let obj = CtlObject::resolve(model, &req.reference).map_err(service_err)?;
+    let result = act(model, engine, &obj, &req);
+    if let Err(addcause) = result {
+        engine.note_error(con_id, &req, addcause);
+    }
+    let resp = ControlResponsePDU::from_req(&req, result.err());
+    let pay_load = aper::aper_to_bytes(&resp)
+        .map_err(|_| service_err(ServiceError::FailedDueToServerConstraint))?;
+    let pay_load = common::check_fit(pay_load, max_len).map_err(service_err)?;
+    match resp.addcause {
+        Some(_) => Err(pay_load),
+        None => Ok(pay_load),
+    }
+}
+
+/*69 带值选择  sbo-with-enhanced-security     */
+pub fn select_with_value(
+    model: &mut Model,
+    engine: &mut ControlEngine,
+    con_id: u32,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, Bytes> {
+    control(
+        model,
+        engine,
+        con_id,
+        pay_load,
+        max_len,
+        |model, engine, obj, req| engine.select_with_value(model, con_id, obj, req, Instant::now()),
+    )
+}
+
+/*70 取消选择     */
+pub fn cancel(
+    model: &mut Model,
+    engine: &mut ControlEngine,
+    con_id: u32,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, Bytes> {
+    control(
+        model,
+        engine,
+        con_id,
+        pay_load,
+        max_len,
+        |model, engine, obj, req| engine.cancel(model, con_id, obj, req, Instant::now()),
+    )
+}
+
+/*71 执行  增强安全的命令结束由引擎排队  有结果以后再发     */
+pub fn operate(
+    model: &mut Model,
+    engine: &mut ControlEngine,
+    con_id: u32,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, Bytes> {
+    control(
+        model,
+        engine,
+        con_id,
+        pay_load,
+        max_len,
+        |model, engine, obj, req| engine.operate(model, con_id, obj, req, Instant::now()),
+    )
+}
+
+/*73 时间激活执行  响应只说明排上了  执行的结果看 74     */
+pub fn time_activated_operate(
+    model: &mut Model,
+    engine: &mut ControlEngine,
+    con_id: u32,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, Bytes> {
+    control(
+        model,
+        engine,
+        con_id,
+        pay_load,
+        max_len,
+        |model, engine, obj, req| {
+            engine.time_activated_operate(model, con_id, obj, req, Instant::now())
+        },
+    )
+}
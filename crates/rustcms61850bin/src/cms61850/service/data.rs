@@ -0,0 +1,1084 @@
+use super::common::{self, RefFc, ReferenceChoice, ServiceError};
+use super::directory;
+use crate::cms61850::codec::aper::{self, AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::model::cb::{CbValue, SGCB_NAME};
+use crate::cms61850::model::overlay::PERSIST_FC;
+use crate::cms61850::model::{
+    DataDefinition, DataNode, LogicalNode, Model, NodeKind, ObjRef, Value,
+};
+use crate::cms61850::sg::{SgEngine, FC_SE, FC_SG};
+use bytes::Bytes;
+use tokio::time::Instant;
+/***************************************/
+/*数据读写 和 数据定义  48 49 50 51 83 155 156     */
+/***************************************/
+
+/*可以用 SetDataValues 写的功能约束  ST MX 这些只读  CO 走控制服务     */
+pub const WRITABLE_FC: [&str; 4] = ["SP", "SE", "CF", "DC"];
+/*这些 valkind 不允许在线写     */
+pub const READONLY_VALKIND: [&str; 3] = ["RO", "Conf", "Spec"];
+
+// GetDataValues-RequestPDU ::= SEQUENCE {
+//     data SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         fc FunctionalConstraint OPTIONAL
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetDataValuesRequestPDU {
+    pub data: Vec<RefFc>,
+}
+
+// GetDataValues-ResponsePDU ::= SEQUENCE {
+//     value SEQUENCE OF Data
+// }
+/*单个失败 对应位置放 Data 的 error 分支     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetDataValuesResponsePDU {
+    pub value: Vec<Value>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetDataValuesItem {
+    pub reference: String,
+    pub fc: Option<String>,
+    pub value: Value,
+}
+
+// SetDataValues-RequestPDU ::= SEQUENCE {
+//     data SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         fc FunctionalConstraint OPTIONAL,
+//         value Data
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetDataValuesRequestPDU {
+    pub data: Vec<SetDataValuesItem>,
+}
+
+// SetDataValues-ResponsePDU ::= SEQUENCE {
+//     result SEQUENCE OF ServiceError
+// }
+/*成功的写 对应位置是 no-error     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetDataValuesResponsePDU {
+    pub result: Vec<ServiceError>,
+}
+
+impl AperCodec for GetDataValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.data.len(), Size::Unconstrained)?;
+        for item in self.data.iter() {
+            item.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.push(RefFc::aper_decode(dec)?);
+        }
+        Ok(GetDataValuesRequestPDU { data })
+    }
+}
+
+impl AperCodec for GetDataValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.value.len(), Size::Unconstrained)?;
+        for val in self.value.iter() {
+            val.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut value = Vec::new();
+        for _ in 0..count {
+            value.push(Value::aper_decode(dec)?);
+        }
+        Ok(GetDataValuesResponsePDU { value })
+    }
+}
+
+impl AperCodec for SetDataValuesItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.fc.is_some()]);
+        common::put_obj_ref(enc, &self.reference)?;
+        if let Some(ref fc) = self.fc {
+            common::put_fc(enc, fc)?;
+        }
+        self.value.aper_encode(enc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let reference = common::get_obj_ref(dec)?;
+        let fc = if present[0] {
+            Some(common::get_fc(dec)?)
+        } else {
+            None
+        };
+        Ok(SetDataValuesItem {
+            reference,
+            fc,
+            value: Value::aper_decode(dec)?,
+        })
+    }
+}
+
+impl AperCodec for SetDataValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.data.len(), Size::Unconstrained)?;
+        for item in self.data.iter() {
+            item.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.push(SetDataValuesItem::aper_decode(dec)?);
+        }
+        Ok(SetDataValuesRequestPDU { data })
+    }
+}
+
+impl AperCodec for SetDataValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.result.len(), Size::Unconstrained)?;
+        for err in self.result.iter() {
+            err.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut result = Vec::new();
+        for _ in 0..count {
+            result.push(ServiceError::aper_decode(dec)?);
+        }
+        Ok(SetDataValuesResponsePDU { result })
+    }
+}
+
+/*读一项  找不到或者没有这个 fc 都是 InstanceNotAvailable     */
+pub fn read_ref(model: &Model, objref: &ObjRef) -> Result<Value, ServiceError> {
+    let node = model
+        .get_node(objref)
+        .ok_or(ServiceError::InstanceNotAvailable)?;
+    node.get_value(objref.fc.as_deref())
+        .ok_or(ServiceError::InstanceNotAvailable)
+}
+
+fn read_one(model: &Model, item: &RefFc) -> Result<Value, ServiceError> {
+    let objref = common::parse_ref_fc(&item.reference, &item.fc)?;
+    read_ref(model, &objref)
+}
+
+/*48 读值  每一项单独出错     */
+pub fn get_data_values(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetDataValuesRequestPDU = common::decode_req(pay_load)?;
+    let value = req
+        .data
+        .iter()
+        .map(|item| read_one(model, item).unwrap_or_else(Value::Error))
+        .collect();
+    let resp = GetDataValuesResponsePDU { value };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*这个节点是不是在带 fc 的值里面  和 DataNode::get_value 的过滤一致     */
+fn selected(node: &DataNode, fc: Option<&str>) -> bool {
+    match fc {
+        Some(fc) => node.has_fc(fc),
+        None => true,
+    }
+}
+
+/*按 get_value 的形状把值写下去
+叶子先看 fc 和 valkind  再看类型  结构体 数组 个数必须对上
+SE 写的是 SG 叶子的编辑区  没选编辑组就没有编辑区  定值组那边也用     */
+pub fn write_node(node: &mut DataNode, fc: Option<&str>, val: Value) -> Result<(), ServiceError> {
+    if node.is_leaf() {
+        if READONLY_VALKIND.contains(&node.valkind.as_str()) {
+            return Err(ServiceError::AccessViolation);
+        }
+        if fc == Some(FC_SE) {
+            if node.fc != FC_SG {
+                return Err(ServiceError::AccessViolation);
+            }
+            let edit = node
+                .edit_value
+                .as_ref()
+                .ok_or(ServiceError::AccessNotAllowedInCurrentState)?;
+            node.edit_value = Some(val.conform_to(edit).ok_or(ServiceError::TypeConflict)?);
+            return Ok(());
+        }
+        if !WRITABLE_FC.contains(&node.fc.as_str()) {
+            return Err(ServiceError::AccessViolation);
+        }
+        node.value = val
+            .conform_to(&node.value)
+            .ok_or(ServiceError::TypeConflict)?;
+        return Ok(());
+    }
+    let vals = match (val, node.is_array()) {
+        (Value::Array(vals), true) | (Value::Struct(vals), false) => vals,
+        _ => return Err(ServiceError::TypeConflict),
+    };
+    let children: Vec<&mut DataNode> = node
+        .child_vec
+        .iter_mut()
+        .filter(|child| selected(child, fc))
+        .collect();
+    if children.len() != vals.len() {
+        return Err(ServiceError::TypeConflict);
+    }
+    for (child, val) in children.into_iter().zip(vals) {
+        write_node(child, fc, val)?;
+    }
+    Ok(())
+}
+
+/*写一项  先在副本上写 全部通过才替换  不会只写一半
+替换以后和原来的比  变化记到模型里面  SP CF 的还要落盘
+SE 的交给定值组  要这个连接占着编辑区     */
+pub fn write_ref(
+    model: &mut Model,
+    sg_engine: &mut SgEngine,
+    con_id: u32,
+    objref: &ObjRef,
+    val: Value,
+) -> Result<(), ServiceError> {
+    if objref.fc.as_deref() == Some(FC_SE) {
+        return sg_engine.set_edit_value(model, con_id, objref, val, Instant::now());
+    }
+    let node = model
+        .get_node_mut(objref)
+        .ok_or(ServiceError::InstanceNotAvailable)?;
+    let mut new_node = node.clone();
+    write_node(&mut new_node, objref.fc.as_deref(), val)?;
+    let old_node = std::mem::replace(node, new_node);
+    model.note_change(objref, &old_node);
+    if objref
+        .fc
+        .as_deref()
+        .is_none_or(|fc| PERSIST_FC.contains(&fc))
+    {
+        model.note_persist(objref);
+    }
+    Ok(())
+}
+
+fn write_one(
+    model: &mut Model,
+    sg_engine: &mut SgEngine,
+    con_id: u32,
+    item: SetDataValuesItem,
+) -> Result<(), ServiceError> {
+    let objref = common::parse_ref_fc(&item.reference, &item.fc)?;
+    write_ref(model, sg_engine, con_id, &objref, item.value)
+}
+
+/*49 写值  每一项一个结果     */
+pub fn set_data_values(
+    model: &mut Model,
+    sg_engine: &mut SgEngine,
+    con_id: u32,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: SetDataValuesRequestPDU = common::decode_req(pay_load)?;
+    let result = req
+        .data
+        .into_iter()
+        .map(|item| match write_one(model, sg_engine, con_id, item) {
+            Ok(()) => ServiceError::NoError,
+            Err(err) => err,
+        })
+        .collect();
+    let resp = SetDataValuesResponsePDU { result };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+// GetDataDirectory-RequestPDU ::= SEQUENCE {
+//     dataReference ObjectReference,
+//     referenceAfter SubReference OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetDataDirectoryRequestPDU {
+    pub datareference: String,
+    pub referenceafter: Option<String>,
+}
+
+// GetDataDirectory-ResponsePDU ::= SEQUENCE {
+//     dataAttribute SEQUENCE OF SEQUENCE {
+//         reference SubReference,
+//         fc FunctionalConstraint OPTIONAL
+//     },
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetDataDirectoryResponsePDU {
+    pub dataattribute: Vec<RefFc>,
+    pub morefollows: bool,
+}
+
+// GetDataDefinition-RequestPDU ::= SEQUENCE {
+//     data SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         fc FunctionalConstraint OPTIONAL
+//     },
+//     referenceAfter ObjectReference OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetDataDefinitionRequestPDU {
+    pub data: Vec<RefFc>,
+    pub referenceafter: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DefinitionItem {
+    pub cdctype: Option<String>,
+    pub definition: DataDefinition,
+}
+
+// GetDataDefinition-ResponsePDU ::= SEQUENCE {
+//     data SEQUENCE OF SEQUENCE {
+//         cdcType VisibleString OPTIONAL,
+//         definition DataDefinition
+//     },
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetDataDefinitionResponsePDU {
+    pub data: Vec<DefinitionItem>,
+    pub morefollows: bool,
+}
+
+// GetAllDataDefinition-RequestPDU ::= SEQUENCE {
+//     reference ReferenceChoice,
+//     fc FunctionalConstraint OPTIONAL,
+//     referenceAfter SubReference OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetAllDataDefinitionRequestPDU {
+    pub reference: ReferenceChoice,
+    pub fc: Option<String>,
+    pub referenceafter: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AllDefinitionItem {
+    pub reference: String,
+    pub cdctype: Option<String>,
+    pub definition: DataDefinition,
+}
+
+// GetAllDataDefinition-ResponsePDU ::= SEQUENCE {
+//     data SEQUENCE OF SEQUENCE {
+//         reference SubReference,
+//         cdcType VisibleString OPTIONAL,
+//         definition DataDefinition
+//     },
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetAllDataDefinitionResponsePDU {
+    pub data: Vec<AllDefinitionItem>,
+    pub morefollows: bool,
+}
+
+impl AperCodec for GetDataDirectoryRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.referenceafter.is_some()]);
+        common::put_obj_ref(enc, &self.datareference)?;
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut pdu = GetDataDirectoryRequestPDU {
+            datareference: common::get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for GetDataDirectoryResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        enc.put_sequence_of_len(self.dataattribute.len(), Size::Unconstrained)?;
+        for item in self.dataattribute.iter() {
+            item.aper_encode(enc)?;
+        }
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut dataattribute = Vec::new();
+        for _ in 0..count {
+            dataattribute.push(RefFc::aper_decode(dec)?);
+        }
+        Ok(GetDataDirectoryResponsePDU {
+            dataattribute,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+impl AperCodec for GetDataDefinitionRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.referenceafter.is_some()]);
+        enc.put_sequence_of_len(self.data.len(), Size::Unconstrained)?;
+        for item in self.data.iter() {
+            item.aper_encode(enc)?;
+        }
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut pdu = GetDataDefinitionRequestPDU::default();
+        for _ in 0..count {
+            pdu.data.push(RefFc::aper_decode(dec)?);
+        }
+        if present[0] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for DefinitionItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.cdctype.is_some()]);
+        if let Some(ref cdctype) = self.cdctype {
+            enc.put_visible_string(cdctype, Size::Unconstrained)?;
+        }
+        self.definition.aper_encode(enc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let cdctype = if present[0] {
+            Some(dec.get_visible_string(Size::Unconstrained)?)
+        } else {
+            None
+        };
+        Ok(DefinitionItem {
+            cdctype,
+            definition: DataDefinition::aper_decode(dec)?,
+        })
+    }
+}
+
+impl AperCodec for GetDataDefinitionResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        enc.put_sequence_of_len(self.data.len(), Size::Unconstrained)?;
+        for item in self.data.iter() {
+            item.aper_encode(enc)?;
+        }
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.push(DefinitionItem::aper_decode(dec)?);
+        }
+        Ok(GetDataDefinitionResponsePDU {
+            data,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+impl AperCodec for GetAllDataDefinitionRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.fc.is_some(), self.referenceafter.is_some()]);
+        self.reference.aper_encode(enc)?;
+        if let Some(ref fc) = self.fc {
+            common::put_fc(enc, fc)?;
+        }
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 2)?;
+        let mut pdu = GetAllDataDefinitionRequestPDU {
+            reference: ReferenceChoice::aper_decode(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.fc = Some(common::get_fc(dec)?);
+        }
+        if present[1] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for AllDefinitionItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.cdctype.is_some()]);
+        common::put_obj_ref(enc, &self.reference)?;
+        if let Some(ref cdctype) = self.cdctype {
+            enc.put_visible_string(cdctype, Size::Unconstrained)?;
+        }
+        self.definition.aper_encode(enc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let reference = common::get_obj_ref(dec)?;
+        let cdctype = if present[0] {
+            Some(dec.get_visible_string(Size::Unconstrained)?)
+        } else {
+            None
+        };
+        Ok(AllDefinitionItem {
+            reference,
+            cdctype,
+            definition: DataDefinition::aper_decode(dec)?,
+        })
+    }
+}
+
+impl AperCodec for GetAllDataDefinitionResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        enc.put_sequence_of_len(self.data.len(), Size::Unconstrained)?;
+        for item in self.data.iter() {
+            item.aper_encode(enc)?;
+        }
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.push(AllDefinitionItem::aper_decode(dec)?);
+        }
+        Ok(GetAllDataDefinitionResponsePDU {
+            data,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+/*DO SDO 才有公共数据类     */
+fn cdc_of(node: &DataNode) -> Option<String> {
+    match node.kind {
+        NodeKind::Do | NodeKind::Sdo => Some(node.cdc.clone()),
+        NodeKind::Da | NodeKind::Bda => None,
+    }
+}
+
+/*50 下一级的名字  DA 带 fc  SDO BDA 不带     */
+pub fn get_data_directory(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetDataDirectoryRequestPDU = common::decode_req(pay_load)?;
+    let objref = common::parse_ref_fc(&req.datareference, &None)?;
+    let node = model
+        .get_node(&objref)
+        .ok_or(ServiceError::InstanceNotAvailable)?;
+    let items: Vec<RefFc> = node
+        .child_vec
+        .iter()
+        .map(|child| RefFc {
+            reference: child.name.clone(),
+            fc: match child.kind {
+                NodeKind::Da => Some(child.fc.clone()),
+                _ => None,
+            },
+        })
+        .collect();
+    let items = common::skip_after(items, &req.referenceafter, |item| &item.reference)?;
+    common::encode_fit(&items, max_len, |dataattribute, morefollows| {
+        GetDataDirectoryResponsePDU {
+            dataattribute,
+            morefollows,
+        }
+    })
+}
+
+fn definition_one(model: &Model, item: &RefFc) -> DefinitionItem {
+    let node = common::parse_ref_fc(&item.reference, &item.fc).and_then(|objref| {
+        let node = model
+            .get_node(&objref)
+            .ok_or(ServiceError::InstanceNotAvailable)?;
+        let definition = DataDefinition::from_node(node, objref.fc.as_deref())
+            .ok_or(ServiceError::InstanceNotAvailable)?;
+        Ok((cdc_of(node), definition))
+    });
+    match node {
+        Ok((cdctype, definition)) => DefinitionItem {
+            cdctype,
+            definition,
+        },
+        Err(err) => DefinitionItem {
+            cdctype: None,
+            definition: DataDefinition::Error(err),
+        },
+    }
+}
+
+/*51 按请求的顺序给定义  referenceAfter 是上一次最后一个引用     */
+pub fn get_data_definition(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetDataDefinitionRequestPDU = common::decode_req(pay_load)?;
+    let data = common::skip_after(req.data, &req.referenceafter, |item| &item.reference)?;
+    let items: Vec<DefinitionItem> = data
+        .iter()
+        .map(|item| definition_one(model, item))
+        .collect();
+    common::encode_fit(&items, max_len, |data, morefollows| {
+        GetDataDefinitionResponsePDU { data, morefollows }
+    })
+}
+
+/*155 LN 或者 LD 下面所有 DO 的定义  带了 fc 没有这个 fc 的 DO 不列     */
+pub fn get_all_data_definition(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetAllDataDefinitionRequestPDU = common::decode_req(pay_load)?;
+    let fc = req.fc.as_deref();
+    let mut items = Vec::new();
+    for (prefix, ln) in common::resolve_lns(model, &req.reference)? {
+        for node in ln.do_vec.iter() {
+            if let Some(definition) = DataDefinition::from_node(node, fc) {
+                items.push(AllDefinitionItem {
+                    reference: format!("{}{}", prefix, node.name),
+                    cdctype: cdc_of(node),
+                    definition,
+                });
+            }
+        }
+    }
+    let items = common::skip_after(items, &req.referenceafter, |item| &item.reference)?;
+    common::encode_fit(&items, max_len, |data, morefollows| {
+        GetAllDataDefinitionResponsePDU { data, morefollows }
+    })
+}
+
+// GetAllDataValues-RequestPDU ::= SEQUENCE {
+//     reference ReferenceChoice,
+//     fc FunctionalConstraint OPTIONAL,
+//     referenceAfter SubReference OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetAllDataValuesRequestPDU {
+    pub reference: ReferenceChoice,
+    pub fc: Option<String>,
+    pub referenceafter: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AllValueItem {
+    pub reference: String,
+    pub value: Value,
+}
+
+// GetAllDataValues-ResponsePDU ::= SEQUENCE {
+//     data SEQUENCE OF SEQUENCE {
+//         reference SubReference,
+//         value Data
+//     },
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetAllDataValuesResponsePDU {
+    pub data: Vec<AllValueItem>,
+    pub morefollows: bool,
+}
+
+// GetAllCBValues-RequestPDU ::= SEQUENCE {
+//     reference ReferenceChoice,
+//     acsiClass ACSIClass,
+//     referenceAfter SubReference OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetAllCBValuesRequestPDU {
+    pub reference: ReferenceChoice,
+    pub acsiclass: u8,
+    pub referenceafter: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CbValueItem {
+    pub reference: String,
+    pub value: CbValue,
+}
+
+// GetAllCBValues-ResponsePDU ::= SEQUENCE {
+//     cbValue SEQUENCE OF SEQUENCE {
+//         reference SubReference,
+//         value CHOICE { brcb BRCB, urcb URCB, lcb LCB, sgcb SGCB, gocb GoCB, msvcb MSVCB }
+//     },
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetAllCBValuesResponsePDU {
+    pub cbvalue: Vec<CbValueItem>,
+    pub morefollows: bool,
+}
+
+impl AperCodec for GetAllDataValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.fc.is_some(), self.referenceafter.is_some()]);
+        self.reference.aper_encode(enc)?;
+        if let Some(ref fc) = self.fc {
+            common::put_fc(enc, fc)?;
+        }
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 2)?;
+        let mut pdu = GetAllDataValuesRequestPDU {
+            reference: ReferenceChoice::aper_decode(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.fc = Some(common::get_fc(dec)?);
+        }
+        if present[1] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for AllValueItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.reference)?;
+        self.value.aper_encode(enc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(AllValueItem {
+            reference: common::get_obj_ref(dec)?,
+            value: Value::aper_decode(dec)?,
+        })
+    }
+}
+
+impl AperCodec for GetAllDataValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        enc.put_sequence_of_len(self.data.len(), Size::Unconstrained)?;
+        for item in self.data.iter() {
+            item.aper_encode(enc)?;
+        }
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.push(AllValueItem::aper_decode(dec)?);
+        }
+        Ok(GetAllDataValuesResponsePDU {
+            data,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+impl AperCodec for GetAllCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.referenceafter.is_some()]);
+        self.reference.aper_encode(enc)?;
+        enc.put_constrained_int(self.acsiclass as i128, 0, 255)?;
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut pdu = GetAllCBValuesRequestPDU {
+            reference: ReferenceChoice::aper_decode(dec)?,
+            acsiclass: dec.get_constrained_int(0, 255)? as u8,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for CbValueItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.reference)?;
+        self.value.aper_encode(enc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(CbValueItem {
+            reference: common::get_obj_ref(dec)?,
+            value: CbValue::aper_decode(dec)?,
+        })
+    }
+}
+
+impl AperCodec for GetAllCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        enc.put_sequence_of_len(self.cbvalue.len(), Size::Unconstrained)?;
+        for item in self.cbvalue.iter() {
+            item.aper_encode(enc)?;
+        }
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut cbvalue = Vec::new();
+        for _ in 0..count {
+            cbvalue.push(CbValueItem::aper_decode(dec)?);
+        }
+        Ok(GetAllCBValuesResponsePDU {
+            cbvalue,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+/*83 逻辑节点下面所有 DO 的值  带了 fc 只要这个 fc 的  一个都没有的 DO 不列     */
+pub fn get_all_data_values(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetAllDataValuesRequestPDU = common::decode_req(pay_load)?;
+    let fc = req.fc.as_deref();
+    let mut items = Vec::new();
+    for (prefix, ln) in common::resolve_lns(model, &req.reference)? {
+        for node in ln.do_vec.iter() {
+            if let Some(value) = node.get_value(fc) {
+                items.push(AllValueItem {
+                    reference: format!("{}{}", prefix, node.name),
+                    value,
+                });
+            }
+        }
+    }
+    let items = common::skip_after(items, &req.referenceafter, |item| &item.reference)?;
+    common::encode_fit(&items, max_len, |data, morefollows| {
+        GetAllDataValuesResponsePDU { data, morefollows }
+    })
+}
+
+/*一个逻辑节点下面 某一类控制块的当前值     */
+fn ln_cb_values(ln: &LogicalNode, acsiclass: u8) -> Result<Vec<(String, CbValue)>, ServiceError> {
+    let cbs = match acsiclass {
+        directory::ACSI_CLASS_BRCB => ln
+            .brcb_vec
+            .iter()
+            .map(|cb| (cb.name.clone(), CbValue::Brcb(cb.clone())))
+            .collect(),
+        directory::ACSI_CLASS_URCB => ln
+            .urcb_vec
+            .iter()
+            .map(|cb| (cb.name.clone(), CbValue::Urcb(cb.clone())))
+            .collect(),
+        directory::ACSI_CLASS_LCB => ln
+            .lcb_vec
+            .iter()
+            .map(|cb| (cb.name.clone(), CbValue::Lcb(cb.clone())))
+            .collect(),
+        directory::ACSI_CLASS_SGCB => ln
+            .sgcb
+            .iter()
+            .map(|cb| (SGCB_NAME.to_string(), CbValue::Sgcb(cb.clone())))
+            .collect(),
+        directory::ACSI_CLASS_GOCB => ln
+            .gocb_vec
+            .iter()
+            .map(|cb| (cb.name.clone(), CbValue::GoCb(cb.clone())))
+            .collect(),
+        directory::ACSI_CLASS_MSVCB => ln
+            .msvcb_vec
+            .iter()
+            .map(|cb| (cb.name.clone(), CbValue::MsvCb(cb.clone())))
+            .collect(),
+        _ => return Err(ServiceError::ClassNotSupported),
+    };
+    Ok(cbs)
+}
+
+/*156 控制块的值  取的是运行时的状态  不是 scl 里面的初始配置     */
+pub fn get_all_cb_values(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetAllCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let mut items = Vec::new();
+    for (prefix, ln) in common::resolve_lns(model, &req.reference)? {
+        for (name, value) in ln_cb_values(ln, req.acsiclass)? {
+            items.push(CbValueItem {
+                reference: format!("{}{}", prefix, name),
+                value,
+            });
+        }
+    }
+    let items = common::skip_after(items, &req.referenceafter, |item| &item.reference)?;
+    common::encode_fit(&items, max_len, |cbvalue, morefollows| {
+        GetAllCBValuesResponsePDU {
+            cbvalue,
+            morefollows,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cms61850::service::sg::{
+        self as sg_service, ConfirmEditSGValuesRequestPDU, SelectSGRequestPDU,
+    };
+
+    const SGCB: &str = "E1Q1SB1C1/LLN0.SGCB";
+    const SETVAL: &str = "E1Q1SB1C1/MHAN1.NumCyc.setVal";
+
+    /*样例里面的定值都是 SP  拿一个改成 SG  两组各给一个值     */
+    async fn setup() -> (Model, SgEngine) {
+        let info = crate::cfg::scl2::scl_parse("sisco_sample.cid", "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let mut model = Model::from_scl(&info).unwrap();
+        let objref = ObjRef::parse(SETVAL).unwrap();
+        let leaf = model.get_node_mut(&objref).unwrap();
+        leaf.fc = FC_SG.to_string();
+        leaf.set_sg_value(1, Value::Int32(10));
+        leaf.set_sg_value(2, Value::Int32(20));
+        let mut sg_engine = SgEngine::new(300);
+        sg_engine.start(&mut model);
+        (model, sg_engine)
+    }
+
+    fn set(
+        model: &mut Model,
+        sg_engine: &mut SgEngine,
+        con_id: u32,
+        val: Value,
+    ) -> Vec<ServiceError> {
+        let req = SetDataValuesRequestPDU {
+            data: vec![SetDataValuesItem {
+                reference: SETVAL.to_string(),
+                fc: Some(FC_SE.to_string()),
+                value: val,
+            }],
+        };
+        let pay_load = aper::aper_to_bytes(&req).unwrap();
+        let resp = set_data_values(model, sg_engine, con_id, &pay_load, 65535).unwrap();
+        let resp: SetDataValuesResponsePDU = aper::aper_from_bytes(&resp).unwrap();
+        resp.result
+    }
+
+    fn get(model: &Model, fc: &str) -> Value {
+        let req = GetDataValuesRequestPDU {
+            data: vec![RefFc {
+                reference: SETVAL.to_string(),
+                fc: Some(fc.to_string()),
+            }],
+        };
+        let pay_load = aper::aper_to_bytes(&req).unwrap();
+        let resp = get_data_values(model, &pay_load, 65535).unwrap();
+        let resp: GetDataValuesResponsePDU = aper::aper_from_bytes(&resp).unwrap();
+        resp.value[0].clone()
+    }
+
+    fn select_edit(model: &mut Model, sg_engine: &mut SgEngine, con_id: u32, sgnum: u8) {
+        let req = SelectSGRequestPDU {
+            sgcbreference: SGCB.to_string(),
+            settinggroupnumber: sgnum,
+        };
+        let pay_load = aper::aper_to_bytes(&req).unwrap();
+        sg_service::select_edit_sg(model, sg_engine, con_id, &pay_load).unwrap();
+    }
+
+    fn confirm(model: &mut Model, sg_engine: &mut SgEngine, con_id: u32) {
+        let req = ConfirmEditSGValuesRequestPDU {
+            sgcbreference: SGCB.to_string(),
+        };
+        let pay_load = aper::aper_to_bytes(&req).unwrap();
+        sg_service::confirm_edit_sg_values(model, sg_engine, con_id, &pay_load).unwrap();
+    }
+
+    #[tokio::test]
+    async fn se_write_goes_through_edit_buffer() {
+        let (mut model, mut sg_engine) = setup().await;
+        assert_eq!(get(&model, FC_SG), Value::Int32(20));
+
+        /*没选编辑组  没有编辑区     */
+        assert_eq!(
+            set(&mut model, &mut sg_engine, 1, Value::Int32(25)),
+            vec![ServiceError::AccessNotAllowedInCurrentState]
+        );
+
+        select_edit(&mut model, &mut sg_engine, 1, 2);
+        assert_eq!(get(&model, FC_SE), Value::Int32(20));
+        /*别的连接占着编辑区不能写     */
+        assert_eq!(
+            set(&mut model, &mut sg_engine, 2, Value::Int32(30)),
+            vec![ServiceError::InstanceLockedByOtherClient]
+        );
+        assert_eq!(
+            set(&mut model, &mut sg_engine, 1, Value::Int32(25)),
+            vec![ServiceError::NoError]
+        );
+        /*确认以前激活组的值不变     */
+        assert_eq!(get(&model, FC_SE), Value::Int32(25));
+        assert_eq!(get(&model, FC_SG), Value::Int32(20));
+
+        confirm(&mut model, &mut sg_engine, 1);
+        assert_eq!(get(&model, FC_SG), Value::Int32(25));
+        assert_eq!(
+            model.find(SETVAL).unwrap().sg_value_vec,
+            vec![(1, Value::Int32(10)), (2, Value::Int32(25))]
+        );
+    }
+}
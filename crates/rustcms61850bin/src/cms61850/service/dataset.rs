@@ -0,0 +1,562 @@
+use super::common::{self, ServiceError};
+use super::data;
+use crate::cms61850::codec::aper::{self, AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::model::dataset::split_dataset_ref;
+use crate::cms61850::model::{DataSet, Model, ObjRef, Value};
+use crate::cms61850::sg::SgEngine;
+use bytes::Bytes;
+/***************************************/
+/*数据集服务  54 55 57 58 59
+@name 是非持久的  只在这个关联里面有  连接断了就没了     */
+/***************************************/
+
+/*非持久数据集的引用前缀     */
+pub const NPS_PREFIX: char = '@';
+
+// CreateDataSet-RequestPDU ::= SEQUENCE {
+//     datasetReference ObjectReference,
+//     referenceAfter ObjectReference OPTIONAL,
+//     memberData SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         fc FunctionalConstraint
+//     }
+// }
+/*CreateDataSet-ResponsePDU ::= NULL     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct CreateDataSetRequestPDU {
+    pub datasetreference: String,
+    pub referenceafter: Option<String>,
+    pub memberdata: Vec<MemberData>,
+}
+
+// DeleteDataSet-RequestPDU ::= SEQUENCE {
+//     datasetReference ObjectReference
+// }
+/*DeleteDataSet-ResponsePDU ::= NULL     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct DeleteDataSetRequestPDU {
+    pub datasetreference: String,
+}
+
+// GetDataSetDirectory-RequestPDU ::= SEQUENCE {
+//     datasetReference ObjectReference,
+//     referenceAfter ObjectReference OPTIONAL
+// }
+/*GetDataSetValues-RequestPDU 也是这个样子     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct DataSetRequestPDU {
+    pub datasetreference: String,
+    pub referenceafter: Option<String>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct MemberData {
+    pub reference: String,
+    pub fc: String,
+}
+
+// GetDataSetDirectory-ResponsePDU ::= SEQUENCE {
+//     memberData SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         fc FunctionalConstraint
+//     },
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetDataSetDirectoryResponsePDU {
+    pub memberdata: Vec<MemberData>,
+    pub morefollows: bool,
+}
+
+// GetDataSetValues-ResponsePDU ::= SEQUENCE {
+//     value SEQUENCE OF Data,
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetDataSetValuesResponsePDU {
+    pub value: Vec<Value>,
+    pub morefollows: bool,
+}
+
+// SetDataSetValues-RequestPDU ::= SEQUENCE {
+//     datasetReference ObjectReference,
+//     referenceAfter ObjectReference OPTIONAL,
+//     memberValue SEQUENCE OF Data
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetDataSetValuesRequestPDU {
+    pub datasetreference: String,
+    pub referenceafter: Option<String>,
+    pub membervalue: Vec<Value>,
+}
+
+// SetDataSetValues-ResponsePDU ::= SEQUENCE {
+//     result SEQUENCE OF ServiceError
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetDataSetValuesResponsePDU {
+    pub result: Vec<ServiceError>,
+}
+
+impl AperCodec for CreateDataSetRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.referenceafter.is_some()]);
+        common::put_obj_ref(enc, &self.datasetreference)?;
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        enc.put_sequence_of_len(self.memberdata.len(), Size::Unconstrained)?;
+        for member in self.memberdata.iter() {
+            member.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut pdu = CreateDataSetRequestPDU {
+            datasetreference: common::get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        for _ in 0..count {
+            pdu.memberdata.push(MemberData::aper_decode(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for DeleteDataSetRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.datasetreference)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(DeleteDataSetRequestPDU {
+            datasetreference: common::get_obj_ref(dec)?,
+        })
+    }
+}
+
+impl AperCodec for DataSetRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.referenceafter.is_some()]);
+        common::put_obj_ref(enc, &self.datasetreference)?;
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut pdu = DataSetRequestPDU {
+            datasetreference: common::get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for MemberData {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.reference)?;
+        common::put_fc(enc, &self.fc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(MemberData {
+            reference: common::get_obj_ref(dec)?,
+            fc: common::get_fc(dec)?,
+        })
+    }
+}
+
+impl AperCodec for GetDataSetDirectoryResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        enc.put_sequence_of_len(self.memberdata.len(), Size::Unconstrained)?;
+        for member in self.memberdata.iter() {
+            member.aper_encode(enc)?;
+        }
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut memberdata = Vec::new();
+        for _ in 0..count {
+            memberdata.push(MemberData::aper_decode(dec)?);
+        }
+        Ok(GetDataSetDirectoryResponsePDU {
+            memberdata,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+impl AperCodec for GetDataSetValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        enc.put_sequence_of_len(self.value.len(), Size::Unconstrained)?;
+        for val in self.value.iter() {
+            val.aper_encode(enc)?;
+        }
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut value = Vec::new();
+        for _ in 0..count {
+            value.push(Value::aper_decode(dec)?);
+        }
+        Ok(GetDataSetValuesResponsePDU {
+            value,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+impl AperCodec for SetDataSetValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.referenceafter.is_some()]);
+        common::put_obj_ref(enc, &self.datasetreference)?;
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        enc.put_sequence_of_len(self.membervalue.len(), Size::Unconstrained)?;
+        for val in self.membervalue.iter() {
+            val.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut pdu = SetDataSetValuesRequestPDU {
+            datasetreference: common::get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        for _ in 0..count {
+            pdu.membervalue.push(Value::aper_decode(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for SetDataSetValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.result.len(), Size::Unconstrained)?;
+        for result in self.result.iter() {
+            result.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut result = Vec::new();
+        for _ in 0..count {
+            result.push(ServiceError::aper_decode(dec)?);
+        }
+        Ok(SetDataSetValuesResponsePDU { result })
+    }
+}
+
+/*找数据集  引用格式不对是参数不对  找不到是实例不存在     */
+pub fn find_dataset<'a>(
+    model: &'a Model,
+    nps_vec: &'a [DataSet],
+    reference: &str,
+) -> Result<&'a DataSet, ServiceError> {
+    if let Some(name) = reference.strip_prefix(NPS_PREFIX) {
+        return nps_vec
+            .iter()
+            .find(|ds| ds.name == name)
+            .ok_or(ServiceError::InstanceNotAvailable);
+    }
+    if split_dataset_ref(reference).is_none() {
+        return Err(ServiceError::ParameterValueInappropriate);
+    }
+    model
+        .get_dataset(reference)
+        .ok_or(ServiceError::InstanceNotAvailable)
+}
+
+/*referenceAfter 后面的成员的下标     */
+fn members_after(dataset: &DataSet, after: &Option<String>) -> Result<usize, ServiceError> {
+    let refs = dataset.member_refs();
+    let rest = common::skip_after(refs.iter().collect(), after, |r| r.as_str())?;
+    Ok(refs.len() - rest.len())
+}
+
+/*57 数据集的成员     */
+pub fn get_dataset_directory(
+    model: &Model,
+    nps_vec: &[DataSet],
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: DataSetRequestPDU = common::decode_req(pay_load)?;
+    let dataset = find_dataset(model, nps_vec, &req.datasetreference)?;
+    let start = members_after(dataset, &req.referenceafter)?;
+    let members: Vec<MemberData> = dataset.member_vec[start..]
+        .iter()
+        .map(|member| MemberData {
+            reference: member.data_ref(),
+            fc: member.fc.clone().unwrap_or_default(),
+        })
+        .collect();
+    common::encode_fit(&members, max_len, |memberdata, morefollows| {
+        GetDataSetDirectoryResponsePDU {
+            memberdata,
+            morefollows,
+        }
+    })
+}
+
+/*58 数据集的值  按成员顺序  读不到的成员放错误     */
+pub fn get_dataset_values(
+    model: &Model,
+    nps_vec: &[DataSet],
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: DataSetRequestPDU = common::decode_req(pay_load)?;
+    let dataset = find_dataset(model, nps_vec, &req.datasetreference)?;
+    let start = members_after(dataset, &req.referenceafter)?;
+    let values: Vec<Value> = dataset.member_vec[start..]
+        .iter()
+        .map(|member| data::read_ref(model, member).unwrap_or_else(Value::Error))
+        .collect();
+    common::encode_fit(&values, max_len, |value, morefollows| {
+        GetDataSetValuesResponsePDU { value, morefollows }
+    })
+}
+
+/*59 写数据集  值从 referenceAfter 后面的成员开始对  每个成员一个结果
+值比剩下的成员多 整个请求不对     */
+pub fn set_dataset_values(
+    model: &mut Model,
+    sg_engine: &mut SgEngine,
+    con_id: u32,
+    nps_vec: &[DataSet],
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: SetDataSetValuesRequestPDU = common::decode_req(pay_load)?;
+    let dataset = find_dataset(model, nps_vec, &req.datasetreference)?;
+    let start = members_after(dataset, &req.referenceafter)?;
+    let members = dataset.member_vec[start..].to_vec();
+    if req.membervalue.len() > members.len() {
+        return Err(ServiceError::ParameterValueInconsistent);
+    }
+    let result = members
+        .iter()
+        .zip(req.membervalue)
+        .map(
+            |(member, val)| match data::write_ref(model, sg_engine, con_id, member, val) {
+                Ok(()) => ServiceError::NoError,
+                Err(err) => err,
+            },
+        )
+        .collect();
+    let resp = SetDataSetValuesResponsePDU { result };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*成员必须是模型里面有的 带 fc 的数据     */
+fn resolve_members(model: &Model, memberdata: &[MemberData]) -> Result<Vec<ObjRef>, ServiceError> {
+    let mut member_vec = Vec::with_capacity(memberdata.len());
+    for member in memberdata.iter() {
+        let objref = common::parse_ref_fc(&member.reference, &Some(member.fc.clone()))?;
+        if objref.path.is_empty() {
+            return Err(ServiceError::ParameterValueInappropriate);
+        }
+        if model.get_node(&objref).is_none() {
+            return Err(ServiceError::InstanceNotAvailable);
+        }
+        member_vec.push(objref);
+    }
+    Ok(member_vec)
+}
+
+/*新建或者续建的数据集  超过 DynDataSet 的限制不行     */
+fn check_dyn_limit(
+    model: &Model,
+    nps_vec: &[DataSet],
+    new_dataset: bool,
+    member_count: usize,
+) -> Result<(), ServiceError> {
+    let limit = &model.dyndataset;
+    if !limit.enabled {
+        return Err(ServiceError::ClassNotSupported);
+    }
+    if limit.maxattributes > 0 && member_count > limit.maxattributes as usize {
+        return Err(ServiceError::FailedDueToServerConstraint);
+    }
+    if new_dataset && model.dyn_dataset_count() + nps_vec.len() >= limit.max as usize {
+        return Err(ServiceError::FailedDueToServerConstraint);
+    }
+    Ok(())
+}
+
+/*成员太多一个请求放不下的时候 带 referenceAfter 接着往后加
+referenceAfter 必须是现在的最后一个成员     */
+fn append_members(
+    dataset: &mut DataSet,
+    after: &str,
+    member_vec: Vec<ObjRef>,
+) -> Result<(), ServiceError> {
+    if !dataset.deletable {
+        return Err(ServiceError::AccessViolation);
+    }
+    match dataset.member_vec.last() {
+        Some(last) if last.data_ref() == after => {
+            dataset.member_vec.extend(member_vec);
+            Ok(())
+        }
+        _ => Err(ServiceError::ParameterValueInconsistent),
+    }
+}
+
+/*54 在线建数据集  LD/LN.name 是持久的  @name 是这个关联的     */
+pub fn create_dataset(
+    model: &mut Model,
+    nps_vec: &mut Vec<DataSet>,
+    pay_load: &[u8],
+) -> Result<Bytes, ServiceError> {
+    let req: CreateDataSetRequestPDU = common::decode_req(pay_load)?;
+    let member_vec = resolve_members(model, &req.memberdata)?;
+    if member_vec.is_empty() {
+        return Err(ServiceError::ParameterValueInappropriate);
+    }
+    let reference = req.datasetreference.as_str();
+    let existing = find_dataset(model, nps_vec, reference);
+    match (req.referenceafter, existing) {
+        (Some(after), Ok(dataset)) => {
+            check_dyn_limit(
+                model,
+                nps_vec,
+                false,
+                dataset.member_vec.len() + member_vec.len(),
+            )?;
+            if model.dataset_in_use(reference) {
+                return Err(ServiceError::InstanceInUse);
+            }
+            let dataset = match reference.strip_prefix(NPS_PREFIX) {
+                Some(name) => nps_vec.iter_mut().find(|ds| ds.name == name),
+                None => split_dataset_ref(reference).and_then(|(objref, name)| {
+                    model
+                        .get_ln_mut(&objref)?
+                        .dataset_vec
+                        .iter_mut()
+                        .find(|ds| ds.name == name)
+                }),
+            }
+            .ok_or(ServiceError::InstanceNotAvailable)?;
+            append_members(dataset, &after, member_vec)?;
+        }
+        (Some(_), Err(err)) => return Err(err),
+        (None, Ok(_)) => return Err(ServiceError::InstanceInUse),
+        (None, Err(ServiceError::InstanceNotAvailable)) => {
+            check_dyn_limit(model, nps_vec, true, member_vec.len())?;
+            let mut dataset = DataSet {
+                member_vec,
+                deletable: true,
+                ..Default::default()
+            };
+            match reference.strip_prefix(NPS_PREFIX) {
+                Some(name) => {
+                    if name.is_empty() || name.contains(['.', '/']) {
+                        return Err(ServiceError::ParameterValueInappropriate);
+                    }
+                    dataset.name = name.to_string();
+                    nps_vec.push(dataset);
+                }
+                None => {
+                    let (objref, name) = split_dataset_ref(reference)
+                        .ok_or(ServiceError::ParameterValueInappropriate)?;
+                    dataset.name = name.to_string();
+                    model
+                        .get_ln_mut(&objref)
+                        .ok_or(ServiceError::InstanceNotAvailable)?
+                        .dataset_vec
+                        .push(dataset);
+                }
+            }
+        }
+        (None, Err(err)) => return Err(err),
+    }
+    Ok(common::null_resp())
+}
+
+/*55 删数据集  scl 里面配的不能删  有控制块在用的也不能删     */
+pub fn delete_dataset(
+    model: &mut Model,
+    nps_vec: &mut Vec<DataSet>,
+    pay_load: &[u8],
+) -> Result<Bytes, ServiceError> {
+    let req: DeleteDataSetRequestPDU = common::decode_req(pay_load)?;
+    let reference = req.datasetreference.as_str();
+    if !find_dataset(model, nps_vec, reference)?.deletable {
+        return Err(ServiceError::AccessViolation);
+    }
+    if model.dataset_in_use(reference) {
+        return Err(ServiceError::InstanceInUse);
+    }
+    match reference.strip_prefix(NPS_PREFIX) {
+        Some(name) => nps_vec.retain(|ds| ds.name != name),
+        None => {
+            if let Some((objref, name)) = split_dataset_ref(reference) {
+                if let Some(ln) = model.get_ln_mut(&objref) {
+                    ln.dataset_vec.retain(|ds| ds.name != name);
+                }
+            }
+        }
+    }
+    Ok(common::null_resp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn create_delete_null_response() {
+        let info = crate::cfg::scl2::scl_parse("sisco_sample.cid", "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let mut model = Model::from_scl(&info).unwrap();
+        let mut nps_vec = Vec::new();
+        let req = CreateDataSetRequestPDU {
+            datasetreference: "@Pos".to_string(),
+            referenceafter: None,
+            memberdata: vec![MemberData {
+                reference: "E1Q1SB1C1/CSWI1.Pos".to_string(),
+                fc: "ST".to_string(),
+            }],
+        };
+        let pay_load = aper::aper_to_bytes(&req).unwrap();
+        let resp = create_dataset(&mut model, &mut nps_vec, &pay_load).unwrap();
+        assert_eq!(resp.as_ref(), &[0u8][..]);
+        assert_eq!(nps_vec.len(), 1);
+
+        let req = DeleteDataSetRequestPDU {
+            datasetreference: "@Pos".to_string(),
+        };
+        let pay_load = aper::aper_to_bytes(&req).unwrap();
+        let resp = delete_dataset(&mut model, &mut nps_vec, &pay_load).unwrap();
+        assert_eq!(resp.as_ref(), &[0u8][..]);
+        assert!(nps_vec.is_empty());
+    }
+}
@@ -0,0 +1,266 @@
+use super::common::{self, ReferenceChoice, ServiceError};
+use crate::cms61850::codec::aper::{AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::model::cb::SGCB_NAME;
+use crate::cms61850::model::{LogicalNode, Model};
+use bytes::Bytes;
+/***************************************/
+/*目录服务  80 81 82     */
+/***************************************/
+
+/*GetServerDirectory 的 objectClass     */
+pub const OBJECT_CLASS_LOGICAL_DEVICE: u8 = 1;
+pub const OBJECT_CLASS_FILE_SYSTEM: u8 = 2;
+
+/*ACSIClass     */
+pub const ACSI_CLASS_DATA_OBJECT: u8 = 1;
+pub const ACSI_CLASS_DATA_SET: u8 = 2;
+pub const ACSI_CLASS_BRCB: u8 = 3;
+pub const ACSI_CLASS_URCB: u8 = 4;
+pub const ACSI_CLASS_LCB: u8 = 5;
+pub const ACSI_CLASS_LOG: u8 = 6;
+pub const ACSI_CLASS_SGCB: u8 = 7;
+pub const ACSI_CLASS_GOCB: u8 = 8;
+pub const ACSI_CLASS_GSCB: u8 = 9;
+pub const ACSI_CLASS_MSVCB: u8 = 10;
+pub const ACSI_CLASS_USVCB: u8 = 11;
+
+// GetServerDirectory-RequestPDU ::= SEQUENCE {
+//     objectClass INTEGER (0..255),
+//     referenceAfter ObjectReference OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetServerDirectoryRequestPDU {
+    pub objectclass: u8,
+    pub referenceafter: Option<String>,
+}
+
+// GetLogicalDeviceDirectory-RequestPDU ::= SEQUENCE {
+//     ldName ObjectName OPTIONAL,
+//     referenceAfter SubReference OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetLogicalDeviceDirectoryRequestPDU {
+    pub ldname: Option<String>,
+    pub referenceafter: Option<String>,
+}
+
+// GetLogicalNodeDirectory-RequestPDU ::= SEQUENCE {
+//     reference ReferenceChoice,
+//     acsiClass ACSIClass,
+//     referenceAfter SubReference OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetLogicalNodeDirectoryRequestPDU {
+    pub reference: ReferenceChoice,
+    pub acsiclass: u8,
+    pub referenceafter: Option<String>,
+}
+
+/*三个目录服务的响应都是一样的
+SEQUENCE { reference SEQUENCE OF SubReference, moreFollows BOOLEAN DEFAULT TRUE }     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct DirectoryResponsePDU {
+    pub reference: Vec<String>,
+    pub morefollows: bool,
+}
+
+impl AperCodec for GetServerDirectoryRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.referenceafter.is_some()]);
+        enc.put_constrained_int(self.objectclass as i128, 0, 255)?;
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut pdu = GetServerDirectoryRequestPDU {
+            objectclass: dec.get_constrained_int(0, 255)? as u8,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for GetLogicalDeviceDirectoryRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[self.ldname.is_some(), self.referenceafter.is_some()],
+        );
+        if let Some(ref ldname) = self.ldname {
+            common::put_obj_name(enc, ldname)?;
+        }
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 2)?;
+        let mut pdu = GetLogicalDeviceDirectoryRequestPDU::default();
+        if present[0] {
+            pdu.ldname = Some(common::get_obj_name(dec)?);
+        }
+        if present[1] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for GetLogicalNodeDirectoryRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.referenceafter.is_some()]);
+        self.reference.aper_encode(enc)?;
+        enc.put_constrained_int(self.acsiclass as i128, 0, 255)?;
+        if let Some(ref after) = self.referenceafter {
+            common::put_obj_ref(enc, after)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let mut pdu = GetLogicalNodeDirectoryRequestPDU {
+            reference: ReferenceChoice::aper_decode(dec)?,
+            acsiclass: dec.get_constrained_int(0, 255)? as u8,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.referenceafter = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for DirectoryResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        enc.put_sequence_of_len(self.reference.len(), Size::Unconstrained)?;
+        for reference in self.reference.iter() {
+            common::put_obj_ref(enc, reference)?;
+        }
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut reference = Vec::new();
+        for _ in 0..count {
+            reference.push(common::get_obj_ref(dec)?);
+        }
+        Ok(DirectoryResponsePDU {
+            reference,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+/*跳过 referenceAfter  放不下的下次再要     */
+fn directory_resp(
+    refs: Vec<String>,
+    after: &Option<String>,
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let refs = common::skip_after(refs, after, |r| r.as_str())?;
+    common::encode_fit(&refs, max_len, |reference, morefollows| {
+        DirectoryResponsePDU {
+            reference,
+            morefollows,
+        }
+    })
+}
+
+/*80 服务器下面的逻辑设备     */
+pub fn get_server_directory(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetServerDirectoryRequestPDU = common::decode_req(pay_load)?;
+    match req.objectclass {
+        OBJECT_CLASS_LOGICAL_DEVICE => {}
+        OBJECT_CLASS_FILE_SYSTEM => return Err(ServiceError::ClassNotSupported),
+        _ => return Err(ServiceError::ParameterValueInappropriate),
+    }
+    let refs = model.ld_vec.iter().map(|ld| ld.name.clone()).collect();
+    directory_resp(refs, &req.referenceafter, max_len)
+}
+
+/*81 逻辑设备下面的逻辑节点  没给 ldName 就是所有的 LD/LN     */
+pub fn get_logical_device_directory(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetLogicalDeviceDirectoryRequestPDU = common::decode_req(pay_load)?;
+    let refs = match req.ldname {
+        Some(ref ldname) => match model.get_ld(ldname) {
+            Some(ld) => ld.ln_vec.iter().map(|ln| ln.name.clone()).collect(),
+            None => return Err(ServiceError::InstanceNotAvailable),
+        },
+        None => model
+            .ld_vec
+            .iter()
+            .flat_map(|ld| {
+                ld.ln_vec
+                    .iter()
+                    .map(move |ln| format!("{}/{}", ld.name, ln.name))
+            })
+            .collect(),
+    };
+    directory_resp(refs, &req.referenceafter, max_len)
+}
+
+/*一个逻辑节点下面 某一类对象的名字     */
+fn ln_class_names(ln: &LogicalNode, acsiclass: u8) -> Result<Vec<String>, ServiceError> {
+    let names = match acsiclass {
+        ACSI_CLASS_DATA_OBJECT => ln.do_vec.iter().map(|d| d.name.clone()).collect(),
+        ACSI_CLASS_DATA_SET => ln.dataset_vec.iter().map(|d| d.name.clone()).collect(),
+        ACSI_CLASS_BRCB => ln.brcb_vec.iter().map(|r| r.name.clone()).collect(),
+        ACSI_CLASS_URCB => ln.urcb_vec.iter().map(|r| r.name.clone()).collect(),
+        ACSI_CLASS_LCB => ln.lcb_vec.iter().map(|l| l.name.clone()).collect(),
+        ACSI_CLASS_LOG => {
+            let mut names: Vec<String> = Vec::new();
+            for lcb in ln.lcb_vec.iter() {
+                let log_name = lcb.log_name();
+                if !log_name.is_empty() && !names.iter().any(|n| n == log_name) {
+                    names.push(log_name.to_string());
+                }
+            }
+            names
+        }
+        ACSI_CLASS_SGCB => match ln.sgcb {
+            Some(_) => vec![SGCB_NAME.to_string()],
+            None => Vec::new(),
+        },
+        ACSI_CLASS_GOCB => ln.gocb_vec.iter().map(|g| g.name.clone()).collect(),
+        ACSI_CLASS_GSCB => ln.gscb_vec.iter().map(|g| g.name.clone()).collect(),
+        ACSI_CLASS_MSVCB => ln.msvcb_vec.iter().map(|s| s.name.clone()).collect(),
+        ACSI_CLASS_USVCB => ln.usvcb_vec.iter().map(|s| s.name.clone()).collect(),
+        _ => return Err(ServiceError::ClassNotSupported),
+    };
+    Ok(names)
+}
+
+/*82 逻辑节点下面的对象
+给的是 LD 的话 所有 LN 的都列出来  名字前面带 LN.     */
+pub fn get_logical_node_directory(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetLogicalNodeDirectoryRequestPDU = common::decode_req(pay_load)?;
+    let mut refs = Vec::new();
+    for (prefix, ln) in common::resolve_lns(model, &req.reference)? {
+        for name in ln_class_names(ln, req.acsiclass)? {
+            refs.push(format!("{}{}", prefix, name));
+        }
+    }
+    directory_resp(refs, &req.referenceafter, max_len)
+}
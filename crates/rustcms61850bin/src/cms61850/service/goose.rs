@@ -0,0 +1,283 @@
+use super::common::{self, ServiceError};
+use super::dataset::find_dataset;
+use crate::cms61850::codec::aper::{self, AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::model::cb::GoCb;
+use crate::cms61850::model::dataset::split_dataset_ref;
+use crate::cms61850::model::Model;
+use bytes::Bytes;
+/***************************************/
+/*GOOSE 控制块服务  102 读  103 写
+能写的只有 GoEna GoID DatSet  使能的时候 GoID DatSet 不能改
+DatSet 要是本 LD 的持久数据集  换了 ConfRev 加一  为空的话 NdsCom 置位 不能使能     */
+/***************************************/
+
+// GetGoCBValues-RequestPDU ::= SEQUENCE {
+//     gocbReference SEQUENCE OF ObjectReference
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetGoCBValuesRequestPDU {
+    pub gocbreference: Vec<String>,
+}
+
+// GetGoCBValues-ResponsePDU ::= SEQUENCE {
+//     gocb SEQUENCE OF CHOICE {
+//         error ServiceError,
+//         value GoCB
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetGoCBValuesResponsePDU {
+    pub gocb: Vec<Result<GoCb, ServiceError>>,
+}
+
+// SetGoCBValues-RequestPDU ::= SEQUENCE {
+//     gocb SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         goEna BOOLEAN OPTIONAL,
+//         goID VisibleString129 OPTIONAL,
+//         datSet ObjectReference OPTIONAL
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetGoCBValuesRequestPDU {
+    pub gocb: Vec<SetGoCbItem>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetGoCbItem {
+    pub reference: String,
+    pub goena: Option<bool>,
+    pub goid: Option<String>,
+    pub datset: Option<String>,
+}
+
+// SetGoCBValues-ResponsePDU ::= SEQUENCE {
+//     result SEQUENCE OF ServiceError
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetGoCBValuesResponsePDU {
+    pub result: Vec<ServiceError>,
+}
+
+impl AperCodec for GetGoCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.gocbreference.len(), Size::Unconstrained)?;
+        for reference in self.gocbreference.iter() {
+            common::put_obj_ref(enc, reference)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut gocbreference = Vec::new();
+        for _ in 0..count {
+            gocbreference.push(common::get_obj_ref(dec)?);
+        }
+        Ok(GetGoCBValuesRequestPDU { gocbreference })
+    }
+}
+
+impl AperCodec for GetGoCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.gocb.len(), Size::Unconstrained)?;
+        for item in self.gocb.iter() {
+            match item {
+                Err(err) => {
+                    enc.put_choice_index(0, 2, false)?;
+                    err.aper_encode(enc)?;
+                }
+                Ok(gocb) => {
+                    enc.put_choice_index(1, 2, false)?;
+                    gocb.aper_encode(enc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut gocb = Vec::new();
+        for _ in 0..count {
+            match dec.get_choice_index(2, false)? {
+                0 => gocb.push(Err(ServiceError::aper_decode(dec)?)),
+                _ => gocb.push(Ok(GoCb::aper_decode(dec)?)),
+            }
+        }
+        Ok(GetGoCBValuesResponsePDU { gocb })
+    }
+}
+
+impl AperCodec for SetGoCbItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[
+                self.goena.is_some(),
+                self.goid.is_some(),
+                self.datset.is_some(),
+            ],
+        );
+        common::put_obj_ref(enc, &self.reference)?;
+        if let Some(goena) = self.goena {
+            enc.put_bool(goena);
+        }
+        if let Some(ref goid) = self.goid {
+            enc.put_visible_string(goid, Size::Range(0, 129))?;
+        }
+        if let Some(ref datset) = self.datset {
+            common::put_obj_ref(enc, datset)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 3)?;
+        let mut item = SetGoCbItem {
+            reference: common::get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            item.goena = Some(dec.get_bool()?);
+        }
+        if present[1] {
+            item.goid = Some(dec.get_visible_string(Size::Range(0, 129))?);
+        }
+        if present[2] {
+            item.datset = Some(common::get_obj_ref(dec)?);
+        }
+        Ok(item)
+    }
+}
+
+impl AperCodec for SetGoCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.gocb.len(), Size::Unconstrained)?;
+        for item in self.gocb.iter() {
+            item.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut gocb = Vec::new();
+        for _ in 0..count {
+            gocb.push(SetGoCbItem::aper_decode(dec)?);
+        }
+        Ok(SetGoCBValuesRequestPDU { gocb })
+    }
+}
+
+impl AperCodec for SetGoCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.result.len(), Size::Unconstrained)?;
+        for err in self.result.iter() {
+            err.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut result = Vec::new();
+        for _ in 0..count {
+            result.push(ServiceError::aper_decode(dec)?);
+        }
+        Ok(SetGoCBValuesResponsePDU { result })
+    }
+}
+
+/***************************************/
+/*服务处理     */
+/***************************************/
+
+/*引用格式不对是参数不对  找不到是实例不存在     */
+fn find_gocb<'a>(model: &'a Model, reference: &str) -> Result<&'a GoCb, ServiceError> {
+    if split_dataset_ref(reference).is_none() {
+        return Err(ServiceError::ParameterValueInappropriate);
+    }
+    model
+        .get_gocb(reference)
+        .ok_or(ServiceError::InstanceNotAvailable)
+}
+
+/*102 读 GOOSE 控制块     */
+pub fn get_gocb_values(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetGoCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let gocb = req
+        .gocbreference
+        .iter()
+        .map(|reference| find_gocb(model, reference).cloned())
+        .collect();
+    let resp = GetGoCBValuesResponsePDU { gocb };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*写一个 GOOSE 控制块  先检查完再改
+同一个请求里面先去使能再改配置是可以的     */
+fn set_gocb_one(model: &mut Model, item: SetGoCbItem) -> Result<(), ServiceError> {
+    let mut cb = find_gocb(model, &item.reference)?.clone();
+    if item.goena == Some(false) {
+        cb.goena = false;
+    }
+    if (item.goid.is_some() || item.datset.is_some()) && cb.goena {
+        return Err(ServiceError::AccessNotAllowedInCurrentState);
+    }
+    if let Some(goid) = item.goid {
+        cb.goid = goid;
+    }
+    if let Some(datset) = item.datset {
+        if !datset.is_empty() {
+            let (ds_ref, _) =
+                split_dataset_ref(&datset).ok_or(ServiceError::ParameterValueInappropriate)?;
+            let (cb_ref, _) = split_dataset_ref(&item.reference)
+                .ok_or(ServiceError::ParameterValueInappropriate)?;
+            find_dataset(model, &[], &datset)?;
+            if ds_ref.ld != cb_ref.ld {
+                return Err(ServiceError::ParameterValueInconsistent);
+            }
+        }
+        if datset != cb.datset {
+            cb.ndscom = datset.is_empty();
+            cb.datset = datset;
+            cb.confrev = cb.confrev.wrapping_add(1);
+        }
+    }
+    if item.goena == Some(true) && !cb.goena {
+        if cb.ndscom || cb.datset.is_empty() {
+            return Err(ServiceError::ParameterValueInconsistent);
+        }
+        cb.goena = true;
+    }
+    if let Some(gocb) = model.get_gocb_mut(&item.reference) {
+        if gocb.goena != cb.goena {
+            println!("gocb {} goena {}", item.reference, cb.goena);
+        }
+        *gocb = cb;
+    }
+    Ok(())
+}
+
+/*103 写 GOOSE 控制块  每个控制块一个结果     */
+pub fn set_gocb_values(
+    model: &mut Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: SetGoCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let result = req
+        .gocb
+        .into_iter()
+        .map(|item| match set_gocb_one(model, item) {
+            Ok(()) => ServiceError::NoError,
+            Err(err) => err,
+        })
+        .collect();
+    let resp = SetGoCBValuesResponsePDU { result };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
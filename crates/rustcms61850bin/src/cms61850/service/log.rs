@@ -0,0 +1,823 @@
+use super::common::{self, ServiceError};
+use super::dataset::find_dataset;
+use super::report::TRGOPS_MASK;
+use crate::cms61850::codec::aper::{self, AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::log::{LogEngine, LogStore};
+use crate::cms61850::model::cb::{Lcb, LCB_OPTFLDS_BITS, TRGOPS_BITS};
+use crate::cms61850::model::change::REASON_BITS;
+use crate::cms61850::model::dataset::split_dataset_ref;
+use crate::cms61850::model::value::entry_time_millis;
+use crate::cms61850::model::{Model, Value};
+use crate::cms61850::report::RptEntry;
+use bytes::Bytes;
+/***************************************/
+/*日志服务  95 96 日志控制块  97 98 查日志  99 日志状态
+查日志的结果放不下  moreFollows 置位  客户端拿最后一条的 EntryID 接着查     */
+/***************************************/
+
+// LogEntry ::= SEQUENCE {
+//     timeOfEntry EntryTime,
+//     entryID EntryID,
+//     entryData SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         fc FunctionalConstraint,
+//         value Data,
+//         reason ReasonCode
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    pub timeofentry: [u8; 6],
+    pub entryid: [u8; 8],
+    pub entrydata: Vec<LogEntryData>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEntryData {
+    pub reference: String,
+    pub fc: String,
+    pub value: Value,
+    pub reason: u8,
+}
+
+// GetLCBValues-RequestPDU ::= SEQUENCE {
+//     lcbReference SEQUENCE OF ObjectReference
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetLCBValuesRequestPDU {
+    pub lcbreference: Vec<String>,
+}
+
+// GetLCBValues-ResponsePDU ::= SEQUENCE {
+//     lcb SEQUENCE OF CHOICE {
+//         error ServiceError,
+//         value LCB
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetLCBValuesResponsePDU {
+    pub lcb: Vec<Result<Lcb, ServiceError>>,
+}
+
+// SetLCBValues-RequestPDU ::= SEQUENCE {
+//     lcb SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         logEna BOOLEAN OPTIONAL,
+//         datSet ObjectReference OPTIONAL,
+//         trgOps TriggerConditions OPTIONAL,
+//         intgPd INT32U OPTIONAL,
+//         logRef ObjectReference OPTIONAL,
+//         optFlds LCBOptFlds OPTIONAL,
+//         bufTm INT32U OPTIONAL
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetLCBValuesRequestPDU {
+    pub lcb: Vec<SetLcbItem>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetLcbItem {
+    pub reference: String,
+    pub logena: Option<bool>,
+    pub datset: Option<String>,
+    pub trgops: Option<u8>,
+    pub intgpd: Option<u32>,
+    pub logref: Option<String>,
+    pub optflds: Option<u8>,
+    pub buftm: Option<u32>,
+}
+
+// SetLCBValues-ResponsePDU ::= SEQUENCE {
+//     result SEQUENCE OF ServiceError
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetLCBValuesResponsePDU {
+    pub result: Vec<ServiceError>,
+}
+
+// QueryLogByTime-RequestPDU ::= SEQUENCE {
+//     logReference ObjectReference,
+//     startTime EntryTime OPTIONAL,
+//     stopTime EntryTime OPTIONAL,
+//     entryAfter EntryID OPTIONAL
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct QueryLogByTimeRequestPDU {
+    pub logreference: String,
+    pub starttime: Option<[u8; 6]>,
+    pub stoptime: Option<[u8; 6]>,
+    pub entryafter: Option<[u8; 8]>,
+}
+
+// QueryLogByTime-ResponsePDU ::= SEQUENCE {
+//     logEntry SEQUENCE OF LogEntry,
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct QueryLogByTimeResponsePDU {
+    pub logentry: Vec<LogEntry>,
+    pub morefollows: bool,
+}
+
+// QueryLogAfter-RequestPDU ::= SEQUENCE {
+//     logReference ObjectReference,
+//     startTime EntryTime OPTIONAL,
+//     entry EntryID
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct QueryLogAfterRequestPDU {
+    pub logreference: String,
+    pub starttime: Option<[u8; 6]>,
+    pub entry: [u8; 8],
+}
+
+// QueryLogAfter-ResponsePDU ::= SEQUENCE {
+//     logEntry SEQUENCE OF LogEntry,
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct QueryLogAfterResponsePDU {
+    pub logentry: Vec<LogEntry>,
+    pub morefollows: bool,
+}
+
+// GetLogStatusValues-RequestPDU ::= SEQUENCE {
+//     logReference SEQUENCE OF ObjectReference
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetLogStatusValuesRequestPDU {
+    pub logreference: Vec<String>,
+}
+
+// GetLogStatusValues-ResponsePDU ::= SEQUENCE {
+//     log SEQUENCE OF CHOICE {
+//         error ServiceError,
+//         value SEQUENCE {
+//             oldEntrTm EntryTime,
+//             newEntrTm EntryTime,
+//             oldEnt EntryID,
+//             newEnt EntryID
+//         }
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetLogStatusValuesResponsePDU {
+    pub log: Vec<Result<LogStatus, ServiceError>>,
+}
+
+/*日志是空的话全是 0     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct LogStatus {
+    pub oldentrtm: [u8; 6],
+    pub newentrtm: [u8; 6],
+    pub oldent: [u8; 8],
+    pub newent: [u8; 8],
+}
+
+fn put_u32(enc: &mut AperEncoder, val: u32) -> crate::Result<()> {
+    enc.put_constrained_int(val as i128, 0, u32::MAX as i128)
+}
+
+fn get_u32(dec: &mut AperDecoder) -> crate::Result<u32> {
+    Ok(dec.get_constrained_int(0, u32::MAX as i128)? as u32)
+}
+
+fn put_entry_time(enc: &mut AperEncoder, time: &[u8; 6]) -> crate::Result<()> {
+    enc.put_octet_string(time, Size::Fixed(6))
+}
+
+fn get_entry_time(dec: &mut AperDecoder) -> crate::Result<[u8; 6]> {
+    let mut time = [0u8; 6];
+    time.copy_from_slice(&dec.get_octet_string(Size::Fixed(6))?);
+    Ok(time)
+}
+
+fn put_entry_id(enc: &mut AperEncoder, entryid: &[u8; 8]) -> crate::Result<()> {
+    enc.put_octet_string(entryid, Size::Fixed(8))
+}
+
+fn get_entry_id(dec: &mut AperDecoder) -> crate::Result<[u8; 8]> {
+    let mut entryid = [0u8; 8];
+    entryid.copy_from_slice(&dec.get_octet_string(Size::Fixed(8))?);
+    Ok(entryid)
+}
+
+fn put_ref_vec(enc: &mut AperEncoder, reference_vec: &[String]) -> crate::Result<()> {
+    enc.put_sequence_of_len(reference_vec.len(), Size::Unconstrained)?;
+    for reference in reference_vec.iter() {
+        common::put_obj_ref(enc, reference)?;
+    }
+    Ok(())
+}
+
+fn get_ref_vec(dec: &mut AperDecoder) -> crate::Result<Vec<String>> {
+    let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+    let mut reference_vec = Vec::new();
+    for _ in 0..count {
+        reference_vec.push(common::get_obj_ref(dec)?);
+    }
+    Ok(reference_vec)
+}
+
+fn put_log_entry_vec(enc: &mut AperEncoder, logentry: &[LogEntry]) -> crate::Result<()> {
+    enc.put_sequence_of_len(logentry.len(), Size::Unconstrained)?;
+    for entry in logentry.iter() {
+        entry.aper_encode(enc)?;
+    }
+    Ok(())
+}
+
+fn get_log_entry_vec(dec: &mut AperDecoder) -> crate::Result<Vec<LogEntry>> {
+    let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+    let mut logentry = Vec::new();
+    for _ in 0..count {
+        logentry.push(LogEntry::aper_decode(dec)?);
+    }
+    Ok(logentry)
+}
+
+impl AperCodec for LogEntryData {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.reference)?;
+        common::put_fc(enc, &self.fc)?;
+        self.value.aper_encode(enc)?;
+        enc.put_bit_string(&[self.reason], REASON_BITS, Size::Fixed(REASON_BITS))
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(LogEntryData {
+            reference: common::get_obj_ref(dec)?,
+            fc: common::get_fc(dec)?,
+            value: Value::aper_decode(dec)?,
+            reason: dec.get_bit_string(Size::Fixed(REASON_BITS))?.0[0],
+        })
+    }
+}
+
+impl AperCodec for LogEntry {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        put_entry_time(enc, &self.timeofentry)?;
+        put_entry_id(enc, &self.entryid)?;
+        enc.put_sequence_of_len(self.entrydata.len(), Size::Unconstrained)?;
+        for data in self.entrydata.iter() {
+            data.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let timeofentry = get_entry_time(dec)?;
+        let entryid = get_entry_id(dec)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut entrydata = Vec::new();
+        for _ in 0..count {
+            entrydata.push(LogEntryData::aper_decode(dec)?);
+        }
+        Ok(LogEntry {
+            timeofentry,
+            entryid,
+            entrydata,
+        })
+    }
+}
+
+impl AperCodec for GetLCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        put_ref_vec(enc, &self.lcbreference)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(GetLCBValuesRequestPDU {
+            lcbreference: get_ref_vec(dec)?,
+        })
+    }
+}
+
+impl AperCodec for GetLCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.lcb.len(), Size::Unconstrained)?;
+        for item in self.lcb.iter() {
+            match item {
+                Err(err) => {
+                    enc.put_choice_index(0, 2, false)?;
+                    err.aper_encode(enc)?;
+                }
+                Ok(lcb) => {
+                    enc.put_choice_index(1, 2, false)?;
+                    lcb.aper_encode(enc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut lcb = Vec::new();
+        for _ in 0..count {
+            match dec.get_choice_index(2, false)? {
+                0 => lcb.push(Err(ServiceError::aper_decode(dec)?)),
+                _ => lcb.push(Ok(Lcb::aper_decode(dec)?)),
+            }
+        }
+        Ok(GetLCBValuesResponsePDU { lcb })
+    }
+}
+
+impl AperCodec for SetLcbItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[
+                self.logena.is_some(),
+                self.datset.is_some(),
+                self.trgops.is_some(),
+                self.intgpd.is_some(),
+                self.logref.is_some(),
+                self.optflds.is_some(),
+                self.buftm.is_some(),
+            ],
+        );
+        common::put_obj_ref(enc, &self.reference)?;
+        if let Some(logena) = self.logena {
+            enc.put_bool(logena);
+        }
+        if let Some(ref datset) = self.datset {
+            common::put_obj_ref(enc, datset)?;
+        }
+        if let Some(trgops) = self.trgops {
+            enc.put_bit_string(&[trgops], TRGOPS_BITS, Size::Fixed(TRGOPS_BITS))?;
+        }
+        if let Some(intgpd) = self.intgpd {
+            put_u32(enc, intgpd)?;
+        }
+        if let Some(ref logref) = self.logref {
+            common::put_obj_ref(enc, logref)?;
+        }
+        if let Some(optflds) = self.optflds {
+            enc.put_bit_string(&[optflds], LCB_OPTFLDS_BITS, Size::Fixed(LCB_OPTFLDS_BITS))?;
+        }
+        if let Some(buftm) = self.buftm {
+            put_u32(enc, buftm)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 7)?;
+        let mut item = SetLcbItem {
+            reference: common::get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            item.logena = Some(dec.get_bool()?);
+        }
+        if present[1] {
+            item.datset = Some(common::get_obj_ref(dec)?);
+        }
+        if present[2] {
+            item.trgops = Some(dec.get_bit_string(Size::Fixed(TRGOPS_BITS))?.0[0]);
+        }
+        if present[3] {
+            item.intgpd = Some(get_u32(dec)?);
+        }
+        if present[4] {
+            item.logref = Some(common::get_obj_ref(dec)?);
+        }
+        if present[5] {
+            item.optflds = Some(dec.get_bit_string(Size::Fixed(LCB_OPTFLDS_BITS))?.0[0]);
+        }
+        if present[6] {
+            item.buftm = Some(get_u32(dec)?);
+        }
+        Ok(item)
+    }
+}
+
+impl AperCodec for SetLCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.lcb.len(), Size::Unconstrained)?;
+        for item in self.lcb.iter() {
+            item.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut lcb = Vec::new();
+        for _ in 0..count {
+            lcb.push(SetLcbItem::aper_decode(dec)?);
+        }
+        Ok(SetLCBValuesRequestPDU { lcb })
+    }
+}
+
+impl AperCodec for SetLCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.result.len(), Size::Unconstrained)?;
+        for err in self.result.iter() {
+            err.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut result = Vec::new();
+        for _ in 0..count {
+            result.push(ServiceError::aper_decode(dec)?);
+        }
+        Ok(SetLCBValuesResponsePDU { result })
+    }
+}
+
+impl AperCodec for QueryLogByTimeRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[
+                self.starttime.is_some(),
+                self.stoptime.is_some(),
+                self.entryafter.is_some(),
+            ],
+        );
+        common::put_obj_ref(enc, &self.logreference)?;
+        if let Some(ref starttime) = self.starttime {
+            put_entry_time(enc, starttime)?;
+        }
+        if let Some(ref stoptime) = self.stoptime {
+            put_entry_time(enc, stoptime)?;
+        }
+        if let Some(ref entryafter) = self.entryafter {
+            put_entry_id(enc, entryafter)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 3)?;
+        let mut pdu = QueryLogByTimeRequestPDU {
+            logreference: common::get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.starttime = Some(get_entry_time(dec)?);
+        }
+        if present[1] {
+            pdu.stoptime = Some(get_entry_time(dec)?);
+        }
+        if present[2] {
+            pdu.entryafter = Some(get_entry_id(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for QueryLogByTimeResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        put_log_entry_vec(enc, &self.logentry)?;
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        Ok(QueryLogByTimeResponsePDU {
+            logentry: get_log_entry_vec(dec)?,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+impl AperCodec for QueryLogAfterRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[self.starttime.is_some()]);
+        common::put_obj_ref(enc, &self.logreference)?;
+        if let Some(ref starttime) = self.starttime {
+            put_entry_time(enc, starttime)?;
+        }
+        put_entry_id(enc, &self.entry)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let logreference = common::get_obj_ref(dec)?;
+        let starttime = if present[0] {
+            Some(get_entry_time(dec)?)
+        } else {
+            None
+        };
+        Ok(QueryLogAfterRequestPDU {
+            logreference,
+            starttime,
+            entry: get_entry_id(dec)?,
+        })
+    }
+}
+
+impl AperCodec for QueryLogAfterResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        put_log_entry_vec(enc, &self.logentry)?;
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        Ok(QueryLogAfterResponsePDU {
+            logentry: get_log_entry_vec(dec)?,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+impl AperCodec for GetLogStatusValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        put_ref_vec(enc, &self.logreference)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(GetLogStatusValuesRequestPDU {
+            logreference: get_ref_vec(dec)?,
+        })
+    }
+}
+
+impl AperCodec for LogStatus {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        put_entry_time(enc, &self.oldentrtm)?;
+        put_entry_time(enc, &self.newentrtm)?;
+        put_entry_id(enc, &self.oldent)?;
+        put_entry_id(enc, &self.newent)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(LogStatus {
+            oldentrtm: get_entry_time(dec)?,
+            newentrtm: get_entry_time(dec)?,
+            oldent: get_entry_id(dec)?,
+            newent: get_entry_id(dec)?,
+        })
+    }
+}
+
+impl AperCodec for GetLogStatusValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.log.len(), Size::Unconstrained)?;
+        for item in self.log.iter() {
+            match item {
+                Err(err) => {
+                    enc.put_choice_index(0, 2, false)?;
+                    err.aper_encode(enc)?;
+                }
+                Ok(status) => {
+                    enc.put_choice_index(1, 2, false)?;
+                    status.aper_encode(enc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut log = Vec::new();
+        for _ in 0..count {
+            match dec.get_choice_index(2, false)? {
+                0 => log.push(Err(ServiceError::aper_decode(dec)?)),
+                _ => log.push(Ok(LogStatus::aper_decode(dec)?)),
+            }
+        }
+        Ok(GetLogStatusValuesResponsePDU { log })
+    }
+}
+
+/***************************************/
+/*服务处理     */
+/***************************************/
+
+/*引用格式不对是参数不对  找不到是实例不存在     */
+fn find_lcb<'a>(model: &'a Model, reference: &str) -> Result<&'a Lcb, ServiceError> {
+    if split_dataset_ref(reference).is_none() {
+        return Err(ServiceError::ParameterValueInappropriate);
+    }
+    model
+        .get_lcb(reference)
+        .ok_or(ServiceError::InstanceNotAvailable)
+}
+
+fn find_log<'a>(engine: &'a LogEngine, reference: &str) -> Result<&'a LogStore, ServiceError> {
+    if split_dataset_ref(reference).is_none() {
+        return Err(ServiceError::ParameterValueInappropriate);
+    }
+    engine
+        .log(reference)
+        .ok_or(ServiceError::InstanceNotAvailable)
+}
+
+/*缓存里面的条目转成 LogEntry  原因都带上     */
+fn to_log_entry(entry: &RptEntry) -> LogEntry {
+    LogEntry {
+        timeofentry: entry.timeofentry,
+        entryid: entry.entryid.to_be_bytes(),
+        entrydata: entry
+            .entrydata
+            .iter()
+            .map(|data| LogEntryData {
+                reference: data.reference.clone().unwrap_or_default(),
+                fc: data.fc.clone().unwrap_or_default(),
+                value: data.value.clone(),
+                reason: data.reason.unwrap_or(0),
+            })
+            .collect(),
+    }
+}
+
+/*95 读日志控制块     */
+pub fn get_lcb_values(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetLCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let lcb = req
+        .lcbreference
+        .iter()
+        .map(|reference| find_lcb(model, reference).cloned())
+        .collect();
+    let resp = GetLCBValuesResponsePDU { lcb };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*写一个日志控制块  先检查完再改
+使能的时候只能去使能  数据集要持久的  LogRef 要是已有的日志     */
+fn set_lcb_one(
+    model: &mut Model,
+    engine: &mut LogEngine,
+    item: SetLcbItem,
+) -> Result<(), ServiceError> {
+    let old = find_lcb(model, &item.reference)?.clone();
+    let key = item.reference.as_str();
+    let mut cb = old.clone();
+    if item.logena == Some(false) {
+        cb.logena = false;
+    }
+    let config = item.datset.is_some()
+        || item.trgops.is_some()
+        || item.intgpd.is_some()
+        || item.logref.is_some()
+        || item.optflds.is_some()
+        || item.buftm.is_some();
+    if config && cb.logena {
+        return Err(ServiceError::AccessNotAllowedInCurrentState);
+    }
+    if let Some(datset) = item.datset {
+        if !datset.is_empty() {
+            find_dataset(model, &[], &datset)?;
+        }
+        cb.datset = datset;
+    }
+    if let Some(trgops) = item.trgops {
+        cb.trgops = trgops & TRGOPS_MASK;
+    }
+    if let Some(intgpd) = item.intgpd {
+        cb.intgpd = intgpd;
+    }
+    if let Some(logref) = item.logref {
+        if !logref.is_empty() {
+            find_log(engine, &logref)?;
+        }
+        cb.logref = logref;
+    }
+    if let Some(optflds) = item.optflds {
+        cb.optflds = optflds & 0x80;
+    }
+    if let Some(buftm) = item.buftm {
+        cb.buftm = buftm;
+    }
+    let mut member_vec = None;
+    if item.logena == Some(true) && !cb.logena {
+        if cb.logref.is_empty() {
+            return Err(ServiceError::ParameterValueInconsistent);
+        }
+        let dataset = find_dataset(model, &[], &cb.datset)
+            .map_err(|_| ServiceError::ParameterValueInconsistent)?;
+        member_vec = Some(dataset.member_vec.clone());
+        cb.logena = true;
+    }
+    if old.logena && !cb.logena {
+        engine.stop_lcb(key);
+    }
+    if let Some(member_vec) = member_vec {
+        engine.start_lcb(key, member_vec, &cb);
+    }
+    if let Some(lcb) = model.get_lcb_mut(key) {
+        *lcb = cb;
+    }
+    Ok(())
+}
+
+/*96 写日志控制块  每个控制块一个结果     */
+pub fn set_lcb_values(
+    model: &mut Model,
+    engine: &mut LogEngine,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: SetLCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let result = req
+        .lcb
+        .into_iter()
+        .map(|item| match set_lcb_one(model, engine, item) {
+            Ok(()) => ServiceError::NoError,
+            Err(err) => err,
+        })
+        .collect();
+    let resp = SetLCBValuesResponsePDU { result };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*按时间和 EntryID 挑条目  after 之后的  时间两头都算     */
+fn query_log(
+    engine: &LogEngine,
+    reference: &str,
+    starttime: Option<[u8; 6]>,
+    stoptime: Option<[u8; 6]>,
+    after: u64,
+) -> Result<Vec<LogEntry>, ServiceError> {
+    let store = find_log(engine, reference)?;
+    let start = starttime.map(|time| entry_time_millis(&time));
+    let stop = stoptime.map(|time| entry_time_millis(&time));
+    Ok(store
+        .entry_vec
+        .iter()
+        .filter(|entry| entry.entryid > after)
+        .filter(|entry| {
+            let time = entry_time_millis(&entry.timeofentry);
+            start.is_none_or(|start| time >= start) && stop.is_none_or(|stop| time <= stop)
+        })
+        .map(to_log_entry)
+        .collect())
+}
+
+/*97 按时间查日志  续查的时候带上 entryAfter     */
+pub fn query_log_by_time(
+    engine: &LogEngine,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: QueryLogByTimeRequestPDU = common::decode_req(pay_load)?;
+    let after = req.entryafter.map(u64::from_be_bytes).unwrap_or(0);
+    let items = query_log(
+        engine,
+        &req.logreference,
+        req.starttime,
+        req.stoptime,
+        after,
+    )?;
+    common::encode_fit(&items, max_len, |logentry, morefollows| {
+        QueryLogByTimeResponsePDU {
+            logentry,
+            morefollows,
+        }
+    })
+}
+
+/*98 查某一条以后的日志     */
+pub fn query_log_after(
+    engine: &LogEngine,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: QueryLogAfterRequestPDU = common::decode_req(pay_load)?;
+    let after = u64::from_be_bytes(req.entry);
+    let items = query_log(engine, &req.logreference, req.starttime, None, after)?;
+    common::encode_fit(&items, max_len, |logentry, morefollows| {
+        QueryLogAfterResponsePDU {
+            logentry,
+            morefollows,
+        }
+    })
+}
+
+/*99 日志状态  最老和最新的条目     */
+pub fn get_log_status_values(
+    engine: &LogEngine,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetLogStatusValuesRequestPDU = common::decode_req(pay_load)?;
+    let log = req
+        .logreference
+        .iter()
+        .map(|reference| {
+            let store = find_log(engine, reference)?;
+            let mut status = LogStatus::default();
+            if let (Some(old), Some(new)) = (store.entry_vec.front(), store.entry_vec.back()) {
+                status.oldentrtm = old.timeofentry;
+                status.newentrtm = new.timeofentry;
+                status.oldent = old.entryid.to_be_bytes();
+                status.newent = new.entryid.to_be_bytes();
+            }
+            Ok(status)
+        })
+        .collect();
+    let resp = GetLogStatusValuesResponsePDU { log };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
@@ -1,7 +1,14 @@
-
-
-
 /***************************************/
 /*用来存放所有的 服务     */
 /***************************************/
-pub mod associatenegotiate;
\ No newline at end of file
+pub mod associate;
+pub mod associatenegotiate;
+pub mod common;
+pub mod control;
+pub mod data;
+pub mod dataset;
+pub mod directory;
+pub mod goose;
+pub mod log;
+pub mod report;
+pub mod sg;
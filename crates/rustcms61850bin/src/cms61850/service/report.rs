@@ -0,0 +1,1245 @@
+use super::common::{self, ServiceError};
+use super::dataset::find_dataset;
+use crate::cms61850::codec::aper::{self, AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::model::cb::{Brcb, Urcb, RCB_OPTFLDS_BITS, TRGOPS_BITS};
+use crate::cms61850::model::change::REASON_BITS;
+use crate::cms61850::model::dataset::split_dataset_ref;
+use crate::cms61850::model::{DataSet, Model, Value};
+use crate::cms61850::report::{RcbRun, ReportEngine};
+use bytes::Bytes;
+/***************************************/
+/*报告服务  90 报告本身  91 92 缓存报告控制块  93 94 非缓存报告控制块
+报告是服务端主动发的  req_id 固定是 0     */
+/***************************************/
+
+/*RCBOptFlds 的位  高位在前  第 n 位在 optflds[n / 8] 的 0x80 >> (n % 8)     */
+pub const OPT_SEQNUM: usize = 1;
+pub const OPT_TIMESTAMP: usize = 2;
+pub const OPT_REASON: usize = 3;
+pub const OPT_DATSET: usize = 4;
+pub const OPT_DATAREF: usize = 5;
+pub const OPT_BUFOVFL: usize = 6;
+pub const OPT_ENTRYID: usize = 7;
+pub const OPT_CONFREV: usize = 8;
+pub const OPT_SEGMENTATION: usize = 9;
+
+/*TriggerConditions 第 0 位保留     */
+pub const TRGOPS_MASK: u8 = 0x7c;
+
+pub fn opt_bit(optflds: &[u8; 2], bit: usize) -> bool {
+    optflds[bit / 8] & (0x80 >> (bit % 8)) != 0
+}
+
+pub fn set_opt_bit(optflds: &mut [u8; 2], bit: usize, on: bool) {
+    if on {
+        optflds[bit / 8] |= 0x80 >> (bit % 8);
+    } else {
+        optflds[bit / 8] &= !(0x80 >> (bit % 8));
+    }
+}
+
+/*报告里面的一个成员  id 是数据集里面的下标     */
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryData {
+    pub reference: Option<String>,
+    pub fc: Option<String>,
+    pub id: u16,
+    pub value: Value,
+    pub reason: Option<u8>,
+}
+
+// Report-RequestPDU ::= SEQUENCE {
+//     rptID VisibleString129,
+//     optFlds RCBOptFlds,
+//     sqNum INT16U OPTIONAL,
+//     subSqNum INT16U OPTIONAL,
+//     moreSegmentsFollow BOOLEAN OPTIONAL,
+//     datSet ObjectReference OPTIONAL,
+//     bufOvfl BOOLEAN OPTIONAL,
+//     confRev INT32U OPTIONAL,
+//     timeOfEntry EntryTime OPTIONAL,
+//     entryID EntryID OPTIONAL,
+//     entry SEQUENCE {
+//         inclusion-bitstring BIT STRING,
+//         entryData SEQUENCE OF SEQUENCE {
+//             reference ObjectReference OPTIONAL,
+//             fc FunctionalConstraint OPTIONAL,
+//             id INT16U,
+//             value Data,
+//             reason ReasonCode OPTIONAL
+//         }
+//     }
+// }
+/*inclusion 是数据集每个成员在不在这一条里面     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ReportPDU {
+    pub rptid: String,
+    pub optflds: [u8; 2],
+    pub sqnum: Option<u16>,
+    pub subsqnum: Option<u16>,
+    pub moresegmentsfollow: Option<bool>,
+    pub datset: Option<String>,
+    pub bufovfl: Option<bool>,
+    pub confrev: Option<u32>,
+    pub timeofentry: Option<[u8; 6]>,
+    pub entryid: Option<[u8; 8]>,
+    pub inclusion: Vec<bool>,
+    pub entrydata: Vec<EntryData>,
+}
+
+// GetBRCBValues-RequestPDU ::= SEQUENCE {
+//     brcbReference SEQUENCE OF ObjectReference
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetBRCBValuesRequestPDU {
+    pub brcbreference: Vec<String>,
+}
+
+// GetBRCBValues-ResponsePDU ::= SEQUENCE {
+//     brcb SEQUENCE OF CHOICE {
+//         error ServiceError,
+//         value BRCB
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetBRCBValuesResponsePDU {
+    pub brcb: Vec<Result<Brcb, ServiceError>>,
+}
+
+// SetBRCBValues-RequestPDU ::= SEQUENCE {
+//     brcb SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         rptID VisibleString129 OPTIONAL,
+//         rptEna BOOLEAN OPTIONAL,
+//         datSet ObjectReference OPTIONAL,
+//         optFlds RCBOptFlds OPTIONAL,
+//         bufTm INT32U OPTIONAL,
+//         trgOps TriggerConditions OPTIONAL,
+//         intgPd INT32U OPTIONAL,
+//         gi BOOLEAN OPTIONAL,
+//         purgeBuf BOOLEAN OPTIONAL,
+//         entryID EntryID OPTIONAL,
+//         resvTms INT16 OPTIONAL
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetBRCBValuesRequestPDU {
+    pub brcb: Vec<SetBrcbItem>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetBrcbItem {
+    pub reference: String,
+    pub rptid: Option<String>,
+    pub rptena: Option<bool>,
+    pub datset: Option<String>,
+    pub optflds: Option<[u8; 2]>,
+    pub buftm: Option<u32>,
+    pub trgops: Option<u8>,
+    pub intgpd: Option<u32>,
+    pub gi: Option<bool>,
+    pub purgebuf: Option<bool>,
+    pub entryid: Option<[u8; 8]>,
+    pub resvtms: Option<i16>,
+}
+
+// SetBRCBValues-ResponsePDU ::= SEQUENCE {
+//     result SEQUENCE OF ServiceError
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetBRCBValuesResponsePDU {
+    pub result: Vec<ServiceError>,
+}
+
+// GetURCBValues-RequestPDU ::= SEQUENCE {
+//     urcbReference SEQUENCE OF ObjectReference
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetURCBValuesRequestPDU {
+    pub urcbreference: Vec<String>,
+}
+
+// GetURCBValues-ResponsePDU ::= SEQUENCE {
+//     urcb SEQUENCE OF CHOICE {
+//         error ServiceError,
+//         value URCB
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetURCBValuesResponsePDU {
+    pub urcb: Vec<Result<Urcb, ServiceError>>,
+}
+
+// SetURCBValues-RequestPDU ::= SEQUENCE {
+//     urcb SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         rptID VisibleString129 OPTIONAL,
+//         rptEna BOOLEAN OPTIONAL,
+//         resv BOOLEAN OPTIONAL,
+//         datSet ObjectReference OPTIONAL,
+//         optFlds RCBOptFlds OPTIONAL,
+//         bufTm INT32U OPTIONAL,
+//         trgOps TriggerConditions OPTIONAL,
+//         intgPd INT32U OPTIONAL,
+//         gi BOOLEAN OPTIONAL
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetURCBValuesRequestPDU {
+    pub urcb: Vec<SetUrcbItem>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetUrcbItem {
+    pub reference: String,
+    pub rptid: Option<String>,
+    pub rptena: Option<bool>,
+    pub resv: Option<bool>,
+    pub datset: Option<String>,
+    pub optflds: Option<[u8; 2]>,
+    pub buftm: Option<u32>,
+    pub trgops: Option<u8>,
+    pub intgpd: Option<u32>,
+    pub gi: Option<bool>,
+}
+
+// SetURCBValues-ResponsePDU ::= SEQUENCE {
+//     result SEQUENCE OF ServiceError
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetURCBValuesResponsePDU {
+    pub result: Vec<ServiceError>,
+}
+
+fn put_u32(enc: &mut AperEncoder, val: u32) -> crate::Result<()> {
+    enc.put_constrained_int(val as i128, 0, u32::MAX as i128)
+}
+
+fn get_u32(dec: &mut AperDecoder) -> crate::Result<u32> {
+    Ok(dec.get_constrained_int(0, u32::MAX as i128)? as u32)
+}
+
+fn put_u16(enc: &mut AperEncoder, val: u16) -> crate::Result<()> {
+    enc.put_constrained_int(val as i128, 0, u16::MAX as i128)
+}
+
+fn get_u16(dec: &mut AperDecoder) -> crate::Result<u16> {
+    Ok(dec.get_constrained_int(0, u16::MAX as i128)? as u16)
+}
+
+fn get_optflds(dec: &mut AperDecoder) -> crate::Result<[u8; 2]> {
+    let (bits, _) = dec.get_bit_string(Size::Fixed(RCB_OPTFLDS_BITS))?;
+    Ok([bits[0], bits[1]])
+}
+
+impl AperCodec for EntryData {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[
+                self.reference.is_some(),
+                self.fc.is_some(),
+                self.reason.is_some(),
+            ],
+        );
+        if let Some(ref reference) = self.reference {
+            common::put_obj_ref(enc, reference)?;
+        }
+        if let Some(ref fc) = self.fc {
+            common::put_fc(enc, fc)?;
+        }
+        put_u16(enc, self.id)?;
+        self.value.aper_encode(enc)?;
+        if let Some(reason) = self.reason {
+            enc.put_bit_string(&[reason], REASON_BITS, Size::Fixed(REASON_BITS))?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 3)?;
+        let reference = if present[0] {
+            Some(common::get_obj_ref(dec)?)
+        } else {
+            None
+        };
+        let fc = if present[1] {
+            Some(common::get_fc(dec)?)
+        } else {
+            None
+        };
+        let id = get_u16(dec)?;
+        let value = Value::aper_decode(dec)?;
+        let reason = if present[2] {
+            Some(dec.get_bit_string(Size::Fixed(REASON_BITS))?.0[0])
+        } else {
+            None
+        };
+        Ok(EntryData {
+            reference,
+            fc,
+            id,
+            value,
+            reason,
+        })
+    }
+}
+
+impl AperCodec for ReportPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[
+                self.sqnum.is_some(),
+                self.subsqnum.is_some(),
+                self.moresegmentsfollow.is_some(),
+                self.datset.is_some(),
+                self.bufovfl.is_some(),
+                self.confrev.is_some(),
+                self.timeofentry.is_some(),
+                self.entryid.is_some(),
+            ],
+        );
+        enc.put_visible_string(&self.rptid, Size::Range(0, 129))?;
+        enc.put_bit_string(
+            &self.optflds,
+            RCB_OPTFLDS_BITS,
+            Size::Fixed(RCB_OPTFLDS_BITS),
+        )?;
+        if let Some(sqnum) = self.sqnum {
+            put_u16(enc, sqnum)?;
+        }
+        if let Some(subsqnum) = self.subsqnum {
+            put_u16(enc, subsqnum)?;
+        }
+        if let Some(more) = self.moresegmentsfollow {
+            enc.put_bool(more);
+        }
+        if let Some(ref datset) = self.datset {
+            common::put_obj_ref(enc, datset)?;
+        }
+        if let Some(bufovfl) = self.bufovfl {
+            enc.put_bool(bufovfl);
+        }
+        if let Some(confrev) = self.confrev {
+            put_u32(enc, confrev)?;
+        }
+        if let Some(ref timeofentry) = self.timeofentry {
+            enc.put_octet_string(timeofentry, Size::Fixed(6))?;
+        }
+        if let Some(ref entryid) = self.entryid {
+            enc.put_octet_string(entryid, Size::Fixed(8))?;
+        }
+        let mut bits = vec![0u8; self.inclusion.len().div_ceil(8)];
+        for (index, _) in self.inclusion.iter().enumerate().filter(|(_, on)| **on) {
+            bits[index / 8] |= 0x80 >> (index % 8);
+        }
+        enc.put_bit_string(&bits, self.inclusion.len(), Size::Unconstrained)?;
+        enc.put_sequence_of_len(self.entrydata.len(), Size::Unconstrained)?;
+        for entry in self.entrydata.iter() {
+            entry.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 8)?;
+        let mut pdu = ReportPDU {
+            rptid: dec.get_visible_string(Size::Range(0, 129))?,
+            optflds: get_optflds(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            pdu.sqnum = Some(get_u16(dec)?);
+        }
+        if present[1] {
+            pdu.subsqnum = Some(get_u16(dec)?);
+        }
+        if present[2] {
+            pdu.moresegmentsfollow = Some(dec.get_bool()?);
+        }
+        if present[3] {
+            pdu.datset = Some(common::get_obj_ref(dec)?);
+        }
+        if present[4] {
+            pdu.bufovfl = Some(dec.get_bool()?);
+        }
+        if present[5] {
+            pdu.confrev = Some(get_u32(dec)?);
+        }
+        if present[6] {
+            let mut timeofentry = [0u8; 6];
+            timeofentry.copy_from_slice(&dec.get_octet_string(Size::Fixed(6))?);
+            pdu.timeofentry = Some(timeofentry);
+        }
+        if present[7] {
+            let mut entryid = [0u8; 8];
+            entryid.copy_from_slice(&dec.get_octet_string(Size::Fixed(8))?);
+            pdu.entryid = Some(entryid);
+        }
+        let (bits, nbits) = dec.get_bit_string(Size::Unconstrained)?;
+        pdu.inclusion = (0..nbits)
+            .map(|index| bits[index / 8] & (0x80 >> (index % 8)) != 0)
+            .collect();
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        for _ in 0..count {
+            pdu.entrydata.push(EntryData::aper_decode(dec)?);
+        }
+        Ok(pdu)
+    }
+}
+
+impl AperCodec for GetBRCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.brcbreference.len(), Size::Unconstrained)?;
+        for reference in self.brcbreference.iter() {
+            common::put_obj_ref(enc, reference)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut brcbreference = Vec::new();
+        for _ in 0..count {
+            brcbreference.push(common::get_obj_ref(dec)?);
+        }
+        Ok(GetBRCBValuesRequestPDU { brcbreference })
+    }
+}
+
+impl AperCodec for GetBRCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.brcb.len(), Size::Unconstrained)?;
+        for item in self.brcb.iter() {
+            match item {
+                Err(err) => {
+                    enc.put_choice_index(0, 2, false)?;
+                    err.aper_encode(enc)?;
+                }
+                Ok(brcb) => {
+                    enc.put_choice_index(1, 2, false)?;
+                    brcb.aper_encode(enc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut brcb = Vec::new();
+        for _ in 0..count {
+            match dec.get_choice_index(2, false)? {
+                0 => brcb.push(Err(ServiceError::aper_decode(dec)?)),
+                _ => brcb.push(Ok(Brcb::aper_decode(dec)?)),
+            }
+        }
+        Ok(GetBRCBValuesResponsePDU { brcb })
+    }
+}
+
+impl AperCodec for SetBrcbItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[
+                self.rptid.is_some(),
+                self.rptena.is_some(),
+                self.datset.is_some(),
+                self.optflds.is_some(),
+                self.buftm.is_some(),
+                self.trgops.is_some(),
+                self.intgpd.is_some(),
+                self.gi.is_some(),
+                self.purgebuf.is_some(),
+                self.entryid.is_some(),
+                self.resvtms.is_some(),
+            ],
+        );
+        common::put_obj_ref(enc, &self.reference)?;
+        if let Some(ref rptid) = self.rptid {
+            enc.put_visible_string(rptid, Size::Range(0, 129))?;
+        }
+        if let Some(rptena) = self.rptena {
+            enc.put_bool(rptena);
+        }
+        if let Some(ref datset) = self.datset {
+            common::put_obj_ref(enc, datset)?;
+        }
+        if let Some(ref optflds) = self.optflds {
+            enc.put_bit_string(optflds, RCB_OPTFLDS_BITS, Size::Fixed(RCB_OPTFLDS_BITS))?;
+        }
+        if let Some(buftm) = self.buftm {
+            put_u32(enc, buftm)?;
+        }
+        if let Some(trgops) = self.trgops {
+            enc.put_bit_string(&[trgops], TRGOPS_BITS, Size::Fixed(TRGOPS_BITS))?;
+        }
+        if let Some(intgpd) = self.intgpd {
+            put_u32(enc, intgpd)?;
+        }
+        if let Some(gi) = self.gi {
+            enc.put_bool(gi);
+        }
+        if let Some(purgebuf) = self.purgebuf {
+            enc.put_bool(purgebuf);
+        }
+        if let Some(ref entryid) = self.entryid {
+            enc.put_octet_string(entryid, Size::Fixed(8))?;
+        }
+        if let Some(resvtms) = self.resvtms {
+            enc.put_constrained_int(resvtms as i128, i16::MIN as i128, i16::MAX as i128)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 11)?;
+        let mut item = SetBrcbItem {
+            reference: common::get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            item.rptid = Some(dec.get_visible_string(Size::Range(0, 129))?);
+        }
+        if present[1] {
+            item.rptena = Some(dec.get_bool()?);
+        }
+        if present[2] {
+            item.datset = Some(common::get_obj_ref(dec)?);
+        }
+        if present[3] {
+            item.optflds = Some(get_optflds(dec)?);
+        }
+        if present[4] {
+            item.buftm = Some(get_u32(dec)?);
+        }
+        if present[5] {
+            item.trgops = Some(dec.get_bit_string(Size::Fixed(TRGOPS_BITS))?.0[0]);
+        }
+        if present[6] {
+            item.intgpd = Some(get_u32(dec)?);
+        }
+        if present[7] {
+            item.gi = Some(dec.get_bool()?);
+        }
+        if present[8] {
+            item.purgebuf = Some(dec.get_bool()?);
+        }
+        if present[9] {
+            let mut entryid = [0u8; 8];
+            entryid.copy_from_slice(&dec.get_octet_string(Size::Fixed(8))?);
+            item.entryid = Some(entryid);
+        }
+        if present[10] {
+            item.resvtms =
+                Some(dec.get_constrained_int(i16::MIN as i128, i16::MAX as i128)? as i16);
+        }
+        Ok(item)
+    }
+}
+
+impl AperCodec for SetBRCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.brcb.len(), Size::Unconstrained)?;
+        for item in self.brcb.iter() {
+            item.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut brcb = Vec::new();
+        for _ in 0..count {
+            brcb.push(SetBrcbItem::aper_decode(dec)?);
+        }
+        Ok(SetBRCBValuesRequestPDU { brcb })
+    }
+}
+
+impl AperCodec for SetBRCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.result.len(), Size::Unconstrained)?;
+        for err in self.result.iter() {
+            err.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut result = Vec::new();
+        for _ in 0..count {
+            result.push(ServiceError::aper_decode(dec)?);
+        }
+        Ok(SetBRCBValuesResponsePDU { result })
+    }
+}
+
+impl AperCodec for GetURCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.urcbreference.len(), Size::Unconstrained)?;
+        for reference in self.urcbreference.iter() {
+            common::put_obj_ref(enc, reference)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut urcbreference = Vec::new();
+        for _ in 0..count {
+            urcbreference.push(common::get_obj_ref(dec)?);
+        }
+        Ok(GetURCBValuesRequestPDU { urcbreference })
+    }
+}
+
+impl AperCodec for GetURCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.urcb.len(), Size::Unconstrained)?;
+        for item in self.urcb.iter() {
+            match item {
+                Err(err) => {
+                    enc.put_choice_index(0, 2, false)?;
+                    err.aper_encode(enc)?;
+                }
+                Ok(urcb) => {
+                    enc.put_choice_index(1, 2, false)?;
+                    urcb.aper_encode(enc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut urcb = Vec::new();
+        for _ in 0..count {
+            match dec.get_choice_index(2, false)? {
+                0 => urcb.push(Err(ServiceError::aper_decode(dec)?)),
+                _ => urcb.push(Ok(Urcb::aper_decode(dec)?)),
+            }
+        }
+        Ok(GetURCBValuesResponsePDU { urcb })
+    }
+}
+
+impl AperCodec for SetUrcbItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(
+            false,
+            &[
+                self.rptid.is_some(),
+                self.rptena.is_some(),
+                self.resv.is_some(),
+                self.datset.is_some(),
+                self.optflds.is_some(),
+                self.buftm.is_some(),
+                self.trgops.is_some(),
+                self.intgpd.is_some(),
+                self.gi.is_some(),
+            ],
+        );
+        common::put_obj_ref(enc, &self.reference)?;
+        if let Some(ref rptid) = self.rptid {
+            enc.put_visible_string(rptid, Size::Range(0, 129))?;
+        }
+        if let Some(rptena) = self.rptena {
+            enc.put_bool(rptena);
+        }
+        if let Some(resv) = self.resv {
+            enc.put_bool(resv);
+        }
+        if let Some(ref datset) = self.datset {
+            common::put_obj_ref(enc, datset)?;
+        }
+        if let Some(ref optflds) = self.optflds {
+            enc.put_bit_string(optflds, RCB_OPTFLDS_BITS, Size::Fixed(RCB_OPTFLDS_BITS))?;
+        }
+        if let Some(buftm) = self.buftm {
+            put_u32(enc, buftm)?;
+        }
+        if let Some(trgops) = self.trgops {
+            enc.put_bit_string(&[trgops], TRGOPS_BITS, Size::Fixed(TRGOPS_BITS))?;
+        }
+        if let Some(intgpd) = self.intgpd {
+            put_u32(enc, intgpd)?;
+        }
+        if let Some(gi) = self.gi {
+            enc.put_bool(gi);
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 9)?;
+        let mut item = SetUrcbItem {
+            reference: common::get_obj_ref(dec)?,
+            ..Default::default()
+        };
+        if present[0] {
+            item.rptid = Some(dec.get_visible_string(Size::Range(0, 129))?);
+        }
+        if present[1] {
+            item.rptena = Some(dec.get_bool()?);
+        }
+        if present[2] {
+            item.resv = Some(dec.get_bool()?);
+        }
+        if present[3] {
+            item.datset = Some(common::get_obj_ref(dec)?);
+        }
+        if present[4] {
+            item.optflds = Some(get_optflds(dec)?);
+        }
+        if present[5] {
+            item.buftm = Some(get_u32(dec)?);
+        }
+        if present[6] {
+            item.trgops = Some(dec.get_bit_string(Size::Fixed(TRGOPS_BITS))?.0[0]);
+        }
+        if present[7] {
+            item.intgpd = Some(get_u32(dec)?);
+        }
+        if present[8] {
+            item.gi = Some(dec.get_bool()?);
+        }
+        Ok(item)
+    }
+}
+
+impl AperCodec for SetURCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.urcb.len(), Size::Unconstrained)?;
+        for item in self.urcb.iter() {
+            item.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut urcb = Vec::new();
+        for _ in 0..count {
+            urcb.push(SetUrcbItem::aper_decode(dec)?);
+        }
+        Ok(SetURCBValuesRequestPDU { urcb })
+    }
+}
+
+impl AperCodec for SetURCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.result.len(), Size::Unconstrained)?;
+        for err in self.result.iter() {
+            err.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut result = Vec::new();
+        for _ in 0..count {
+            result.push(ServiceError::aper_decode(dec)?);
+        }
+        Ok(SetURCBValuesResponsePDU { result })
+    }
+}
+
+/*报告按协商长度分段  放不下的成员放到下一段
+各段 sqNum 一样  subSqNum 从 0 开始  最后一段 moreSegmentsFollow 是 false     */
+pub fn segment_report(report: ReportPDU, max_len: usize) -> Result<Vec<Bytes>, ServiceError> {
+    let encode = |pdu: &ReportPDU| {
+        aper::aper_to_bytes(pdu).map_err(|_| ServiceError::FailedDueToServerConstraint)
+    };
+    let whole = encode(&report)?;
+    if whole.len() <= max_len {
+        return Ok(vec![whole]);
+    }
+    let mut head = report.clone();
+    set_opt_bit(&mut head.optflds, OPT_SEGMENTATION, true);
+    head.sqnum = Some(report.sqnum.unwrap_or_default());
+    set_opt_bit(&mut head.optflds, OPT_SEQNUM, true);
+    let make = |entries: &[EntryData], subsqnum: usize, more: bool| {
+        let mut pdu = ReportPDU {
+            subsqnum: Some(subsqnum as u16),
+            moresegmentsfollow: Some(more),
+            inclusion: vec![false; report.inclusion.len()],
+            entrydata: entries.to_vec(),
+            ..head.clone()
+        };
+        for entry in entries.iter() {
+            if let Some(on) = pdu.inclusion.get_mut(entry.id as usize) {
+                *on = true;
+            }
+        }
+        pdu
+    };
+    let mut ret = Vec::new();
+    let mut rest = report.entrydata.as_slice();
+    while !rest.is_empty() {
+        /*二分找这一段能放下的最多个数     */
+        let (mut lo, mut hi) = (0, rest.len());
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            if encode(&make(&rest[..mid], ret.len(), true))?.len() <= max_len {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        if lo == 0 {
+            return Err(ServiceError::FailedDueToCommunicationsConstraint);
+        }
+        ret.push(encode(&make(&rest[..lo], ret.len(), lo < rest.len()))?);
+        rest = &rest[lo..];
+    }
+    Ok(ret)
+}
+
+/*引用格式不对是参数不对  找不到是实例不存在     */
+fn find_urcb<'a>(model: &'a Model, reference: &str) -> Result<&'a Urcb, ServiceError> {
+    if split_dataset_ref(reference).is_none() {
+        return Err(ServiceError::ParameterValueInappropriate);
+    }
+    model
+        .get_urcb(reference)
+        .ok_or(ServiceError::InstanceNotAvailable)
+}
+
+fn find_brcb<'a>(model: &'a Model, reference: &str) -> Result<&'a Brcb, ServiceError> {
+    if split_dataset_ref(reference).is_none() {
+        return Err(ServiceError::ParameterValueInappropriate);
+    }
+    model
+        .get_brcb(reference)
+        .ok_or(ServiceError::InstanceNotAvailable)
+}
+
+/*91 读缓存报告控制块     */
+pub fn get_brcb_values(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetBRCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let brcb = req
+        .brcbreference
+        .iter()
+        .map(|reference| find_brcb(model, reference).cloned())
+        .collect();
+    let resp = GetBRCBValuesResponsePDU { brcb };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*93 读非缓存报告控制块     */
+pub fn get_urcb_values(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetURCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let urcb = req
+        .urcbreference
+        .iter()
+        .map(|reference| find_urcb(model, reference).cloned())
+        .collect();
+    let resp = GetURCBValuesResponsePDU { urcb };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*写一个缓存报告控制块  先检查完再改
+数据集 触发条件 缓存时间 完整性周期 变了 缓存清掉重新攒
+EntryID 是客户端重新同步用的  之后从它后面那条开始发
+占用看 RptEna 和 ResvTms  缓存要跨关联  不能用非持久数据集     */
+fn set_brcb_one(
+    model: &mut Model,
+    engine: &mut ReportEngine,
+    con_id: u32,
+    owner: &[u8],
+    item: SetBrcbItem,
+) -> Result<(), ServiceError> {
+    let old = find_brcb(model, &item.reference)?.clone();
+    let key = item.reference.as_str();
+    if engine.locked(key, con_id, owner) {
+        return Err(ServiceError::InstanceLockedByOtherClient);
+    }
+    let mut cb = old.clone();
+    if item.rptena == Some(false) {
+        cb.rptena = false;
+    }
+    let config = item.rptid.is_some()
+        || item.datset.is_some()
+        || item.optflds.is_some()
+        || item.buftm.is_some()
+        || item.trgops.is_some()
+        || item.intgpd.is_some()
+        || item.purgebuf.is_some()
+        || item.entryid.is_some();
+    if config && cb.rptena {
+        return Err(ServiceError::AccessNotAllowedInCurrentState);
+    }
+    if let Some(rptid) = item.rptid {
+        cb.rptid = rptid;
+    }
+    if let Some(datset) = item.datset {
+        if !datset.is_empty() {
+            find_dataset(model, &[], &datset)?;
+        }
+        if datset != cb.datset {
+            cb.datset = datset;
+            cb.confrev = cb.confrev.wrapping_add(1);
+        }
+    }
+    if let Some(optflds) = item.optflds {
+        cb.optflds = [optflds[0], optflds[1] & 0xc0];
+    }
+    if let Some(buftm) = item.buftm {
+        cb.buftm = buftm;
+    }
+    if let Some(trgops) = item.trgops {
+        cb.trgops = trgops & TRGOPS_MASK;
+    }
+    if let Some(intgpd) = item.intgpd {
+        cb.intgpd = intgpd;
+    }
+    if let Some(resvtms) = item.resvtms {
+        if cb.resvtms.is_none() {
+            return Err(ServiceError::AccessViolation);
+        }
+        if resvtms < 0 {
+            return Err(ServiceError::ParameterValueInappropriate);
+        }
+        cb.resvtms = Some(resvtms);
+    }
+    let reconfig = cb.datset != old.datset
+        || cb.trgops != old.trgops
+        || cb.buftm != old.buftm
+        || cb.intgpd != old.intgpd;
+    let purge = item.purgebuf == Some(true);
+    let entryid = item.entryid.map(u64::from_be_bytes);
+    if let Some(entryid) = entryid.filter(|entryid| *entryid != 0) {
+        let found = !reconfig
+            && !purge
+            && engine
+                .run(key)
+                .and_then(|run| run.buffer.as_ref())
+                .is_some_and(|buffer| buffer.contains(entryid));
+        if !found {
+            return Err(ServiceError::ParameterValueInconsistent);
+        }
+    }
+    let mut member_vec = None;
+    if reconfig && !cb.datset.is_empty() {
+        member_vec = Some(find_dataset(model, &[], &cb.datset)?.member_vec.clone());
+    }
+    if item.rptena == Some(true) && !cb.rptena {
+        if cb.datset.is_empty() {
+            return Err(ServiceError::ParameterValueInconsistent);
+        }
+        cb.rptena = true;
+    }
+    /*检查完了  下面开始改     */
+    if reconfig {
+        match member_vec {
+            Some(member_vec) => {
+                let run = RcbRun::new(member_vec, cb.trgops, cb.buftm, cb.intgpd);
+                engine.restart_buffered(key, run);
+            }
+            None => engine.stop(key),
+        }
+        cb.entryid = [0; 8];
+    }
+    if let Some(buffer) = engine.run_mut(key).and_then(|run| run.buffer.as_mut()) {
+        if purge {
+            buffer.purge();
+            cb.entryid = [0; 8];
+        }
+        if let Some(entryid) = entryid {
+            buffer.sent_id = entryid;
+            cb.entryid = entryid.to_be_bytes();
+        }
+    }
+    if let Some(run) = engine.run_mut(key) {
+        if run.enabled != cb.rptena {
+            run.set_enabled(cb.rptena);
+        }
+    }
+    let owned = cb.rptena || cb.resvtms.is_some_and(|resvtms| resvtms > 0);
+    if cb.owner.is_some() {
+        cb.owner = Some(if owned { owner.to_vec() } else { Vec::new() });
+    }
+    if owned {
+        engine.set_owner(key, con_id);
+    } else {
+        engine.release(key);
+    }
+    if item.gi == Some(true) {
+        engine.request_gi(key);
+    }
+    if let Some(brcb) = model.get_brcb_mut(key) {
+        *brcb = cb;
+    }
+    Ok(())
+}
+
+/*92 写缓存报告控制块  每个控制块一个结果     */
+pub fn set_brcb_values(
+    model: &mut Model,
+    engine: &mut ReportEngine,
+    con_id: u32,
+    owner: &[u8],
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: SetBRCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let result = req
+        .brcb
+        .into_iter()
+        .map(
+            |item| match set_brcb_one(model, engine, con_id, owner, item) {
+                Ok(()) => ServiceError::NoError,
+                Err(err) => err,
+            },
+        )
+        .collect();
+    let resp = SetBRCBValuesResponsePDU { result };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*写一个非缓存报告控制块  先在副本上改 全部通过才生效
+先处理去使能  使能的时候只能改 RptEna 和 GI  使能放在最后     */
+fn set_urcb_one(
+    model: &mut Model,
+    engine: &mut ReportEngine,
+    con_id: u32,
+    owner: &[u8],
+    nps_vec: &[DataSet],
+    item: SetUrcbItem,
+) -> Result<(), ServiceError> {
+    let old = find_urcb(model, &item.reference)?.clone();
+    let key = item.reference.as_str();
+    if engine.locked(key, con_id, owner) {
+        return Err(ServiceError::InstanceLockedByOtherClient);
+    }
+    let mut cb = old.clone();
+    if item.rptena == Some(false) {
+        cb.rptena = false;
+    }
+    if let Some(resv) = item.resv {
+        if !resv && cb.rptena {
+            return Err(ServiceError::AccessNotAllowedInCurrentState);
+        }
+        cb.resv = resv;
+    }
+    let config = item.rptid.is_some()
+        || item.datset.is_some()
+        || item.optflds.is_some()
+        || item.buftm.is_some()
+        || item.trgops.is_some()
+        || item.intgpd.is_some();
+    if config && cb.rptena {
+        return Err(ServiceError::AccessNotAllowedInCurrentState);
+    }
+    if let Some(rptid) = item.rptid {
+        cb.rptid = rptid;
+    }
+    if let Some(datset) = item.datset {
+        if !datset.is_empty() {
+            find_dataset(model, nps_vec, &datset)?;
+        }
+        if datset != cb.datset {
+            cb.datset = datset;
+            cb.confrev = cb.confrev.wrapping_add(1);
+        }
+    }
+    if let Some(optflds) = item.optflds {
+        cb.optflds = [optflds[0], optflds[1] & 0xc0];
+    }
+    if let Some(buftm) = item.buftm {
+        cb.buftm = buftm;
+    }
+    if let Some(trgops) = item.trgops {
+        cb.trgops = trgops & TRGOPS_MASK;
+    }
+    if let Some(intgpd) = item.intgpd {
+        cb.intgpd = intgpd;
+    }
+    let mut member_vec = None;
+    if item.rptena == Some(true) && !cb.rptena {
+        let dataset = find_dataset(model, nps_vec, &cb.datset)
+            .map_err(|_| ServiceError::ParameterValueInconsistent)?;
+        member_vec = Some(dataset.member_vec.clone());
+        cb.rptena = true;
+        cb.resv = true;
+    }
+    let owned = cb.resv || cb.rptena;
+    if cb.owner.is_some() {
+        cb.owner = Some(if owned { owner.to_vec() } else { Vec::new() });
+    }
+    if old.rptena && !cb.rptena {
+        engine.stop(key);
+    }
+    if owned {
+        engine.set_owner(key, con_id);
+    } else {
+        engine.release(key);
+    }
+    if let Some(member_vec) = member_vec {
+        let mut run = RcbRun::new(member_vec, cb.trgops, cb.buftm, cb.intgpd);
+        run.set_enabled(true);
+        engine.start(key, run);
+    }
+    if item.gi == Some(true) {
+        engine.request_gi(key);
+    }
+    if let Some(urcb) = model.get_urcb_mut(key) {
+        *urcb = cb;
+    }
+    Ok(())
+}
+
+/*94 写非缓存报告控制块  每个控制块一个结果
+owner 是这个关联对端的地址     */
+pub fn set_urcb_values(
+    model: &mut Model,
+    engine: &mut ReportEngine,
+    con_id: u32,
+    owner: &[u8],
+    nps_vec: &[DataSet],
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: SetURCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let result = req
+        .urcb
+        .into_iter()
+        .map(
+            |item| match set_urcb_one(model, engine, con_id, owner, nps_vec, item) {
+                Ok(()) => ServiceError::NoError,
+                Err(err) => err,
+            },
+        )
+        .collect();
+    let resp = SetURCBValuesResponsePDU { result };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*关联断开  它占着的控制块都去使能
+非缓存的直接放掉  非持久数据集跟着关联一起没了  引用它的数据集清空
+缓存的接着攒  ResvTms 大于 0 的话 留给同一个地址这么多秒     */
+pub fn release_con(model: &mut Model, engine: &mut ReportEngine, con_id: u32, owner: &[u8]) {
+    for key in engine.owned_by(con_id) {
+        if let Some(cb) = model.get_brcb_mut(&key) {
+            cb.rptena = false;
+            let resvtms = cb.resvtms.unwrap_or(0);
+            if resvtms > 0 {
+                engine.hold(&key, owner, resvtms as u64);
+            } else {
+                engine.release(&key);
+                if cb.owner.is_some() {
+                    cb.owner = Some(Vec::new());
+                }
+            }
+            if let Some(run) = engine.run_mut(&key) {
+                run.set_enabled(false);
+            }
+            continue;
+        }
+        engine.stop(&key);
+        engine.release(&key);
+        if let Some(cb) = model.get_urcb_mut(&key) {
+            cb.rptena = false;
+            cb.resv = false;
+            if cb.owner.is_some() {
+                cb.owner = Some(Vec::new());
+            }
+            if cb.datset.starts_with(super::dataset::NPS_PREFIX) {
+                cb.datset.clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*样例里面 ReportSettings 没有 resvTms  拷一份加上     */
+    async fn model_with_resvtms(resvtms: bool) -> Model {
+        let scl = std::fs::read_to_string("sisco_sample.cid").unwrap();
+        let scl = if resvtms {
+            scl.replace(
+                "optFields=\"Conf\"/>",
+                "optFields=\"Conf\" resvTms=\"true\"/>",
+            )
+        } else {
+            scl
+        };
+        let path = std::env::temp_dir().join(format!(
+            "cms61850_resvtms_{}_{}.cid",
+            resvtms,
+            std::process::id()
+        ));
+        std::fs::write(&path, scl).unwrap();
+        let info = crate::cfg::scl2::scl_parse(path.to_str().unwrap(), "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+        Model::from_scl(&info).unwrap()
+    }
+
+    fn brcb_key(model: &Model) -> String {
+        let ld = &model.ld_vec[0];
+        let ln = ld.ln_vec.iter().find(|ln| !ln.brcb_vec.is_empty()).unwrap();
+        format!("{}/{}.{}", ld.name, ln.name, ln.brcb_vec[0].name)
+    }
+
+    fn set_resvtms(key: &str, resvtms: i16) -> SetBrcbItem {
+        SetBrcbItem {
+            reference: key.to_string(),
+            resvtms: Some(resvtms),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn resvtms_reserves_brcb() {
+        let mut model = model_with_resvtms(true).await;
+        let mut engine = ReportEngine::new(10000);
+        engine.start_brcbs(&model);
+        let key = brcb_key(&model);
+        assert_eq!(model.get_brcb(&key).unwrap().resvtms, Some(0));
+
+        set_brcb_one(&mut model, &mut engine, 1, &[1], set_resvtms(&key, 30)).unwrap();
+        assert_eq!(engine.owner(&key), Some(1));
+        assert_eq!(model.get_brcb(&key).unwrap().resvtms, Some(30));
+        assert_eq!(
+            set_brcb_one(&mut model, &mut engine, 2, &[2], set_resvtms(&key, 10)),
+            Err(ServiceError::InstanceLockedByOtherClient)
+        );
+        assert_eq!(
+            set_brcb_one(&mut model, &mut engine, 1, &[1], set_resvtms(&key, -1)),
+            Err(ServiceError::ParameterValueInappropriate)
+        );
+
+        /*写 0 放掉  别的连接就能占     */
+        set_brcb_one(&mut model, &mut engine, 1, &[1], set_resvtms(&key, 0)).unwrap();
+        assert_eq!(engine.owner(&key), None);
+        set_brcb_one(&mut model, &mut engine, 2, &[2], set_resvtms(&key, 10)).unwrap();
+        assert_eq!(engine.owner(&key), Some(2));
+    }
+
+    #[tokio::test]
+    async fn resvtms_not_configured() {
+        let mut model = model_with_resvtms(false).await;
+        let mut engine = ReportEngine::new(10000);
+        engine.start_brcbs(&model);
+        let key = brcb_key(&model);
+        assert_eq!(model.get_brcb(&key).unwrap().resvtms, None);
+        assert_eq!(
+            set_brcb_one(&mut model, &mut engine, 1, &[1], set_resvtms(&key, 30)),
+            Err(ServiceError::AccessViolation)
+        );
+    }
+}
@@ -0,0 +1,442 @@
+use super::common::{self, ServiceError};
+use super::data::read_ref;
+use crate::cms61850::codec::aper::{self, AperCodec, AperDecoder, AperEncoder, Size};
+use crate::cms61850::model::cb::Sgcb;
+use crate::cms61850::model::{Model, Value};
+use crate::cms61850::sg::{SgEngine, FC_SE, FC_SG};
+use bytes::Bytes;
+use tokio::time::Instant;
+/***************************************/
+/*定值组服务  84 85 选激活组 编辑组  86 87 写编辑区 确认  88 读定值  89 读 SGCB
+编辑相关的都要这个连接占着编辑区     */
+/***************************************/
+
+// SelectActiveSG-RequestPDU ::= SEQUENCE {
+//     sgcbReference ObjectReference,
+//     settingGroupNumber INT8U
+// }
+// SelectEditSG-RequestPDU ::= SEQUENCE {
+//     sgcbReference ObjectReference,
+//     settingGroupNumber INT8U
+// }
+/*两个请求一样  响应都是 NULL     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SelectSGRequestPDU {
+    pub sgcbreference: String,
+    pub settinggroupnumber: u8,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetEditSGValueItem {
+    pub reference: String,
+    pub value: Value,
+}
+
+// SetEditSGValue-RequestPDU ::= SEQUENCE {
+//     data SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         value Data
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetEditSGValueRequestPDU {
+    pub data: Vec<SetEditSGValueItem>,
+}
+
+// SetEditSGValue-ResponsePDU ::= SEQUENCE {
+//     result SEQUENCE OF ServiceError
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SetEditSGValueResponsePDU {
+    pub result: Vec<ServiceError>,
+}
+
+// ConfirmEditSGValues-RequestPDU ::= SEQUENCE {
+//     sgcbReference ObjectReference
+// }
+/*响应是 NULL     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ConfirmEditSGValuesRequestPDU {
+    pub sgcbreference: String,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetEditSGValueItem {
+    pub reference: String,
+    pub fc: String,
+}
+
+// GetEditSGValue-RequestPDU ::= SEQUENCE {
+//     data SEQUENCE OF SEQUENCE {
+//         reference ObjectReference,
+//         fc FunctionalConstraint
+//     }
+// }
+/*fc 只能是 SG 或者 SE     */
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetEditSGValueRequestPDU {
+    pub data: Vec<GetEditSGValueItem>,
+}
+
+// GetEditSGValue-ResponsePDU ::= SEQUENCE {
+//     value SEQUENCE OF Data,
+//     moreFollows BOOLEAN DEFAULT TRUE
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetEditSGValueResponsePDU {
+    pub value: Vec<Value>,
+    pub morefollows: bool,
+}
+
+// GetSGCBValues-RequestPDU ::= SEQUENCE {
+//     sgcbReference SEQUENCE OF ObjectReference
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetSGCBValuesRequestPDU {
+    pub sgcbreference: Vec<String>,
+}
+
+// GetSGCBValues-ResponsePDU ::= SEQUENCE {
+//     sgcb SEQUENCE OF CHOICE {
+//         error ServiceError,
+//         value SGCB
+//     }
+// }
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct GetSGCBValuesResponsePDU {
+    pub sgcb: Vec<Result<Sgcb, ServiceError>>,
+}
+
+impl AperCodec for SelectSGRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.sgcbreference)?;
+        enc.put_constrained_int(self.settinggroupnumber as i128, 0, 255)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(SelectSGRequestPDU {
+            sgcbreference: common::get_obj_ref(dec)?,
+            settinggroupnumber: dec.get_constrained_int(0, 255)? as u8,
+        })
+    }
+}
+
+impl AperCodec for SetEditSGValueItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.reference)?;
+        self.value.aper_encode(enc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(SetEditSGValueItem {
+            reference: common::get_obj_ref(dec)?,
+            value: Value::aper_decode(dec)?,
+        })
+    }
+}
+
+impl AperCodec for SetEditSGValueRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.data.len(), Size::Unconstrained)?;
+        for item in self.data.iter() {
+            item.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.push(SetEditSGValueItem::aper_decode(dec)?);
+        }
+        Ok(SetEditSGValueRequestPDU { data })
+    }
+}
+
+impl AperCodec for SetEditSGValueResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.result.len(), Size::Unconstrained)?;
+        for err in self.result.iter() {
+            err.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut result = Vec::new();
+        for _ in 0..count {
+            result.push(ServiceError::aper_decode(dec)?);
+        }
+        Ok(SetEditSGValueResponsePDU { result })
+    }
+}
+
+impl AperCodec for ConfirmEditSGValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.sgcbreference)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(ConfirmEditSGValuesRequestPDU {
+            sgcbreference: common::get_obj_ref(dec)?,
+        })
+    }
+}
+
+impl AperCodec for GetEditSGValueItem {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        common::put_obj_ref(enc, &self.reference)?;
+        common::put_fc(enc, &self.fc)
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        Ok(GetEditSGValueItem {
+            reference: common::get_obj_ref(dec)?,
+            fc: common::get_fc(dec)?,
+        })
+    }
+}
+
+impl AperCodec for GetEditSGValueRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.data.len(), Size::Unconstrained)?;
+        for item in self.data.iter() {
+            item.aper_encode(enc)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.push(GetEditSGValueItem::aper_decode(dec)?);
+        }
+        Ok(GetEditSGValueRequestPDU { data })
+    }
+}
+
+impl AperCodec for GetEditSGValueResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_preamble(false, &[!self.morefollows]);
+        enc.put_sequence_of_len(self.value.len(), Size::Unconstrained)?;
+        for val in self.value.iter() {
+            val.aper_encode(enc)?;
+        }
+        common::put_more_follows(enc, self.morefollows);
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let present = dec.get_sequence_preamble(false, 1)?;
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut value = Vec::new();
+        for _ in 0..count {
+            value.push(Value::aper_decode(dec)?);
+        }
+        Ok(GetEditSGValueResponsePDU {
+            value,
+            morefollows: common::get_more_follows(dec, present[0])?,
+        })
+    }
+}
+
+impl AperCodec for GetSGCBValuesRequestPDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.sgcbreference.len(), Size::Unconstrained)?;
+        for reference in self.sgcbreference.iter() {
+            common::put_obj_ref(enc, reference)?;
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut sgcbreference = Vec::new();
+        for _ in 0..count {
+            sgcbreference.push(common::get_obj_ref(dec)?);
+        }
+        Ok(GetSGCBValuesRequestPDU { sgcbreference })
+    }
+}
+
+impl AperCodec for GetSGCBValuesResponsePDU {
+    fn aper_encode(&self, enc: &mut AperEncoder) -> crate::Result<()> {
+        enc.put_sequence_of_len(self.sgcb.len(), Size::Unconstrained)?;
+        for item in self.sgcb.iter() {
+            match item {
+                Err(err) => {
+                    enc.put_choice_index(0, 2, false)?;
+                    err.aper_encode(enc)?;
+                }
+                Ok(sgcb) => {
+                    enc.put_choice_index(1, 2, false)?;
+                    sgcb.aper_encode(enc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn aper_decode(dec: &mut AperDecoder) -> crate::Result<Self> {
+        let count = dec.get_sequence_of_len(Size::Unconstrained)?;
+        let mut sgcb = Vec::new();
+        for _ in 0..count {
+            match dec.get_choice_index(2, false)? {
+                0 => sgcb.push(Err(ServiceError::aper_decode(dec)?)),
+                _ => sgcb.push(Ok(Sgcb::aper_decode(dec)?)),
+            }
+        }
+        Ok(GetSGCBValuesResponsePDU { sgcb })
+    }
+}
+
+/***************************************/
+/*服务处理     */
+/***************************************/
+
+/*84 选激活组     */
+pub fn select_active_sg(
+    model: &mut Model,
+    engine: &mut SgEngine,
+    pay_load: &[u8],
+) -> Result<Bytes, ServiceError> {
+    let req: SelectSGRequestPDU = common::decode_req(pay_load)?;
+    engine.select_active(model, &req.sgcbreference, req.settinggroupnumber)?;
+    Ok(common::null_resp())
+}
+
+/*85 选编辑组  0 放掉编辑区     */
+pub fn select_edit_sg(
+    model: &mut Model,
+    engine: &mut SgEngine,
+    con_id: u32,
+    pay_load: &[u8],
+) -> Result<Bytes, ServiceError> {
+    let req: SelectSGRequestPDU = common::decode_req(pay_load)?;
+    engine.select_edit(
+        model,
+        con_id,
+        &req.sgcbreference,
+        req.settinggroupnumber,
+        Instant::now(),
+    )?;
+    Ok(common::null_resp())
+}
+
+/*86 写编辑区  每一项一个结果     */
+pub fn set_edit_sg_value(
+    model: &mut Model,
+    engine: &mut SgEngine,
+    con_id: u32,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: SetEditSGValueRequestPDU = common::decode_req(pay_load)?;
+    let now = Instant::now();
+    let result = req
+        .data
+        .into_iter()
+        .map(|item| {
+            common::parse_ref_fc(&item.reference, &None)
+                .and_then(|objref| engine.set_edit_value(model, con_id, &objref, item.value, now))
+                .err()
+                .unwrap_or(ServiceError::NoError)
+        })
+        .collect();
+    let resp = SetEditSGValueResponsePDU { result };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+/*87 确认编辑     */
+pub fn confirm_edit_sg_values(
+    model: &mut Model,
+    engine: &mut SgEngine,
+    con_id: u32,
+    pay_load: &[u8],
+) -> Result<Bytes, ServiceError> {
+    let req: ConfirmEditSGValuesRequestPDU = common::decode_req(pay_load)?;
+    engine.confirm_edit(model, con_id, &req.sgcbreference, Instant::now())?;
+    Ok(common::null_resp())
+}
+
+/*88 读定值  SG 是激活组  SE 是编辑区  没在编辑读 SE 是 InstanceNotAvailable     */
+fn read_sg(model: &Model, item: &GetEditSGValueItem) -> Result<Value, ServiceError> {
+    if item.fc != FC_SG && item.fc != FC_SE {
+        return Err(ServiceError::ParameterValueInappropriate);
+    }
+    let objref = common::parse_ref_fc(&item.reference, &Some(item.fc.clone()))?;
+    read_ref(model, &objref)
+}
+
+pub fn get_edit_sg_value(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetEditSGValueRequestPDU = common::decode_req(pay_load)?;
+    let value: Vec<Value> = req
+        .data
+        .iter()
+        .map(|item| read_sg(model, item).unwrap_or_else(Value::Error))
+        .collect();
+    common::encode_fit(&value, max_len, |value, morefollows| {
+        GetEditSGValueResponsePDU { value, morefollows }
+    })
+}
+
+/*89 读定值组控制块     */
+pub fn get_sgcb_values(
+    model: &Model,
+    pay_load: &[u8],
+    max_len: usize,
+) -> Result<Bytes, ServiceError> {
+    let req: GetSGCBValuesRequestPDU = common::decode_req(pay_load)?;
+    let sgcb = req
+        .sgcbreference
+        .iter()
+        .map(|reference| {
+            model
+                .get_sgcb(reference)
+                .cloned()
+                .ok_or(ServiceError::InstanceNotAvailable)
+        })
+        .collect();
+    let resp = GetSGCBValuesResponsePDU { sgcb };
+    let pay_load =
+        aper::aper_to_bytes(&resp).map_err(|_| ServiceError::FailedDueToServerConstraint)?;
+    common::check_fit(pay_load, max_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGCB: &str = "E1Q1SB1C1/LLN0.SGCB";
+
+    fn select_req(sgnum: u8) -> Bytes {
+        aper::aper_to_bytes(&SelectSGRequestPDU {
+            sgcbreference: SGCB.to_string(),
+            settinggroupnumber: sgnum,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn select_confirm_null_response() {
+        let info = crate::cfg::scl2::scl_parse("sisco_sample.cid", "E1Q1SB1", "S1")
+            .await
+            .unwrap();
+        let mut model = Model::from_scl(&info).unwrap();
+        let mut engine = SgEngine::new(30);
+
+        let resp = select_active_sg(&mut model, &mut engine, &select_req(1)).unwrap();
+        assert_eq!(resp.as_ref(), &[0u8][..]);
+        assert_eq!(model.get_sgcb(SGCB).unwrap().actsg, 1);
+
+        let resp = select_edit_sg(&mut model, &mut engine, 1, &select_req(2)).unwrap();
+        assert_eq!(resp.as_ref(), &[0u8][..]);
+
+        let req = aper::aper_to_bytes(&ConfirmEditSGValuesRequestPDU {
+            sgcbreference: SGCB.to_string(),
+        })
+        .unwrap();
+        let resp = confirm_edit_sg_values(&mut model, &mut engine, 1, &req).unwrap();
+        assert_eq!(resp.as_ref(), &[0u8][..]);
+        assert!(model.get_sgcb(SGCB).unwrap().cnfedit);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::cms61850::model::cb::Sgcb;
+use crate::cms61850::model::dataset::split_dataset_ref;
+use crate::cms61850::model::value::utc_time;
+use crate::cms61850::model::{DataNode, Model, ObjRef, Value};
+use crate::cms61850::service::common::ServiceError;
+use crate::cms61850::service::data;
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+/***************************************/
+/*定值组的运行时  SGCB 在 LLN0 下面  管整个 LD 的定值
+模型里面只有 fc 是 SG 的叶子  value 是激活组的值  sg_value_vec 是各组的值
+SE 是编辑组  SelectEditSG 的时候拷到叶子的 edit_value  确认以后写回 sg_value_vec
+编辑区一个 LD 只能一个连接占着  ResvTms 秒没有动作就放掉     */
+/***************************************/
+
+pub const FC_SG: &str = "SG";
+pub const FC_SE: &str = "SE";
+
+/*占着编辑区的连接  每次编辑往后推     */
+#[derive(Debug)]
+struct Editing {
+    con_id: u32,
+    deadline: Instant,
+}
+
+/*key 都是 LD 名     */
+#[derive(Default, Debug)]
+pub struct SgEngine {
+    edit_map: HashMap<String, Editing>,
+    /*编辑区保留的秒数  SGCB 里面的 ResvTms 也是它     */
+    resvtms: u16,
+}
+
+impl SgEngine {
+    pub fn new(resvtms: u16) -> SgEngine {
+        SgEngine {
+            resvtms,
+            ..Default::default()
+        }
+    }
+
+    /*启动的时候 SG 叶子换成激活组的值  不算变化     */
+    pub fn start(&mut self, model: &mut Model) {
+        let ld_vec: Vec<(String, u8)> = model
+            .ld_vec
+            .iter_mut()
+            .filter_map(|ld| {
+                let cb = ld.ln_vec.iter_mut().find_map(|ln| ln.sgcb.as_mut())?;
+                cb.resvtms = Some(self.resvtms);
+                Some((ld.name.clone(), cb.actsg))
+            })
+            .collect();
+        for (ld, actsg) in ld_vec {
+            update_sg(model, &ld, false, |leaf| {
+                leaf.value = leaf.sg_value(actsg as u32);
+            });
+            println!("sgcb {} active sg {}", ld, actsg);
+        }
+    }
+
+    /*84 换激活组  SG 叶子的值跟着变  按变化报     */
+    pub fn select_active(
+        &mut self,
+        model: &mut Model,
+        reference: &str,
+        sgnum: u8,
+    ) -> Result<(), ServiceError> {
+        let ld = sgcb_ld(reference)?;
+        let cb = model
+            .get_sgcb_mut(reference)
+            .ok_or(ServiceError::InstanceNotAvailable)?;
+        if sgnum == 0 || sgnum > cb.numofsg {
+            return Err(ServiceError::ParameterValueInconsistent);
+        }
+        cb.actsg = sgnum;
+        cb.lacttm = utc_time(chrono::Utc::now());
+        update_sg(model, &ld, true, |leaf| {
+            leaf.value = leaf.sg_value(sgnum as u32);
+        });
+        if let Ok(objref) = ObjRef::parse(reference) {
+            model.note_persist(&objref);
+        }
+        println!("sgcb {} active sg {}", reference, sgnum);
+        Ok(())
+    }
+
+    /*85 选编辑组  别的连接占着不行  0 是放掉编辑区
+    重新选的话 没确认的编辑都丢掉     */
+    pub fn select_edit(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        reference: &str,
+        sgnum: u8,
+        now: Instant,
+    ) -> Result<(), ServiceError> {
+        self.expire(model, now);
+        let ld = sgcb_ld(reference)?;
+        let cb = model
+            .get_sgcb(reference)
+            .ok_or(ServiceError::InstanceNotAvailable)?;
+        if sgnum > cb.numofsg {
+            return Err(ServiceError::ParameterValueInconsistent);
+        }
+        if self
+            .edit_map
+            .get(&ld)
+            .is_some_and(|editing| editing.con_id != con_id)
+        {
+            return Err(ServiceError::InstanceLockedByOtherClient);
+        }
+        if sgnum == 0 {
+            self.release(model, &ld);
+            return Ok(());
+        }
+        self.edit_map.insert(
+            ld.clone(),
+            Editing {
+                con_id,
+                deadline: now + Duration::from_secs(self.resvtms as u64),
+            },
+        );
+        if let Some(cb) = model.get_sgcb_mut(reference) {
+            cb.editsg = sgnum;
+            cb.cnfedit = false;
+        }
+        update_sg(model, &ld, false, |leaf| {
+            leaf.edit_value = Some(leaf.sg_value(sgnum as u32));
+        });
+        println!("sgcb {} edit sg {} by con {}", reference, sgnum, con_id);
+        Ok(())
+    }
+
+    /*86 写编辑区  下面的 SG 叶子按 SE 的形状写  SetDataValues 带 SE 的也走这里     */
+    pub fn set_edit_value(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        objref: &ObjRef,
+        val: Value,
+        now: Instant,
+    ) -> Result<(), ServiceError> {
+        self.touch(model, con_id, &objref.ld, now)?;
+        let node = model
+            .get_node_mut(objref)
+            .ok_or(ServiceError::InstanceNotAvailable)?;
+        let mut new_node = node.clone();
+        data::write_node(&mut new_node, Some(FC_SE), val)?;
+        *node = new_node;
+        Ok(())
+    }
+
+    /*87 确认编辑  编辑区写回编辑的那一组  编辑的就是激活组的话 值马上生效
+    确认以后还在编辑  可以接着改     */
+    pub fn confirm_edit(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        reference: &str,
+        now: Instant,
+    ) -> Result<(), ServiceError> {
+        let ld = sgcb_ld(reference)?;
+        let cb = model
+            .get_sgcb(reference)
+            .ok_or(ServiceError::InstanceNotAvailable)?;
+        let (editsg, actsg) = (cb.editsg, cb.actsg);
+        self.touch(model, con_id, &ld, now)?;
+        let objref_vec = update_sg(model, &ld, editsg == actsg, |leaf| {
+            if let Some(val) = leaf.edit_value.clone() {
+                leaf.set_sg_value(editsg as u32, val.clone());
+                if editsg == actsg {
+                    leaf.value = val;
+                }
+            }
+        });
+        for objref in objref_vec.iter() {
+            model.note_persist(objref);
+        }
+        if let Some(cb) = model.get_sgcb_mut(reference) {
+            cb.cnfedit = true;
+        }
+        println!(
+            "sgcb {} edit sg {} confirmed by con {}",
+            reference, editsg, con_id
+        );
+        Ok(())
+    }
+
+    /*编辑要这个连接占着编辑区  占着的话保留时间往后推     */
+    fn touch(
+        &mut self,
+        model: &mut Model,
+        con_id: u32,
+        ld: &str,
+        now: Instant,
+    ) -> Result<(), ServiceError> {
+        self.expire(model, now);
+        let editing = self
+            .edit_map
+            .get_mut(ld)
+            .ok_or(ServiceError::AccessNotAllowedInCurrentState)?;
+        if editing.con_id != con_id {
+            return Err(ServiceError::InstanceLockedByOtherClient);
+        }
+        editing.deadline = now + Duration::from_secs(self.resvtms as u64);
+        Ok(())
+    }
+
+    /*放掉编辑区  EditSG 回到 0     */
+    fn release(&mut self, model: &mut Model, ld: &str) {
+        self.edit_map.remove(ld);
+        if let Some(cb) = ld_sgcb_mut(model, ld) {
+            cb.editsg = 0;
+        }
+        update_sg(model, ld, false, |leaf| leaf.edit_value = None);
+    }
+
+    /*ResvTms 到了  没确认的编辑丢掉     */
+    pub fn expire(&mut self, model: &mut Model, now: Instant) {
+        let expired: Vec<String> = self
+            .edit_map
+            .iter()
+            .filter(|(_, editing)| editing.deadline <= now)
+            .map(|(ld, _)| ld.clone())
+            .collect();
+        for ld in expired {
+            println!("sgcb {} edit reservation expired", ld);
+            self.release(model, &ld);
+        }
+    }
+
+    /*连接断了  它占着的编辑区放掉     */
+    pub fn release_con(&mut self, model: &mut Model, con_id: u32) {
+        let ld_vec: Vec<String> = self
+            .edit_map
+            .iter()
+            .filter(|(_, editing)| editing.con_id == con_id)
+            .map(|(ld, _)| ld.clone())
+            .collect();
+        for ld in ld_vec {
+            println!("sgcb {} edit released with con {}", ld, con_id);
+            self.release(model, &ld);
+        }
+    }
+}
+
+/*SGCB 的引用 LD/LLN0.SGCB  编辑区按 LD 算     */
+fn sgcb_ld(reference: &str) -> Result<String, ServiceError> {
+    split_dataset_ref(reference)
+        .map(|(ln_ref, _)| ln_ref.ld)
+        .ok_or(ServiceError::ParameterValueInappropriate)
+}
+
+fn ld_sgcb_mut<'a>(model: &'a mut Model, ld: &str) -> Option<&'a mut Sgcb> {
+    model
+        .get_ld_mut(ld)?
+        .ln_vec
+        .iter_mut()
+        .find_map(|ln| ln.sgcb.as_mut())
+}
+
+fn for_sg_leaves(node: &mut DataNode, f: &impl Fn(&mut DataNode)) {
+    if node.is_leaf() {
+        if node.fc == FC_SG {
+            f(node);
+        }
+        return;
+    }
+    for child in node.child_vec.iter_mut() {
+        for_sg_leaves(child, f);
+    }
+}
+
+/*LD 下面所有 SG 叶子改一遍  note 的话按 DO 和原来的比  记变化
+返回改过的 DO     */
+fn update_sg(
+    model: &mut Model,
+    ld_name: &str,
+    note: bool,
+    f: impl Fn(&mut DataNode),
+) -> Vec<ObjRef> {
+    let objref_vec: Vec<ObjRef> = match model.get_ld(ld_name) {
+        Some(ld) => ld
+            .ln_vec
+            .iter()
+            .flat_map(|ln| {
+                ln.do_vec
+                    .iter()
+                    .filter(|node| node.has_fc(FC_SG))
+                    .map(|node| ObjRef {
+                        ld: ld_name.to_string(),
+                        ln: ln.name.clone(),
+                        path: vec![node.name.clone()],
+                        fc: Some(FC_SG.to_string()),
+                    })
+            })
+            .collect(),
+        None => return Vec::new(),
+    };
+    for objref in objref_vec.iter() {
+        let node = match model.get_node_mut(objref) {
+            Some(node) => node,
+            None => continue,
+        };
+        if !note {
+            for_sg_leaves(node, &f);
+            continue;
+        }
+        let mut new_node = node.clone();
+        for_sg_leaves(&mut new_node, &f);
+        let old_node = std::mem::replace(node, new_node);
+        model.note_change(objref, &old_node);
+    }
+    objref_vec
+}
@@ -1,7 +1,10 @@
 pub use crate::cms61850::net::cmscon::connection;
 pub use crate::cms61850::net::cmscon::connection_manager;
+use crate::cms61850::net::cmscon::frame;
+use tokio::sync::mpsc;
 use tokio::fs;
 use anyhow::{bail,Context};
+use std::net::IpAddr;
 use std::path::Path;
 use serde::{Deserialize, Serialize};
 use serde_json;
@@ -32,6 +35,102 @@ fn default_reboot_when_no_con_sec() -> u32 {
     300
 }
 
+/*缓存报告的日志文件  重启以后缓存里面的条目还在     */
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct BrcbJournalCfg {
+    /*是否写到文件  不写的话重启就丢了     */
+    #[serde(default)]
+    pub enable: bool,
+    /*目录  下面按 IED/LD/LN.RCB.jnl 一个控制块一个文件     */
+    #[serde(default = "default_journal_dir")]
+    pub journal_dir: String,
+    /*重启恢复的时候  多少秒以前的条目不要了  0 表示都要     */
+    #[serde(default = "default_journal_retention_sec")]
+    pub retention_sec: u32,
+}
+fn default_journal_dir() -> String {
+    String::from("brcb_journal")
+}
+fn default_journal_retention_sec() -> u32 {
+    86400
+}
+
+/*日志  条目个数和扫描周期在 startupcfg 里面  这里只有存放的位置     */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogCfg {
+    /*目录  下面按 IED/LD/LN.LogName.jnl 一个日志一个文件  空的话只放内存     */
+    #[serde(default = "default_log_dir")]
+    pub log_dir: String,
+}
+impl Default for LogCfg {
+    fn default() -> Self {
+        LogCfg {
+            log_dir: default_log_dir(),
+        }
+    }
+}
+fn default_log_dir() -> String {
+    String::from("log")
+}
+
+/*控制配置     */
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ControlCfg {
+    /*没有接过程的控制对象用仿真执行  多少毫秒以后到位  模拟开关分合的时间  0 马上到位     */
+    #[serde(default)]
+    pub sim_operate_ms: u32,
+}
+
+/*定值组配置     */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SgCfg {
+    /*编辑区保留的秒数  这么久没有编辑服务就放掉  也是 SGCB 的 ResvTms     */
+    #[serde(default = "default_edit_resv_sec")]
+    pub edit_resv_sec: u16,
+}
+impl Default for SgCfg {
+    fn default() -> Self {
+        SgCfg {
+            edit_resv_sec: default_edit_resv_sec(),
+        }
+    }
+}
+fn default_edit_resv_sec() -> u16 {
+    300
+}
+
+/*落盘的改动  写过的 SP CF 和确认过的定值组  重启以后盖在 scl 的值上面     */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OverlayCfg {
+    /*文件  空的话不落盘  scl 头的 id version 变了自动作废     */
+    #[serde(default = "default_overlay_file")]
+    pub overlay_file: String,
+}
+impl Default for OverlayCfg {
+    fn default() -> Self {
+        OverlayCfg {
+            overlay_file: default_overlay_file(),
+        }
+    }
+}
+fn default_overlay_file() -> String {
+    String::from("cms61850_overlay.json")
+}
+
+/*GOOSE 发布 订阅  走原始以太网  要有 CAP_NET_RAW     */
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct GooseCfg {
+    /*不开的话 GoCB 只能读写  不发报文     */
+    #[serde(default)]
+    pub enable: bool,
+    /*按 ExtRef 订阅别的 IED 的 GOOSE  和发布用同一个网口     */
+    #[serde(default)]
+    pub subscribe: bool,
+    /*收发报文的网口  调试的时候可以是 veth     */
+    #[serde(default)]
+    pub ifname: String,
+}
+
 /***************************************/
 /*这次准备 各个配置 分区域划分功能     */
 /***************************************/
@@ -43,6 +142,27 @@ pub struct CfgData {
     pub con_mgr_cfg: connection_manager::ConManagerCfg,
     /*看门狗配置     */
     pub wd_cfg: WdCfg,
+    /*缓存报告日志配置  没有就不写     */
+    #[serde(default)]
+    pub brcb_journal_cfg: BrcbJournalCfg,
+    /*日志配置     */
+    #[serde(default)]
+    pub log_cfg: LogCfg,
+    /*控制配置     */
+    #[serde(default)]
+    pub control_cfg: ControlCfg,
+    /*定值组配置     */
+    #[serde(default)]
+    pub sg_cfg: SgCfg,
+    /*落盘配置     */
+    #[serde(default)]
+    pub overlay_cfg: OverlayCfg,
+    /*GOOSE 配置     */
+    #[serde(default)]
+    pub goose_cfg: GooseCfg,
+    /*不从配置文件读取 ，启动时根据 scl 头生成 关联协商的时候使用     */
+    #[serde(skip)]
+    pub model_version: String,
 }
 
 impl CfgData {
@@ -55,7 +175,30 @@ impl CfgData {
     
 }
 
+/*连接转给汇总中心的数据服务请求     */
+#[derive(Clone, Debug)]
+pub struct ServiceReq {
+    pub con_id: u32,
+    /*已经重组好的完整请求     */
+    pub apdu: frame::APDU,
+    /*协商过的 asdu 长度  响应超了要分批     */
+    pub neg_asdu_len: usize,
+}
+
+/*汇总中心发给单个连接的     */
+#[derive(Clone, Debug)]
+pub enum ToConData {
+    /*服务响应  连接按协商长度分帧发出去     */
+    ServiceResp(frame::APDUBuilder),
+    /*服务端主动发的  报告 命令结束这些  不算在未回复的请求里面     */
+    Report(frame::APDUBuilder),
+}
+
 #[derive(Clone, Debug)]
 pub enum ExData {
     CfgData(CfgData),
+    /*连接建立  带上对端地址和回给这个连接的通道     */
+    ConOpen(u32, Option<IpAddr>, mpsc::Sender<ToConData>),
+    ConClose(u32),
+    ServiceReq(ServiceReq),
 }
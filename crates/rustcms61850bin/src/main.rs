@@ -92,15 +92,75 @@ pub async fn main() -> Result<()> {
     /***************************************/
     //用来群发订阅消息的
     let (to_each_handle_pubch, _) = broadcast::channel(6);
-    let (to_main_info_tx, mut to_main_info_rx) = mpsc::channel(CHANELENUM);
+    let (to_main_info_tx, to_main_info_rx) = mpsc::channel(CHANELENUM);
 
     /***************************************/
     /***********  消息中枢        End ******************/
     /***************************************/
 
-    let local_cfg = comdata::CfgData::read_startup_cfg_file(CFG_FILE_NAME).await?;
+    let mut local_cfg = comdata::CfgData::read_startup_cfg_file(CFG_FILE_NAME).await?;
+    /*关联协商里面回复的模型版本     */
+    local_cfg.model_version = scl_info.header.model_version();
     println!("local cfg {:?}", local_cfg);
 
+    /*GOOSE 订阅的发布方  同一个 scl 文件里面别的 IED     */
+    let pub_model_vec = if local_cfg.goose_cfg.subscribe {
+        cms61850::goose::subscriber::load_publishers(&startup_cfg.scl_filename, &scl_info).await
+    } else {
+        Vec::new()
+    };
+    /*运行时模型 放在汇总中心里面     */
+    let mut cms_unit =
+        cms61850::cms_unit::CmsUnit::new(&local_cfg, &startup_cfg, &scl_info, &pub_model_vec)?;
+    let unit_shutdown =
+        cms61850::net::shutdown::Shutdown::new(notify_shutdown_origin.subscribe());
+    let unit_complete_tx = shutdown_complete_tx.clone();
+    tokio::spawn(async move {
+        cms_unit
+            .run(to_main_info_rx, unit_shutdown, unit_complete_tx)
+            .await;
+    });
+
+    /***************************************/
+    /***********   SIGHUP 重新加载配置      Start ******************/
+    /***************************************/
+    /*重新读配置文件  发给汇总中心和各个连接  模型版本还是启动时候的     */
+    let mut reload_shutdown = notify_shutdown_origin.subscribe();
+    let reload_pubch = to_each_handle_pubch.clone();
+    let reload_main_tx = to_main_info_tx.clone();
+    let model_version = local_cfg.model_version.clone();
+    tokio::spawn(async move {
+        let mut hangup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(err) => {
+                println!("cfg reload signal err {}", err);
+                return;
+            }
+        };
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {}
+                _ = reload_shutdown.recv() => break,
+            }
+            let mut cfg = match comdata::CfgData::read_startup_cfg_file(CFG_FILE_NAME).await {
+                Ok(cfg) => cfg,
+                Err(err) => {
+                    println!("cfg reload failed {:?}", err);
+                    continue;
+                }
+            };
+            cfg.model_version = model_version.clone();
+            println!("cfg reload {:?}", cfg);
+            let _ = reload_pubch.send(comdata::ExData::CfgData(cfg.clone()));
+            if reload_main_tx.send(comdata::ExData::CfgData(cfg)).await.is_err() {
+                break;
+            }
+        }
+    });
+    /***************************************/
+    /***********   SIGHUP 重新加载配置        End ******************/
+    /***************************************/
+
     /***************************************/
     /***********   启动安全 和非安全端口      Start ******************/
     /***************************************/
@@ -173,5 +233,10 @@ pub async fn main() -> Result<()> {
     /***********  启动安全 和非安全端口       End ******************/
     /***************************************/
 
+    /*等所有子模块退出 再结束 ，不然主函数返回了 所有任务都跟着结束     */
+    drop(shutdown_complete_tx);
+    let _ = shutdown_complete_rx.recv().await;
+    println!("all modules exit");
+
     Ok(())
 }
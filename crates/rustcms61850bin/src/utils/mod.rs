@@ -8,6 +8,7 @@ use anyhow::{bail, Result};
 /***************************************/
 /*1 字符串处理函数     */
 /*2 位操作     */
+/*3 校验     */
 
 /***************************************/
 /*********** 目录        End ******************/
@@ -56,6 +57,29 @@ pub fn hex_to_ascii(hex_str: &str) -> Result<String> {
     Ok(result)
 }
 /***************************************/
+/*  字节转成 hex 字符串  [0x01, 0xab] 转成 01ab   */
+/***************************************/
+pub fn bytes_to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/***************************************/
+/*  hex 字符串转成字节  必须是偶数个   */
+/***************************************/
+pub fn hex_to_bytes(hex_str: &str) -> Result<Vec<u8>> {
+    if !hex_str.len().is_multiple_of(2) || !hex_str.is_ascii() {
+        bail!(format!("Error: hex_to_bytes failed {}", hex_str));
+    }
+    let mut result = Vec::with_capacity(hex_str.len() / 2);
+    for index in (0..hex_str.len()).step_by(2) {
+        match u8::from_str_radix(&hex_str[index..index + 2], 16) {
+            Ok(byte) => result.push(byte),
+            Err(e) => bail!(format!("Error: hex_to_bytes failed {} {}", hex_str, e)),
+        }
+    }
+    Ok(result)
+}
+/***************************************/
 /***********1 字符串处理函数          End ******************/
 /***************************************/
 
@@ -105,3 +129,27 @@ pub fn bstr_bit_get(buf: &[u8], bitnum: usize) -> bool {
 /***************************************/
 /***********位操作         End ******************/
 /***************************************/
+
+/***************************************/
+/***********3 校验         Start ******************/
+/***************************************/
+
+/* crc32  IEEE 802.3 多项式  和 zip 的一样     */
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/***************************************/
+/***********3 校验         End ******************/
+/***************************************/